@@ -1,30 +1,74 @@
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+
+use crate::shell::Mode;
 
 pub enum Command {
-    DatabaseInformation { filename: String },
-    ListTables { filename: String },
-    Query { filename: String, query: String },
+    /// Runs each of the commands, a dot-command or SQL statements, on the database
+    Commands {
+        filename: String,
+        commands: Vec<String>,
+        options: OutputOptions,
+    },
+    /// Reads statements and dot-commands from the standard input, interactively when it is a
+    /// terminal
+    Shell {
+        filename: Option<String>,
+        options: OutputOptions,
+    },
 }
 
-const DBINFO_COMMAND: &str = ".dbinfo";
-const TABLES_COMMAND: &str = ".tables";
+/// How the shell prints rows, as set by options rather than dot-commands
+#[derive(Default)]
+pub struct OutputOptions {
+    pub mode: Option<Mode>,
+    pub headers: Option<bool>,
+    pub null_value: Option<String>,
+    pub column_separator: Option<String>,
+    pub row_separator: Option<String>,
+}
 
+/// Parses the arguments: options, the database and the commands to run on it. Like sqlite3,
+/// options can come anywhere, until one is `--`.
 pub fn parse_command() -> Result<Command> {
-    let args: Vec<String> = std::env::args().collect::<Vec<_>>();
-    match args.len() {
-        0 | 1 => bail!("Missing <database file path> and <command>"),
-        2 => bail!("Missing <command>"),
-        _ => {
-            let filename = args[1];
-            let command: &str = &args[2];
-            match command {
-                DBINFO_COMMAND => Ok(Command::DatabaseInformation { filename }),
-                TABLES_COMMAND => Ok(Command::ListTables { filename }),
-                query => Ok(Command::Query {
-                    filename,
-                    query: query.to_string(),
-                }),
-            }
+    let mut args = std::env::args().skip(1);
+    let mut options = OutputOptions::default();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref());
+            break;
         }
+        // options are written with one dash or two
+        let Some(option) = arg.strip_prefix("--").or_else(|| arg.strip_prefix('-')) else {
+            positional.push(arg);
+            continue;
+        };
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("missing argument to {}", arg))
+        };
+        match option {
+            "mode" => options.mode = Some(Mode::parse(&value()?)?),
+            "header" => options.headers = Some(true),
+            "noheader" => options.headers = Some(false),
+            "nullvalue" => options.null_value = Some(value()?),
+            "separator" => options.column_separator = Some(value()?),
+            "newline" => options.row_separator = Some(value()?),
+            // sqlite3's options setting a mode
+            "ascii" | "box" | "column" | "csv" | "html" | "json" | "line" | "list" | "markdown"
+            | "quote" | "table" | "tabs" => options.mode = Some(Mode::parse(option)?),
+            _ => bail!("unknown option: {}", arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let filename = positional.next();
+    let commands: Vec<String> = positional.collect();
+    match filename {
+        Some(filename) if !commands.is_empty() => Ok(Command::Commands {
+            filename,
+            commands,
+            options,
+        }),
+        filename => Ok(Command::Shell { filename, options }),
     }
 }
@@ -0,0 +1,398 @@
+use std::ops::BitOr;
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::database::Database;
+use crate::engine::parameter::Parameters;
+use crate::engine::select;
+use crate::sql::{self, SelectStatement};
+
+pub use self::error::{Error, Result};
+pub use self::value::{FromValue, ToValue};
+
+mod error;
+pub mod value;
+
+/// How a database is opened, flags combined with `|`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags(u32);
+
+impl OpenFlags {
+    pub const READ_ONLY: OpenFlags = OpenFlags(0x01);
+    /// Not supported: connections only run SELECT statements. Rows are written to tables with
+    /// the transactions of `Database::begin` instead, as the shell's `.import` does.
+    pub const READ_WRITE: OpenFlags = OpenFlags(0x02);
+    /// Not supported: connections only open existing databases, or empty ones held in memory
+    pub const CREATE: OpenFlags = OpenFlags(0x04);
+    /// Opens an empty database held in memory, whatever the path
+    pub const MEMORY: OpenFlags = OpenFlags(0x80);
+
+    pub fn contains(self, flags: OpenFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = OpenFlags;
+
+    fn bitor(self, flags: OpenFlags) -> OpenFlags {
+        OpenFlags(self.0 | flags.0)
+    }
+}
+
+/// A connection to a database, which statements are prepared on
+pub struct Connection {
+    database: Database,
+}
+
+impl Connection {
+    /// Opens the database at `path`, or an empty one held in memory when the path is
+    /// `:memory:` or the flags include `MEMORY`.
+    pub fn open(path: &str, flags: OpenFlags) -> Result<Connection> {
+        if flags.contains(OpenFlags::READ_WRITE) || flags.contains(OpenFlags::CREATE) {
+            return Err(Error::ReadOnly(path.to_string()));
+        }
+        let database = if path == ":memory:" || flags.contains(OpenFlags::MEMORY) {
+            Database::empty()?
+        } else {
+            Database::init_from_file(path)?
+        };
+        Ok(Connection { database })
+    }
+
+    /// Parses a statement, which can then be run any number of times with different values
+    /// bound to its parameters.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        let sql::Statement::SelectStatement(statement) = sql::parse_statement(sql)? else {
+            return Err(Error::Sql(
+                "Only SELECT statements can be executed".to_string(),
+            ));
+        };
+        let parameters = Parameters::of(&statement)?;
+        let columns = select::column_names(&self.database, &statement, &Default::default())?;
+        Ok(Statement {
+            database: &self.database,
+            values: vec![Value::Null; parameters.count()],
+            bound: (*statement).clone(),
+            statement: *statement,
+            parameters,
+            columns,
+        })
+    }
+
+    /// The database, whose functions and collating sequences statements can use
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    /// Mutable access to the database, to define functions and collating sequences on it
+    pub fn database_mut(&mut self) -> &mut Database {
+        &mut self.database
+    }
+}
+
+/// A prepared statement, with the values bound to its parameters
+pub struct Statement<'c> {
+    database: &'c Database,
+    statement: SelectStatement,
+    /// The statement with its parameters replaced by the values bound to them
+    bound: SelectStatement,
+    parameters: Parameters,
+    values: Vec<Value>,
+    columns: Vec<String>,
+}
+
+impl<'c> Statement<'c> {
+    /// The names of the result columns
+    pub fn column_names(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// How many parameters the statement has: the largest of their numbers
+    pub fn parameter_count(&self) -> usize {
+        self.parameters.count()
+    }
+
+    /// The name of the parameter with the given number, such as `:name` or `?2`. Parameters
+    /// written `?` have none.
+    pub fn parameter_name(&self, number: usize) -> Option<&str> {
+        self.parameters.name(number)
+    }
+
+    /// The number of the parameter with the given name, such as `:name`
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        self.parameters.index(name)
+    }
+
+    /// Binds a value to the parameter with the given number, from 1.
+    pub fn bind(&mut self, number: usize, value: &dyn ToValue) -> Result<()> {
+        if number == 0 || number > self.values.len() {
+            return Err(Error::ParameterOutOfRange(number));
+        }
+        self.values[number - 1] = value.to_value();
+        Ok(())
+    }
+
+    /// Binds a value to the parameter with the given name, such as `:name`.
+    pub fn bind_named(&mut self, name: &str, value: &dyn ToValue) -> Result<()> {
+        let number = self
+            .parameter_index(name)
+            .ok_or_else(|| Error::NoSuchParameter(name.to_string()))?;
+        self.bind(number, value)
+    }
+
+    /// Sets all parameters back to NULL.
+    pub fn clear_bindings(&mut self) {
+        self.values.fill(Value::Null);
+    }
+
+    /// Runs the statement, after binding the values given, if any, to its first parameters.
+    pub fn query(&mut self, values: &[&dyn ToValue]) -> Result<Rows<'_>> {
+        for (index, value) in values.iter().enumerate() {
+            self.bind(index + 1, *value)?;
+        }
+        self.bound = self.statement.clone();
+        self.parameters.bind(&mut self.bound, &self.values);
+        let rows = select::execute(self.database, &self.bound, &Default::default())?;
+        Ok(Rows {
+            rows,
+            columns: &self.columns,
+        })
+    }
+
+    /// Runs the statement like `query`, reading each row as a `T`.
+    pub fn query_as<T: FromRow>(
+        &mut self,
+        values: &[&dyn ToValue],
+    ) -> Result<impl Iterator<Item = Result<T>> + '_> {
+        Ok(self.query(values)?.map(|row| T::from_row(&row?)))
+    }
+}
+
+/// The rows a statement returns, read as they are asked for
+pub struct Rows<'s> {
+    rows: select::Rows<'s>,
+    columns: &'s [String],
+}
+
+impl<'s> Iterator for Rows<'s> {
+    type Item = Result<Row<'s>>;
+
+    fn next(&mut self) -> Option<Result<Row<'s>>> {
+        let values = self.rows.next()?;
+        Some(
+            values
+                .map(|values| Row {
+                    columns: self.columns,
+                    values,
+                })
+                .map_err(Error::from),
+        )
+    }
+}
+
+/// A result row, whose columns can be read by position or by name
+#[derive(Debug)]
+pub struct Row<'s> {
+    columns: &'s [String],
+    values: Vec<Value>,
+}
+
+impl Row<'_> {
+    /// Reads the value of a column, given by its position from 0 or by its name, as a `T`.
+    pub fn get<T: FromValue>(&self, column: impl ColumnIndex) -> Result<T> {
+        let index = column.index(self.columns)?;
+        T::from_value(&self.values[index])
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+/// A type rows can be read as, such as a struct whose fields are read from the columns of the
+/// same name with `Row::get`, which `from_row!` implements. Tuples are read from the columns in
+/// order.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self>;
+}
+
+/// Implements `FromRow` for a struct, reading each of the fields listed from the column of the
+/// same name, whatever its position in the rows.
+///
+/// ```
+/// use sqlite_starter_rust::{from_row, Connection, OpenFlags};
+///
+/// struct Apple {
+///     name: String,
+///     color: Option<String>,
+/// }
+///
+/// from_row!(Apple { name, color });
+///
+/// let connection = Connection::open("sample.db", OpenFlags::READ_ONLY)?;
+/// let mut statement = connection.prepare("SELECT color, name FROM apples WHERE id = 1")?;
+/// let apple: Apple = statement.query_as(&[])?.next().unwrap()?;
+/// assert_eq!(apple.name, "Granny Smith");
+/// # Ok::<(), sqlite_starter_rust::Error>(())
+/// ```
+#[macro_export]
+macro_rules! from_row {
+    ($type:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::FromRow for $type {
+            fn from_row(row: &$crate::Row) -> $crate::connection::Result<$type> {
+                ::std::result::Result::Ok($type {
+                    $($field: row.get(stringify!($field))?,)*
+                })
+            }
+        }
+    };
+}
+
+macro_rules! tuple_from_row {
+    ($($value:ident $index:tt),*) => {
+        impl<$($value: FromValue),*> FromRow for ($($value,)*) {
+            fn from_row(row: &Row) -> Result<Self> {
+                Ok(($(row.get::<$value>($index)?,)*))
+            }
+        }
+    };
+}
+
+tuple_from_row!(A 0);
+tuple_from_row!(A 0, B 1);
+tuple_from_row!(A 0, B 1, C 2);
+tuple_from_row!(A 0, B 1, C 2, D 3);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+/// What result columns can be looked up by: their position or their name
+pub trait ColumnIndex {
+    fn index(&self, columns: &[String]) -> Result<usize>;
+}
+
+impl ColumnIndex for usize {
+    fn index(&self, columns: &[String]) -> Result<usize> {
+        match *self < columns.len() {
+            true => Ok(*self),
+            false => Err(Error::ColumnOutOfRange(*self)),
+        }
+    }
+}
+
+/// Names are matched regardless of case, like SQLite matches them.
+impl ColumnIndex for &str {
+    fn index(&self, columns: &[String]) -> Result<usize> {
+        columns
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(self))
+            .ok_or_else(|| Error::NoSuchColumn(self.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database;
+
+    #[test]
+    fn runs_prepared_statements() -> Result<()> {
+        let connection = Connection::open("sample.db", OpenFlags::READ_ONLY)?;
+        let mut statement =
+            connection.prepare("SELECT id, name FROM apples WHERE id > :min AND color <> ?")?;
+        assert_eq!(statement.column_names(), ["id", "name"]);
+        assert_eq!(statement.parameter_count(), 2);
+        statement.bind_named(":min", &2)?;
+        let names: Vec<String> = statement
+            .query(&[&2, &"Yellow"])?
+            .map(|row| row?.get("NAME"))
+            .collect::<Result<_>>()?;
+        assert_eq!(names, ["Honeycrisp"]);
+
+        statement.clear_bindings();
+        assert_eq!(statement.query(&[])?.count(), 0);
+        assert!(statement.bind(3, &1).is_err());
+
+        let mut statement = connection.prepare("SELECT ?1 + 1, ?2")?;
+        let row = statement.query(&[&41, &None::<i64>])?.next().unwrap()?;
+        assert_eq!(row.get::<i64>(0)?, 42);
+        assert_eq!(row.get::<Option<String>>(1)?, None);
+        assert!(row.get::<i64>(2).is_err());
+        assert!(Connection::open("sample.db", OpenFlags::READ_WRITE).is_err());
+        let error = Connection::open("no/such.db", OpenFlags::READ_ONLY)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            Error::Database(crate::database::Error::CantOpen(_))
+        ));
+        assert_eq!(error.code(), 14);
+        let error = connection.prepare("SELECT * FORM apples").err().unwrap();
+        assert!(matches!(&error, Error::Parse(e) if e.position == 9));
+        let mut statement = connection.prepare("SELECT nope FROM apples")?;
+        let error = statement.query(&[]).err().unwrap();
+        assert!(matches!(
+            &error,
+            Error::Database(database::Error::Schema(message)) if message == "no such column: nope"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn reports_errors_of_application_defined_functions() -> Result<()> {
+        let mut connection = Connection::open("sample.db", OpenFlags::READ_ONLY)?;
+        let database = connection.database_mut();
+        database.create_scalar_function("fail", 1, true, |arguments| {
+            Err(database::Error::Function(format!(
+                "failed on {}",
+                arguments[0]
+            )))
+        })?;
+        assert!(matches!(
+            database.create_scalar_function("", 0, true, |_| Ok(Value::Null)),
+            Err(database::Error::Misuse)
+        ));
+        let mut statement = connection.prepare("SELECT fail(id) FROM apples")?;
+        let error = statement.query(&[])?.next().unwrap().err().unwrap();
+        assert!(matches!(
+            &error,
+            Error::Database(database::Error::Function(message)) if message == "failed on 1"
+        ));
+        assert_eq!(error.code(), 1);
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Apple {
+        name: String,
+        color: Option<String>,
+    }
+
+    from_row!(Apple { name, color });
+
+    #[test]
+    fn reads_rows_as_structs() -> Result<()> {
+        let connection = Connection::open("sample.db", OpenFlags::READ_ONLY)?;
+        let mut statement = connection.prepare("SELECT color, name FROM apples WHERE id = ?")?;
+        let apples: Vec<Apple> = statement.query_as(&[&1])?.collect::<Result<_>>()?;
+        let (color, name) = statement
+            .query_as::<(String, String)>(&[&1])?
+            .next()
+            .unwrap()?;
+        assert_eq!(
+            apples,
+            [Apple {
+                name: name.clone(),
+                color: Some(color)
+            }]
+        );
+        assert_eq!(name, "Granny Smith");
+        assert!(matches!(
+            statement.query_as::<(i64, String)>(&[&1])?.next().unwrap(),
+            Err(Error::InvalidColumnType {
+                expected: "integer",
+                found: "text"
+            })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use thiserror::Error;
+
+use crate::database;
+use crate::sql::ParseError;
+
+/// The ways using a connection can fail. Errors reading the database are kept as they are, so
+/// that callers can tell them apart.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Database(#[from] database::Error),
+    /// The SQL of a statement doesn't parse.
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    /// Statements can't be prepared or run, for reasons such as a function called wrongly.
+    /// Columns and tables which don't exist are reported as `database::Error::Schema`.
+    #[error("{0}")]
+    Sql(String),
+    /// The database can't be opened in the way asked for, connections only reading databases.
+    #[error("unable to open database \"{0}\" for writing: connections only run SELECT statements")]
+    ReadOnly(String),
+    /// A parameter number given isn't one of those of the statement.
+    #[error("parameter number {0} out of range")]
+    ParameterOutOfRange(usize),
+    #[error("no such parameter: {0}")]
+    NoSuchParameter(String),
+    /// A column position given isn't one of those of the result rows.
+    #[error("column index {0} out of range")]
+    ColumnOutOfRange(usize),
+    #[error("no such column: {0}")]
+    NoSuchColumn(String),
+    /// A value can't be read as the type asked for, `found` being its storage class.
+    #[error("invalid column type: expected {expected}, found {found}")]
+    InvalidColumnType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// An integer doesn't fit in the type asked for.
+    #[error("integer {0} out of range")]
+    IntegerOutOfRange(i64),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// The result code SQLite fails with for the error
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::Database(error) => error.code(),
+            Error::ReadOnly(_) => 14,
+            Error::InvalidColumnType { .. } | Error::IntegerOutOfRange(_) => 20,
+            Error::ParameterOutOfRange(_) | Error::ColumnOutOfRange(_) => 25,
+            Error::Parse(_)
+            | Error::Sql(_)
+            | Error::NoSuchParameter(_)
+            | Error::NoSuchColumn(_) => 1,
+        }
+    }
+}
+
+/// The engine reports errors with `anyhow`: those of the database are taken back out, the
+/// others only kept as their message.
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Error {
+        match error.downcast::<database::Error>() {
+            Ok(error) => Error::Database(error),
+            Err(error) => match error.downcast::<ParseError>() {
+                Ok(error) => Error::Parse(error),
+                Err(error) => Error::Sql(error.to_string()),
+            },
+        }
+    }
+}
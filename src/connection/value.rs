@@ -0,0 +1,202 @@
+use crate::database::page::btree::data::serial_types::Value;
+
+use super::{Error, Result};
+
+/// A type the values of result columns can be read as. NULL can only be read as `Option` or
+/// as `Value`.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self>;
+}
+
+/// A type that can be bound to the parameters of statements
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+/// The storage class of a value, as named by `typeof()`
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Float64(_) => "real",
+        Value::String(_) => "text",
+        Value::Blob(_) => "blob",
+        _ => "integer",
+    }
+}
+
+fn invalid_type<T>(value: &Value, expected: &'static str) -> Result<T> {
+    Err(Error::InvalidColumnType {
+        expected,
+        found: type_name(value),
+    })
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Value> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<i64> {
+        match value.as_i64() {
+            Some(i) => Ok(i),
+            None => invalid_type(value, "integer"),
+        }
+    }
+}
+
+/// The smaller integers are read from integers within their range.
+macro_rules! from_integer {
+    ($($integer:ty),*) => {$(
+        impl FromValue for $integer {
+            fn from_value(value: &Value) -> Result<$integer> {
+                let i = i64::from_value(value)?;
+                <$integer>::try_from(i)
+                    .map_err(|_| Error::IntegerOutOfRange(i))
+            }
+        }
+    )*};
+}
+
+from_integer!(i8, i16, i32, u8, u16, u32, u64, usize);
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<f64> {
+        match value {
+            Value::Float64(r) => Ok(*r),
+            value => match value.as_i64() {
+                Some(i) => Ok(i as f64),
+                None => invalid_type(value, "real"),
+            },
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<bool> {
+        Ok(i64::from_value(value)? != 0)
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<String> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            value => invalid_type(value, "text"),
+        }
+    }
+}
+
+/// Blobs, or the bytes of text
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> Result<Vec<u8>> {
+        match value {
+            Value::Blob(b) => Ok(b.clone()),
+            Value::String(s) => Ok(s.as_bytes().to_vec()),
+            value => invalid_type(value, "blob"),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Option<T>> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+macro_rules! to_integer {
+    ($($integer:ty),*) => {$(
+        impl ToValue for $integer {
+            fn to_value(&self) -> Value {
+                Value::Int64(i64::from(*self))
+            }
+        }
+    )*};
+}
+
+to_integer!(i8, i16, i32, i64, u8, u16, u32, bool);
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float64(*self)
+    }
+}
+
+impl ToValue for f32 {
+    fn to_value(&self) -> Value {
+        Value::Float64(f64::from(*self))
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl ToValue for [u8] {
+    fn to_value(&self) -> Value {
+        Value::Blob(self.to_vec())
+    }
+}
+
+impl ToValue for Vec<u8> {
+    fn to_value(&self) -> Value {
+        Value::Blob(self.clone())
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            Some(value) => value.to_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_values() {
+        assert_eq!(i64::from_value(&Value::Int8(-3)).unwrap(), -3);
+        assert_eq!(f64::from_value(&Value::Int64(2)).unwrap(), 2.0);
+        assert!(u8::from_value(&Value::Int64(300)).is_err());
+        assert!(String::from_value(&Value::Int64(1)).is_err());
+        assert_eq!(Option::<String>::from_value(&Value::Null).unwrap(), None);
+        assert!(i64::from_value(&Value::Null).is_err());
+        assert_eq!(
+            Vec::<u8>::from_value(&Value::String("ab".into())).unwrap(),
+            b"ab"
+        );
+
+        assert_eq!(true.to_value(), Value::Int64(1));
+        assert_eq!("text".to_value(), Value::String("text".into()));
+        assert_eq!(None::<i32>.to_value(), Value::Null);
+        assert_eq!(b"\x01"[..].to_value(), Value::Blob(vec![1]));
+    }
+}
@@ -1,49 +1,62 @@
-use anyhow::{anyhow, bail, Result};
 use page::btree::data::record::Record;
 use page::btree::data::serial_types::Value;
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::io::SeekFrom;
 use std::ops::Index;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
 
-use crate::sql;
+use crate::engine::collation::Collations;
+use crate::engine::function::user::{Aggregate, Functions};
 
+use self::cursor::{IndexCursor, TableCursor};
+pub use self::error::{Error, Result};
 use self::header::{DatabaseHeader, DATABASE_HEADER_SIZE};
 use self::io::SQLiteFile;
-use self::page::btree::data::{CellType, TableLeafCell};
-use self::page::{
-    btree::page::BTreePage, freelist_page::FreeListPage, lockbyte_page::LockBytePage,
-    payload_overflow_page::PayloadOverflowPage, pointer_map_page::PointerMapPage, Page, PageType,
-};
-use self::schema::{ObjectInformation, TableInformation};
+use self::page::btree::data::Payload;
+use self::page::btree::page::BTreePage;
+use self::schema::{IndexInformation, ObjectInformation, ObjectType, TableInformation};
 
+pub mod cursor;
+pub mod encoding;
+pub mod error;
 pub mod header;
 mod io;
 pub mod page;
 pub mod schema;
+pub mod write;
 
-pub const TABLE_SCHEMA_ROOT_PAGE_NUMBER: u32 = 0;
+pub const TABLE_SCHEMA_ROOT_PAGE_NUMBER: u32 = 1;
 
 pub struct Database {
-    db_file: SQLiteFile,
+    // reads only need a shared reference to the database so that several cursors can walk
+    // different b-trees at the same time
+    db_file: RefCell<SQLiteFile>,
     pub header: DatabaseHeader,
+    /// The tables and indexes of the schema, parsed the first time they are needed. Statements
+    /// are planned again for each row a correlated subquery is evaluated against, so parsing
+    /// the schema every time would dominate their cost.
+    tables: RefCell<Option<Vec<TableInformation>>>,
+    indexes: RefCell<Option<Vec<IndexInformation>>>,
+    /// The functions defined by the application, which SQL statements can call
+    functions: Functions,
+    /// The collating sequences defined by the application
+    collations: Collations,
+    /// Whether text which isn't validly encoded is an error rather than being decoded with
+    /// replacement characters
+    lossless: bool,
+    /// Set from elsewhere, such as a signal handler, to stop the statement being run
+    interrupt: Option<Arc<AtomicBool>>,
 }
 
 pub trait Filter {
     fn evaluate(&self, row: &Row) -> bool;
 }
 
-impl Filter for sql::SelectStatement {
-    fn evaluate(&self, row: &Row) -> bool {
-        self.conditions.iter().all(|condition| {
-            // it's fine to unwrap here because the statement has been validated
-            // at this point so we know the column exists
-            let value = &row[&condition.column].unwrap();
-            // this only accounts for string literals which is fine for now
-            value == condition.value
-        })
-    }
-}
-
 impl Filter for bool {
     fn evaluate(&self, _row: &Row) -> bool {
         *self
@@ -56,23 +69,17 @@ pub struct Row {
     pub hmap: HashMap<String, Value>,
 }
 
-fn cell_to_row(cell: CellType, table_information: &TableInformation) -> Result<Row> {
-    match cell {
-        CellType::TableLeaf(TableLeafCell { key, payload, .. }) => {
-            let record = Record::try_from(payload)?;
-            let columns: Vec<Column> = table_information
-                .column_names
-                .iter()
-                .zip(record.values)
-                .map(|(name, value)| Column {
-                    name: name.to_string(),
-                    value,
-                })
-                .collect();
-            Ok(Row::new(key, columns))
-        }
-        _ => bail!("Expected TableLeafCell, got {:?}", cell),
-    }
+fn record_to_row(rowid: u64, values: Vec<Value>, table_information: &TableInformation) -> Row {
+    let columns: Vec<Column> = table_information
+        .column_names
+        .iter()
+        .zip(table_information.row_values(rowid, values))
+        .map(|(name, value)| Column {
+            name: name.to_string(),
+            value,
+        })
+        .collect();
+    Row::new(rowid, columns)
 }
 
 impl Row {
@@ -80,7 +87,7 @@ impl Row {
         let mut hmap = HashMap::new();
         hmap.insert("rowid".to_string(), Value::Int64(rowid as i64));
         for column in &columns {
-            hmap.insert(column.name, column.value);
+            hmap.insert(column.name.clone(), column.value.clone());
         }
         Row {
             rowid,
@@ -90,14 +97,14 @@ impl Row {
     }
 }
 
-impl<K> Index<K> for Row
-where
-    K: std::hash::Hash + Eq,
-{
-    type Output = Option<Value>;
+static NULL_VALUE: Value = Value::Null;
+
+impl Index<&str> for Row {
+    type Output = Value;
 
-    fn index(&self, index: K) -> &Self::Output {
-        self.hmap.get(&index)
+    /// Missing columns read as NULL.
+    fn index(&self, index: &str) -> &Self::Output {
+        self.hmap.get(index).unwrap_or(&NULL_VALUE)
     }
 }
 
@@ -106,135 +113,481 @@ pub struct Column {
     pub value: page::btree::data::serial_types::Value,
 }
 
-fn page_number_to_offset(page_number: u64, page_size: u32) -> SeekFrom {
-    if page_number == 1 {
-        SeekFrom::Start(DATABASE_HEADER_SIZE as u64)
-    } else {
-        SeekFrom::Start((page_number - 1) * page_size as u64)
-    }
+fn page_number_to_offset(page_number: u32, page_size: usize) -> SeekFrom {
+    SeekFrom::Start((page_number as u64 - 1) * page_size as u64)
+}
+
+/// The corruption of a page of a table or index b-tree holding the other kind of b-tree
+pub(crate) fn wrong_page_type(page_number: u32, expected: &str, page: &BTreePage) -> Error {
+    let offset = match page_number {
+        1 => DATABASE_HEADER_SIZE,
+        _ => 0,
+    };
+    Error::corrupt(
+        offset,
+        format!(
+            "expected a {} b-tree page, got {:?}",
+            expected,
+            page.header().page_type
+        ),
+    )
+    .on_page(page_number)
+}
+
+/// The size of the pages of the database `Database::empty` holds
+const EMPTY_DATABASE_PAGE_SIZE: usize = 4096;
+
+/// The bytes of a database holding nothing: a single page with the database header followed by
+/// the schema table, a leaf page without cells.
+fn empty_database() -> Vec<u8> {
+    let mut page = vec![0; EMPTY_DATABASE_PAGE_SIZE];
+    page[..16].copy_from_slice(b"SQLite format 3\0");
+    page[16..18].copy_from_slice(&(EMPTY_DATABASE_PAGE_SIZE as u16).to_be_bytes());
+    // file format versions, reserved space and payload fractions
+    page[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+    // the database holds one page
+    page[28..32].copy_from_slice(&1u32.to_be_bytes());
+    // schema format 4 and UTF-8 text
+    page[44..48].copy_from_slice(&4u32.to_be_bytes());
+    page[56..60].copy_from_slice(&1u32.to_be_bytes());
+    // a table leaf page whose cell content area starts at the end of the page
+    page[DATABASE_HEADER_SIZE] = 0x0d;
+    page[DATABASE_HEADER_SIZE + 5..DATABASE_HEADER_SIZE + 7]
+        .copy_from_slice(&(EMPTY_DATABASE_PAGE_SIZE as u16).to_be_bytes());
+    page
 }
 
 impl Database {
     pub fn init_from_file(path: &str) -> Result<Database> {
-        let mut db_file = SQLiteFile::new(path)?;
-        let header_bytes = db_file.read_exact_at(DATABASE_HEADER_SIZE, SeekFrom::Start(0))?;
+        let mut database = Database::from_source(SQLiteFile::new(path)?)?;
+        // the changes of a connection cut short writing them are rolled back before reading
+        if Path::new(&format!("{}-journal", path)).exists() {
+            database.recover()?;
+        }
+        Ok(database)
+    }
+
+    /// A database held in memory without any table, which statements not reading tables can
+    /// be run against.
+    pub fn empty() -> Result<Database> {
+        Database::from_source(SQLiteFile::from_bytes(empty_database()))
+    }
+
+    fn from_source(mut db_file: SQLiteFile) -> Result<Database> {
+        // a file too short to hold the header isn't a database either
+        let header_bytes = db_file
+            .read_exact_at(DATABASE_HEADER_SIZE, SeekFrom::Start(0))
+            .map_err(|e| match e {
+                Error::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    Error::NotADatabase
+                }
+                e => e,
+            })?;
         let header = DatabaseHeader::try_from(header_bytes)?;
-        Ok(Database { db_file, header })
-    }
-
-    fn read_page(&mut self, page_number: u64, page_type: PageType) -> Result<Page> {
-        let offset: SeekFrom = page_number_to_offset(page_number, self.header.page_size);
-        let page_contents: Vec<u8> = self
-            .db_file
-            .read_exact_at(self.header.page_size as usize, offset)?;
-        match page_type {
-            PageType::LockByte => {
-                let page = LockBytePage::try_from(page_contents)?;
-                Ok(Page::LockByte(page))
-            }
-            PageType::FreeList => {
-                let page = FreeListPage::try_from(page_contents)?;
-                Ok(Page::FreeList(page))
-            }
-            PageType::PayloadOverflow => {
-                let page = PayloadOverflowPage::try_from(page_contents)?;
-                Ok(Page::PayloadOverflow(page))
-            }
-            PageType::PointerMap => {
-                let page = PointerMapPage::try_from(page_contents)?;
-                Ok(Page::PointerMap(page))
+        Ok(Database {
+            db_file: RefCell::new(db_file),
+            header,
+            tables: RefCell::new(None),
+            indexes: RefCell::new(None),
+            functions: Functions::default(),
+            collations: Collations::default(),
+            lossless: false,
+            interrupt: None,
+        })
+    }
+
+    /// Defines a scalar function which SQL statements can call, taking the given number of
+    /// arguments, or any number of them when negative. Deterministic functions always return
+    /// the same result given the same arguments. A function with the same name and number of
+    /// arguments is replaced, built-in functions included. The errors the function returns,
+    /// `Error::Function` for those of its own, fail the statement calling it.
+    pub fn create_scalar_function<F>(
+        &mut self,
+        name: &str,
+        arguments: i32,
+        deterministic: bool,
+        function: F,
+    ) -> Result<()>
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        self.functions
+            .add_scalar(name, arguments, deterministic, Box::new(function))
+    }
+
+    /// Defines an aggregate function which SQL statements can call, taking the given number of
+    /// arguments, or any number of them when negative. `new` creates the state of the
+    /// aggregate for each group of rows, or each window frame when called as a window function.
+    pub fn create_aggregate_function<A, F>(
+        &mut self,
+        name: &str,
+        arguments: i32,
+        new: F,
+    ) -> Result<()>
+    where
+        A: Aggregate + 'static,
+        F: Fn() -> A + 'static,
+    {
+        self.functions.add_aggregate(
+            name,
+            arguments,
+            Rc::new(move || Box::new(new()) as Box<dyn Aggregate>),
+        )
+    }
+
+    /// The functions defined by the application
+    pub fn functions(&self) -> &Functions {
+        &self.functions
+    }
+
+    /// Defines a collating sequence which SQL statements can compare text with, by name in a
+    /// COLLATE clause. A collation with the same name is replaced, built-in ones included.
+    pub fn create_collation<F>(&mut self, name: &str, compare: F) -> Result<()>
+    where
+        F: Fn(&str, &str) -> Ordering + 'static,
+    {
+        self.collations.add(name, Box::new(compare))
+    }
+
+    /// The collating sequences defined by the application
+    pub fn collations(&self) -> &Collations {
+        &self.collations
+    }
+
+    /// Makes reading text which isn't validly encoded in the database's encoding an error,
+    /// rather than replacing the invalid sequences with U+FFFD as SQLite does.
+    pub fn set_lossless(&mut self, lossless: bool) {
+        self.lossless = lossless;
+    }
+
+    /// Makes statements stop with an error once `flag` is set. It isn't cleared by the
+    /// database, so it should be before running the next statement.
+    pub fn set_interrupt(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    /// Fails if the statement being run was interrupted. Reading a page checks it, as do the
+    /// steps of a statement which don't read from the database.
+    pub fn check_interrupt(&self) -> Result<()> {
+        match &self.interrupt {
+            Some(flag) if flag.load(AtomicOrdering::Relaxed) => Err(Error::Interrupted),
+            _ => Ok(()),
+        }
+    }
+
+    fn read_page_bytes(&self, page_number: u32) -> Result<Vec<u8>> {
+        self.check_interrupt()?;
+        if page_number == 0 {
+            return Err(Error::corrupt(0, "page number 0 does not exist"));
+        }
+        let page_size = self.header.page_size_in_bytes();
+        let offset: SeekFrom = page_number_to_offset(page_number, page_size);
+        match self.db_file.borrow_mut().read_exact_at(page_size, offset) {
+            // like SQLite, a page the file is too short to hold is a corruption
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                Err(Error::corrupt(0, "page past the end of the file").on_page(page_number))
             }
-            PageType::BTree => {
-                let page = BTreePage::try_from(page_contents)?;
-                Ok(Page::BTree(page))
+            result => result,
+        }
+    }
+
+    fn read_btree_page(&self, page_number: u32) -> Result<BTreePage> {
+        let page_contents = self.read_page_bytes(page_number)?;
+        // the first page starts with the database header, the b-tree page header follows it
+        let header_offset = if page_number == 1 {
+            DATABASE_HEADER_SIZE
+        } else {
+            0
+        };
+        BTreePage::parse(
+            &page_contents,
+            header_offset,
+            self.header.usable_page_size(),
+        )
+        .map_err(|e| e.on_page(page_number))
+    }
+
+    /// Reassembles a payload, following the chain of overflow pages when it didn't fit on its
+    /// b-tree page.
+    fn read_payload(
+        &self,
+        payload: Payload,
+        payload_size: u64,
+        first_overflow_page_number: Option<u32>,
+    ) -> Result<Vec<u8>> {
+        let mut content = payload.content;
+        let mut next_page = first_overflow_page_number;
+        let usable_size = self.header.usable_page_size();
+        while let Some(page_number) = next_page {
+            if content.len() as u64 >= payload_size {
+                break;
             }
+            let page = self.read_page_bytes(page_number)?;
+            // overflow pages start with the number of the next overflow page, 0 for the last one
+            let next = u32::from_be_bytes([page[0], page[1], page[2], page[3]]);
+            next_page = (next != 0).then_some(next);
+            let remaining = payload_size as usize - content.len();
+            let available = usable_size - 4;
+            content.extend_from_slice(&page[4..4 + remaining.min(available)]);
+        }
+        if (content.len() as u64) < payload_size {
+            return Err(Error::corrupt(
+                0,
+                format!(
+                    "payload is truncated: expected {} bytes, got {}",
+                    payload_size,
+                    content.len()
+                ),
+            ));
         }
+        Ok(content)
     }
 
-    fn write_page(&mut self, page_contents: Vec<u8>, page_number: u64) -> Result<usize> {
-        let offset: SeekFrom = SeekFrom::Start((page_number - 1) * self.header.page_size as u64);
-        // TODO: write a copy of that page into the rollback journal before doing any modifications
-        // (unless page is a freelist leaf page)
-        self.db_file.write_at(page_contents, offset)
+    /// Reads the record a cell of the given page holds. Corruptions are reported at the offset
+    /// of the cell.
+    fn read_record(
+        &self,
+        page_number: u32,
+        payload: Payload,
+        payload_size: u64,
+        first_overflow_page_number: Option<u32>,
+    ) -> Result<Vec<Value>> {
+        let offset = payload.offset;
+        self.read_payload(payload, payload_size, first_overflow_page_number)
+            .and_then(|content| Record::parse(&content, self.header.encoding(), self.lossless))
+            .map(|record| record.values)
+            .map_err(|e| match e {
+                Error::Corrupt {
+                    page: 0, detail, ..
+                } => Error::Corrupt {
+                    page: page_number,
+                    offset,
+                    detail,
+                },
+                e => e,
+            })
     }
 
-    fn read_btree_page(&mut self, page_number: u64) -> Result<BTreePage> {
-        let offset: SeekFrom = page_number_to_offset(page_number, self.header.page_size);
-        let page_contents: Vec<u8> = self
-            .db_file
-            .read_exact_at(self.header.page_size as usize, offset)?;
-        BTreePage::try_from(page_contents)
+    pub fn list_objects(&self) -> Result<Vec<ObjectInformation>> {
+        let schema_rows = self.traverse_btree_table(
+            TABLE_SCHEMA_ROOT_PAGE_NUMBER,
+            &true,
+            &schema::schema_table_information(),
+        )?;
+        schema_rows
+            .iter()
+            .map(ObjectInformation::try_from)
+            .collect()
     }
 
-    pub fn list_objects(&mut self) -> Result<Vec<ObjectInformation>> {
-        if let Ok(page::Page::BTree(table_schema_page)) = self.read_page(0, PageType::BTree) {
-            todo!()
-        } else {
-            todo!()
+    pub fn list_tables(&self) -> Result<Vec<TableInformation>> {
+        if let Some(tables) = self.tables.borrow().as_ref() {
+            return Ok(tables.clone());
+        }
+        let tables: Vec<TableInformation> = self
+            .list_objects()?
+            .into_iter()
+            .filter(|o| o.object_type == ObjectType::Table)
+            .map(TableInformation::try_from)
+            .collect::<Result<_>>()?;
+        *self.tables.borrow_mut() = Some(tables.clone());
+        Ok(tables)
+    }
+
+    /// Lists the indexes which can be used to read a table in index order. Automatic and partial
+    /// indexes are left out.
+    pub fn list_indexes(&self) -> Result<Vec<IndexInformation>> {
+        if let Some(indexes) = self.indexes.borrow().as_ref() {
+            return Ok(indexes.clone());
+        }
+        let indexes: Vec<IndexInformation> = self
+            .list_objects()?
+            .into_iter()
+            .filter(|o| o.object_type == ObjectType::Index)
+            .filter_map(|o| IndexInformation::try_from(o).ok())
+            .collect();
+        *self.indexes.borrow_mut() = Some(indexes.clone());
+        Ok(indexes)
+    }
+
+    pub fn table_information(&self, table_name: &str) -> Result<TableInformation> {
+        if table_name.eq_ignore_ascii_case("sqlite_schema")
+            || table_name.eq_ignore_ascii_case("sqlite_master")
+        {
+            return Ok(schema::schema_table_information());
+        }
+        self.list_tables()?
+            .into_iter()
+            .find(|t| t.table_name.eq_ignore_ascii_case(table_name))
+            .ok_or_else(|| Error::Schema(format!("no such table: {}", table_name)))
+    }
+
+    /// Iterates over the rows of a table b-tree in rowid order, or in reverse rowid order.
+    pub fn table_cursor(&self, root_page_number: u32, reverse: bool) -> TableCursor<'_> {
+        TableCursor::new(self, root_page_number, reverse)
+    }
+
+    /// Iterates over the entries of an index b-tree in key order, or in reverse key order.
+    pub fn index_cursor(&self, root_page_number: u32, reverse: bool) -> IndexCursor<'_> {
+        IndexCursor::new(self, root_page_number, reverse)
+    }
+
+    /// Looks up a single row by rowid, descending the table b-tree from its root.
+    pub fn find_row(&self, root_page_number: u32, rowid: u64) -> Result<Option<Vec<Value>>> {
+        let mut page_number = root_page_number;
+        loop {
+            match self.read_btree_page(page_number)? {
+                BTreePage::TableInterior(header, cells) => {
+                    // the left child of a cell holds the rowids lower than or equal to its key
+                    let position = cells.partition_point(|c| c.key < rowid);
+                    page_number = match cells.get(position) {
+                        Some(cell) => cell.left_child_pointer,
+                        None => header.right_most_pointer.ok_or_else(|| {
+                            Error::corrupt(0, "interior page has no right-most pointer")
+                                .on_page(page_number)
+                        })?,
+                    };
+                }
+                BTreePage::TableLeaf(_, cells) => {
+                    return match cells.into_iter().find(|c| c.key == rowid) {
+                        Some(cell) => Ok(Some(self.read_record(
+                            page_number,
+                            cell.payload,
+                            cell.payload_size,
+                            cell.first_overflow_page_number,
+                        )?)),
+                        None => Ok(None),
+                    };
+                }
+                page => return Err(wrong_page_type(page_number, "table", &page)),
+            }
         }
     }
 
-    pub fn list_tables(&mut self) -> Result<Vec<TableInformation>> {
-        todo!()
-    }
-
-    fn traverse_btree_index(&mut self, root_page_number: u32, condition: dyn Filter) -> Result<()> {
-        //let mut page_pointer_stack: Vec<u32> = vec![root_page_number];
-        //while let Some(page_number) = page_pointer_stack.pop() {
-        //    if let Ok(page::Page::BTree(btree_page)) =
-        //        self.read_page(page_number as u64, page::PageType::BTree)
-        //    {
-        //        match btree_page {
-        //            page::btree::page::BTreePage::IndexLeaf(header, cells) => {
-        //                // do we really have the correct data structures for this?
-        //                todo!("finish this");
-        //            }
-        //            page::btree::page::BTreePage::IndexInterior(header, cells) => {
-        //                todo!("traverse interior indexes, pushing pointers to page numbers onto a stack etc")
-        //            }
-        //            _ => bail!(
-        //                "Wrong BTreePageType; expected LeafIndex or InteriorIndex, got {:?}",
-        //                btree_page.page_header.page_type
-        //            ),
-        //        }
-        //    } else {
-        //        bail!("Couldn't read page number {}", page_number);
-        //    }
-        //}
-        todo!()
+    /// Finds the entries of an index b-tree for which `compare`, telling how an entry orders
+    /// relative to the key looked for, returns `Ordering::Equal`. Entries come out in key order.
+    ///
+    /// Subtrees holding only entries smaller or only entries larger than the key are skipped.
+    pub fn find_index_entries(
+        &self,
+        root_page_number: u32,
+        compare: &dyn Fn(&[Value]) -> Ordering,
+    ) -> Result<Vec<Vec<Value>>> {
+        let mut entries = Vec::new();
+        self.collect_index_entries(root_page_number, compare, &mut entries)?;
+        Ok(entries)
+    }
+
+    fn collect_index_entries(
+        &self,
+        page_number: u32,
+        compare: &dyn Fn(&[Value]) -> Ordering,
+        entries: &mut Vec<Vec<Value>>,
+    ) -> Result<()> {
+        match self.read_btree_page(page_number)? {
+            BTreePage::IndexLeaf(_, cells) => {
+                for cell in cells {
+                    let entry = self.read_record(
+                        page_number,
+                        cell.payload,
+                        cell.payload_size,
+                        cell.first_overflow_page_number,
+                    )?;
+                    match compare(&entry) {
+                        Ordering::Less => {}
+                        Ordering::Equal => entries.push(entry),
+                        Ordering::Greater => break,
+                    }
+                }
+            }
+            BTreePage::IndexInterior(header, cells) => {
+                for cell in cells {
+                    let left_child = cell.left_child_pointer;
+                    let entry = self.read_record(
+                        page_number,
+                        cell.payload,
+                        cell.payload_size,
+                        cell.first_overflow_page_number,
+                    )?;
+                    // the left child holds the entries smaller than the cell's own entry
+                    let ordering = compare(&entry);
+                    if ordering != Ordering::Less {
+                        self.collect_index_entries(left_child, compare, entries)?;
+                    }
+                    match ordering {
+                        Ordering::Less => {}
+                        Ordering::Equal => entries.push(entry),
+                        Ordering::Greater => return Ok(()),
+                    }
+                }
+                if let Some(right_most_pointer) = header.right_most_pointer {
+                    self.collect_index_entries(right_most_pointer, compare, entries)?;
+                }
+            }
+            page => return Err(wrong_page_type(page_number, "index", &page)),
+        }
+        Ok(())
     }
 
     /// Traverse a BTree table and return all rows that satisfy the given condition.
     /// This function traverses the Btree in a depth-first manner, starting from the root page.
     pub fn traverse_btree_table(
-        &mut self,
+        &self,
         root_page_number: u32,
-        condition: dyn Filter,
+        condition: &dyn Filter,
         table_information: &TableInformation,
     ) -> Result<Vec<Row>> {
-        let mut page_pointer_stack: Vec<u32> = vec![root_page_number];
         let mut rows: Vec<Row> = Vec::new();
-        while let Some(page_number) = page_pointer_stack.pop() {
-            let btree_page = self.read_btree_page(page_number)?;
-            match btree_page {
-                page::btree::page::BTreePage::TableLeaf(header, cells) => {
-                    for cell in cells {
-                        let row = cell_to_row(cell, table_information)?;
-                        if condition.evaluate(&row) {
-                            rows.push(row);
-                        }
-                    }
-                }
-                page::btree::page::BTreePage::TableInterior(header, cells) => {
-                    for cell in cells {
-                        page_pointer_stack.push(cell.left_child_pointer);
-                    }
-                }
-                _ => bail!(
-                    "Wrong BTreePageType; expected LeafTable or InteriorTable, got {:?}",
-                    btree_page
-                ),
+        for entry in self.table_cursor(root_page_number, false) {
+            let (rowid, values) = entry?;
+            let row = record_to_row(rowid, values, table_information);
+            if condition.evaluate(&row) {
+                rows.push(row);
             }
         }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_typed_errors() {
+        let open = |bytes: Vec<u8>| Database::from_source(SQLiteFile::from_bytes(bytes));
+        assert!(matches!(open(vec![0; 50]), Err(Error::NotADatabase)));
+        assert!(matches!(
+            open(vec![0; EMPTY_DATABASE_PAGE_SIZE]),
+            Err(Error::NotADatabase)
+        ));
+
+        let mut bytes = empty_database();
+        bytes[DATABASE_HEADER_SIZE] = 0x77;
+        let database = open(bytes).unwrap();
+        let error = database.list_objects().err().unwrap();
+        assert!(matches!(
+            error,
+            Error::Corrupt {
+                page: 1,
+                offset: DATABASE_HEADER_SIZE,
+                ..
+            }
+        ));
+        assert_eq!(error.to_string(), "database disk image is malformed");
+
+        let database = open(empty_database()).unwrap();
+        assert!(matches!(
+            database.table_cursor(2, false).next(),
+            Some(Err(Error::Corrupt { page: 2, .. }))
+        ));
+        assert_eq!(
+            database.table_information("t").err().unwrap().to_string(),
+            "no such table: t"
+        );
+        assert!(matches!(
+            Database::init_from_file("/nonexistent/database.db"),
+            Err(Error::CantOpen(_))
+        ));
     }
 }
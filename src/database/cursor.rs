@@ -0,0 +1,168 @@
+use super::page::btree::data::serial_types::Value;
+use super::page::btree::data::{IndexInteriorCell, IndexLeafCell, TableLeafCell};
+use super::page::btree::page::BTreePage;
+use super::{wrong_page_type, Database, Result};
+
+/// Walks a table b-tree depth-first, yielding `(rowid, record values)` pairs in rowid order.
+///
+/// Only one leaf page is held in memory at a time, interior pages being reduced to the list of
+/// child pages still to visit.
+pub struct TableCursor<'a> {
+    database: &'a Database,
+    reverse: bool,
+    pending_pages: Vec<u32>,
+    /// The leaf page the cells left to read come from
+    current_page: u32,
+    current_cells: std::vec::IntoIter<TableLeafCell>,
+}
+
+impl<'a> TableCursor<'a> {
+    pub fn new(database: &'a Database, root_page_number: u32, reverse: bool) -> Self {
+        TableCursor {
+            database,
+            reverse,
+            pending_pages: vec![root_page_number],
+            current_page: root_page_number,
+            current_cells: Vec::new().into_iter(),
+        }
+    }
+
+    fn load_next_page(&mut self, page_number: u32) -> Result<()> {
+        match self.database.read_btree_page(page_number)? {
+            BTreePage::TableLeaf(_, mut cells) => {
+                if self.reverse {
+                    cells.reverse();
+                }
+                self.current_page = page_number;
+                self.current_cells = cells.into_iter();
+            }
+            BTreePage::TableInterior(header, cells) => {
+                // children in key order are each cell's left child followed by the right-most
+                // pointer; they are pushed so that the next one to visit ends up on top
+                let mut children: Vec<u32> = cells.iter().map(|c| c.left_child_pointer).collect();
+                children.extend(header.right_most_pointer);
+                if !self.reverse {
+                    children.reverse();
+                }
+                self.pending_pages.extend(children);
+            }
+            page => return Err(wrong_page_type(page_number, "table", &page)),
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for TableCursor<'a> {
+    type Item = Result<(u64, Vec<Value>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(cell) = self.current_cells.next() {
+                let record = self.database.read_record(
+                    self.current_page,
+                    cell.payload,
+                    cell.payload_size,
+                    cell.first_overflow_page_number,
+                );
+                return Some(record.map(|values| (cell.key, values)));
+            }
+            let page_number = self.pending_pages.pop()?;
+            if let Err(e) = self.load_next_page(page_number) {
+                // stop iterating after the first error
+                self.pending_pages.clear();
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+enum PendingIndexItem {
+    Page(u32),
+    /// A cell of an interior page, with the number of the page
+    Entry(u32, IndexInteriorCell),
+}
+
+/// Walks an index b-tree, yielding the records of its entries in key order. Each record holds
+/// the indexed column values followed by the rowid of the corresponding table row.
+///
+/// Unlike table b-trees, the cells of interior index pages are entries too, sitting in between
+/// the entries of their left child and those of the next child.
+pub struct IndexCursor<'a> {
+    database: &'a Database,
+    reverse: bool,
+    pending: Vec<PendingIndexItem>,
+    /// The leaf page the cells left to read come from
+    current_page: u32,
+    current_cells: std::vec::IntoIter<IndexLeafCell>,
+}
+
+impl<'a> IndexCursor<'a> {
+    pub fn new(database: &'a Database, root_page_number: u32, reverse: bool) -> Self {
+        IndexCursor {
+            database,
+            reverse,
+            pending: vec![PendingIndexItem::Page(root_page_number)],
+            current_page: root_page_number,
+            current_cells: Vec::new().into_iter(),
+        }
+    }
+
+    fn load_next_page(&mut self, page_number: u32) -> Result<()> {
+        match self.database.read_btree_page(page_number)? {
+            BTreePage::IndexLeaf(_, mut cells) => {
+                if self.reverse {
+                    cells.reverse();
+                }
+                self.current_page = page_number;
+                self.current_cells = cells.into_iter();
+            }
+            BTreePage::IndexInterior(header, cells) => {
+                let mut items = Vec::with_capacity(cells.len() * 2 + 1);
+                for cell in cells {
+                    items.push(PendingIndexItem::Page(cell.left_child_pointer));
+                    items.push(PendingIndexItem::Entry(page_number, cell));
+                }
+                items.extend(header.right_most_pointer.map(PendingIndexItem::Page));
+                if !self.reverse {
+                    items.reverse();
+                }
+                self.pending.extend(items);
+            }
+            page => return Err(wrong_page_type(page_number, "index", &page)),
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for IndexCursor<'a> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(cell) = self.current_cells.next() {
+                return Some(self.database.read_record(
+                    self.current_page,
+                    cell.payload,
+                    cell.payload_size,
+                    cell.first_overflow_page_number,
+                ));
+            }
+            match self.pending.pop()? {
+                PendingIndexItem::Entry(page_number, cell) => {
+                    return Some(self.database.read_record(
+                        page_number,
+                        cell.payload,
+                        cell.payload_size,
+                        cell.first_overflow_page_number,
+                    ))
+                }
+                PendingIndexItem::Page(page_number) => {
+                    if let Err(e) = self.load_next_page(page_number) {
+                        self.pending.clear();
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::error::{Error, Result};
+
+/// The encoding text is stored with in a database, chosen when it is created and recorded in
+/// its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    Utf16le,
+    Utf16be,
+}
+
+impl TextEncoding {
+    /// The encoding stored in the database header: 1 means UTF-8, 2 UTF-16le and 3 UTF-16be.
+    /// A database which hasn't been written to yet has 0, standing for UTF-8.
+    pub fn from_header(text_encoding: u32) -> Result<TextEncoding> {
+        match text_encoding {
+            0 | 1 => Ok(TextEncoding::Utf8),
+            2 => Ok(TextEncoding::Utf16le),
+            3 => Ok(TextEncoding::Utf16be),
+            _ => Err(Error::NotADatabase),
+        }
+    }
+
+    /// Decodes stored text. Invalid sequences are replaced with U+FFFD, like SQLite does when
+    /// converting between encodings, unless `lossless` is set, in which case they are an error.
+    pub fn decode(self, bytes: &[u8], lossless: bool) -> Result<String> {
+        let units = |to_unit: fn([u8; 2]) -> u16| {
+            bytes
+                .chunks_exact(2)
+                .map(move |pair| to_unit([pair[0], pair[1]]))
+        };
+        let utf16 = match self {
+            TextEncoding::Utf8 if lossless => match String::from_utf8(bytes.to_vec()) {
+                Ok(text) => return Ok(text),
+                Err(_) => return Err(Error::corrupt(0, "invalid UTF-8 text")),
+            },
+            TextEncoding::Utf8 => return Ok(String::from_utf8_lossy(bytes).into_owned()),
+            TextEncoding::Utf16le => units(u16::from_le_bytes),
+            TextEncoding::Utf16be => units(u16::from_be_bytes),
+        };
+        if lossless {
+            if !bytes.len().is_multiple_of(2) {
+                return Err(Error::corrupt(
+                    0,
+                    format!("invalid {} text: odd number of bytes", self),
+                ));
+            }
+            match char::decode_utf16(utf16).collect() {
+                Ok(text) => Ok(text),
+                Err(_) => Err(Error::corrupt(
+                    0,
+                    format!("invalid {} text: unpaired surrogate", self),
+                )),
+            }
+        } else {
+            // a trailing odd byte is ignored, as it is by SQLite
+            Ok(char::decode_utf16(utf16)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect())
+        }
+    }
+
+    /// Encodes text the way it is stored.
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            TextEncoding::Utf8 => text.as_bytes().to_vec(),
+            TextEncoding::Utf16le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            TextEncoding::Utf16be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        }
+    }
+
+    /// Compares text by its encoded bytes, which is how SQLite's BINARY collation orders the
+    /// text of a database. UTF-16 orders characters differently from UTF-8 beyond ASCII.
+    pub fn compare(self, left: &str, right: &str) -> Ordering {
+        match self {
+            TextEncoding::Utf8 => left.as_bytes().cmp(right.as_bytes()),
+            _ => self.encode(left).cmp(&self.encode(right)),
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    /// The name of the encoding, as reported by `PRAGMA encoding`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf16le => "UTF-16le",
+            TextEncoding::Utf16be => "UTF-16be",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_and_encodes_text() {
+        for encoding in [
+            TextEncoding::Utf8,
+            TextEncoding::Utf16le,
+            TextEncoding::Utf16be,
+        ] {
+            let bytes = encoding.encode("pâté 🍎");
+            assert_eq!(encoding.decode(&bytes, true).unwrap(), "pâté 🍎");
+        }
+        assert_eq!(TextEncoding::Utf16le.encode("a"), vec![0x61, 0]);
+        assert_eq!(TextEncoding::Utf16be.encode("a"), vec![0, 0x61]);
+
+        let detail = |result: Result<String>| match result {
+            Err(Error::Corrupt { detail, .. }) => detail,
+            _ => String::new(),
+        };
+        let invalid_utf8 = [0x61, 0xff];
+        assert_eq!(
+            TextEncoding::Utf8.decode(&invalid_utf8, false).unwrap(),
+            "a\u{fffd}"
+        );
+        assert_eq!(
+            detail(TextEncoding::Utf8.decode(&invalid_utf8, true)),
+            "invalid UTF-8 text"
+        );
+        // an unpaired high surrogate
+        let invalid_utf16 = [0x61, 0, 0x00, 0xd8];
+        assert_eq!(
+            TextEncoding::Utf16le.decode(&invalid_utf16, false).unwrap(),
+            "a\u{fffd}"
+        );
+        assert_eq!(
+            detail(TextEncoding::Utf16le.decode(&invalid_utf16, true)),
+            "invalid UTF-16le text: unpaired surrogate"
+        );
+        assert_eq!(
+            TextEncoding::Utf16be.decode(&[0, 0x61, 0], false).unwrap(),
+            "a"
+        );
+        assert!(TextEncoding::Utf16be.decode(&[0, 0x61, 0], true).is_err());
+    }
+
+    #[test]
+    fn compares_encoded_text() {
+        // U+0100 is stored as 00 01 in UTF-16le, before U+00FF stored as FF 00
+        assert_eq!(TextEncoding::Utf8.compare("Ā", "ÿ"), Ordering::Greater);
+        assert_eq!(TextEncoding::Utf16be.compare("Ā", "ÿ"), Ordering::Greater);
+        assert_eq!(TextEncoding::Utf16le.compare("Ā", "ÿ"), Ordering::Less);
+        // surrogate pairs come before U+E000 and above in UTF-16
+        assert_eq!(
+            TextEncoding::Utf8.compare("🍎", "\u{ff21}"),
+            Ordering::Greater
+        );
+        assert_eq!(
+            TextEncoding::Utf16be.compare("🍎", "\u{ff21}"),
+            Ordering::Less
+        );
+        assert_eq!(TextEncoding::Utf16le.compare("a", "ab"), Ordering::Less);
+    }
+}
@@ -0,0 +1,118 @@
+use std::io;
+
+use thiserror::Error;
+
+/// The ways reading or writing a database can fail. They are displayed the way SQLite reports
+/// them, the details being kept in their fields.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The file doesn't start with the header of a SQLite database.
+    #[error("file is not a database")]
+    NotADatabase,
+    /// Something read from the database isn't what the file format says it should be: `detail`
+    /// tells what, found at `offset` in page `page`. Pages are numbered from 1, 0 standing for
+    /// a page not known yet.
+    #[error("database disk image is malformed")]
+    Corrupt {
+        page: u32,
+        offset: usize,
+        detail: String,
+    },
+    /// A schema object can't be read, or doesn't exist.
+    #[error("{0}")]
+    Schema(String),
+    /// SQL which doesn't parse, `position` being the offset of the token it fails at.
+    #[error("{message}")]
+    Parse { position: usize, message: String },
+    /// A change would break a constraint.
+    #[error("{0}")]
+    Constraint(String),
+    /// A value can't be stored in a column, such as text in the rowid.
+    #[error("datatype mismatch")]
+    Mismatch,
+    /// A function defined by the application failed, with the message it gave.
+    #[error("{0}")]
+    Function(String),
+    /// A function or collation is defined with a name or number of arguments which isn't
+    /// valid, such as an empty name.
+    #[error("bad parameter or other API misuse")]
+    Misuse,
+    /// A change needs what isn't supported, such as running the triggers of a table.
+    #[error("{0}")]
+    Unsupported(String),
+    /// Another connection holds a lock on the database file keeping it from being changed.
+    /// Only changing databases takes locks, reading them doesn't.
+    #[error("database is locked")]
+    Busy,
+    /// No rowid is left for a new row.
+    #[error("database or disk is full")]
+    Full,
+    /// The file can't be opened for writing.
+    #[error("attempt to write a readonly database")]
+    ReadOnly(#[source] io::Error),
+    /// The statement was interrupted.
+    #[error("interrupted")]
+    Interrupted,
+    #[error("unable to open database file")]
+    CantOpen(#[source] io::Error),
+    #[error("disk I/O error")]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// A corruption found at an offset in a page whose number isn't known yet
+    pub fn corrupt(offset: usize, detail: impl Into<String>) -> Error {
+        Error::Corrupt {
+            page: 0,
+            offset,
+            detail: detail.into(),
+        }
+    }
+
+    /// The result code SQLite fails with for the error, as the sqlite3 shell shows it
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::Schema(_) | Error::Parse { .. } | Error::Unsupported(_) | Error::Function(_) => {
+                1
+            }
+            Error::Busy => 5,
+            Error::ReadOnly(_) => 8,
+            Error::Interrupted => 9,
+            Error::Io(_) => 10,
+            Error::Corrupt { .. } => 11,
+            Error::Full => 13,
+            Error::CantOpen(_) => 14,
+            Error::Constraint(_) => 19,
+            Error::Misuse => 21,
+            Error::Mismatch => 20,
+            Error::NotADatabase => 26,
+        }
+    }
+
+    /// Sets the page a corruption was found in, if not known yet.
+    pub fn on_page(self, page_number: u32) -> Error {
+        match self {
+            Error::Corrupt {
+                page: 0,
+                offset,
+                detail,
+            } => Error::Corrupt {
+                page: page_number,
+                offset,
+                detail,
+            },
+            error => error,
+        }
+    }
+}
+
+impl From<crate::sql::ParseError> for Error {
+    fn from(error: crate::sql::ParseError) -> Error {
+        Error::Parse {
+            position: error.position,
+            message: error.to_string(),
+        }
+    }
+}
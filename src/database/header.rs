@@ -1,9 +1,10 @@
-use anyhow::anyhow;
-use anyhow::Result;
 use nom::AsChar;
 use std::fs::File;
 use std::io::Read;
 
+use super::encoding::TextEncoding;
+use super::error::{Error, Result};
+
 const MAGIC_STRING: &str = "SQLite format 3\0";
 pub const DATABASE_HEADER_SIZE: usize = 100;
 
@@ -43,8 +44,30 @@ pub struct DatabaseHeader {
     pub sqlite_version_number: u32,
 }
 
+impl DatabaseHeader {
+    /// The page size in bytes, the stored value 1 standing for 65536.
+    pub fn page_size_in_bytes(&self) -> usize {
+        if self.page_size == 1 {
+            65536
+        } else {
+            self.page_size as usize
+        }
+    }
+
+    /// The usable size of a page, excluding the space reserved at the end of each page.
+    pub fn usable_page_size(&self) -> usize {
+        self.page_size_in_bytes() - self.page_reserved_space as usize
+    }
+
+    /// The encoding of the text stored in the database.
+    pub fn encoding(&self) -> TextEncoding {
+        // the header was validated when parsed
+        TextEncoding::from_header(self.text_encoding).unwrap_or_default()
+    }
+}
+
 impl TryFrom<[u8; DATABASE_HEADER_SIZE]> for DatabaseHeader {
-    type Error = anyhow::Error;
+    type Error = Error;
 
     fn try_from(value: [u8; DATABASE_HEADER_SIZE]) -> Result<Self, Self::Error> {
         let magic_bytes: String = value[..16].iter().map(|i| i.as_char()).collect();
@@ -68,7 +91,7 @@ impl TryFrom<[u8; DATABASE_HEADER_SIZE]> for DatabaseHeader {
         let user_version = u32::from_be_bytes(value[60..64].try_into().unwrap());
         let incremental_vacuum_mode = u32::from_be_bytes(value[64..68].try_into().unwrap());
         let application_id = u32::from_be_bytes(value[68..72].try_into().unwrap());
-        let reserved_for_expansion: [u8; 20] = value[72..92].try_into()?;
+        let reserved_for_expansion: [u8; 20] = value[72..92].try_into().unwrap();
         let version_valid_for = u32::from_be_bytes(value[92..96].try_into().unwrap());
         let sqlite_version_number = u32::from_be_bytes(value[96..].try_into().unwrap());
 
@@ -99,73 +122,20 @@ impl TryFrom<[u8; DATABASE_HEADER_SIZE]> for DatabaseHeader {
         };
 
         if !validate_header(&header) {
-            return Err(anyhow!("header is invalid"));
+            Err(Error::NotADatabase)
         } else {
-            return Ok(header);
+            Ok(header)
         }
     }
 }
 
 impl TryFrom<Vec<u8>> for DatabaseHeader {
-    type Error = anyhow::Error;
+    type Error = Error;
 
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        let magic_bytes: String = value[..16].iter().map(|i| i.as_char()).collect();
-        let page_size = u16::from_be_bytes(value[16..18].try_into().unwrap());
-        let file_format_write_version = u8::from_be_bytes(value[18..19].try_into().unwrap());
-        let file_format_read_version = u8::from_be_bytes(value[19..20].try_into().unwrap());
-        let page_reserved_space = u8::from_be_bytes(value[20..21].try_into().unwrap());
-        let max_embedded_payload_fraction = u8::from_be_bytes(value[21..22].try_into().unwrap());
-        let min_embedded_payload_fraction = u8::from_be_bytes(value[22..23].try_into().unwrap());
-        let leaf_payload_fraction = u8::from_be_bytes(value[23..24].try_into().unwrap());
-        let file_change_counter = u32::from_be_bytes(value[24..28].try_into().unwrap());
-        let db_size_in_pages = u32::from_be_bytes(value[28..32].try_into().unwrap());
-        let first_freelist_trunk_page_number =
-            u32::from_be_bytes(value[32..36].try_into().unwrap());
-        let number_of_freelist_pages = u32::from_be_bytes(value[36..40].try_into().unwrap());
-        let schema_cookie = u32::from_be_bytes(value[40..44].try_into().unwrap());
-        let schema_format_number = u32::from_be_bytes(value[44..48].try_into().unwrap());
-        let default_page_cache_size = u32::from_be_bytes(value[48..52].try_into().unwrap());
-        let largest_root_btree_page_number = u32::from_be_bytes(value[52..56].try_into().unwrap());
-        let text_encoding = u32::from_be_bytes(value[56..60].try_into().unwrap());
-        let user_version = u32::from_be_bytes(value[60..64].try_into().unwrap());
-        let incremental_vacuum_mode = u32::from_be_bytes(value[64..68].try_into().unwrap());
-        let application_id = u32::from_be_bytes(value[68..72].try_into().unwrap());
-        let reserved_for_expansion: [u8; 20] = value[72..92].try_into()?;
-        let version_valid_for = u32::from_be_bytes(value[92..96].try_into().unwrap());
-        let sqlite_version_number = u32::from_be_bytes(value[96..].try_into().unwrap());
-
-        let header = DatabaseHeader {
-            magic_bytes,
-            page_size,
-            file_format_write_version,
-            file_format_read_version,
-            page_reserved_space,
-            max_embedded_payload_fraction,
-            min_embedded_payload_fraction,
-            leaf_payload_fraction,
-            file_change_counter,
-            db_size_in_pages,
-            first_freelist_trunk_page_number,
-            number_of_freelist_pages,
-            schema_cookie,
-            schema_format_number,
-            default_page_cache_size,
-            largest_root_btree_page_number,
-            text_encoding,
-            user_version,
-            incremental_vacuum_mode,
-            application_id,
-            reserved_for_expansion,
-            version_valid_for,
-            sqlite_version_number,
-        };
-
-        if !validate_header(&header) {
-            return Err(anyhow!("header is invalid"));
-        } else {
-            return Ok(header);
-        }
+        let header: [u8; DATABASE_HEADER_SIZE] =
+            value.try_into().map_err(|_| Error::NotADatabase)?;
+        DatabaseHeader::try_from(header)
     }
 }
 
@@ -174,12 +144,12 @@ fn validate_header(header: &DatabaseHeader) -> bool {
         || header.max_embedded_payload_fraction != 64
         || header.min_embedded_payload_fraction != 32
         || header.leaf_payload_fraction != 32
-        || header.text_encoding > 3
+        || TextEncoding::from_header(header.text_encoding).is_err()
         || header.reserved_for_expansion != [0; 20]
     {
         return false;
     }
-    return true;
+    true
 }
 
 pub fn parse_database_header(file: &mut File) -> Result<DatabaseHeader> {
@@ -1,17 +1,167 @@
-use anyhow::Result;
 use std::{
-    fs::File,
-    io::{Read, Seek, SeekFrom, Write},
+    fs::{File, OpenOptions},
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
 };
 
+use super::error::{Error, Result};
+
+/// The byte SQLite takes its locks from, the page holding it never being used
+pub const PENDING_BYTE: u64 = 0x4000_0000;
+/// The byte locked by the connection about to change the database
+const RESERVED_BYTE: u64 = PENDING_BYTE + 1;
+/// The bytes read-locked by the connections reading the database, and write-locked by the one
+/// writing to it
+const SHARED_FIRST: u64 = PENDING_BYTE + 2;
+const SHARED_SIZE: u64 = 510;
+
+/// The locks SQLite connections take on database files, each allowing more than the last:
+/// reading the database, changing the copies of its pages, and writing them to the file
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Lock {
+    None,
+    Shared,
+    Reserved,
+    Exclusive,
+}
+
+/// How a range of bytes of a file is locked
+#[derive(Clone, Copy)]
+enum RangeLock {
+    Read,
+    Write,
+    Unlock,
+}
+
+/// Where the bytes of a database are read from, and written to
+trait Source: Read + Write + Seek {
+    /// Makes sure what was written is stored durably.
+    fn sync(&mut self) -> io::Result<()>;
+
+    fn set_len(&mut self, length: u64) -> io::Result<()>;
+
+    /// Locks a range of bytes, returning whether another process holding a lock on it kept
+    /// it from being taken.
+    fn lock_range(&mut self, lock: RangeLock, start: u64, length: u64) -> Result<bool>;
+}
+
+impl Source for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_all()
+    }
+
+    fn set_len(&mut self, length: u64) -> io::Result<()> {
+        File::set_len(self, length)
+    }
+
+    fn lock_range(&mut self, lock: RangeLock, start: u64, length: u64) -> Result<bool> {
+        lock_file_range(self, lock, start, length)
+    }
+}
+
+impl Source for Cursor<Vec<u8>> {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_len(&mut self, length: u64) -> io::Result<()> {
+        self.get_mut().resize(length as usize, 0);
+        Ok(())
+    }
+
+    /// Databases held in memory are only ever used by the connection holding them.
+    fn lock_range(&mut self, _lock: RangeLock, _start: u64, _length: u64) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+/// Takes a POSIX advisory lock, as SQLite does, without waiting for it.
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+fn lock_file_range(file: &File, lock: RangeLock, start: u64, length: u64) -> Result<bool> {
+    use std::os::raw::{c_int, c_short};
+    use std::os::unix::io::AsRawFd;
+
+    const F_SETLK: c_int = 6;
+    const EAGAIN: i32 = 11;
+    const EACCES: i32 = 13;
+
+    #[repr(C)]
+    struct Flock {
+        l_type: c_short,
+        l_whence: c_short,
+        l_start: i64,
+        l_len: i64,
+        l_pid: c_int,
+    }
+
+    extern "C" {
+        fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+    }
+
+    let description = Flock {
+        l_type: match lock {
+            RangeLock::Read => 0,
+            RangeLock::Write => 1,
+            RangeLock::Unlock => 2,
+        },
+        // the range starts from the start of the file
+        l_whence: 0,
+        l_start: start as i64,
+        l_len: length as i64,
+        l_pid: 0,
+    };
+    // SAFETY: F_SETLK only reads the lock description, which outlives the call
+    if unsafe { fcntl(file.as_raw_fd(), F_SETLK, &description as *const Flock) } == 0 {
+        return Ok(true);
+    }
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(EAGAIN | EACCES) => Ok(false),
+        _ => Err(Error::Io(error)),
+    }
+}
+
+/// Elsewhere the locks aren't taken, so that database files are only ever read: other
+/// connections could otherwise read pages while they are being written.
+#[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+fn lock_file_range(_file: &File, _lock: RangeLock, _start: u64, _length: u64) -> Result<bool> {
+    Err(Error::Unsupported(String::from(
+        "database files can only be written to on 64-bit Linux",
+    )))
+}
+
 pub struct SQLiteFile {
-    file: File,
+    file: Box<dyn Source>,
+    /// The path of the file, none for a database held in memory
+    path: Option<String>,
+    /// Whether the file was opened for writing
+    writable: bool,
+    /// The lock held on the file, none while only reading it
+    lock: Lock,
 }
 
 impl SQLiteFile {
     pub fn new(path: &str) -> Result<SQLiteFile> {
-        let file = File::open(path)?;
-        Ok(SQLiteFile { file })
+        let file = File::open(path).map_err(Error::CantOpen)?;
+        Ok(SQLiteFile {
+            file: Box::new(file),
+            path: Some(path.to_string()),
+            writable: false,
+            lock: Lock::None,
+        })
+    }
+
+    /// A database held in memory rather than in a file
+    pub fn from_bytes(bytes: Vec<u8>) -> SQLiteFile {
+        SQLiteFile {
+            file: Box::new(Cursor::new(bytes)),
+            path: None,
+            writable: true,
+            lock: Lock::None,
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
     }
 
     pub fn read_exact_at(&mut self, n_bytes: usize, offset: SeekFrom) -> Result<Vec<u8>> {
@@ -21,8 +171,86 @@ impl SQLiteFile {
         Ok(buf)
     }
 
-    pub fn write_at(&mut self, buf: Vec<u8>, offset: SeekFrom) -> Result<usize> {
+    /// The size of the file in bytes
+    pub fn len(&mut self) -> Result<u64> {
+        Ok(self.file.seek(SeekFrom::End(0))?)
+    }
+
+    /// Opens the file again for writing, the first time it is written to: databases are
+    /// opened for reading only.
+    pub fn make_writable(&mut self) -> Result<()> {
+        if let (Some(path), false) = (&self.path, self.writable) {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(path)
+                .map_err(Error::ReadOnly)?;
+            self.file = Box::new(file);
+            self.writable = true;
+        }
+        Ok(())
+    }
+
+    pub fn write_all_at(&mut self, bytes: &[u8], offset: SeekFrom) -> Result<()> {
         self.file.seek(offset)?;
-        Ok(self.file.write(&buf)?)
+        self.file.write_all(bytes)?;
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        Ok(self.file.sync()?)
+    }
+
+    pub fn set_len(&mut self, length: u64) -> Result<()> {
+        Ok(self.file.set_len(length)?)
+    }
+
+    /// Takes the locks SQLite takes up to the one given, in the same order, failing with
+    /// `Error::Busy` when another connection holds one keeping it from being taken. The file
+    /// must have been made writable.
+    pub fn lock(&mut self, lock: Lock) -> Result<()> {
+        while self.lock < lock {
+            let (next, taken) = match self.lock {
+                Lock::None => {
+                    // PENDING is read-locked while taking SHARED, so that a connection waiting
+                    // for EXCLUSIVE keeps new ones from reading
+                    let taken = self.file.lock_range(RangeLock::Read, PENDING_BYTE, 1)?
+                        && self
+                            .file
+                            .lock_range(RangeLock::Read, SHARED_FIRST, SHARED_SIZE)?;
+                    self.file.lock_range(RangeLock::Unlock, PENDING_BYTE, 1)?;
+                    (Lock::Shared, taken)
+                }
+                Lock::Shared => {
+                    let taken = self.file.lock_range(RangeLock::Write, RESERVED_BYTE, 1)?;
+                    (Lock::Reserved, taken)
+                }
+                // PENDING stays locked should the connections reading keep EXCLUSIVE from
+                // being taken, until unlocking
+                _ => {
+                    let taken = self.file.lock_range(RangeLock::Write, PENDING_BYTE, 1)?
+                        && self
+                            .file
+                            .lock_range(RangeLock::Write, SHARED_FIRST, SHARED_SIZE)?;
+                    (Lock::Exclusive, taken)
+                }
+            };
+            if !taken {
+                return Err(Error::Busy);
+            }
+            self.lock = next;
+        }
+        Ok(())
+    }
+
+    /// Releases the locks held on the file.
+    pub fn unlock(&mut self) -> Result<()> {
+        if self.writable {
+            let length = SHARED_FIRST + SHARED_SIZE - PENDING_BYTE;
+            self.file
+                .lock_range(RangeLock::Unlock, PENDING_BYTE, length)?;
+        }
+        self.lock = Lock::None;
+        Ok(())
     }
 }
@@ -1,27 +1,21 @@
-use anyhow::{anyhow, Result};
 use nom::{number::complete::be_u32, IResult};
 
+use crate::database::error::{Error, Result};
 use crate::parsing::utils::take_varint;
 
-pub enum CellType {
-    TableLeaf(TableLeafCell),
-    TableInterior(TableInteriorCell),
-    IndexLeaf(IndexLeafCell),
-    IndexInterior(IndexInteriorCell),
-}
 pub mod record;
 pub mod serial_types;
 
 /// Table B-Tree Leaf Cell (header 0x0d)
 pub struct TableLeafCell {
     /// A varint which is the total number of bytes of payload, including any overflow
-    payload_size: u64,
+    pub payload_size: u64,
     /// A varint which is the integer key, a.k.a. "rowid"
     pub key: u64,
     /// The initial portion of the payload that does not spill to overflow pages.
     pub payload: Payload,
     /// A 4-byte big-endian integer page number for the first page of the overflow page list - omitted if all payload fits on the b-tree page.
-    first_overflow_page_number: Option<u32>,
+    pub first_overflow_page_number: Option<u32>,
 }
 
 /// Table B-Tree Interior Cell (header 0x05):
@@ -35,116 +29,171 @@ pub struct TableInteriorCell {
 /// Index B-Tree Leaf Cell (header 0x0a):
 pub struct IndexLeafCell {
     /// A varint which is the total number of bytes of key payload, including any overflow
-    payload_size: u64,
+    pub payload_size: u64,
     /// The initial portion of the payload that does not spill to overflow pages.
-    payload: Payload,
+    pub payload: Payload,
     /// A 4-byte big-endian integer page number for the first page of the overflow page list - omitted if all payload fits on the b-tree page.
-    first_overflow_page_number: Option<u32>,
+    pub first_overflow_page_number: Option<u32>,
 }
 
 /// Index B-Tree Interior Cell (header 0x02):
 pub struct IndexInteriorCell {
     /// A 4-byte big-endian page number which is the left child pointer.
-    left_child_pointer: u32,
+    pub left_child_pointer: u32,
     /// A varint which is the total number of bytes of key payload, including any overflow
-    payload_size: u64,
+    pub payload_size: u64,
     /// The initial portion of the payload that does not spill to overflow pages.
-    payload: Payload,
+    pub payload: Payload,
     /// A 4-byte big-endian integer page number for the first page of the overflow page list - omitted if all payload fits on the b-tree page.
-    first_overflow_page_number: Option<u32>,
+    pub first_overflow_page_number: Option<u32>,
 }
 
 // varint
-fn parse_payload_size(input: &[u8]) -> IResult<&[u8], u64> {
-    take_varint(input)
+fn parse_payload_size(input: &[u8], offset: usize) -> Result<(&[u8], u64)> {
+    take_varint::<()>(input)
+        .map_err(|_| Error::corrupt(offset, "unable to parse cell payload size"))
 }
 
 // varint
-fn parse_rowid(input: &[u8]) -> IResult<&[u8], u64> {
-    take_varint(input)
+fn parse_rowid(input: &[u8], offset: usize) -> Result<(&[u8], u64)> {
+    take_varint::<()>(input).map_err(|_| Error::corrupt(offset, "unable to parse cell rowid"))
 }
 
 // u32
-fn parse_left_child_pointer(input: &[u8]) -> IResult<&[u8], u32> {
-    be_u32(input)
+fn parse_left_child_pointer(input: &[u8], offset: usize) -> Result<(&[u8], u32)> {
+    let result: IResult<&[u8], u32, ()> = be_u32(input);
+    result.map_err(|_| Error::corrupt(offset, "unable to parse cell left child pointer"))
 }
 
 // u32
-fn parse_first_overflow_page_number(input: &[u8]) -> IResult<&[u8], u32> {
-    be_u32(input)
+fn parse_first_overflow_page_number(input: &[u8], offset: usize) -> Result<(&[u8], u32)> {
+    let result: IResult<&[u8], u32, ()> = be_u32(input);
+    result.map_err(|_| Error::corrupt(offset, "unable to parse cell first overflow page number"))
 }
 
-pub fn parse_table_leaf_cell(input: &[u8]) -> Result<CellType> {
-    let (input, payload_size) = parse_payload_size(input)?;
-    let (input, key) = parse_rowid(input)?;
-    // overflow is not handled for the moment
-    if let Some(payload_content) = input.get(..payload_size as usize) {
-        Ok(CellType::TableLeaf(TableLeafCell {
-            payload_size,
-            key,
-            payload: Payload {
-                content: payload_content.to_vec(),
-            },
-            first_overflow_page_number: None,
-        }))
+/// Computes how many bytes of a payload of `payload_size` bytes are stored on the b-tree page
+/// itself, the rest spilling onto overflow pages.
+///
+/// `usable_size` is the page size minus the reserved space at the end of each page.
+pub(crate) fn local_payload_size(
+    payload_size: u64,
+    usable_size: usize,
+    is_table_leaf: bool,
+) -> usize {
+    let payload_size = payload_size as usize;
+    let max_local = if is_table_leaf {
+        usable_size - 35
     } else {
-        Err(anyhow!(
-            "Couldn't read enough bytes from page to extract the entire payload.\
-            This might be a case of payload overflow, for which the logic isn't yet implemented."
-        ))
+        ((usable_size - 12) * 64 / 255) - 23
+    };
+    if payload_size <= max_local {
+        return payload_size;
     }
+    let min_local = ((usable_size - 12) * 32 / 255) - 23;
+    let local = min_local + ((payload_size - min_local) % (usable_size - 4));
+    if local <= max_local {
+        local
+    } else {
+        min_local
+    }
+}
+
+/// Parses the local part of a payload along with the overflow page pointer that follows it when
+/// the payload doesn't fit on the page.
+fn parse_payload(
+    input: &[u8],
+    offset: usize,
+    payload_size: u64,
+    usable_size: usize,
+    is_table_leaf: bool,
+) -> Result<(Payload, Option<u32>)> {
+    let local_size = local_payload_size(payload_size, usable_size, is_table_leaf);
+    let payload_content = input.get(..local_size).ok_or_else(|| {
+        Error::corrupt(
+            offset,
+            "couldn't read enough bytes from page to extract the payload",
+        )
+    })?;
+    let first_overflow_page_number = if local_size < payload_size as usize {
+        let (_, page_number) = parse_first_overflow_page_number(&input[local_size..], offset)?;
+        Some(page_number)
+    } else {
+        None
+    };
+    Ok((
+        Payload {
+            content: payload_content.to_vec(),
+            offset,
+        },
+        first_overflow_page_number,
+    ))
+}
+
+pub fn parse_table_leaf_cell(
+    input: &[u8],
+    offset: usize,
+    usable_size: usize,
+) -> Result<TableLeafCell> {
+    let (input, payload_size) = parse_payload_size(input, offset)?;
+    let (input, key) = parse_rowid(input, offset)?;
+    let (payload, first_overflow_page_number) =
+        parse_payload(input, offset, payload_size, usable_size, true)?;
+    Ok(TableLeafCell {
+        payload_size,
+        key,
+        payload,
+        first_overflow_page_number,
+    })
 }
 
-pub fn parse_table_interior_cell(input: &[u8]) -> Result<CellType> {
-    let (input, left_child_pointer) = parse_left_child_pointer(input)?;
-    let (input, key) = parse_rowid(input)?;
-    Ok(CellType::TableInterior(TableInteriorCell {
+pub fn parse_table_interior_cell(
+    input: &[u8],
+    offset: usize,
+    _usable_size: usize,
+) -> Result<TableInteriorCell> {
+    let (input, left_child_pointer) = parse_left_child_pointer(input, offset)?;
+    let (_, key) = parse_rowid(input, offset)?;
+    Ok(TableInteriorCell {
         left_child_pointer,
         key,
-    }))
+    })
 }
 
-pub fn parse_index_leaf_cell(input: &[u8]) -> Result<CellType> {
-    let (input, payload_size) = parse_payload_size(input)?;
-    // overflow is not handled for the moment
-    if let Some(payload_content) = input.get(..payload_size as usize) {
-        Ok(CellType::IndexLeaf(IndexLeafCell {
-            payload_size,
-            payload: Payload {
-                content: payload_content.to_vec(),
-            },
-            first_overflow_page_number: None,
-        }))
-    } else {
-        Err(anyhow!(
-            "Couldn't read enough bytes from page to extract the entire payload.\
-            This might be a case of payload overflow, for which the logic isn't yet implemented."
-        ))
-    }
+pub fn parse_index_leaf_cell(
+    input: &[u8],
+    offset: usize,
+    usable_size: usize,
+) -> Result<IndexLeafCell> {
+    let (input, payload_size) = parse_payload_size(input, offset)?;
+    let (payload, first_overflow_page_number) =
+        parse_payload(input, offset, payload_size, usable_size, false)?;
+    Ok(IndexLeafCell {
+        payload_size,
+        payload,
+        first_overflow_page_number,
+    })
 }
 
-pub fn parse_index_interior_cell(input: &[u8]) -> Result<CellType> {
-    let (input, left_child_pointer) = parse_left_child_pointer(input)?;
-    let (input, payload_size) = parse_payload_size(input)?;
-    // overflow is not handled for the moment
-    if let Some(payload_content) = input.get(..payload_size as usize) {
-        Ok(CellType::IndexInterior(IndexInteriorCell {
-            left_child_pointer,
-            payload_size,
-            payload: Payload {
-                content: payload_content.to_vec(),
-            },
-            first_overflow_page_number: None,
-        }))
-    } else {
-        Err(anyhow!(
-            "Couldn't read enough bytes from page to extract the entire payload.\
-            This might be a case of payload overflow, for which the logic isn't yet implemented."
-        ))
-    }
+pub fn parse_index_interior_cell(
+    input: &[u8],
+    offset: usize,
+    usable_size: usize,
+) -> Result<IndexInteriorCell> {
+    let (input, left_child_pointer) = parse_left_child_pointer(input, offset)?;
+    let (input, payload_size) = parse_payload_size(input, offset)?;
+    let (payload, first_overflow_page_number) =
+        parse_payload(input, offset, payload_size, usable_size, false)?;
+    Ok(IndexInteriorCell {
+        left_child_pointer,
+        payload_size,
+        payload,
+        first_overflow_page_number,
+    })
 }
 
 /// a cell's payload section
 pub struct Payload {
     pub content: Vec<u8>,
+    /// where the cell holding the payload starts in its page
+    pub offset: usize,
 }
@@ -1,86 +1,137 @@
-use anyhow::{bail, Result};
-use nom::{error::Error, multi::fold_many1, IResult};
+use crate::{
+    database::{
+        encoding::TextEncoding,
+        error::{Error, Result},
+        page::btree::data::Payload,
+    },
+    parsing::utils::{encode_varint, take_varint},
+};
 
-use crate::{database::page::btree::data::Payload, parsing::utils::take_varint};
-
-use super::serial_types::{parse_value, SerialType, Value};
+use super::serial_types::{parse_value, serialize_value, SerialType, Value};
 
 pub struct Record {
-    serial_types: Vec<SerialType>,
+    pub serial_types: Vec<SerialType>,
     pub values: Vec<Value>,
 }
 
 impl TryFrom<Payload> for Record {
-    type Error = anyhow::Error;
+    type Error = Error;
 
     fn try_from(value: Payload) -> Result<Self> {
-        parse_record(&value.content)
+        Record::parse(&value.content, TextEncoding::Utf8, false)
+    }
+}
+
+impl TryFrom<&[u8]> for Record {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        Record::parse(value, TextEncoding::Utf8, false)
+    }
+}
+
+impl Record {
+    /// Parses a record whose text is stored with the given encoding. Invalid text sequences
+    /// are replaced with U+FFFD, unless `lossless` is set, in which case they are an error.
+    pub fn parse(payload: &[u8], encoding: TextEncoding, lossless: bool) -> Result<Record> {
+        parse_record(payload, encoding, lossless)
     }
 }
 
-fn parse_record(payload: &[u8]) -> Result<Record> {
+fn parse_record(payload: &[u8], encoding: TextEncoding, lossless: bool) -> Result<Record> {
     // parse header size
-    let (rest, header_size) = take_varint::<Error<&[u8]>>(payload)?;
+    let (rest, header_size) = take_varint::<()>(payload)
+        .map_err(|_| Error::corrupt(0, "malformed record header: unable to read header size"))?;
     let varint_size = payload.len() - rest.len();
+    let header_end = header_size as usize;
+    if header_end < varint_size || header_end > payload.len() {
+        return Err(Error::corrupt(
+            0,
+            format!(
+                "malformed record header: invalid header size {}",
+                header_size
+            ),
+        ));
+    }
     // parse serial types
-    let remaining_header_size = header_size as usize - varint_size;
-    let (remaining_header_bytes, coll_maybe_serial_types) = fold_many1(
-        take_varint::<Error<&[u8]>>,
-        Vec::new,
-        |mut acc: Vec<Result<SerialType>>, varint| {
-            acc.push(SerialType::try_from(varint));
-            acc
-        },
-    )(&rest[..remaining_header_size])?;
-    let maybe_serial_types: Result<Vec<SerialType>> = coll_maybe_serial_types.into_iter().collect();
-    // check the entire header was consumed
-    if remaining_header_bytes.len() > 0 {
-        bail!("Malformed record header")
+    let mut header = &rest[..header_end - varint_size];
+    let mut serial_types = Vec::new();
+    while !header.is_empty() {
+        let (remaining_header, varint) = take_varint::<()>(header)
+            .map_err(|_| Error::corrupt(0, "malformed record header: truncated serial type"))?;
+        serial_types.push(SerialType::try_from(varint)?);
+        header = remaining_header;
     }
-    // if serial types parsing succeeded, parse corresponding values
-    match maybe_serial_types {
-        Ok(serial_types) => {
-            let parsers: Vec<_> = serial_types
-                .iter()
-                .map(|st| curried_parse_value(st))
-                .collect();
-            let (rest, values) =
-                dynamic_parser_sequence(&parsers)(&payload[header_size as usize..])?;
-            Ok(Record {
-                serial_types,
-                values,
-            })
-        }
-        Err(E) => Err(E),
+    // parse the value corresponding to each serial type
+    let mut body = &payload[header_end..];
+    let mut values = Vec::with_capacity(serial_types.len());
+    for serial_type in &serial_types {
+        let (remaining_body, value) =
+            parse_value(body, serial_type, encoding, lossless).map_err(|e| match e {
+                // only text fails to decode, other values fail when the body is truncated
+                nom::Err::Failure(_) => Error::corrupt(
+                    0,
+                    format!("malformed record body: invalid {} text", encoding),
+                ),
+                _ => Error::corrupt(
+                    0,
+                    format!("malformed record body: unable to parse {:?}", serial_type),
+                ),
+            })?;
+        values.push(value);
+        body = remaining_body;
     }
+    Ok(Record {
+        serial_types,
+        values,
+    })
 }
 
-fn curried_parse_value<'a>(
-    serial_type: &SerialType,
-) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Value> + '_ {
-    |data| parse_value(data, serial_type)
+/// Encodes values into the record format with UTF-8 text, the inverse of [`Record::try_from`].
+pub fn encode_record(values: &[Value]) -> Vec<u8> {
+    encode_record_with(values, TextEncoding::Utf8)
 }
 
-fn dynamic_parser_sequence<'a, O, P>(
-    parsers: &'a [P],
-) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Vec<O>> + 'a
-where
-    P: Fn(&'a [u8]) -> IResult<&'a [u8], O> + 'a,
-{
-    move |input: &'a [u8]| {
-        let mut results = Vec::new();
-        let mut current_input = input;
+/// Encodes values into the record format with text in the given encoding, the inverse of
+/// [`Record::parse`].
+pub fn encode_record_with(values: &[Value], encoding: TextEncoding) -> Vec<u8> {
+    let mut header = Vec::new();
+    let mut body = Vec::new();
+    for value in values {
+        let (serial_type, content) = serialize_value(value, encoding);
+        header.extend(encode_varint(serial_type));
+        body.extend(content);
+    }
+    // the header size includes the varint holding it, whose own size depends on the total
+    let mut header_size = header.len() + 1;
+    while encode_varint(header_size as u64).len() + header.len() > header_size {
+        header_size += 1;
+    }
+    let mut record = encode_varint(header_size as u64);
+    record.extend(header);
+    record.extend(body);
+    record
+}
 
-        for parser in parsers {
-            match parser(current_input) {
-                Ok((next_input, result)) => {
-                    current_input = next_input;
-                    results.push(result);
-                }
-                Err(e) => return Err(e),
-            }
-        }
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        Ok((current_input, results))
+    #[test]
+    fn decodes_text_with_the_database_encoding() {
+        let values = [Value::Int8(7), Value::String("pâté".to_string())];
+        for encoding in [TextEncoding::Utf16le, TextEncoding::Utf16be] {
+            let record = encode_record_with(&values, encoding);
+            let parsed = Record::parse(&record, encoding, true).unwrap();
+            assert_eq!(parsed.values, values);
+        }
+        // an unpaired surrogate is replaced, unless decoding losslessly
+        let record = [2, 17, 0x00, 0xd8];
+        let parsed = Record::parse(&record, TextEncoding::Utf16le, false).unwrap();
+        assert_eq!(parsed.values, [Value::String("\u{fffd}".to_string())]);
+        assert!(matches!(
+            Record::parse(&record, TextEncoding::Utf16le, true),
+            Err(Error::Corrupt { detail, .. }) if detail == "malformed record body: invalid UTF-16le text"
+        ));
     }
 }
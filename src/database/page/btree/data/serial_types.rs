@@ -1,6 +1,6 @@
 use std::cmp::Ordering;
+use std::fmt;
 
-use anyhow::{anyhow, Result};
 use nom::error::ErrorKind;
 use nom::number::complete::{be_f64, be_i64};
 use nom::{
@@ -9,6 +9,10 @@ use nom::{
 };
 use nom::{Err, IResult};
 
+use crate::database::error::Error;
+
+use crate::database::encoding::TextEncoding;
+
 #[derive(Debug, Clone)]
 pub enum SerialType {
     Null,
@@ -27,7 +31,7 @@ pub enum SerialType {
 }
 
 impl TryFrom<u64> for SerialType {
-    type Error = anyhow::Error;
+    type Error = Error;
 
     fn try_from(value: u64) -> Result<Self, Self::Error> {
         match value {
@@ -48,7 +52,10 @@ impl TryFrom<u64> for SerialType {
             v if v % 2 == 1 => Ok(SerialType::String {
                 length: (v - 13) / 2,
             }),
-            v => Err(anyhow!("Unrecognized serial type value {}", v)),
+            v => Err(Error::corrupt(
+                0,
+                format!("unrecognized serial type value {}", v),
+            )),
         }
     }
 }
@@ -66,46 +73,302 @@ pub enum Value {
     String(String),
 }
 
-impl PartialEq for Value {
-    fn eq(&self, other: &Self) -> bool {
+impl Value {
+    /// Returns the integer held by any of the integer variants.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int8(i) => Some(*i as i64),
+            Value::Int16(i) => Some(*i as i64),
+            Value::Int32(i) => Some(*i as i64),
+            Value::Int64(i) => Some(*i),
+            Value::Bool(b) => Some(*b as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+}
+
+impl Value {
+    /// Compares two values like SQL comparison operators do: the result is unknown when either
+    /// of them is NULL, and values are otherwise ordered as described for `Ord`.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        if self.is_null() || other.is_null() {
+            None
+        } else {
+            Some(self.cmp(other))
+        }
+    }
+
+    /// The storage class of the value, in the order values of different classes sort in
+    fn storage_class(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::String(_) => 2,
+            Value::Blob(_) => 3,
+            _ => 1,
+        }
+    }
+}
+
+/// Compares an integer with a real exactly, even beyond the integers a real represents
+/// exactly, like SQLite does
+fn compare_integer_real(integer: i64, real: f64) -> Ordering {
+    if real.is_nan() {
+        return Ordering::Greater;
+    }
+    // i64::MIN is -2^63, whose opposite is one past the largest integer
+    if real < i64::MIN as f64 {
+        return Ordering::Greater;
+    }
+    if real >= -(i64::MIN as f64) {
+        return Ordering::Less;
+    }
+    integer.cmp(&(real as i64)).then_with(|| {
+        (integer as f64)
+            .partial_cmp(&real)
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+/// Compares reals by value, NaN (which SQLite never keeps, making it NULL) being less than
+/// every other real, like it is less than every integer, so that the order stays total
+fn compare_reals(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b)
+        .unwrap_or_else(|| b.is_nan().cmp(&a.is_nan()))
+}
+
+/// Values are ordered like SQLite sorts them: NULLs first, then numbers by value whatever their
+/// storage (integers of any width, reals, and booleans stored as serial types 8 and 9), then
+/// text byte by byte, then blobs. Unlike SQL's `=`, NULL equals NULL here; use
+/// `Value::compare` for comparisons with SQL semantics.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
-            (Self::Null, _) | (_, Self::Null) => true,
-            // numbers
-            (Value::Int8(a), Value::Int8(b)) => a == b,
-            (Value::Int16(a), Value::Int16(b)) => a == b,
-            (Value::Int32(a), Value::Int32(b)) => a == b,
-            (Value::Int64(a), Value::Int64(b)) => a == b,
-            (Value::Float64(a), Value::Float64(b)) => a == b,
-            // boolean comparisons
-            (Value::Bool(a), Value::Bool(b)) => a == b,
-            // blobs and strings
-            (Value::Blob(a), Value::Blob(b)) => a == b,
-            (Value::String(a), Value::String(b)) => a == b,
-            _ => false,
+            (Value::String(a), Value::String(b)) => a.as_bytes().cmp(b.as_bytes()),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            (Value::Float64(a), Value::Float64(b)) => compare_reals(*a, *b),
+            (Value::Float64(r), other) if other.storage_class() == 1 => {
+                compare_integer_real(other.as_i64().unwrap_or(0), *r).reverse()
+            }
+            (value, Value::Float64(r)) if value.storage_class() == 1 => {
+                compare_integer_real(value.as_i64().unwrap_or(0), *r)
+            }
+            _ => match (self.as_i64(), other.as_i64()) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => self.storage_class().cmp(&other.storage_class()),
+            },
         }
     }
 }
+
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match (self, other) {
-            (Self::Null, _) | (_, Self::Null) => None,
-            // numbers
-            (Value::Int8(a), Value::Int8(b)) => a.partial_cmp(b),
-            (Value::Int16(a), Value::Int16(b)) => a.partial_cmp(b),
-            (Value::Int32(a), Value::Int32(b)) => a.partial_cmp(b),
-            (Value::Int64(a), Value::Int64(b)) => a.partial_cmp(b),
-            (Value::Float64(a), Value::Float64(b)) => a.partial_cmp(b),
-            // boolean comparisons
-            (Value::Bool(a), Value::Bool(b)) => a.partial_cmp(b),
-            // blobs and strings
-            (Value::Blob(a), Value::Blob(b)) => a.partial_cmp(b),
-            (Value::String(a), Value::String(b)) => a.partial_cmp(b),
-            _ => None,
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl fmt::Display for Value {
+    /// Renders the value the way the sqlite3 shell does in its default output mode.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Int8(i) => write!(f, "{}", i),
+            Value::Int16(i) => write!(f, "{}", i),
+            Value::Int32(i) => write!(f, "{}", i),
+            Value::Int64(i) => write!(f, "{}", i),
+            Value::Float64(r) => write!(f, "{}", format_real(*r)),
+            Value::Bool(b) => write!(f, "{}", *b as u8),
+            Value::Blob(b) => write!(f, "{}", String::from_utf8_lossy(b)),
+            Value::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// Formats a floating point number like SQLite's `%!.15g`: 15 significant digits, trailing
+/// zeros removed but always keeping a decimal point.
+pub fn format_real(real: f64) -> String {
+    if real.is_nan() {
+        return String::new();
+    }
+    if real.is_infinite() {
+        return if real > 0.0 { "Inf" } else { "-Inf" }.to_string();
+    }
+    if real == 0.0 {
+        return "0.0".to_string();
+    }
+    // `{:.14e}` gives us exactly 15 significant digits along with the decimal exponent
+    let scientific = format!("{:.14e}", real);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(m) => ("-", m),
+        None => ("", mantissa),
+    };
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    layout_real(sign, &digits, exponent, 15)
+}
+
+/// Formats a floating point number so that it reads back as the same number, the way the
+/// sqlite3 shell writes SQL literals and JSON: integral numbers with a `.0` appended, others
+/// like `%!.20g`. The digits are those SQLite computes with double-double arithmetic, which
+/// past the 17th aren't always the exact ones.
+// the constants are written as in SQLite, to be told apart from one another
+#[allow(clippy::excessive_precision)]
+pub fn format_real_exact(real: f64) -> String {
+    if !real.is_finite() {
+        return format_real(real);
+    }
+    if real.fract() == 0.0 && real.abs() < 9.223372036854775808e18 {
+        return format!("{}.0", real as i64);
+    }
+    let sign = if real < 0.0 { "-" } else { "" };
+    // scale the number into the range of a u64 with as many digits as it holds, like
+    // `sqlite3FpDecode`, keeping track of the error of each multiplication
+    let mut scaled = [real.abs(), 0.0];
+    let mut exponent = 0;
+    if scaled[0] > 9.223372036854774784e18 {
+        while scaled[0] > 9.223372036854774784e118 {
+            exponent += 100;
+            dekker_multiply(&mut scaled, 1.0e-100, -1.99918998026028836196e-117);
+        }
+        while scaled[0] > 9.223372036854774784e28 {
+            exponent += 10;
+            dekker_multiply(&mut scaled, 1.0e-10, -3.6432197315497741579e-27);
+        }
+        while scaled[0] > 9.223372036854774784e18 {
+            exponent += 1;
+            dekker_multiply(&mut scaled, 1.0e-1, -5.5511151231257827021e-18);
+        }
+    } else {
+        while scaled[0] < 9.223372036854774784e-83 {
+            exponent -= 100;
+            dekker_multiply(&mut scaled, 1.0e100, -1.5902891109759918046e83);
+        }
+        while scaled[0] < 9.223372036854774784e7 {
+            exponent -= 10;
+            dekker_multiply(&mut scaled, 1.0e10, 0.0);
+        }
+        while scaled[0] < 9.22337203685477478e17 {
+            exponent -= 1;
+            dekker_multiply(&mut scaled, 1.0e1, 0.0);
         }
     }
+    let integer = if scaled[1] < 0.0 {
+        (scaled[0] as u64).wrapping_sub((-scaled[1]) as u64)
+    } else {
+        (scaled[0] as u64).wrapping_add(scaled[1] as u64)
+    };
+    // a u64 has at most 20 digits, so there is nothing to round
+    let digits = integer.to_string();
+    let exponent = digits.len() as i32 + exponent - 1;
+    layout_real(sign, &digits, exponent, 20)
 }
 
-pub fn parse_value<'a>(data: &'a [u8], serial_type: &SerialType) -> IResult<&'a [u8], Value> {
+/// Multiplies a number held as the sum of two doubles by `y`, whose error is `yy`, like
+/// SQLite's `dekkerMul2`.
+fn dekker_multiply(x: &mut [f64; 2], y: f64, yy: f64) {
+    // the upper half of the mantissa, whose products with another are exact
+    let split = |r: f64| f64::from_bits(r.to_bits() & 0xffff_ffff_fc00_0000);
+    let (hx, hy) = (split(x[0]), split(y));
+    let (tx, ty) = (x[0] - hx, y - hy);
+    let p = hx * hy;
+    let q = hx * ty + tx * hy;
+    let c = p + q;
+    let cc = p - c + q + tx * ty;
+    let cc = x[0] * yy + x[1] * y + cc;
+    x[0] = c + cc;
+    x[1] = c - x[0] + cc;
+}
+
+/// Lays out the significant digits of a number whose first digit is at the given decimal
+/// exponent like `%!.<precision>g`: trailing zeros removed but always keeping a decimal point.
+fn layout_real(sign: &str, digits: &str, exponent: i32, precision: i32) -> String {
+    let digits = digits.trim_end_matches('0');
+    let digits = if digits.is_empty() { "0" } else { digits };
+
+    if !(-4..precision).contains(&exponent) {
+        let (first, rest) = digits.split_at(1);
+        let rest = if rest.is_empty() { "0" } else { rest };
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        return format!(
+            "{}{}.{}e{}{:02}",
+            sign,
+            first,
+            rest,
+            exponent_sign,
+            exponent.abs()
+        );
+    }
+    if exponent < 0 {
+        let zeros = "0".repeat((-exponent - 1) as usize);
+        return format!("{}0.{}{}", sign, zeros, digits);
+    }
+    let integer_digits = exponent as usize + 1;
+    if digits.len() <= integer_digits {
+        let zeros = "0".repeat(integer_digits - digits.len());
+        format!("{}{}{}.0", sign, digits, zeros)
+    } else {
+        let (integer, fraction) = digits.split_at(integer_digits);
+        format!("{}{}.{}", sign, integer, fraction)
+    }
+}
+
+/// Returns the serial type code used to store `value` in a record along with its encoded body,
+/// text being stored with the given encoding.
+pub fn serialize_value(value: &Value, encoding: TextEncoding) -> (u64, Vec<u8>) {
+    let integer = match value {
+        Value::Null => return (0, vec![]),
+        Value::Int8(i) => *i as i64,
+        Value::Int16(i) => *i as i64,
+        Value::Int32(i) => *i as i64,
+        Value::Int64(i) => *i,
+        Value::Bool(b) => *b as i64,
+        Value::Float64(r) => return (7, r.to_be_bytes().to_vec()),
+        Value::Blob(b) => return (b.len() as u64 * 2 + 12, b.clone()),
+        Value::String(s) => {
+            let content = encoding.encode(s);
+            return (content.len() as u64 * 2 + 13, content);
+        }
+    };
+    let bytes = integer.to_be_bytes();
+    match integer {
+        0 => (8, vec![]),
+        1 => (9, vec![]),
+        -128..=127 => (1, bytes[7..].to_vec()),
+        -32768..=32767 => (2, bytes[6..].to_vec()),
+        -8388608..=8388607 => (3, bytes[5..].to_vec()),
+        -2147483648..=2147483647 => (4, bytes[4..].to_vec()),
+        -140737488355328..=140737488355327 => (5, bytes[2..].to_vec()),
+        _ => (6, bytes.to_vec()),
+    }
+}
+
+/// Parses the value of a record body with the given serial type. Text is decoded from the
+/// given encoding, failing on invalid sequences when `lossless` is set.
+pub fn parse_value<'a>(
+    data: &'a [u8],
+    serial_type: &SerialType,
+    encoding: TextEncoding,
+    lossless: bool,
+) -> IResult<&'a [u8], Value> {
     match serial_type {
         SerialType::Null => Ok((data, Value::Null)),
         SerialType::Int8 => {
@@ -118,7 +381,7 @@ pub fn parse_value<'a>(data: &'a [u8], serial_type: &SerialType) -> IResult<&'a
         }
         SerialType::Int24 => {
             let (rest, result) = be_i24(data)?;
-            Ok((rest, Value::Int32(result as i32)))
+            Ok((rest, Value::Int32(result)))
         }
         SerialType::Int32 => {
             let (rest, result) = be_i32(data)?;
@@ -128,7 +391,8 @@ pub fn parse_value<'a>(data: &'a [u8], serial_type: &SerialType) -> IResult<&'a
             let (rest, result) = take(6usize)(data)?;
             let mut buf = [0; 8];
             buf[2..].clone_from_slice(result);
-            Ok((rest, Value::Int64(i64::from_be_bytes(buf))))
+            // shift back and forth to sign-extend the 48-bit value
+            Ok((rest, Value::Int64(i64::from_be_bytes(buf) << 16 >> 16)))
         }
         SerialType::Int64 => {
             let (rest, result) = be_i64(data)?;
@@ -146,10 +410,13 @@ pub fn parse_value<'a>(data: &'a [u8], serial_type: &SerialType) -> IResult<&'a
         }
         SerialType::String { length } => {
             let (rest, result) = take(*length)(data)?;
-            Ok((
-                rest,
-                Value::String(String::from_utf8_lossy(result).to_string()),
-            ))
+            match encoding.decode(result, lossless) {
+                Ok(text) => Ok((rest, Value::String(text))),
+                Err(_) => Err(Err::Failure(nom::error::Error {
+                    input: result,
+                    code: ErrorKind::Verify,
+                })),
+            }
         }
         SerialType::Reserved => Err(Err::Error(nom::error::Error {
             input: data,
@@ -157,3 +424,77 @@ pub fn parse_value<'a>(data: &'a [u8], serial_type: &SerialType) -> IResult<&'a
         })),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn orders_values_like_sqlite() {
+        let text = |s: &str| Value::String(s.to_string());
+        let mut values = [
+            Value::Blob(vec![0]),
+            text("10"),
+            Value::Float64(2.5),
+            Value::Bool(true),
+            Value::Null,
+            text("9"),
+            Value::Int8(-1),
+        ];
+        values.sort();
+        assert_eq!(
+            values
+                .iter()
+                .map(|value| format!("{:?}", value))
+                .collect::<Vec<_>>(),
+            [
+                "Null",
+                "Int8(-1)",
+                "Bool(true)",
+                "Float64(2.5)",
+                "String(\"10\")",
+                "String(\"9\")",
+                "Blob([0])",
+            ]
+        );
+        // numbers compare by value whatever their storage
+        assert_eq!(Value::Int8(1), Value::Int64(1));
+        assert_eq!(Value::Bool(true), Value::Float64(1.0));
+        assert_ne!(Value::Int64(1), text("1"));
+        // integers too large for reals to represent exactly are still told apart from them
+        assert!(Value::Int64(i64::MAX) < Value::Float64(9223372036854775807.0));
+        assert!(Value::Int64(9007199254740993) > Value::Float64(9007199254740992.0));
+        // NaN is only equal to itself
+        let nan = Value::Float64(f64::NAN);
+        assert_ne!(nan, Value::Float64(5.0));
+        assert!(nan < Value::Float64(f64::NEG_INFINITY) && nan < Value::Int64(i64::MIN));
+        assert_eq!(nan, Value::Float64(f64::NAN));
+        // NULL is unknown to SQL comparisons, though it sorts first
+        assert_eq!(Value::Null.compare(&Value::Null), None);
+        assert_eq!(Value::Null, Value::Null);
+        assert_eq!(
+            Value::Int32(2).compare(&Value::Float64(1.5)),
+            Some(Ordering::Greater)
+        );
+    }
+
+    #[test]
+    fn formats_reals_like_sqlite() {
+        assert_eq!(format_real(2.5), "2.5");
+        assert_eq!(format_real(0.1), "0.1");
+        assert_eq!(format_real(1e20), "1.0e+20");
+        assert_eq!(format_real(-1.5e-7), "-1.5e-07");
+        // the digits past the 17th are those sqlite3 prints, not the exact ones
+        assert_eq!(format_real_exact(0.1), "0.1000000000000000055");
+        assert_eq!(
+            format_real_exact(std::f64::consts::PI),
+            "3.141592653589793116"
+        );
+        assert_eq!(format_real_exact(1.5e-7), "1.499999999999999933e-07");
+        assert_eq!(format_real_exact(-1e-5), "-1.000000000000000082e-05");
+        assert_eq!(format_real_exact(1e20), "1.0e+20");
+        assert_eq!(format_real_exact(1e19), "10000000000000000000.0");
+        assert_eq!(format_real_exact(100.0), "100.0");
+        assert_eq!(format_real_exact(-0.0), "0.0");
+    }
+}
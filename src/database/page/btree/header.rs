@@ -1,4 +1,4 @@
-use anyhow::Result;
+use crate::database::error::Result;
 
 use super::page::BTreePageType;
 
@@ -29,18 +29,13 @@ pub fn parse_btree_page_header(page_bytes: &[u8]) -> Result<(&[u8], BTreePageHea
     let number_of_cells = u16::from_be_bytes(page_bytes[3..5].try_into().unwrap());
     let cell_content_area_offset = u16::from_be_bytes(page_bytes[5..7].try_into().unwrap());
     let number_of_fragmented_free_bytes = u8::from_be_bytes([page_bytes[7]]);
-    let right_most_pointer: Option<u32>;
-
-    match page_type {
+    let right_most_pointer = match page_type {
         BTreePageType::IndexInterior | BTreePageType::TableInterior => {
             // read the 4 extra bytes and produce value
-            right_most_pointer = Some(u32::from_be_bytes(page_bytes[8..12].try_into().unwrap()));
-        }
-        _ => {
-            // set value to None
-            right_most_pointer = None;
+            Some(u32::from_be_bytes(page_bytes[8..12].try_into().unwrap()))
         }
-    }
+        _ => None,
+    };
 
     let page_header = BTreePageHeader {
         page_type,
@@ -1,17 +1,12 @@
-use std::ops::Range;
-
-use anyhow::{anyhow, Context, Error, Result};
-use itertools::Itertools;
-
+use crate::database::error::{Error, Result};
 use crate::database::page::btree::data::{
     parse_index_interior_cell, parse_index_leaf_cell, parse_table_interior_cell,
     parse_table_leaf_cell, IndexInteriorCell, IndexLeafCell, TableInteriorCell, TableLeafCell,
 };
 
-use super::data::CellType;
 use super::header::{parse_btree_page_header, BTreePageHeader};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BTreePageType {
     IndexInterior,
     IndexLeaf,
@@ -27,9 +22,9 @@ impl TryFrom<u8> for BTreePageType {
             5 => Ok(BTreePageType::TableInterior),
             10 => Ok(BTreePageType::IndexLeaf),
             13 => Ok(BTreePageType::TableLeaf),
-            i => Err(anyhow!(
-                "Value {} does not correspond to any valid page type",
-                i
+            i => Err(Error::corrupt(
+                0,
+                format!("value {} does not correspond to any valid page type", i),
             )),
         }
     }
@@ -42,65 +37,105 @@ pub enum BTreePage {
     TableLeaf(BTreePageHeader, Vec<TableLeafCell>),
 }
 
-impl TryFrom<Vec<u8>> for BTreePage {
-    type Error = anyhow::Error;
-
-    fn try_from(value: Vec<u8>) -> Result<Self> {
+impl BTreePage {
+    /// Parses a b-tree page out of the raw page contents.
+    ///
+    /// `header_offset` is where the b-tree page header starts within the page (100 for page 1,
+    /// which also holds the database header, 0 otherwise). Cell pointers are always relative to
+    /// the start of the page.
+    pub fn parse(page: &[u8], header_offset: usize, usable_size: usize) -> Result<Self> {
         // parse page header
-        let rest: &[u8];
-        let (rest, header) =
-            parse_btree_page_header(&value).context("Unable to parse btree page header")?;
+        let (rest, header) = match parse_btree_page_header(&page[header_offset..]) {
+            Err(Error::Corrupt { detail, .. }) => {
+                return Err(Error::corrupt(header_offset, detail))
+            }
+            result => result?,
+        };
 
         // parse cell pointer array
-        let (_, mut cell_pointer_array) =
-            parse_cell_pointer_array(rest, header.number_of_cells as usize)?;
-        // sort the pointer array so we can then parse cells working with smaller bounded
-        // contiguous chunks of memory safely
-        cell_pointer_array.sort();
-        cell_pointer_array.push(value.len() as u16);
+        let cell_pointer_array = parse_cell_pointer_array(rest, header.number_of_cells as usize)
+            .ok_or_else(|| {
+                Error::corrupt(
+                    page.len() - rest.len(),
+                    "cell pointer array runs past the end of the page",
+                )
+            })?;
 
-        // parse cells
-        let cell_parser: fn(&[u8]) -> Result<CellType>;
+        // parse cells, keeping them in the order of the cell pointer array which is the key order
         match header.page_type {
             BTreePageType::TableInterior => {
-                let cells = parse_cells(&value, cell_pointer_array, parse_table_interior_cell)?;
-                return Ok(BTreePage::TableInterior(header, cells));
+                let cells = parse_cells(
+                    page,
+                    &cell_pointer_array,
+                    usable_size,
+                    parse_table_interior_cell,
+                )?;
+                Ok(BTreePage::TableInterior(header, cells))
             }
             BTreePageType::TableLeaf => {
-                let cells = parse_cells(&value, cell_pointer_array, parse_table_leaf_cell)?;
-                return Ok(BTreePage::TableLeaf(header, cells));
+                let cells = parse_cells(
+                    page,
+                    &cell_pointer_array,
+                    usable_size,
+                    parse_table_leaf_cell,
+                )?;
+                Ok(BTreePage::TableLeaf(header, cells))
             }
             BTreePageType::IndexInterior => {
-                let cells = parse_cells(&value, cell_pointer_array, parse_index_interior_cell)?;
-                return Ok(BTreePage::IndexInterior(header, cells));
+                let cells = parse_cells(
+                    page,
+                    &cell_pointer_array,
+                    usable_size,
+                    parse_index_interior_cell,
+                )?;
+                Ok(BTreePage::IndexInterior(header, cells))
             }
             BTreePageType::IndexLeaf => {
-                let cells = parse_cells(&value, cell_pointer_array, parse_index_leaf_cell)?;
-                return Ok(BTreePage::IndexLeaf(header, cells));
+                let cells = parse_cells(
+                    page,
+                    &cell_pointer_array,
+                    usable_size,
+                    parse_index_leaf_cell,
+                )?;
+                Ok(BTreePage::IndexLeaf(header, cells))
             }
         }
     }
-}
 
-fn parse_cell_pointer_array(data: &[u8], number_of_cells: usize) -> Result<(&[u8], Vec<u16>)> {
-    let cell_pointer_array: Vec<u16> = Range {
-        start: 0,
-        end: number_of_cells,
+    pub fn header(&self) -> &BTreePageHeader {
+        match self {
+            BTreePage::IndexInterior(header, _)
+            | BTreePage::IndexLeaf(header, _)
+            | BTreePage::TableInterior(header, _)
+            | BTreePage::TableLeaf(header, _) => header,
+        }
     }
-    .map(|i| u16::from_be_bytes(data[i..i + 2].try_into().unwrap()))
-    .collect();
-    Ok((&data[number_of_cells + 2..], cell_pointer_array))
 }
 
-fn parse_cells(
+fn parse_cell_pointer_array(data: &[u8], number_of_cells: usize) -> Option<Vec<u16>> {
+    let bytes = data.get(..number_of_cells * 2)?;
+    Some(
+        bytes
+            .chunks_exact(2)
+            .map(|pointer| u16::from_be_bytes([pointer[0], pointer[1]]))
+            .collect(),
+    )
+}
+
+fn parse_cells<T>(
     page_data: &[u8],
-    cell_pointer_array: Vec<u16>,
-    cell_parser: fn(&[u8]) -> Result<CellType>,
-) -> Result<Vec<CellType>> {
-    Ok(cell_pointer_array
-        .windows(2)
-        .map(|[ps, pe]| {
-            cell_parser(&page_data[*ps as usize..*pe as usize]).expect("error parsing cell")
+    cell_pointer_array: &[u16],
+    usable_size: usize,
+    cell_parser: fn(&[u8], usize, usize) -> Result<T>,
+) -> Result<Vec<T>> {
+    cell_pointer_array
+        .iter()
+        .map(|pointer| {
+            let offset = *pointer as usize;
+            let cell = page_data
+                .get(offset..)
+                .ok_or_else(|| Error::corrupt(offset, "cell pointer is out of the page bounds"))?;
+            cell_parser(cell, offset, usable_size)
         })
-        .collect_vec())
+        .collect()
 }
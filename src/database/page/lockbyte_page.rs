@@ -1,11 +1,11 @@
-use anyhow::Result;
+use crate::database::error::{Error, Result};
 
 pub struct LockBytePage;
 
 impl TryFrom<Vec<u8>> for LockBytePage {
-    type Error = anyhow::Error;
+    type Error = Error;
 
-    fn try_from(value: Vec<u8>) -> Result<Self> {
+    fn try_from(_value: Vec<u8>) -> Result<Self> {
         todo!()
     }
 }
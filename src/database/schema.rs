@@ -1,26 +1,271 @@
+use super::error::{Error, Result};
+use super::page::btree::data::serial_types::Value;
 use super::Row;
-use anyhow::{bail, Result};
+use crate::engine::affinity::Affinity;
+use crate::sql::{self, sql_query, ColumnConstraint, ParseError, SortOrder, TableConstraint};
 
+#[derive(Clone)]
 pub struct TableInformation {
     pub table_name: String,
     pub root_page: u64,
     pub ddl: Option<String>,
     pub column_names: Vec<String>,
+    /// The collating sequence each column is declared with, if any
+    pub column_collations: Vec<Option<String>>,
+    /// The type affinity of each column, given by its declared type
+    pub column_affinities: Vec<Affinity>,
+    /// Position of the `INTEGER PRIMARY KEY` column, if any. Such a column is an alias for the
+    /// rowid and is stored as NULL in the table records.
+    pub rowid_alias: Option<usize>,
+    pub without_rowid: bool,
+    /// The columns of the PRIMARY KEY, in key order. The records of WITHOUT ROWID tables hold
+    /// their values first.
+    pub primary_key: Vec<usize>,
+    /// How each generated column is generated, none for the other columns
+    pub generated_columns: Vec<Option<GeneratedColumn>>,
+}
+
+#[derive(Clone)]
+pub struct GeneratedColumn {
+    /// The expression giving the value of the column, none when it can't be parsed
+    pub expression: Option<sql::Expression>,
+    /// Whether the value is held in the records of the table rather than worked out when read
+    pub stored: bool,
+}
+
+impl TableInformation {
+    /// Returns the position of the given column, matched case-insensitively like SQLite does.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.column_names
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether a column is a VIRTUAL generated column, which table records don't hold
+    pub fn is_virtual(&self, column: usize) -> bool {
+        self.generated_columns[column]
+            .as_ref()
+            .is_some_and(|generated| !generated.stored)
+    }
+
+    /// Builds the full list of column values of a row out of the values stored in its record,
+    /// filling in the rowid alias column and any column added after the row was written.
+    /// VIRTUAL generated columns are left NULL, for the engine to evaluate.
+    pub fn row_values(&self, rowid: u64, values: Vec<Value>) -> Vec<Value> {
+        let mut stored = values.into_iter();
+        let mut values: Vec<Value> = (0..self.column_names.len())
+            .map(|column| match self.is_virtual(column) {
+                true => Value::Null,
+                false => stored.next().unwrap_or(Value::Null),
+            })
+            .collect();
+        if let Some(alias) = self.rowid_alias {
+            values[alias] = Value::Int64(rowid as i64);
+        }
+        // SQLite stores reals without a fractional part as integers to save space, and turns
+        // them back into reals when reading columns with REAL affinity
+        for (value, affinity) in values.iter_mut().zip(&self.column_affinities) {
+            if *affinity == Affinity::Real {
+                if let Some(integer) = value.as_i64() {
+                    *value = Value::Float64(integer as f64);
+                }
+            }
+        }
+        values
+    }
+
+    /// Builds the full list of column values of a row of a WITHOUT ROWID table out of the
+    /// values stored in its record, which holds the PRIMARY KEY columns first, then the
+    /// others in table order.
+    pub fn without_rowid_row_values(&self, values: Vec<Value>) -> Vec<Value> {
+        let others = (0..self.column_names.len())
+            .filter(|column| !self.primary_key.contains(column) && !self.is_virtual(*column));
+        let mut columns = vec![Value::Null; self.column_names.len()];
+        for (column, value) in self.primary_key.iter().copied().chain(others).zip(values) {
+            columns[column] = value;
+        }
+        // the values in table order, as rowid tables store them
+        let stored = (0..columns.len())
+            .filter(|column| !self.is_virtual(*column))
+            .map(|column| std::mem::replace(&mut columns[column], Value::Null))
+            .collect();
+        self.row_values(0, stored)
+    }
 }
 
 impl TryFrom<ObjectInformation> for TableInformation {
-    type Error = anyhow::Error;
+    type Error = Error;
+
+    fn try_from(object_information: ObjectInformation) -> Result<Self> {
+        match object_information.object_type {
+            ObjectType::Table => {
+                let name = &object_information.object_name;
+                let ddl = object_information
+                    .object_ddl
+                    .ok_or_else(|| Error::Schema(format!("table {} has no DDL", name)))?;
+                let statement = sql_query::create_table_statement(&ddl)
+                    .map_err(|e| malformed_schema(name, ParseError::new(&ddl, e)))?;
+                let rowid_alias = find_rowid_alias(&statement);
+                let column_collations = statement
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        // the last COLLATE constraint of a column is the one that applies
+                        column.constraints.iter().rev().find_map(|c| match c {
+                            ColumnConstraint::Collate(collation) => Some(collation.clone()),
+                            _ => None,
+                        })
+                    })
+                    .collect();
+                let generated_columns = statement
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        column.constraints.iter().find_map(|c| match c {
+                            ColumnConstraint::Generated { expression, stored } => {
+                                Some(GeneratedColumn {
+                                    expression: expression.clone(),
+                                    stored: *stored,
+                                })
+                            }
+                            _ => None,
+                        })
+                    })
+                    .collect();
+                let column_affinities = statement
+                    .columns
+                    .iter()
+                    .map(|column| Affinity::of(column.type_name.as_deref().unwrap_or("")))
+                    .collect();
+                let position = |name: &str| {
+                    statement
+                        .columns
+                        .iter()
+                        .position(|column| column.name.eq_ignore_ascii_case(name))
+                };
+                let column_key = statement.columns.iter().position(|column| {
+                    column
+                        .constraints
+                        .iter()
+                        .any(|c| matches!(c, ColumnConstraint::PrimaryKey { .. }))
+                });
+                let mut primary_key: Vec<usize> = match column_key {
+                    Some(column) => vec![column],
+                    None => statement
+                        .constraints
+                        .iter()
+                        .find_map(|c| match c {
+                            TableConstraint::PrimaryKey(columns) => {
+                                Some(columns.iter().filter_map(|c| position(&c.name)).collect())
+                            }
+                            _ => None,
+                        })
+                        .unwrap_or_default(),
+                };
+                // a column listed twice is only held once
+                let mut seen = Vec::new();
+                primary_key.retain(|column| {
+                    let first = !seen.contains(column);
+                    seen.push(*column);
+                    first
+                });
+                Ok(TableInformation {
+                    table_name: object_information.object_name,
+                    root_page: object_information.root_page,
+                    ddl: Some(ddl),
+                    column_names: statement.columns.into_iter().map(|c| c.name).collect(),
+                    column_collations,
+                    column_affinities,
+                    rowid_alias,
+                    without_rowid: statement.without_rowid,
+                    primary_key,
+                    generated_columns,
+                })
+            }
+            _ => Err(Error::Schema(format!(
+                "{} is not a table",
+                object_information.object_name
+            ))),
+        }
+    }
+}
+
+/// The error for the DDL of a schema object which doesn't parse, reported like SQLite does
+fn malformed_schema(name: &str, error: impl Into<Error>) -> Error {
+    Error::Schema(format!(
+        "malformed database schema ({}) - {}",
+        name,
+        error.into()
+    ))
+}
+
+/// A column declared as `INTEGER PRIMARY KEY` (in any letter case, and not DESC) becomes an
+/// alias for the rowid, unless the table is a WITHOUT ROWID table.
+fn find_rowid_alias(statement: &sql::CreateTableStatement) -> Option<usize> {
+    if statement.without_rowid {
+        return None;
+    }
+    let is_integer = |index: usize| {
+        statement.columns[index]
+            .type_name
+            .as_deref()
+            .is_some_and(|t| t.eq_ignore_ascii_case("integer"))
+    };
+    let column_key = statement.columns.iter().position(|c| {
+        c.constraints.iter().any(|constraint| {
+            matches!(
+                constraint,
+                ColumnConstraint::PrimaryKey { order, .. } if *order != Some(SortOrder::Descending)
+            )
+        })
+    });
+    let table_key = statement.constraints.iter().find_map(|c| match c {
+        TableConstraint::PrimaryKey(columns) if columns.len() == 1 => statement
+            .columns
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(&columns[0].name)),
+        _ => None,
+    });
+    column_key.or(table_key).filter(|index| is_integer(*index))
+}
+
+#[derive(Clone)]
+pub struct IndexInformation {
+    pub index_name: String,
+    pub table_name: String,
+    pub root_page: u64,
+    /// The indexed columns, in index order. Index records hold these values followed by the
+    /// rowid of the row they point to.
+    pub columns: Vec<sql::IndexedColumn>,
+}
+
+impl TryFrom<ObjectInformation> for IndexInformation {
+    type Error = Error;
 
     fn try_from(object_information: ObjectInformation) -> Result<Self> {
         match object_information.object_type {
-            // TODO: parse column names from DDL
-            ObjectType::Table => Ok(TableInformation {
-                table_name: object_information.table_name.unwrap(),
-                root_page: object_information.root_page,
-                ddl: Some(object_information.object_ddl),
-                column_names: vec![],
-            }),
-            _ => bail!("Object is not a table"),
+            ObjectType::Index => {
+                // automatic indexes (for UNIQUE and PRIMARY KEY constraints) have no DDL
+                let name = &object_information.object_name;
+                let ddl = object_information.object_ddl.ok_or_else(|| {
+                    Error::Schema(format!("index {} is an automatic index", name))
+                })?;
+                let statement = sql_query::create_index_statement(&ddl)
+                    .map_err(|e| malformed_schema(name, ParseError::new(&ddl, e)))?;
+                if statement.partial {
+                    return Err(Error::Schema(format!("index {} is a partial index", name)));
+                }
+                Ok(IndexInformation {
+                    index_name: object_information.object_name,
+                    table_name: statement.table_name,
+                    root_page: object_information.root_page,
+                    columns: statement.columns,
+                })
+            }
+            _ => Err(Error::Schema(format!(
+                "{} is not an index",
+                object_information.object_name
+            ))),
         }
     }
 }
@@ -30,32 +275,44 @@ pub struct ObjectInformation {
     pub object_name: String,
     pub table_name: Option<String>,
     pub root_page: u64,
-    pub object_ddl: String,
+    pub object_ddl: Option<String>,
 }
 
-impl From<Row> for ObjectInformation {
-    fn from(row: Row) -> Self {
-        let object_type = match row["type"] {
-            "table" => ObjectType::Table,
-            "index" => ObjectType::Index,
-            "view" => ObjectType::View,
-            "trigger" => ObjectType::Trigger,
-            _ => panic!("Unknown object type"),
+impl TryFrom<&Row> for ObjectInformation {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self> {
+        let object_type = match row["type"].as_str() {
+            Some("table") => ObjectType::Table,
+            Some("index") => ObjectType::Index,
+            Some("view") => ObjectType::View,
+            Some("trigger") => ObjectType::Trigger,
+            _ => {
+                return Err(Error::Schema(format!(
+                    "unknown object type {:?}",
+                    row["type"]
+                )))
+            }
         };
-        let object_name = row["name"];
-        let table_name = row["tbl_name"];
-        let root_page = row["rootpage"];
-        let object_ddl = row["sql"];
-        ObjectInformation {
+        let object_name = row["name"]
+            .as_str()
+            .ok_or_else(|| Error::Schema(String::from("schema object has no name")))?
+            .to_string();
+        let table_name = row["tbl_name"].as_str().map(|s| s.to_string());
+        // views and triggers have a root page of 0
+        let root_page = row["rootpage"].as_i64().unwrap_or(0) as u64;
+        let object_ddl = row["sql"].as_str().map(|s| s.to_string());
+        Ok(ObjectInformation {
             object_type,
             object_name,
             table_name,
             root_page,
             object_ddl,
-        }
+        })
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub enum ObjectType {
     Table,
     Index,
@@ -63,15 +320,31 @@ pub enum ObjectType {
     Trigger,
 }
 
-pub const SCHEMA_TABLE_INFORMATION: TableInformation = TableInformation {
-    table_name: String::from("schema"),
-    root_page: 1,
-    ddl: None,
-    column_names: vec![
-        String::from("type"),
-        String::from("name"),
-        String::from("tbl_name"),
-        String::from("rootpage"),
-        String::from("sql"),
-    ],
-};
+/// The schema table, which is always rooted at page 1 and holds the definition of every other
+/// object in the database.
+pub fn schema_table_information() -> TableInformation {
+    TableInformation {
+        table_name: String::from("sqlite_schema"),
+        root_page: 1,
+        ddl: None,
+        column_names: vec![
+            String::from("type"),
+            String::from("name"),
+            String::from("tbl_name"),
+            String::from("rootpage"),
+            String::from("sql"),
+        ],
+        column_collations: vec![None; 5],
+        column_affinities: vec![
+            Affinity::Text,
+            Affinity::Text,
+            Affinity::Text,
+            Affinity::Integer,
+            Affinity::Text,
+        ],
+        rowid_alias: None,
+        without_rowid: false,
+        primary_key: Vec::new(),
+        generated_columns: vec![None; 5],
+    }
+}
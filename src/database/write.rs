@@ -0,0 +1,1319 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::engine::affinity::apply;
+use crate::engine::collation::{Collation, BINARY};
+use crate::parsing::utils::encode_varint;
+use crate::sql::{
+    sql_query, ColumnConstraint, CreateTableStatement, IndexedColumn, SortOrder, TableConstraint,
+};
+
+use super::encoding::TextEncoding;
+use super::error::{Error, Result};
+use super::header::{DatabaseHeader, DATABASE_HEADER_SIZE};
+use super::io::{Lock, PENDING_BYTE};
+use super::page::btree::data::local_payload_size;
+use super::page::btree::data::record::encode_record_with;
+use super::page::btree::data::serial_types::Value;
+use super::page::btree::page::BTreePage;
+use super::schema::{ObjectInformation, ObjectType, TableInformation};
+use super::{page_number_to_offset, Database, TABLE_SCHEMA_ROOT_PAGE_NUMBER};
+
+/// The bytes rollback journals start with
+const JOURNAL_MAGIC: [u8; 8] = [0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+/// The size of the header of rollback journals, that of a disk sector
+const JOURNAL_HEADER_SIZE: usize = 512;
+
+/// Changes to a database, which tables are created and rows inserted with. They are made to
+/// copies of the pages in memory, and only written to the database once committed, all at once,
+/// so that dropping the transaction rolls them back.
+///
+/// Only tables without triggers, or constraints which would need checking other than NOT NULL,
+/// UNIQUE and PRIMARY KEY ones, are written to. Their indexes get the entries of the rows
+/// inserted, unless they are partial or on expressions. Pages are added at the end of the file rather than taken from the freelist. The file is
+/// locked the way SQLite locks it, from the start of the transaction until it ends.
+pub struct Transaction<'d> {
+    database: &'d mut Database,
+    /// The pages changed or added, by number
+    pages: BTreeMap<u32, Vec<u8>>,
+    /// The b-tree pages read or changed, by number, kept parsed as rows are inserted one after
+    /// the other on the same pages
+    nodes: HashMap<u32, Node>,
+    /// Those of the b-tree pages which were changed, written to `pages` once committing
+    changed: BTreeSet<u32>,
+    /// The number of pages the database held before the transaction
+    original_page_count: u32,
+    page_count: u32,
+    /// Whether the schema changed, which tells the connections having read it to read it again
+    schema_changed: bool,
+    /// What inserting rows takes for each table rows were inserted into, by root page, once
+    /// the table was checked to be one rows can be inserted into
+    insertable: HashMap<u64, Rc<Insertable>>,
+}
+
+/// A cell of a b-tree page: a row of a table or an entry of an index on a leaf page, or a
+/// pointer to a child page on an interior page, along with its key
+struct Cell {
+    /// The rowid the cell is ordered by in a table, or that of the row an index entry is for
+    rowid: i64,
+    /// The values of an index entry, ending with the rowid, which index cells are ordered by
+    entry: Vec<Value>,
+    bytes: Vec<u8>,
+}
+
+impl Cell {
+    fn interior(child: u32, rowid: i64) -> Cell {
+        let mut bytes = child.to_be_bytes().to_vec();
+        bytes.extend(encode_varint(rowid as u64));
+        Cell {
+            rowid,
+            entry: Vec::new(),
+            bytes,
+        }
+    }
+
+    fn child(&self) -> u32 {
+        u32::from_be_bytes([self.bytes[0], self.bytes[1], self.bytes[2], self.bytes[3]])
+    }
+
+    fn set_child(&mut self, child: u32) {
+        self.bytes[..4].copy_from_slice(&child.to_be_bytes());
+    }
+}
+
+/// A page of a b-tree, the cells of which are being changed
+struct Node {
+    cells: Vec<Cell>,
+    /// The page holding the keys larger than those of the cells, on interior pages only
+    right_most: Option<u32>,
+    /// Whether the page is part of an index b-tree rather than a table b-tree
+    index: bool,
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.right_most.is_none()
+    }
+
+    /// The space its cells take on a page, along with their pointers
+    fn size(&self) -> usize {
+        self.cells.iter().map(|cell| cell.bytes.len() + 2).sum()
+    }
+
+    /// The child page holding the keys of the cell at a position, or those larger than all
+    fn child(&self, position: usize) -> u32 {
+        match self.cells.get(position) {
+            Some(cell) => cell.child(),
+            None => self.right_most.unwrap_or_default(),
+        }
+    }
+
+    /// Splits the cells into nodes fitting in `available` bytes each, in order, along with the
+    /// interior cell dividing each node from the next, its child being left for the caller to
+    /// set. On table leaf pages, the divider holds the rowid of the last cell of the node. The
+    /// last cell of each node but the last is taken out of it otherwise: it becomes the
+    /// divider on index pages, which hold entries on interior pages too, and its rowid does on
+    /// table interior pages. Taken out of an interior page, its child becomes the right-most
+    /// one of the node. At least two nodes are made when there are cells enough.
+    fn split(self, available: usize) -> Vec<(Node, Option<Cell>)> {
+        let (leaf, index, right_most) = (self.is_leaf(), self.index, self.right_most);
+        // cells which would all fit on one page are split in half
+        let middle = match self.size() <= available {
+            true => self.cells.len() / 2,
+            false => usize::MAX,
+        };
+        let mut chunks: Vec<Vec<Cell>> = vec![Vec::new()];
+        let mut used = 0;
+        for (position, cell) in self.cells.into_iter().enumerate() {
+            let size = cell.bytes.len() + 2;
+            let chunk = chunks.last_mut().unwrap();
+            if !chunk.is_empty() && (used + size > available || position == middle) {
+                chunks.push(Vec::new());
+                used = 0;
+            }
+            used += size;
+            chunks.last_mut().unwrap().push(cell);
+        }
+        let count = chunks.len();
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(position, mut cells)| {
+                if position == count - 1 {
+                    return (
+                        Node {
+                            cells,
+                            right_most,
+                            index,
+                        },
+                        None,
+                    );
+                }
+                if leaf && !index {
+                    let divider = Cell::interior(0, cells.last().unwrap().rowid);
+                    let node = Node {
+                        cells,
+                        right_most: None,
+                        index,
+                    };
+                    return (node, Some(divider));
+                }
+                let mut last = cells.pop().unwrap();
+                let right_most = (!leaf).then(|| last.child());
+                let divider = match (index, leaf) {
+                    (false, _) => Cell::interior(0, last.rowid),
+                    (true, true) => {
+                        last.bytes.splice(0..0, [0; 4]);
+                        last
+                    }
+                    (true, false) => last,
+                };
+                let node = Node {
+                    cells,
+                    right_most,
+                    index,
+                };
+                (node, Some(divider))
+            })
+            .collect()
+    }
+}
+
+/// What inserting rows into a table takes beyond its columns, found the first time rows are
+/// inserted into it
+struct Insertable {
+    /// Which of the columns are declared NOT NULL
+    not_null: Vec<bool>,
+    indexes: Vec<IndexOrder>,
+}
+
+/// An index of a table rows are inserted into, and how its entries are ordered
+struct IndexOrder {
+    root_page: u32,
+    /// The columns of the table the entries hold the values of, before the rowid
+    columns: Vec<usize>,
+    collations: Vec<Collation>,
+    descending: Vec<bool>,
+    /// Whether no two entries can hold the same values, unless one of them is NULL
+    unique: bool,
+}
+
+impl IndexOrder {
+    /// The entry of a row, its values being converted to the affinity of their columns
+    fn entry(&self, values: &[Value], rowid: i64, rowid_alias: Option<usize>) -> Vec<Value> {
+        let mut entry: Vec<Value> = self
+            .columns
+            .iter()
+            .map(|column| match Some(*column) == rowid_alias {
+                true => Value::Int64(rowid),
+                false => values[*column].clone(),
+            })
+            .collect();
+        entry.push(Value::Int64(rowid));
+        entry
+    }
+
+    /// Compares an entry with another one, or with the values an entry starts with, as the
+    /// index orders them
+    fn compare(&self, entry: &[Value], other: &[Value], encoding: TextEncoding) -> Ordering {
+        entry
+            .iter()
+            .zip(other)
+            .enumerate()
+            .map(|(position, (a, b))| {
+                let ordering = match (a, b, self.collations.get(position)) {
+                    (Value::Null, Value::Null, _) => Ordering::Equal,
+                    (Value::Null, _, _) => Ordering::Less,
+                    (_, Value::Null, _) => Ordering::Greater,
+                    (a, b, Some(collation)) => collation.compare_stored(a, b, encoding),
+                    // the rowid ending the entry
+                    (a, b, None) => a.cmp(b),
+                };
+                match self.descending.get(position) {
+                    Some(true) => ordering.reverse(),
+                    _ => ordering,
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+/// The columns of the indexes SQLite makes for the PRIMARY KEY and UNIQUE constraints of a
+/// table, in the order they are numbered in, which is the one they are declared in. Constraints
+/// on the same columns share an index, and the rowid alias has none.
+fn automatic_indexes(
+    statement: &CreateTableStatement,
+    rowid_alias: Option<usize>,
+) -> Vec<Vec<IndexedColumn>> {
+    let mut indexes: Vec<Vec<IndexedColumn>> = Vec::new();
+    let mut add = |columns: Vec<IndexedColumn>| {
+        let same = |other: &Vec<IndexedColumn>| {
+            other.len() == columns.len()
+                && other.iter().zip(&columns).all(|(a, b)| {
+                    a.name.eq_ignore_ascii_case(&b.name) && a.collation == b.collation
+                })
+        };
+        if !indexes.iter().any(same) {
+            indexes.push(columns);
+        }
+    };
+    let is_alias = |name: &str| {
+        rowid_alias.is_some_and(|alias| statement.columns[alias].name.eq_ignore_ascii_case(name))
+    };
+    for column in &statement.columns {
+        let indexed = |order: Option<SortOrder>| IndexedColumn {
+            name: column.name.clone(),
+            collation: None,
+            order: order.unwrap_or(SortOrder::Ascending),
+        };
+        for constraint in &column.constraints {
+            match constraint {
+                ColumnConstraint::PrimaryKey { order, .. } if !is_alias(&column.name) => {
+                    add(vec![indexed(*order)])
+                }
+                ColumnConstraint::Unique => add(vec![indexed(None)]),
+                _ => {}
+            }
+        }
+    }
+    for constraint in &statement.constraints {
+        match constraint {
+            TableConstraint::PrimaryKey(columns)
+                if !(columns.len() == 1 && is_alias(&columns[0].name)) =>
+            {
+                add(columns.clone())
+            }
+            TableConstraint::Unique(columns) => add(columns.clone()),
+            _ => {}
+        }
+    }
+    indexes
+}
+
+impl Database {
+    /// Starts a transaction changing the database, taking the RESERVED lock on its file as
+    /// SQLite does: other connections can go on reading it, but not start changing it.
+    pub fn begin(&mut self) -> Result<Transaction<'_>> {
+        if self.header.largest_root_btree_page_number != 0 {
+            return Err(Error::Unsupported(String::from(
+                "auto-vacuum databases can't be written to",
+            )));
+        }
+        if self.header.file_format_write_version != 1 {
+            return Err(Error::Unsupported(String::from(
+                "WAL databases can't be written to",
+            )));
+        }
+        if let Err(e) = self.lock_for_writing() {
+            self.db_file.borrow_mut().unlock()?;
+            return Err(e);
+        }
+        let page_count = self.page_count()?;
+        Ok(Transaction {
+            database: self,
+            pages: BTreeMap::new(),
+            nodes: HashMap::new(),
+            changed: BTreeSet::new(),
+            original_page_count: page_count,
+            page_count,
+            schema_changed: false,
+            insertable: HashMap::new(),
+        })
+    }
+
+    /// Rolls back the changes a connection was cut short writing to the database, which its
+    /// rollback journal holds the pages they replaced of, unless it is still writing them.
+    pub(super) fn recover(&mut self) -> Result<()> {
+        let result = self.lock_for_writing();
+        self.db_file.borrow_mut().unlock()?;
+        match result {
+            Err(Error::Busy) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Takes the RESERVED lock, first rolling back the journal of a connection cut short
+    /// changing the database, and reads the header again should another connection have
+    /// changed the database since.
+    fn lock_for_writing(&mut self) -> Result<()> {
+        let mut file = self.db_file.borrow_mut();
+        file.make_writable()?;
+        file.lock(Lock::Reserved)?;
+        drop(file);
+        // no other connection changing the database, the journal is left from one cut short
+        if let Some(journal) = self.journal_path().filter(|path| Path::new(path).exists()) {
+            self.db_file.borrow_mut().lock(Lock::Exclusive)?;
+            self.roll_back(&journal)?;
+        }
+        let header_bytes = self
+            .db_file
+            .borrow_mut()
+            .read_exact_at(DATABASE_HEADER_SIZE, SeekFrom::Start(0))?;
+        let header = DatabaseHeader::try_from(header_bytes)?;
+        if header.file_change_counter != self.header.file_change_counter {
+            *self.tables.borrow_mut() = None;
+            *self.indexes.borrow_mut() = None;
+        }
+        self.header = header;
+        Ok(())
+    }
+
+    /// The path of the rollback journal of the database, none for those held in memory
+    fn journal_path(&self) -> Option<String> {
+        let file = self.db_file.borrow();
+        file.path().map(|path| format!("{}-journal", path))
+    }
+
+    /// Writes back the pages saved in a rollback journal, and removes it. Pages whose checksum
+    /// doesn't match were being saved when the journal was cut short, and are left out along
+    /// with those following them, the database not having been written to yet.
+    fn roll_back(&mut self, path: &str) -> Result<()> {
+        let journal = fs::read(path)?;
+        let mut file = self.db_file.borrow_mut();
+        // the page size and number of pages of the database before the changes
+        let mut original = None;
+        let mut offset = 0;
+        // a journal is made of segments, each starting with a header
+        'segments: while journal.get(offset..offset + 8) == Some(&JOURNAL_MAGIC) {
+            let Some(header) = journal.get(offset..offset + 28) else {
+                break;
+            };
+            let field = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+            let (nonce, sector_size, page_size) =
+                (field(12), field(20) as usize, field(24) as usize);
+            if sector_size == 0 || page_size == 0 {
+                break;
+            }
+            original.get_or_insert((page_size, field(16)));
+            let start = offset + sector_size;
+            let record_size = page_size + 8;
+            let records = match field(8) {
+                // the number of pages saved wasn't written, the length of the journal tells
+                u32::MAX => journal.len().saturating_sub(start) / record_size,
+                records => records as usize,
+            };
+            for index in 0..records {
+                let at = start + index * record_size;
+                let Some(record) = journal.get(at..at + record_size) else {
+                    break 'segments;
+                };
+                let page_number = u32::from_be_bytes(record[..4].try_into().unwrap());
+                let page = &record[4..4 + page_size];
+                let checksum = u32::from_be_bytes(record[4 + page_size..].try_into().unwrap());
+                if page_number == 0 || checksum != journal_checksum(nonce, page) {
+                    break 'segments;
+                }
+                file.write_all_at(page, page_number_to_offset(page_number, page_size))?;
+            }
+            offset = (start + records * record_size).div_ceil(sector_size) * sector_size;
+        }
+        if let Some((page_size, page_count)) = original {
+            file.set_len(page_count as u64 * page_size as u64)?;
+        }
+        file.sync()?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// The number of pages of the database: the one in the header when it was written by
+    /// SQLite versions keeping it up to date, the one the size of the file tells otherwise
+    fn page_count(&self) -> Result<u32> {
+        let header = &self.header;
+        if header.db_size_in_pages != 0 && header.version_valid_for == header.file_change_counter {
+            return Ok(header.db_size_in_pages);
+        }
+        let length = self.db_file.borrow_mut().len()?;
+        Ok((length / header.page_size_in_bytes() as u64) as u32)
+    }
+}
+
+/// The checksum of a page saved in a rollback journal, which adds every 200th byte of the page
+/// from its end to the nonce of the journal
+fn journal_checksum(nonce: u32, page: &[u8]) -> u32 {
+    (1..)
+        .map(|i| page.len() as isize - 200 * i)
+        .take_while(|offset| *offset > 0)
+        .fold(nonce, |sum, offset| {
+            sum.wrapping_add(page[offset as usize] as u32)
+        })
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        // the file being unlocked is all that can fail, which the next lock taken reports
+        let _ = self.database.db_file.borrow_mut().unlock();
+    }
+}
+
+impl Transaction<'_> {
+    /// Creates a table, given the statement creating it, and returns what it is like.
+    pub fn create_table(&mut self, name: &str, sql: &str) -> Result<TableInformation> {
+        if self.database.table_information(name).is_ok() {
+            return Err(Error::Schema(format!("table {} already exists", name)));
+        }
+        let root_page = self.allocate();
+        self.put_node(
+            root_page,
+            Node {
+                cells: Vec::new(),
+                right_most: None,
+                index: false,
+            },
+        );
+        let values = [
+            Value::String(String::from("table")),
+            Value::String(name.to_string()),
+            Value::String(name.to_string()),
+            Value::Int64(root_page as i64),
+            Value::String(sql.to_string()),
+        ];
+        let rowid = self.next_rowid(TABLE_SCHEMA_ROOT_PAGE_NUMBER)?;
+        self.insert_record(TABLE_SCHEMA_ROOT_PAGE_NUMBER, rowid, &values)?;
+        self.schema_changed = true;
+        TableInformation::try_from(ObjectInformation {
+            object_type: ObjectType::Table,
+            object_name: name.to_string(),
+            table_name: Some(name.to_string()),
+            root_page: root_page as u64,
+            object_ddl: Some(sql.to_string()),
+        })
+    }
+
+    /// Inserts a row into a table, given the value of each of its columns, NULL for those
+    /// missing, and returns its rowid. Values are converted to the affinity of their column,
+    /// and a NULL rowid alias column gets the rowid following the largest one. The indexes of
+    /// the table get the entries of the row.
+    pub fn insert(&mut self, table: &TableInformation, mut values: Vec<Value>) -> Result<i64> {
+        let insertable = self.check_insertable(table)?;
+        values.resize(table.column_names.len(), Value::Null);
+        let mut values: Vec<Value> = values
+            .into_iter()
+            .zip(&table.column_affinities)
+            .map(|(value, affinity)| apply(value, *affinity))
+            .collect();
+        let root_page = table.root_page as u32;
+        let rowid = match table.rowid_alias.map(|alias| &mut values[alias]) {
+            None | Some(Value::Null) => self.next_rowid(root_page)?,
+            // the rowid alias is stored as NULL, its value being the rowid
+            Some(value) => std::mem::replace(value, Value::Null)
+                .as_i64()
+                .ok_or(Error::Mismatch)?,
+        };
+        // like in SQLite, the rowid is checked first, the rowid alias now being NULL
+        let missing = (0..values.len()).find(|i| {
+            insertable.not_null[*i] && values[*i].is_null() && Some(*i) != table.rowid_alias
+        });
+        let unique = |columns: &[usize]| {
+            let names: Vec<String> = columns
+                .iter()
+                .map(|column| format!("{}.{}", table.table_name, table.column_names[*column]))
+                .collect();
+            Error::Constraint(format!("UNIQUE constraint failed: {}", names.join(", ")))
+        };
+        if let Some(column) = missing {
+            return Err(Error::Constraint(format!(
+                "NOT NULL constraint failed: {}.{}",
+                table.table_name, table.column_names[column]
+            )));
+        }
+        if self.contains_rowid(root_page, rowid)? {
+            return Err(unique(&table.rowid_alias.into_iter().collect::<Vec<_>>()));
+        }
+        // the entries are all checked before the row is inserted, so that it is inserted whole
+        // or not at all
+        let entries: Vec<Vec<Value>> = insertable
+            .indexes
+            .iter()
+            .map(|index| index.entry(&values, rowid, table.rowid_alias))
+            .collect();
+        for (index, entry) in insertable.indexes.iter().zip(&entries) {
+            let key = &entry[..index.columns.len()];
+            if index.unique && !key.iter().any(Value::is_null) && self.index_contains(index, key)? {
+                return Err(unique(&index.columns));
+            }
+        }
+        self.insert_record(root_page, rowid, &values)?;
+        for (index, entry) in insertable.indexes.iter().zip(entries) {
+            self.insert_entry(index, entry)?;
+        }
+        Ok(rowid)
+    }
+
+    /// Writes the changes to the database. The pages they replace are first saved to a rollback
+    /// journal, as SQLite does, which SQLite rolls back should the writes be cut short, as
+    /// does opening the database here. The pages are only written once the connections reading
+    /// the database are done, which fails with `Error::Busy` otherwise.
+    pub fn commit(mut self) -> Result<()> {
+        let Some(header) = self.finish()? else {
+            return Ok(());
+        };
+        let journal = self.write_journal()?;
+        let locked = self.database.db_file.borrow_mut().lock(Lock::Exclusive);
+        if let Err(e) = locked {
+            if let Some(journal) = journal {
+                fs::remove_file(journal)?;
+            }
+            return Err(e);
+        }
+        self.write_pages()?;
+        if let Some(journal) = journal {
+            fs::remove_file(journal)?;
+        }
+        self.database.header = header;
+        *self.database.tables.borrow_mut() = None;
+        *self.database.indexes.borrow_mut() = None;
+        Ok(())
+    }
+
+    /// Writes the nodes changed to their pages, along with the header, returning it. Nothing
+    /// is returned when nothing changed.
+    fn finish(&mut self) -> Result<Option<DatabaseHeader>> {
+        for page_number in std::mem::take(&mut self.changed) {
+            let node = self.nodes.remove(&page_number).unwrap();
+            self.write_node(page_number, &node);
+        }
+        if self.pages.is_empty() {
+            return Ok(None);
+        }
+        let mut first_page = self.page(1)?;
+        let counter = |offset: usize, page: &[u8]| {
+            u32::from_be_bytes(page[offset..offset + 4].try_into().unwrap())
+        };
+        let change_counter = counter(24, &first_page).wrapping_add(1);
+        first_page[24..28].copy_from_slice(&change_counter.to_be_bytes());
+        first_page[28..32].copy_from_slice(&self.page_count.to_be_bytes());
+        first_page[92..96].copy_from_slice(&change_counter.to_be_bytes());
+        if self.schema_changed {
+            let schema_cookie = counter(40, &first_page).wrapping_add(1);
+            first_page[40..44].copy_from_slice(&schema_cookie.to_be_bytes());
+        }
+        let header = DatabaseHeader::try_from(first_page[..DATABASE_HEADER_SIZE].to_vec())?;
+        self.pages.insert(1, first_page);
+        Ok(Some(header))
+    }
+
+    /// Writes the pages to the database file, once the EXCLUSIVE lock is held.
+    fn write_pages(&mut self) -> Result<()> {
+        let page_size = self.page_size();
+        let mut file = self.database.db_file.borrow_mut();
+        for (page_number, page) in &self.pages {
+            file.write_all_at(page, page_number_to_offset(*page_number, page_size))?;
+        }
+        file.sync()
+    }
+
+    /// Saves the pages about to be replaced in the rollback journal of the database, returning
+    /// its path. Databases held in memory have none.
+    fn write_journal(&self) -> Result<Option<String>> {
+        let Some(path) = self.database.journal_path() else {
+            return Ok(None);
+        };
+        let page_size = self.page_size();
+        let replaced = self
+            .pages
+            .keys()
+            .filter(|page_number| **page_number <= self.original_page_count)
+            .collect::<Vec<_>>();
+        // any number does, it only tells this journal's pages from those of an earlier one
+        let nonce = std::process::id().wrapping_mul(0x9e37_79b9);
+        let mut journal = vec![0; JOURNAL_HEADER_SIZE];
+        journal[..8].copy_from_slice(&JOURNAL_MAGIC);
+        journal[8..12].copy_from_slice(&(replaced.len() as u32).to_be_bytes());
+        journal[12..16].copy_from_slice(&nonce.to_be_bytes());
+        journal[16..20].copy_from_slice(&self.original_page_count.to_be_bytes());
+        journal[20..24].copy_from_slice(&(JOURNAL_HEADER_SIZE as u32).to_be_bytes());
+        journal[24..28].copy_from_slice(&(page_size as u32).to_be_bytes());
+        for page_number in replaced {
+            let page = self.database.read_page_bytes(*page_number)?;
+            let checksum = journal_checksum(nonce, &page);
+            journal.extend(page_number.to_be_bytes());
+            journal.extend(page);
+            journal.extend(checksum.to_be_bytes());
+        }
+        // the RESERVED lock keeps other connections from writing a journal, but not those
+        // which don't lock the database
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => return Err(Error::Busy),
+            result => result?,
+        };
+        file.write_all(&journal)?;
+        file.sync_all()?;
+        Ok(Some(path))
+    }
+
+    /// Checks a table can have rows inserted into it, returning which of its columns are
+    /// declared NOT NULL and how its indexes are ordered.
+    fn check_insertable(&mut self, table: &TableInformation) -> Result<Rc<Insertable>> {
+        if let Some(insertable) = self.insertable.get(&table.root_page) {
+            return Ok(insertable.clone());
+        }
+        let unsupported = |what: &str| {
+            Err(Error::Unsupported(format!(
+                "{} can't be inserted into: it has {}",
+                table.table_name, what
+            )))
+        };
+        if table.without_rowid {
+            return unsupported("no rowid");
+        }
+        let ddl = table.ddl.as_deref().unwrap_or_default();
+        let malformed = || Error::Schema(format!("malformed schema of {}", table.table_name));
+        let statement = sql_query::create_table_statement(ddl).map_err(|_| malformed())?;
+        let constraints = statement.columns.iter().flat_map(|c| &c.constraints);
+        for constraint in constraints {
+            match constraint {
+                ColumnConstraint::PrimaryKey {
+                    autoincrement: true,
+                    ..
+                } => return unsupported("an AUTOINCREMENT column"),
+                ColumnConstraint::Check => return unsupported("CHECK constraints"),
+                ColumnConstraint::Generated { .. } => return unsupported("generated columns"),
+                _ => {}
+            }
+        }
+        if statement.constraints.contains(&TableConstraint::Check) {
+            return unsupported("CHECK constraints");
+        }
+        let automatic = automatic_indexes(&statement, table.rowid_alias);
+        let mut indexes = Vec::new();
+        let objects = self.database.list_objects()?;
+        let dependent = objects.iter().filter(|object| {
+            object
+                .table_name
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(&table.table_name))
+        });
+        for object in dependent {
+            let (columns, unique) = match (&object.object_type, &object.object_ddl) {
+                (ObjectType::Trigger, _) => return unsupported("triggers"),
+                (ObjectType::Index, Some(ddl)) => {
+                    let Ok(statement) = sql_query::create_index_statement(ddl) else {
+                        return unsupported("an index on expressions");
+                    };
+                    if statement.partial {
+                        return unsupported("a partial index");
+                    }
+                    (statement.columns, statement.unique)
+                }
+                // automatic indexes are named after the table, followed by their number
+                (ObjectType::Index, None) => {
+                    let number = object.object_name.rsplit('_').next();
+                    let columns = number
+                        .and_then(|number| number.parse::<usize>().ok())
+                        .and_then(|number| automatic.get(number.wrapping_sub(1)));
+                    match columns {
+                        Some(columns) => (columns.clone(), true),
+                        None => return Err(malformed()),
+                    }
+                }
+                _ => continue,
+            };
+            let mut index = IndexOrder {
+                root_page: object.root_page as u32,
+                columns: Vec::new(),
+                collations: Vec::new(),
+                descending: Vec::new(),
+                unique,
+            };
+            for column in columns {
+                let position = table.column_index(&column.name).ok_or_else(malformed)?;
+                let collation = column
+                    .collation
+                    .as_deref()
+                    .or(table.column_collations[position].as_deref())
+                    .unwrap_or(BINARY);
+                let collation = self
+                    .database
+                    .collations()
+                    .find(collation)
+                    .map_err(|e| Error::Schema(e.to_string()))?;
+                index.columns.push(position);
+                index.collations.push(collation);
+                index.descending.push(column.order == SortOrder::Descending);
+            }
+            indexes.push(index);
+        }
+        let not_null = statement
+            .columns
+            .iter()
+            .map(|column| column.constraints.contains(&ColumnConstraint::NotNull))
+            .collect();
+        let insertable = Rc::new(Insertable { not_null, indexes });
+        self.insertable.insert(table.root_page, insertable.clone());
+        Ok(insertable)
+    }
+
+    fn page_size(&self) -> usize {
+        self.database.header.page_size_in_bytes()
+    }
+
+    fn usable_size(&self) -> usize {
+        self.database.header.usable_page_size()
+    }
+
+    /// The content of a page, as changed by the transaction
+    fn page(&self, page_number: u32) -> Result<Vec<u8>> {
+        match self.pages.get(&page_number) {
+            Some(page) => Ok(page.clone()),
+            None => self.database.read_page_bytes(page_number),
+        }
+    }
+
+    /// Adds a page to the database, filled with zeros.
+    fn allocate(&mut self) -> u32 {
+        self.page_count += 1;
+        if self.page_count as usize == PENDING_BYTE as usize / self.page_size() + 1 {
+            self.page_count += 1;
+        }
+        self.pages
+            .insert(self.page_count, vec![0; self.page_size()]);
+        self.page_count
+    }
+
+    /// Where the b-tree page header of a page starts, after the database header on page 1
+    fn header_offset(page_number: u32) -> usize {
+        match page_number {
+            1 => DATABASE_HEADER_SIZE,
+            _ => 0,
+        }
+    }
+
+    /// The space available to the cells of a page and their pointers
+    fn available(&self, page_number: u32, node: &Node) -> usize {
+        let header_size = if node.is_leaf() { 8 } else { 12 };
+        self.usable_size() - Self::header_offset(page_number) - header_size
+    }
+
+    /// A b-tree page, parsed the first time it is needed
+    fn node(&mut self, page_number: u32) -> Result<&Node> {
+        if !self.nodes.contains_key(&page_number) {
+            let node = self.read_node(page_number)?;
+            self.nodes.insert(page_number, node);
+        }
+        Ok(&self.nodes[&page_number])
+    }
+
+    /// Takes a b-tree page out to change it, to be put back with `put_node`.
+    fn take_node(&mut self, page_number: u32) -> Result<Node> {
+        self.node(page_number)?;
+        Ok(self.nodes.remove(&page_number).unwrap())
+    }
+
+    fn put_node(&mut self, page_number: u32, node: Node) {
+        self.nodes.insert(page_number, node);
+        self.changed.insert(page_number);
+    }
+
+    fn read_node(&self, page_number: u32) -> Result<Node> {
+        let page = self.page(page_number)?;
+        let usable_size = self.usable_size();
+        let page = BTreePage::parse(&page, Self::header_offset(page_number), usable_size)
+            .map_err(|e| e.on_page(page_number))?;
+        // the part of a payload on the page, followed by the number of its first overflow page
+        let local = |content: &[u8], overflow: Option<u32>| {
+            let mut bytes = content.to_vec();
+            if let Some(overflow) = overflow {
+                bytes.extend(overflow.to_be_bytes());
+            }
+            bytes
+        };
+        // the rowid ends the entries of indexes
+        let index_cell = |entry: Vec<Value>, bytes: Vec<u8>| Cell {
+            rowid: entry.last().and_then(Value::as_i64).unwrap_or_default(),
+            entry,
+            bytes,
+        };
+        match page {
+            BTreePage::TableLeaf(_, cells) => Ok(Node {
+                cells: cells
+                    .into_iter()
+                    .map(|cell| {
+                        let mut bytes = encode_varint(cell.payload_size);
+                        bytes.extend(encode_varint(cell.key));
+                        bytes.extend(local(
+                            &cell.payload.content,
+                            cell.first_overflow_page_number,
+                        ));
+                        Cell {
+                            rowid: cell.key as i64,
+                            entry: Vec::new(),
+                            bytes,
+                        }
+                    })
+                    .collect(),
+                right_most: None,
+                index: false,
+            }),
+            BTreePage::TableInterior(header, cells) => Ok(Node {
+                cells: cells
+                    .into_iter()
+                    .map(|cell| Cell::interior(cell.left_child_pointer, cell.key as i64))
+                    .collect(),
+                right_most: header.right_most_pointer,
+                index: false,
+            }),
+            BTreePage::IndexLeaf(_, cells) => Ok(Node {
+                cells: cells
+                    .into_iter()
+                    .map(|cell| {
+                        let mut bytes = encode_varint(cell.payload_size);
+                        bytes.extend(local(
+                            &cell.payload.content,
+                            cell.first_overflow_page_number,
+                        ));
+                        let entry = self.database.read_record(
+                            page_number,
+                            cell.payload,
+                            cell.payload_size,
+                            cell.first_overflow_page_number,
+                        )?;
+                        Ok(index_cell(entry, bytes))
+                    })
+                    .collect::<Result<_>>()?,
+                right_most: None,
+                index: true,
+            }),
+            BTreePage::IndexInterior(header, cells) => Ok(Node {
+                cells: cells
+                    .into_iter()
+                    .map(|cell| {
+                        let mut bytes = cell.left_child_pointer.to_be_bytes().to_vec();
+                        bytes.extend(encode_varint(cell.payload_size));
+                        bytes.extend(local(
+                            &cell.payload.content,
+                            cell.first_overflow_page_number,
+                        ));
+                        let entry = self.database.read_record(
+                            page_number,
+                            cell.payload,
+                            cell.payload_size,
+                            cell.first_overflow_page_number,
+                        )?;
+                        Ok(index_cell(entry, bytes))
+                    })
+                    .collect::<Result<_>>()?,
+                right_most: header.right_most_pointer,
+                index: true,
+            }),
+        }
+    }
+
+    /// Writes the cells of a node on a page, their content packed at the end of the page.
+    fn write_node(&mut self, page_number: u32, node: &Node) {
+        let offset = Self::header_offset(page_number);
+        let mut page = match page_number {
+            // page 1 starts with the database header
+            1 => self.page(1).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        page.resize(self.page_size(), 0);
+        page[offset..].fill(0);
+        page[offset] = match (node.index, node.is_leaf()) {
+            (false, true) => 0x0d,
+            (false, false) => 0x05,
+            (true, true) => 0x0a,
+            (true, false) => 0x02,
+        };
+        page[offset + 3..offset + 5].copy_from_slice(&(node.cells.len() as u16).to_be_bytes());
+        let mut pointer = offset + 8;
+        if let Some(right_most) = node.right_most {
+            page[offset + 8..offset + 12].copy_from_slice(&right_most.to_be_bytes());
+            pointer += 4;
+        }
+        let mut content = self.usable_size();
+        for cell in &node.cells {
+            content -= cell.bytes.len();
+            page[content..content + cell.bytes.len()].copy_from_slice(&cell.bytes);
+            page[pointer..pointer + 2].copy_from_slice(&(content as u16).to_be_bytes());
+            pointer += 2;
+        }
+        // a content area starting at 65536 is written as 0
+        page[offset + 5..offset + 7].copy_from_slice(&(content as u16).to_be_bytes());
+        self.pages.insert(page_number, page);
+    }
+
+    /// The rowid following the largest one of a table, found on the right-most leaf page
+    fn next_rowid(&mut self, root_page: u32) -> Result<i64> {
+        let mut node = self.node(root_page)?;
+        while let Some(right_most) = node.right_most {
+            node = self.node(right_most)?;
+        }
+        match node.cells.last() {
+            None => Ok(1),
+            Some(cell) if cell.rowid == i64::MAX => Err(Error::Full),
+            Some(cell) => Ok(cell.rowid + 1),
+        }
+    }
+
+    /// Whether a table holds a row with the given rowid
+    fn contains_rowid(&mut self, root_page: u32, rowid: i64) -> Result<bool> {
+        let mut page_number = root_page;
+        loop {
+            let node = self.node(page_number)?;
+            let position = node.cells.partition_point(|c| c.rowid < rowid);
+            if node.is_leaf() {
+                return Ok(node.cells.get(position).is_some_and(|c| c.rowid == rowid));
+            }
+            page_number = node.child(position);
+        }
+    }
+
+    /// Whether an index holds an entry starting with the given values. Its interior pages
+    /// holding entries too, the first entry not smaller than the values is either the one
+    /// dividing the children at the position they would be at, or in the child before it.
+    fn index_contains(&mut self, index: &IndexOrder, key: &[Value]) -> Result<bool> {
+        let encoding = self.database.header.encoding();
+        let mut page_number = index.root_page;
+        loop {
+            let node = self.node(page_number)?;
+            let compare = |cell: &Cell| index.compare(&cell.entry, key, encoding);
+            let position = node.cells.partition_point(|c| compare(c).is_lt());
+            if node.cells.get(position).is_some_and(|c| compare(c).is_eq()) {
+                return Ok(true);
+            }
+            if node.is_leaf() {
+                return Ok(false);
+            }
+            page_number = node.child(position);
+        }
+    }
+
+    /// Inserts a record into a table b-tree.
+    fn insert_record(&mut self, root_page: u32, rowid: i64, values: &[Value]) -> Result<i64> {
+        let record = encode_record_with(values, self.database.header.encoding());
+        let mut bytes = encode_varint(record.len() as u64);
+        bytes.extend(encode_varint(rowid as u64));
+        bytes.extend(self.spill(&record, true));
+        let cell = Cell {
+            rowid,
+            entry: Vec::new(),
+            bytes,
+        };
+        self.insert_cell(root_page, cell, &|c: &Cell, new: &Cell| {
+            c.rowid.cmp(&new.rowid)
+        })?;
+        Ok(rowid)
+    }
+
+    /// Inserts an entry into an index b-tree.
+    fn insert_entry(&mut self, index: &IndexOrder, entry: Vec<Value>) -> Result<()> {
+        let encoding = self.database.header.encoding();
+        let record = encode_record_with(&entry, encoding);
+        let mut bytes = encode_varint(record.len() as u64);
+        bytes.extend(self.spill(&record, false));
+        let cell = Cell {
+            rowid: entry.last().and_then(Value::as_i64).unwrap_or_default(),
+            entry,
+            bytes,
+        };
+        let compare = |c: &Cell, new: &Cell| index.compare(&c.entry, &new.entry, encoding);
+        self.insert_cell(index.root_page, cell, &compare)
+    }
+
+    /// The part of a payload stored in its cell, followed by the number of the first overflow
+    /// page when it doesn't fit on the page, the rest of it being written to overflow pages
+    fn spill(&mut self, payload: &[u8], is_table_leaf: bool) -> Vec<u8> {
+        let usable_size = self.usable_size();
+        let local = local_payload_size(payload.len() as u64, usable_size, is_table_leaf);
+        let mut bytes = payload[..local].to_vec();
+        let chunks = payload[local..].chunks(usable_size - 4).collect::<Vec<_>>();
+        let pages = chunks.iter().map(|_| self.allocate()).collect::<Vec<_>>();
+        for (position, chunk) in chunks.iter().enumerate() {
+            // overflow pages start with the number of the next one, 0 on the last one
+            let next = pages.get(position + 1).copied().unwrap_or(0);
+            let page = self.pages.get_mut(&pages[position]).unwrap();
+            page[..4].copy_from_slice(&next.to_be_bytes());
+            page[4..4 + chunk.len()].copy_from_slice(chunk);
+        }
+        if let Some(first) = pages.first() {
+            bytes.extend(first.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Inserts a cell into the leaf page of a b-tree its key belongs on, `compare` telling how
+    /// the key of a cell orders relative to that of the cell inserted. Pages it no longer fits
+    /// on are split, the pages they are split into being added to their parent, up to the
+    /// root, which moves to a new page when it doesn't fit, becoming its parent.
+    fn insert_cell(
+        &mut self,
+        root_page: u32,
+        cell: Cell,
+        compare: &dyn Fn(&Cell, &Cell) -> Ordering,
+    ) -> Result<()> {
+        // the interior pages descended through, with the position of the child taken
+        let mut path = Vec::new();
+        let mut page_number = root_page;
+        let mut node = self.take_node(page_number)?;
+        while !node.is_leaf() {
+            let position = node.cells.partition_point(|c| compare(c, &cell).is_lt());
+            let child = node.child(position);
+            path.push((page_number, node, position));
+            page_number = child;
+            node = self.take_node(page_number)?;
+        }
+        let position = node.cells.partition_point(|c| compare(c, &cell).is_lt());
+        if node
+            .cells
+            .get(position)
+            .is_some_and(|c| compare(c, &cell).is_eq())
+        {
+            // the pages taken out are put back as they were
+            self.nodes.insert(page_number, node);
+            for (page_number, node, _) in path {
+                self.nodes.insert(page_number, node);
+            }
+            return Err(Error::Constraint(String::from(
+                "UNIQUE constraint failed: rowid",
+            )));
+        }
+        node.cells.insert(position, cell);
+
+        let mut split = false;
+        loop {
+            if !split && node.size() <= self.available(page_number, &node) {
+                self.put_node(page_number, node);
+                // the pages above are unchanged
+                for (page_number, node, _) in path {
+                    self.nodes.insert(page_number, node);
+                }
+                return Ok(());
+            }
+            let Some((parent_number, mut parent, position)) = path.pop() else {
+                // the root stays on its page, its cells moving to a new child page
+                let child = self.allocate();
+                let root = Node {
+                    cells: Vec::new(),
+                    right_most: Some(child),
+                    index: node.index,
+                };
+                self.changed.insert(page_number);
+                path.push((page_number, root, 0));
+                page_number = child;
+                split = true;
+                continue;
+            };
+            // the last part stays on the page, the parent pointing to it already
+            let available = self.available(page_number, &node);
+            let mut parts = node.split(available);
+            let (last, _) = parts.pop().unwrap();
+            for (offset, (part, divider)) in parts.into_iter().enumerate() {
+                let part_page = self.allocate();
+                self.put_node(part_page, part);
+                let mut divider = divider.unwrap();
+                divider.set_child(part_page);
+                parent.cells.insert(position + offset, divider);
+            }
+            self.put_node(page_number, last);
+            node = parent;
+            page_number = parent_number;
+            split = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rows(database: &Database, table: &TableInformation) -> Vec<(u64, Vec<Value>)> {
+        database
+            .table_cursor(table.root_page as u32, false)
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn inserts_rows_across_pages() {
+        let mut database = Database::empty().unwrap();
+        let mut transaction = database.begin().unwrap();
+        let table = transaction
+            .create_table(
+                "t",
+                "CREATE TABLE t(id INTEGER PRIMARY KEY, note TEXT NOT NULL, n)",
+            )
+            .unwrap();
+        let note = |i: usize| {
+            "x".repeat(if i.is_multiple_of(100) {
+                10_000
+            } else {
+                i % 50
+            })
+        };
+        for i in 1..=3000 {
+            let rowid = transaction
+                .insert(&table, vec![Value::Null, Value::String(note(i))])
+                .unwrap();
+            assert_eq!(rowid, i as i64);
+        }
+        assert!(matches!(
+            transaction.insert(&table, vec![Value::Int64(7), Value::String(note(7))]),
+            Err(Error::Constraint(message)) if message == "UNIQUE constraint failed: t.id"
+        ));
+        assert!(matches!(
+            transaction.insert(&table, vec![Value::String(String::from("x"))]),
+            Err(Error::Mismatch)
+        ));
+        assert!(matches!(
+            transaction.insert(&table, vec![Value::Int64(5000), Value::Null]),
+            Err(Error::Constraint(message)) if message == "NOT NULL constraint failed: t.note"
+        ));
+        transaction.commit().unwrap();
+
+        let table = database.table_information("t").unwrap();
+        let rows = rows(&database, &table);
+        assert_eq!(rows.len(), 3000);
+        for (i, (rowid, values)) in rows.into_iter().enumerate() {
+            assert_eq!(rowid, i as u64 + 1);
+            assert_eq!(values[1..], [Value::String(note(i + 1)), Value::Null]);
+        }
+    }
+
+    #[test]
+    fn splits_pages_holding_rows_on_overflow_pages() {
+        let mut database = Database::empty().unwrap();
+        let mut transaction = database.begin().unwrap();
+        let table = transaction.create_table("t", "CREATE TABLE t(a)").unwrap();
+        // from rows fitting on the page to rows spilling over several overflow pages
+        let value = |i: usize| Value::Blob(vec![i as u8; 900 + i * 37 % 9000]);
+        for i in 0..400 {
+            transaction.insert(&table, vec![value(i)]).unwrap();
+        }
+        transaction.commit().unwrap();
+
+        let table = database.table_information("t").unwrap();
+        let rows = rows(&database, &table);
+        assert_eq!(rows.len(), 400);
+        for (i, (rowid, values)) in rows.into_iter().enumerate() {
+            assert_eq!(rowid, i as u64 + 1);
+            assert_eq!(values, [value(i)]);
+        }
+        let root = database.read_btree_page(table.root_page as u32).unwrap();
+        assert!(matches!(root, BTreePage::TableInterior(..)));
+    }
+
+    #[test]
+    fn updates_the_indexes_of_tables() {
+        // built by tests/fixtures/indexed.sql
+        let path = std::env::temp_dir().join(format!("resql-indexed-{}.db", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        fs::copy("tests/fixtures/indexed.db", &path).unwrap();
+        let mut database = Database::init_from_file(&path).unwrap();
+        let table = database.table_information("t").unwrap();
+        let row = |id: i64, code: &str, n: i64| {
+            vec![
+                Value::Int64(id),
+                Value::String(code.to_string()),
+                Value::Int64(n),
+            ]
+        };
+        let mut transaction = database.begin().unwrap();
+        assert!(matches!(
+            transaction.insert(&table, row(4, "b", 40)),
+            Err(Error::Constraint(message)) if message == "UNIQUE constraint failed: t.code"
+        ));
+        assert!(matches!(
+            transaction.insert(&table, row(2, "c", 40)),
+            Err(Error::Constraint(message)) if message == "UNIQUE constraint failed: t.id"
+        ));
+        // NULLs are distinct from one another
+        let rowid = transaction
+            .insert(&table, vec![Value::Null, Value::Null, Value::Int64(10)])
+            .unwrap();
+        assert_eq!(rowid, 4);
+        for id in 5..1000 {
+            transaction
+                .insert(&table, row(id, &format!("c{}", id), id % 50))
+                .unwrap();
+        }
+        assert!(matches!(
+            transaction.insert(&table, row(1000, "C999", 0)),
+            Err(Error::Constraint(message)) if message == "UNIQUE constraint failed: t.code"
+        ));
+        transaction.commit().unwrap();
+
+        let objects = database.list_objects().unwrap();
+        let entries = |name: &str| -> Vec<Vec<Value>> {
+            let object = objects.iter().find(|o| o.object_name == name).unwrap();
+            database
+                .index_cursor(object.root_page as u32, false)
+                .collect::<Result<_>>()
+                .unwrap()
+        };
+        let codes = entries("sqlite_autoindex_t_1");
+        assert_eq!(codes.len(), 999);
+        assert_eq!(
+            codes[..3],
+            [
+                [Value::Null, Value::Int64(3)],
+                [Value::Null, Value::Int64(4)],
+                [Value::String(String::from("a")), Value::Int64(1)]
+            ]
+        );
+        let by_n = entries("t_n");
+        assert_eq!(by_n.len(), 999);
+        assert!(by_n
+            .windows(2)
+            .all(|pair| pair[0][0] > pair[1][0]
+                || pair[0][0] == pair[1][0] && pair[0][1] < pair[1][1]));
+        assert_eq!(entries("sqlite_autoindex_t_2").len(), 999);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rolls_back_journals_of_commits_cut_short() {
+        let path = std::env::temp_dir().join(format!("resql-journal-{}.db", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let journal = format!("{}-journal", path);
+        fs::write(&path, super::super::empty_database()).unwrap();
+        let mut database = Database::init_from_file(&path).unwrap();
+        let mut transaction = database.begin().unwrap();
+        let table = transaction.create_table("t", "CREATE TABLE t(a)").unwrap();
+        for i in 0..100 {
+            transaction.insert(&table, vec![Value::Int64(i)]).unwrap();
+        }
+        transaction.commit().unwrap();
+        let before = (rows(&database, &table), fs::read(&path).unwrap());
+
+        // the commit stops having written the journal and some of the pages only
+        let mut transaction = database.begin().unwrap();
+        for i in 0..2000 {
+            let value = Value::String("x".repeat(i % 3000));
+            transaction.insert(&table, vec![value]).unwrap();
+        }
+        transaction.finish().unwrap();
+        transaction.write_journal().unwrap();
+        let pages = std::mem::take(&mut transaction.pages);
+        let page_size = transaction.page_size();
+        let mut file = transaction.database.db_file.borrow_mut();
+        file.lock(Lock::Exclusive).unwrap();
+        for (page_number, page) in pages.iter().step_by(2) {
+            file.write_all_at(page, page_number_to_offset(*page_number, page_size))
+                .unwrap();
+        }
+        drop(file);
+        drop(transaction);
+        drop(database);
+        assert!(Path::new(&journal).exists());
+
+        let database = Database::init_from_file(&path).unwrap();
+        assert!(!Path::new(&journal).exists());
+        assert_eq!((rows(&database, &table), fs::read(&path).unwrap()), before);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rolls_back_transactions_not_committed() {
+        let mut database = Database::empty().unwrap();
+        let mut transaction = database.begin().unwrap();
+        let table = transaction.create_table("t", "CREATE TABLE t(a)").unwrap();
+        transaction.insert(&table, vec![Value::Int64(1)]).unwrap();
+        drop(transaction);
+        assert!(database.table_information("t").is_err());
+    }
+}
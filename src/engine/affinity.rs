@@ -0,0 +1,290 @@
+use anyhow::Result;
+
+use crate::database::encoding::TextEncoding;
+use crate::database::page::btree::data::serial_types::Value;
+use crate::sql::Expression;
+
+use super::expression::{parse_numeric, to_numeric, to_text, Numeric, Scope};
+
+/// The type affinity of a declared type, which tells how values are converted to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Affinity {
+    Integer,
+    Text,
+    Blob,
+    Real,
+    Numeric,
+}
+
+impl Affinity {
+    /// Determines the affinity of a type name from the words it contains, following the rules
+    /// SQLite applies in order: INT, then CHAR, CLOB or TEXT, then BLOB or no type at all, then
+    /// REAL, FLOA or DOUB. Any other type has NUMERIC affinity.
+    pub fn of(type_name: &str) -> Affinity {
+        let type_name = type_name.to_ascii_uppercase();
+        let contains_any = |words: &[&str]| words.iter().any(|word| type_name.contains(word));
+        if type_name.contains("INT") {
+            Affinity::Integer
+        } else if contains_any(&["CHAR", "CLOB", "TEXT"]) {
+            Affinity::Text
+        } else if type_name.contains("BLOB") || type_name.trim().is_empty() {
+            Affinity::Blob
+        } else if contains_any(&["REAL", "FLOA", "DOUB"]) {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    /// Whether the affinity is INTEGER, REAL or NUMERIC, which all turn text holding a number
+    /// into that number
+    pub fn is_numeric(self) -> bool {
+        matches!(self, Affinity::Integer | Affinity::Real | Affinity::Numeric)
+    }
+}
+
+/// The affinity of an expression: that of the column it refers to, or of the type it is cast
+/// to, or of the column of a scalar subquery. COLLATE keeps the affinity of its operand, and
+/// other expressions have none.
+pub fn expression_affinity(expression: &Expression, scope: &Scope) -> Result<Option<Affinity>> {
+    Ok(match expression {
+        Expression::Column { table, name } => scope
+            .find(table.as_deref(), name)?
+            .and_then(|index| scope.columns[index].affinity),
+        Expression::Bound { affinity, .. } => *affinity,
+        Expression::Cast { type_name, .. } => Some(Affinity::of(type_name)),
+        Expression::Collate { expression, .. } => expression_affinity(expression, scope)?,
+        Expression::Subquery(query) => match &scope.subqueries {
+            Some(subqueries) => subqueries.column_type(query)?.affinity,
+            None => None,
+        },
+        _ => None,
+    })
+}
+
+/// The affinity the operands of a comparison are converted to before being compared: NUMERIC
+/// when both have an affinity and one of them is numeric, or else the affinity of the operand
+/// having one. `None` when the operands are compared as they are.
+pub fn comparison_affinity(left: Option<Affinity>, right: Option<Affinity>) -> Option<Affinity> {
+    let affinity = match (left, right) {
+        (Some(left), Some(right)) if left.is_numeric() || right.is_numeric() => Affinity::Numeric,
+        (Some(_), Some(_)) | (None, None) => return None,
+        (Some(affinity), None) | (None, Some(affinity)) => affinity,
+    };
+    match affinity {
+        Affinity::Blob => None,
+        Affinity::Text => Some(Affinity::Text),
+        _ => Some(Affinity::Numeric),
+    }
+}
+
+/// Converts the operands of a comparison to its affinity, as given by `comparison_affinity`.
+/// Numbers only become text when compared with text.
+pub fn apply_comparison_affinity(
+    affinity: Option<Affinity>,
+    left: Value,
+    right: Value,
+) -> (Value, Value) {
+    match affinity {
+        Some(Affinity::Text)
+            if !matches!(left, Value::String(_)) && !matches!(right, Value::String(_)) =>
+        {
+            (left, right)
+        }
+        Some(affinity) => (apply(left, affinity), apply(right, affinity)),
+        None => (left, right),
+    }
+}
+
+/// Converts a value compared with others to an affinity: text holding a well-formed number
+/// becomes that number for the numeric affinities, and numbers become text for TEXT. Other
+/// values are left as they are.
+pub fn apply(value: Value, affinity: Affinity) -> Value {
+    match (affinity, value) {
+        (Affinity::Text, value @ (Value::Null | Value::String(_) | Value::Blob(_))) => value,
+        (Affinity::Text, number) => Value::String(to_text(&number).unwrap_or_default()),
+        (affinity, Value::String(text)) if affinity.is_numeric() => match parse_numeric(&text) {
+            Some(number) => number.into_value(),
+            None => Value::String(text),
+        },
+        (_, value) => value,
+    }
+}
+
+/// Converts a value to the given affinity like `CAST` does. NULL stays NULL.
+///
+/// Text and blobs are read as the longest number they start with, 0 if there is none. Text
+/// converted to INTEGER only keeps the integer part of that number, and text converted to
+/// NUMERIC becomes an integer when the number it holds is one. Blobs are read as text, and text
+/// converted to a blob becomes, in the encoding of the database's text.
+pub fn cast(value: Value, affinity: Affinity, encoding: TextEncoding) -> Value {
+    let value = match value {
+        Value::Null => return Value::Null,
+        // invalid text is replaced, as it is wherever blobs are read as text
+        Value::Blob(b) if affinity != Affinity::Blob => {
+            Value::String(encoding.decode(&b, false).unwrap_or_default())
+        }
+        value => value,
+    };
+    match affinity {
+        Affinity::Integer => match &value {
+            Value::String(s) => Value::Int64(integer_prefix(s)),
+            // conversions of reals saturate at the bounds of 64 bit integers
+            Value::Float64(r) => Value::Int64(*r as i64),
+            other => Value::Int64(other.as_i64().unwrap_or(0)),
+        },
+        Affinity::Real => Value::Float64(to_numeric(&value).map_or(0.0, Numeric::as_real)),
+        Affinity::Numeric => match (&value, to_numeric(&value)) {
+            (Value::String(_), Some(Numeric::Real(r)))
+                if r.fract() == 0.0 && r >= i64::MIN as f64 && r < -(i64::MIN as f64) =>
+            {
+                Value::Int64(r as i64)
+            }
+            (_, Some(Numeric::Real(r))) => Value::Float64(r),
+            (_, Some(Numeric::Integer(i))) => Value::Int64(i),
+            (_, None) => Value::Null,
+        },
+        Affinity::Text => match value {
+            Value::String(_) => value,
+            other => Value::String(to_text(&other).unwrap_or_default()),
+        },
+        Affinity::Blob => match value {
+            Value::Blob(_) => value,
+            Value::String(s) => Value::Blob(encoding.encode(&s)),
+            other => Value::Blob(encoding.encode(&to_text(&other).unwrap_or_default())),
+        },
+    }
+}
+
+/// The integer a text starts with, after any leading spaces, saturating at the bounds of 64 bit
+/// integers
+fn integer_prefix(text: &str) -> i64 {
+    let text = text.trim_start();
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    digits
+        .bytes()
+        .take_while(u8::is_ascii_digit)
+        .fold(0i64, |value, digit| {
+            let digit = (digit - b'0') as i64;
+            if negative {
+                value.saturating_mul(10).saturating_sub(digit)
+            } else {
+                value.saturating_mul(10).saturating_add(digit)
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Values compare equal whatever their storage, so their variants are compared as well
+    fn assert_identical(actual: Value, expected: Value) {
+        assert_eq!(format!("{:?}", actual), format!("{:?}", expected));
+    }
+
+    #[test]
+    fn determines_affinity_from_type_names() {
+        assert_eq!(Affinity::of("BIGINT"), Affinity::Integer);
+        assert_eq!(Affinity::of("varchar(10)"), Affinity::Text);
+        assert_eq!(Affinity::of(""), Affinity::Blob);
+        assert_eq!(Affinity::of("DOUBLE PRECISION"), Affinity::Real);
+        assert_eq!(Affinity::of("DECIMAL(10,5)"), Affinity::Numeric);
+        // INT wins over the other rules, even inside another word
+        assert_eq!(Affinity::of("FLOATING POINT"), Affinity::Integer);
+    }
+
+    #[test]
+    fn casts_like_sqlite() {
+        use TextEncoding::{Utf16le, Utf8};
+        let text = |s: &str| Value::String(s.to_string());
+        assert_identical(
+            cast(text("12abc"), Affinity::Integer, Utf8),
+            Value::Int64(12),
+        );
+        assert_identical(
+            cast(text("  -3.9"), Affinity::Integer, Utf8),
+            Value::Int64(-3),
+        );
+        assert_identical(cast(text("1e3"), Affinity::Integer, Utf8), Value::Int64(1));
+        assert_identical(
+            cast(text("9999999999999999999"), Affinity::Integer, Utf8),
+            Value::Int64(i64::MAX),
+        );
+        assert_identical(
+            cast(Value::Float64(1e20), Affinity::Integer, Utf8),
+            Value::Int64(i64::MAX),
+        );
+        assert_identical(cast(text("3.5"), Affinity::Real, Utf8), Value::Float64(3.5));
+        assert_identical(
+            cast(text("1e3"), Affinity::Numeric, Utf8),
+            Value::Int64(1000),
+        );
+        assert_identical(cast(text("3.0"), Affinity::Numeric, Utf8), Value::Int64(3));
+        assert_identical(
+            cast(Value::Float64(4.0), Affinity::Numeric, Utf8),
+            Value::Float64(4.0),
+        );
+        assert_identical(
+            cast(text("9999999999999999999"), Affinity::Numeric, Utf8),
+            Value::Float64(1e19),
+        );
+        assert_identical(cast(Value::Float64(2.5), Affinity::Text, Utf8), text("2.5"));
+        assert_identical(
+            cast(Value::Int64(1), Affinity::Blob, Utf8),
+            Value::Blob(b"1".to_vec()),
+        );
+        assert_identical(cast(Value::Null, Affinity::Integer, Utf8), Value::Null);
+        // blobs hold text as the database encodes it
+        assert_identical(
+            cast(text("Zed"), Affinity::Blob, Utf16le),
+            Value::Blob(b"Z\0e\0d\0".to_vec()),
+        );
+        assert_identical(
+            cast(Value::Blob(b"a\0".to_vec()), Affinity::Text, Utf16le),
+            text("a"),
+        );
+        assert_identical(
+            cast(
+                Value::Blob(b"4\x002\0".to_vec()),
+                Affinity::Integer,
+                Utf16le,
+            ),
+            Value::Int64(42),
+        );
+    }
+
+    #[test]
+    fn converts_operands_of_comparisons() {
+        let text = |s: &str| Value::String(s.to_string());
+        let (integer, text_affinity) = (Some(Affinity::Integer), Some(Affinity::Text));
+        assert_eq!(
+            comparison_affinity(integer, text_affinity),
+            Some(Affinity::Numeric)
+        );
+        assert_eq!(comparison_affinity(text_affinity, text_affinity), None);
+        assert_eq!(comparison_affinity(Some(Affinity::Blob), None), None);
+        assert_eq!(comparison_affinity(None, text_affinity), text_affinity);
+        assert_eq!(
+            comparison_affinity(Some(Affinity::Real), None),
+            Some(Affinity::Numeric)
+        );
+
+        // text becomes a number only when it is nothing but one
+        assert_identical(apply(text(" 12 "), Affinity::Numeric), Value::Int64(12));
+        assert_identical(apply(text("1e2"), Affinity::Integer), Value::Float64(100.0));
+        assert_identical(apply(text("12abc"), Affinity::Numeric), text("12abc"));
+        assert_identical(apply(Value::Float64(2.5), Affinity::Text), text("2.5"));
+        assert_identical(apply(Value::Int8(3), Affinity::Blob), Value::Int8(3));
+        // numbers only become text when compared with text
+        let (left, right) =
+            apply_comparison_affinity(text_affinity, Value::Int64(10), Value::Int64(9));
+        assert!(left > right);
+        let (left, right) = apply_comparison_affinity(text_affinity, Value::Int64(10), text("9"));
+        assert!(left < right);
+    }
+}
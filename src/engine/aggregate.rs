@@ -0,0 +1,901 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::iter::Peekable;
+
+use anyhow::{bail, Result};
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::sql::{Expression, FunctionArguments};
+
+use super::collation::{collation_of, Collation, HashKeys};
+use super::expression::{evaluate, to_numeric, to_text, validate, Numeric, Scope};
+use super::function::json;
+use super::function::user::{Aggregate, AggregateFactory, Functions};
+use super::select::{read_in_full, Rows};
+use super::sort::{compare_sort_keys, SortKey, SortedRows, Sorter};
+
+/// How much memory the groups of a hash aggregation may take before falling back to sorting
+/// the rows by group
+pub const DEFAULT_AGGREGATE_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AggregateFunction {
+    CountStar,
+    Count,
+    Sum,
+    Total,
+    Avg,
+    Min,
+    Max,
+    GroupConcat,
+    JsonGroupArray,
+    JsonGroupObject,
+    /// An aggregate defined by the application
+    User,
+}
+
+impl AggregateFunction {
+    /// Looks up the aggregate function called by `name`. `min` and `max` are only aggregates
+    /// when given a single argument, with more they are scalar functions. Functions defined by
+    /// the application override the built-in ones.
+    fn lookup(
+        name: &str,
+        arguments: &FunctionArguments,
+        functions: &Functions,
+    ) -> Option<AggregateFunction> {
+        let count = arguments.expressions().len();
+        if functions.aggregate(name, count).is_some() {
+            return Some(AggregateFunction::User);
+        }
+        if functions.scalar(name, count).is_some() {
+            return None;
+        }
+        let function = match (name.to_ascii_lowercase().as_str(), arguments) {
+            ("count", FunctionArguments::Star) => AggregateFunction::CountStar,
+            ("count", _) if count == 0 => AggregateFunction::CountStar,
+            ("count", _) if count == 1 => AggregateFunction::Count,
+            ("sum", _) if count == 1 => AggregateFunction::Sum,
+            ("total", _) if count == 1 => AggregateFunction::Total,
+            ("avg", _) if count == 1 => AggregateFunction::Avg,
+            ("min", _) if count == 1 => AggregateFunction::Min,
+            ("max", _) if count == 1 => AggregateFunction::Max,
+            ("group_concat", _) if count == 1 || count == 2 => AggregateFunction::GroupConcat,
+            ("string_agg", _) if count == 2 => AggregateFunction::GroupConcat,
+            ("json_group_array", _) if count == 1 => AggregateFunction::JsonGroupArray,
+            ("json_group_object", _) if count == 2 => AggregateFunction::JsonGroupObject,
+            _ => return None,
+        };
+        Some(function)
+    }
+}
+
+/// Returns whether the expression is a call to an aggregate function. Aggregate functions
+/// called with an OVER clause are window functions instead.
+pub fn is_aggregate_call(expression: &Expression, functions: &Functions) -> bool {
+    match expression {
+        Expression::Function {
+            name,
+            arguments,
+            over: None,
+            ..
+        } => is_aggregate_function(name, arguments, functions),
+        _ => false,
+    }
+}
+
+/// Returns whether calling the function with these arguments computes an aggregate.
+pub fn is_aggregate_function(
+    name: &str,
+    arguments: &FunctionArguments,
+    functions: &Functions,
+) -> bool {
+    AggregateFunction::lookup(name, arguments, functions).is_some()
+}
+
+/// Fails when a call to a function named like an aggregate function doesn't take as many
+/// arguments as the aggregate. `min` and `max` are scalar functions with more than one.
+pub fn check_aggregate_arguments(
+    name: &str,
+    arguments: &FunctionArguments,
+    functions: &Functions,
+) -> Result<()> {
+    let aggregates = [
+        "count",
+        "sum",
+        "total",
+        "avg",
+        "group_concat",
+        "string_agg",
+        "json_group_array",
+        "json_group_object",
+    ];
+    let lowercase_name = name.to_ascii_lowercase();
+    if AggregateFunction::lookup(name, arguments, functions).is_none()
+        && functions
+            .scalar(name, arguments.expressions().len())
+            .is_none()
+        && (aggregates.contains(&lowercase_name.as_str())
+            || (["min", "max"].contains(&lowercase_name.as_str())
+                && arguments.expressions().is_empty()))
+    {
+        bail!("wrong number of arguments to function {}()", name);
+    }
+    Ok(())
+}
+
+/// Collects the distinct aggregate function calls found in an expression, in the order they
+/// appear. Aggregates can't be nested.
+pub fn collect_aggregates(
+    expression: &Expression,
+    functions: &Functions,
+    aggregates: &mut Vec<Expression>,
+) -> Result<()> {
+    if let Expression::Function {
+        name,
+        distinct,
+        arguments,
+        over: None,
+    } = expression
+    {
+        check_aggregate_arguments(name, arguments, functions)?;
+        if AggregateFunction::lookup(name, arguments, functions).is_some() {
+            if *distinct && arguments.expressions().len() != 1 {
+                bail!("DISTINCT aggregates must have exactly one argument");
+            }
+            if let Some(nested) = arguments
+                .expressions()
+                .iter()
+                .find_map(|argument| find_aggregate(argument, functions))
+            {
+                bail!("misuse of aggregate function {}()", nested);
+            }
+            if !aggregates.contains(expression) {
+                aggregates.push(expression.clone());
+            }
+            return Ok(());
+        }
+    }
+    for child in expression.children() {
+        collect_aggregates(child, functions, aggregates)?;
+    }
+    Ok(())
+}
+
+/// Returns whether the expression calls an aggregate function anywhere.
+pub fn contains_aggregate(expression: &Expression, functions: &Functions) -> bool {
+    find_aggregate(expression, functions).is_some()
+}
+
+/// Finds the name of the first aggregate function called by the expression.
+fn find_aggregate<'e>(expression: &'e Expression, functions: &Functions) -> Option<&'e str> {
+    match expression {
+        Expression::Function { name, .. } if is_aggregate_call(expression, functions) => Some(name),
+        _ => expression
+            .children()
+            .into_iter()
+            .find_map(|child| find_aggregate(child, functions)),
+    }
+}
+
+fn approximate_size(values: &[Value]) -> usize {
+    values
+        .iter()
+        .map(|value| {
+            std::mem::size_of::<Value>()
+                + match value {
+                    Value::String(s) => s.len(),
+                    Value::Blob(b) => b.len(),
+                    _ => 0,
+                }
+        })
+        .sum()
+}
+
+/// The running sum of `sum`, `total` and `avg`. Integers are summed exactly until a real
+/// value shows up or the sum overflows, reals use Kahan-Babuska-Neumaier summation.
+#[derive(Clone, Default)]
+struct Sum {
+    count: i64,
+    integer: i64,
+    approximate: bool,
+    overflow: bool,
+    real: f64,
+    compensation: f64,
+}
+
+impl Sum {
+    fn add_real(&mut self, value: f64) {
+        let total = self.real + value;
+        if self.real.abs() >= value.abs() {
+            self.compensation += (self.real - total) + value;
+        } else {
+            self.compensation += (value - total) + self.real;
+        }
+        self.real = total;
+    }
+
+    fn switch_to_real(&mut self) {
+        if !self.approximate {
+            self.approximate = true;
+            self.real = 0.0;
+            self.compensation = 0.0;
+            self.add_real(self.integer as f64);
+        }
+    }
+
+    fn add(&mut self, value: &Value) {
+        let Some(numeric) = numeric_type(value) else {
+            return;
+        };
+        self.count += 1;
+        match numeric {
+            Numeric::Integer(i) if !self.approximate => match self.integer.checked_add(i) {
+                Some(sum) => self.integer = sum,
+                None => {
+                    self.overflow = true;
+                    self.switch_to_real();
+                    self.add_real(i as f64);
+                }
+            },
+            other => {
+                self.switch_to_real();
+                self.add_real(other.as_real());
+            }
+        }
+    }
+
+    /// Takes a value back out of the sum, as when it leaves a window frame. Like SQLite, the
+    /// sum stays approximate once a real value went through it.
+    fn remove(&mut self, value: &Value) {
+        let Some(numeric) = numeric_type(value) else {
+            return;
+        };
+        self.count -= 1;
+        match numeric {
+            Numeric::Integer(i) if !self.approximate => match self.integer.checked_sub(i) {
+                Some(sum) => self.integer = sum,
+                None => {
+                    self.overflow = true;
+                    self.switch_to_real();
+                    self.add_real(-(i as f64));
+                }
+            },
+            other => self.add_real(-other.as_real()),
+        }
+    }
+
+    fn real_total(&self) -> f64 {
+        if self.approximate {
+            self.real + self.compensation
+        } else {
+            self.integer as f64
+        }
+    }
+}
+
+/// The numeric value used by `sum`: integers and text holding a well-formed integer count as
+/// integers, anything else as a real.
+fn numeric_type(value: &Value) -> Option<Numeric> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => match s.trim().parse::<i64>() {
+            Ok(i) => Some(Numeric::Integer(i)),
+            Err(_) => Some(Numeric::Real(to_numeric(value)?.as_real())),
+        },
+        Value::Blob(_) | Value::Float64(_) => Some(Numeric::Real(to_numeric(value)?.as_real())),
+        other => to_numeric(other),
+    }
+}
+
+#[derive(Clone)]
+enum State {
+    Count(i64),
+    Sum(Sum),
+    Extreme(Option<Value>),
+    GroupConcat(Option<String>),
+    /// The elements of the array or the members of the object built by `json_group_array`
+    /// and `json_group_object`, as JSON text
+    Json(Vec<String>),
+    User(UserState),
+}
+
+/// The state of an aggregate defined by the application: an instance of it, created when it
+/// is fed its first row
+struct UserState {
+    new: AggregateFactory,
+    instance: Option<Box<dyn Aggregate>>,
+}
+
+impl UserState {
+    fn instance(&mut self) -> &mut Box<dyn Aggregate> {
+        self.instance.get_or_insert_with(|| (self.new)())
+    }
+}
+
+/// Accumulators are cloned before being fed rows, to start a group or a window frame, so the
+/// instance isn't cloned.
+impl Clone for UserState {
+    fn clone(&self) -> UserState {
+        debug_assert!(self.instance.is_none(), "only new aggregates are cloned");
+        UserState {
+            new: self.new.clone(),
+            instance: None,
+        }
+    }
+}
+
+/// A call to an aggregate function, along with its running state for one group or one window
+/// frame
+#[derive(Clone)]
+pub struct Accumulator {
+    function: AggregateFunction,
+    arguments: Vec<Expression>,
+    /// The argument values seen so far, for DISTINCT aggregates
+    seen: Option<(HashSet<Vec<u8>>, HashKeys)>,
+    /// How the first argument compares, for DISTINCT aggregates, `min()` and `max()`
+    collation: Collation,
+    state: State,
+}
+
+impl Accumulator {
+    pub fn new(call: &Expression, scope: &Scope) -> Result<Accumulator> {
+        let functions = scope.functions();
+        let Expression::Function {
+            name,
+            distinct,
+            arguments,
+            ..
+        } = call
+        else {
+            unreachable!("aggregates are function calls");
+        };
+        let function = AggregateFunction::lookup(name, arguments, functions)
+            .expect("aggregates are known functions");
+        // arguments are checked before any row is read, aggregates not being allowed in them
+        for argument in arguments.expressions() {
+            validate(argument, scope)?;
+        }
+        let state = match function {
+            AggregateFunction::CountStar | AggregateFunction::Count => State::Count(0),
+            AggregateFunction::Sum | AggregateFunction::Total | AggregateFunction::Avg => {
+                State::Sum(Sum::default())
+            }
+            AggregateFunction::Min | AggregateFunction::Max => State::Extreme(None),
+            AggregateFunction::GroupConcat => State::GroupConcat(None),
+            AggregateFunction::JsonGroupArray | AggregateFunction::JsonGroupObject => {
+                State::Json(Vec::new())
+            }
+            AggregateFunction::User => State::User(UserState {
+                new: functions
+                    .aggregate(name, arguments.expressions().len())
+                    .expect("aggregates are known functions")
+                    .factory(),
+                instance: None,
+            }),
+        };
+        let collation = match arguments.expressions().first() {
+            Some(argument) => collation_of(argument, scope)?,
+            None => Collation::Binary,
+        };
+        Ok(Accumulator {
+            function,
+            arguments: arguments.expressions().to_vec(),
+            seen: distinct.then(|| (HashSet::new(), HashKeys::new(vec![collation.clone()]))),
+            collation,
+            state,
+        })
+    }
+
+    /// Feeds a row to the aggregate, returning roughly how many bytes its state grew by, and
+    /// whether the row holds a new minimum or maximum.
+    pub fn update(&mut self, scope: &Scope, row: &[Value]) -> Result<(usize, bool)> {
+        let arguments = self
+            .arguments
+            .iter()
+            .map(|argument| evaluate(argument, scope, row))
+            .collect::<Result<Vec<_>>>()?;
+        let mut growth = 0;
+        if let Some((seen, keys)) = &mut self.seen {
+            if arguments[0].is_null() {
+                return Ok((0, false));
+            }
+            let key = keys.key(&arguments[..1]);
+            growth += key.len();
+            if !seen.insert(key) {
+                return Ok((0, false));
+            }
+        }
+        let mut new_extreme = false;
+        match &mut self.state {
+            State::Count(count) => {
+                if self.function == AggregateFunction::CountStar || !arguments[0].is_null() {
+                    *count += 1;
+                }
+            }
+            State::Sum(sum) => sum.add(&arguments[0]),
+            State::Extreme(extreme) => {
+                if !arguments[0].is_null() {
+                    let wanted = match self.function {
+                        AggregateFunction::Min => Ordering::Less,
+                        _ => Ordering::Greater,
+                    };
+                    new_extreme = extreme.as_ref().is_none_or(|current| {
+                        self.collation.compare(&arguments[0], current) == wanted
+                    });
+                    if new_extreme {
+                        growth += approximate_size(&arguments[..1]);
+                        *extreme = Some(arguments[0].clone());
+                    }
+                }
+            }
+            State::GroupConcat(text) => {
+                if let Some(value) = to_text(&arguments[0]) {
+                    match text {
+                        None => *text = Some(value),
+                        Some(text) => {
+                            // the separator is evaluated for each row, NULL means none
+                            let separator = match arguments.get(1) {
+                                Some(separator) => to_text(separator).unwrap_or_default(),
+                                None => String::from(","),
+                            };
+                            text.push_str(&separator);
+                            text.push_str(&value);
+                        }
+                    }
+                    growth += approximate_size(&arguments);
+                }
+            }
+            State::Json(items) => {
+                let value = arguments.last().expect("JSON aggregates have arguments");
+                let is_json = json::is_json(&self.arguments[arguments.len() - 1], value);
+                let item = match self.function {
+                    AggregateFunction::JsonGroupArray => Some(json::array_element(value, is_json)?),
+                    _ => json::object_member(&arguments[0], value, is_json)?,
+                };
+                if let Some(item) = item {
+                    growth += item.len();
+                    items.push(item);
+                }
+            }
+            State::User(state) => {
+                state.instance().step(&arguments)?;
+                growth += approximate_size(&arguments);
+            }
+        }
+        Ok((growth, new_extreme))
+    }
+
+    /// Whether rows can be taken back out of the aggregate with `remove`
+    pub fn is_invertible(&self) -> bool {
+        match &self.state {
+            State::Count(_) | State::Sum(_) => true,
+            State::User(state) => (state.new)().has_inverse(),
+            _ => false,
+        }
+    }
+
+    /// Takes a row fed to the aggregate back out of it, which only counts, sums and aggregates
+    /// defined by the application with an inverse support.
+    pub fn remove(&mut self, scope: &Scope, row: &[Value]) -> Result<()> {
+        let arguments = self
+            .arguments
+            .iter()
+            .map(|argument| evaluate(argument, scope, row))
+            .collect::<Result<Vec<_>>>()?;
+        match &mut self.state {
+            State::Count(count) => {
+                if self.function == AggregateFunction::CountStar || !arguments[0].is_null() {
+                    *count -= 1;
+                }
+            }
+            State::Sum(sum) => sum.remove(&arguments[0]),
+            State::User(state) => state.instance().inverse(&arguments)?,
+            _ => unreachable!("only counts, sums and some user aggregates are invertible"),
+        }
+        Ok(())
+    }
+
+    /// The value of the aggregate over the rows fed so far
+    pub fn finish(&self) -> Result<Value> {
+        Ok(match (self.function, &self.state) {
+            (_, State::Count(count)) => Value::Int64(*count),
+            (AggregateFunction::Sum, State::Sum(sum)) => {
+                if sum.count == 0 {
+                    Value::Null
+                } else if sum.overflow {
+                    bail!("integer overflow")
+                } else if sum.approximate {
+                    Value::Float64(sum.real_total())
+                } else {
+                    Value::Int64(sum.integer)
+                }
+            }
+            (AggregateFunction::Total, State::Sum(sum)) => Value::Float64(sum.real_total()),
+            (_, State::Sum(sum)) if sum.count == 0 => Value::Null,
+            (_, State::Sum(sum)) => Value::Float64(sum.real_total() / sum.count as f64),
+            (_, State::Extreme(extreme)) => extreme.clone().unwrap_or(Value::Null),
+            (_, State::GroupConcat(text)) => text.clone().map_or(Value::Null, Value::String),
+            (AggregateFunction::JsonGroupArray, State::Json(elements)) => {
+                Value::String(format!("[{}]", elements.join(",")))
+            }
+            (_, State::Json(members)) => Value::String(format!("{{{}}}", members.join(","))),
+            (_, State::User(state)) => match &state.instance {
+                Some(instance) => instance.finalize()?,
+                // the aggregate is finalized without rows, as over an empty group
+                None => (state.new)().finalize()?,
+            },
+        })
+    }
+}
+
+/// The rows of one group being aggregated
+struct Group {
+    key: Vec<Value>,
+    /// The row bare columns take their values from: the first row of the group, or the row
+    /// holding the minimum or maximum when the query has a single `min()` or `max()`. Only
+    /// missing for the group of an aggregate query without GROUP BY over no rows.
+    representative: Option<Vec<Value>>,
+    accumulators: Vec<Accumulator>,
+}
+
+/// Aggregates rows into groups, computing aggregate function calls over each group.
+struct Aggregator<'a> {
+    scope: Scope<'a>,
+    group_by: Vec<Expression>,
+    /// How the values of the GROUP BY expressions compare
+    collations: Vec<Collation>,
+    accumulators: Vec<Accumulator>,
+    /// The aggregate whose minimum or maximum row provides the values of bare columns
+    extreme_aggregate: Option<usize>,
+}
+
+impl<'a> Aggregator<'a> {
+    fn new(
+        scope: &Scope<'a>,
+        group_by: &[Expression],
+        aggregates: &[Expression],
+    ) -> Result<Aggregator<'a>> {
+        let accumulators = aggregates
+            .iter()
+            .map(|call| Accumulator::new(call, scope))
+            .collect::<Result<Vec<_>>>()?;
+        let extremes: Vec<usize> = accumulators
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| matches!(a.function, AggregateFunction::Min | AggregateFunction::Max))
+            .map(|(i, _)| i)
+            .collect();
+        Ok(Aggregator {
+            scope: scope.clone(),
+            group_by: group_by.to_vec(),
+            collations: group_by
+                .iter()
+                .map(|expression| collation_of(expression, scope))
+                .collect::<Result<_>>()?,
+            accumulators,
+            extreme_aggregate: match extremes.as_slice() {
+                [index] if aggregates.len() == 1 => Some(*index),
+                _ => None,
+            },
+        })
+    }
+
+    fn group_key(&self, row: &[Value]) -> Result<Vec<Value>> {
+        self.group_by
+            .iter()
+            .map(|expression| evaluate(expression, &self.scope, row))
+            .collect()
+    }
+
+    fn new_group(&self, key: Vec<Value>, row: Option<Vec<Value>>) -> Group {
+        Group {
+            key,
+            representative: row,
+            accumulators: self.accumulators.clone(),
+        }
+    }
+
+    /// Feeds a row to its group, returning roughly how many bytes the group grew by.
+    fn update(&self, group: &mut Group, row: &[Value]) -> Result<usize> {
+        let mut growth = 0;
+        if group.representative.is_none() {
+            group.representative = Some(row.to_vec());
+            growth += approximate_size(row);
+        }
+        for (index, accumulator) in group.accumulators.iter_mut().enumerate() {
+            let (accumulator_growth, new_extreme) = accumulator.update(&self.scope, row)?;
+            growth += accumulator_growth;
+            if new_extreme && self.extreme_aggregate == Some(index) {
+                group.representative = Some(row.to_vec());
+            }
+        }
+        Ok(growth)
+    }
+
+    /// Turns a group into an output row: its representative row followed by the values of the
+    /// aggregates.
+    fn finish(&self, group: Group) -> Result<Vec<Value>> {
+        let mut row = group
+            .representative
+            .unwrap_or_else(|| vec![Value::Null; self.scope.columns.len()]);
+        for accumulator in &group.accumulators {
+            row.push(accumulator.finish()?);
+        }
+        Ok(row)
+    }
+
+    fn group_keys(&self) -> Vec<SortKey> {
+        self.collations
+            .iter()
+            .cloned()
+            .map(SortKey::ascending)
+            .collect()
+    }
+
+    /// Aggregates the rows in a hash table of groups. Gives up, returning `None`, once the
+    /// groups take more memory than the budget allows.
+    fn hash_aggregate(&self, rows: Rows<'_>, memory_budget: usize) -> Result<Option<Vec<Group>>> {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut positions: HashMap<Vec<u8>, usize> = HashMap::new();
+        let mut keys = HashKeys::new(self.collations.clone());
+        let mut used_memory = 0;
+        if self.group_by.is_empty() {
+            // without GROUP BY there is always a single group, even without any row
+            groups.push(self.new_group(vec![], None));
+            positions.insert(keys.key(&[]), 0);
+        }
+        for row in rows {
+            let row = row?;
+            let key = self.group_key(&row)?;
+            let hashed_key = keys.key(&key);
+            let position = match positions.get(&hashed_key) {
+                Some(position) => *position,
+                None => {
+                    used_memory +=
+                        std::mem::size_of::<Group>() + hashed_key.len() + approximate_size(&key);
+                    positions.insert(hashed_key, groups.len());
+                    groups.push(self.new_group(key, None));
+                    groups.len() - 1
+                }
+            };
+            used_memory += self.update(&mut groups[position], &row)?;
+            if used_memory > memory_budget {
+                return Ok(None);
+            }
+        }
+        let keys = self.group_keys();
+        groups.sort_by(|a, b| compare_sort_keys(&keys, &a.key, &b.key));
+        Ok(Some(groups))
+    }
+
+    /// Sorts the rows by group, then aggregates consecutive rows of the same group.
+    fn sort_aggregate(self, rows: Rows<'_>, memory_budget: usize) -> Result<SortedGroups<'a>> {
+        let mut sorter = Sorter::new(self.group_keys(), memory_budget);
+        for row in rows {
+            let row = row?;
+            let key = self.group_key(&row)?;
+            // the group key is kept along with the row to find where groups end
+            let mut entry = key.clone();
+            entry.extend(row);
+            sorter.push(key, entry)?;
+        }
+        Ok(SortedGroups {
+            rows: sorter.finish()?.peekable(),
+            produced_any: false,
+            aggregator: self,
+        })
+    }
+}
+
+/// The groups of a sort-based aggregation, produced one at a time
+struct SortedGroups<'a> {
+    aggregator: Aggregator<'a>,
+    rows: Peekable<SortedRows>,
+    produced_any: bool,
+}
+
+impl SortedGroups<'_> {
+    fn next_group(&mut self) -> Result<Option<Vec<Value>>> {
+        let key_length = self.aggregator.group_by.len();
+        let Some(first) = self.rows.next().transpose()? else {
+            // without GROUP BY, an empty input still yields a single group
+            if !self.produced_any && key_length == 0 {
+                self.produced_any = true;
+                let group = self.aggregator.new_group(vec![], None);
+                return self.aggregator.finish(group).map(Some);
+            }
+            return Ok(None);
+        };
+        self.produced_any = true;
+        let mut first = first;
+        let row = first.split_off(key_length);
+        let mut group = self.aggregator.new_group(first, None);
+        self.aggregator.update(&mut group, &row)?;
+        let keys = self.aggregator.group_keys();
+        while let Some(Ok(next)) = self.rows.peek() {
+            if compare_sort_keys(&keys, &next[..key_length], &group.key) != Ordering::Equal {
+                break;
+            }
+            let mut next = self.rows.next().transpose()?.unwrap_or_default();
+            let row = next.split_off(key_length);
+            self.aggregator.update(&mut group, &row)?;
+        }
+        self.aggregator.finish(group).map(Some)
+    }
+}
+
+impl Iterator for SortedGroups<'_> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_group().transpose()
+    }
+}
+
+/// Groups the rows produced by `input` by the values of the `group_by` expressions, and
+/// computes the `aggregates` function calls over each group. Each output row holds the values
+/// of a row of the group (as laid out by `scope`) followed by the values of the aggregates,
+/// and groups come out in the order of their keys.
+///
+/// Groups are first gathered in a hash table. When they outgrow the memory budget, the input
+/// is read again and sorted by group instead, which can spill to disk.
+pub fn aggregate<'a>(
+    input: impl Fn() -> Result<Rows<'a>>,
+    scope: &Scope<'a>,
+    group_by: &[Expression],
+    aggregates: &[Expression],
+    memory_budget: usize,
+) -> Result<Rows<'a>> {
+    let aggregator = Aggregator::new(scope, group_by, aggregates)?;
+    let rows = input()?;
+    let rows = match aggregator.hash_aggregate(rows, memory_budget) {
+        Ok(Some(groups)) => groups
+            .into_iter()
+            .map(|group| aggregator.finish(group))
+            .collect::<Result<Vec<_>>>()
+            .map(|rows| Box::new(rows.into_iter().map(Ok)) as Rows),
+        Ok(None) => input()
+            .and_then(|rows| aggregator.sort_aggregate(rows, memory_budget))
+            .map(|rows| Box::new(rows) as Rows),
+        Err(e) => Err(e),
+    };
+    Ok(read_in_full(rows))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{aggregate, collect_aggregates};
+    use crate::database::page::btree::data::serial_types::Value;
+    use crate::engine::expression::{Scope, ScopeColumn};
+    use crate::engine::function::user::NO_FUNCTIONS;
+    use crate::sql::{sql_query, Expression};
+
+    fn scope() -> Scope<'static> {
+        Scope {
+            columns: ["city", "age"]
+                .iter()
+                .map(|name| ScopeColumn {
+                    table: String::from("people"),
+                    name: name.to_string(),
+                    hidden: false,
+                    merged: false,
+                    collation: String::from("BINARY"),
+                    affinity: None,
+                })
+                .collect(),
+            ..Scope::default()
+        }
+    }
+
+    fn rows() -> Vec<Vec<Value>> {
+        let city = |name: &str| Value::String(name.to_string());
+        vec![
+            vec![city("Oslo"), Value::Int64(30)],
+            vec![city("Lima"), Value::Int64(20)],
+            vec![Value::Null, Value::Int64(50)],
+            vec![city("Oslo"), Value::Float64(30.0)],
+            vec![city("Lima"), Value::Null],
+            vec![city("Oslo"), Value::Int64(41)],
+        ]
+    }
+
+    /// Aggregates the test rows, rendering each group as its values separated by `|`
+    fn run(group_by: &[&str], calls: &[&str], memory_budget: usize) -> Vec<String> {
+        let group_by: Vec<Expression> = group_by
+            .iter()
+            .map(|e| sql_query::expression(e).unwrap())
+            .collect();
+        let mut aggregates = Vec::new();
+        for call in calls {
+            let call = sql_query::expression(call).unwrap();
+            collect_aggregates(&call, NO_FUNCTIONS, &mut aggregates).unwrap();
+        }
+        let input = || Ok(Box::new(rows().into_iter().map(Ok)) as super::Rows<'static>);
+        aggregate(input, &scope(), &group_by, &aggregates, memory_budget)
+            .unwrap()
+            .map(|row| {
+                row.unwrap()
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>()
+                    .join("|")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aggregates_groups_in_key_order() {
+        let groups = run(
+            &["city"],
+            &["count(*)", "count(age)", "sum(age)", "avg(age)", "max(age)"],
+            1 << 20,
+        );
+        assert_eq!(
+            groups,
+            vec![
+                "|50|1|1|50|50.0|50",
+                "Lima|20|2|1|20|20.0|20",
+                "Oslo|30|3|3|101.0|33.6666666666667|41",
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_sorting_when_out_of_memory() {
+        let calls = [
+            "count(DISTINCT age)",
+            "group_concat(age, ';')",
+            "min(age)",
+            "string_agg(age, '-')",
+        ];
+        let sorted = run(&["city"], &calls, 0);
+        assert_eq!(sorted, run(&["city"], &calls, 1 << 20));
+        assert_eq!(sorted[2], "Oslo|30|2|30;30.0;41|30|30-30.0-41");
+    }
+
+    #[test]
+    fn reports_errors_reading_the_input_as_rows() {
+        for memory_budget in [1 << 20, 0] {
+            let input = || {
+                let rows = [i64::MAX, 1].map(|age| Ok(vec![Value::Null, Value::Int64(age)]));
+                Ok(Box::new(rows.into_iter()) as super::Rows<'static>)
+            };
+            let mut aggregates = Vec::new();
+            let call = sql_query::expression("sum(age)").unwrap();
+            collect_aggregates(&call, NO_FUNCTIONS, &mut aggregates).unwrap();
+            let mut groups = aggregate(input, &scope(), &[], &aggregates, memory_budget).unwrap();
+            let error = groups.next().unwrap().err().unwrap();
+            assert_eq!(error.to_string(), "integer overflow");
+        }
+        let call = sql_query::expression("sum(nothing)").unwrap();
+        let mut aggregates = Vec::new();
+        collect_aggregates(&call, NO_FUNCTIONS, &mut aggregates).unwrap();
+        let input = || Ok(Box::new(std::iter::empty()) as super::Rows<'static>);
+        assert!(aggregate(input, &scope(), &[], &aggregates, 0).is_err());
+    }
+
+    #[test]
+    fn aggregates_empty_input_into_a_single_group() {
+        for memory_budget in [1 << 20, 0] {
+            let input = || Ok(Box::new(std::iter::empty()) as super::Rows<'static>);
+            let mut aggregates = Vec::new();
+            for call in ["count(*)", "sum(age)", "total(age)"] {
+                let call = sql_query::expression(call).unwrap();
+                collect_aggregates(&call, NO_FUNCTIONS, &mut aggregates).unwrap();
+            }
+            let groups: Vec<Vec<Value>> =
+                aggregate(input, &scope(), &[], &aggregates, memory_budget)
+                    .unwrap()
+                    .map(|row| row.unwrap())
+                    .collect();
+            assert_eq!(groups.len(), 1);
+            assert_eq!(
+                groups[0][2..]
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>(),
+                ["0", "", "0.0"]
+            );
+        }
+    }
+}
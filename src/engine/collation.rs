@@ -0,0 +1,402 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+
+use crate::database;
+use crate::database::encoding::TextEncoding;
+use crate::database::page::btree::data::serial_types::Value;
+use crate::sql::{Expression, IndexedColumn, UnaryOperator};
+
+use super::expression::{hashable_key, Scope};
+
+/// The name of the collating sequence columns have unless declared with another one
+pub const BINARY: &str = "BINARY";
+
+/// A collating sequence, which compares text values. Other values compare the same whatever
+/// the collation.
+#[derive(Clone, Default)]
+pub enum Collation {
+    /// Byte by byte
+    #[default]
+    Binary,
+    /// Byte by byte, ASCII letters being folded to lower case
+    NoCase,
+    /// Byte by byte, trailing spaces being ignored
+    RTrim,
+    User(Rc<UserCollation>),
+}
+
+impl PartialEq for Collation {
+    fn eq(&self, other: &Collation) -> bool {
+        match (self, other) {
+            (Collation::User(a), Collation::User(b)) => Rc::ptr_eq(a, b),
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl std::fmt::Debug for Collation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Collation {
+    pub fn name(&self) -> &str {
+        match self {
+            Collation::Binary => BINARY,
+            Collation::NoCase => "NOCASE",
+            Collation::RTrim => "RTRIM",
+            Collation::User(collation) => &collation.name,
+        }
+    }
+
+    pub fn compare_text(&self, left: &str, right: &str) -> Ordering {
+        match self {
+            Collation::Binary => left.as_bytes().cmp(right.as_bytes()),
+            Collation::NoCase => {
+                let folded = |text: &'_ str| {
+                    text.bytes()
+                        .map(|byte| byte.to_ascii_lowercase())
+                        .collect::<Vec<_>>()
+                };
+                folded(left).cmp(&folded(right))
+            }
+            Collation::RTrim => left
+                .trim_end_matches(' ')
+                .as_bytes()
+                .cmp(right.trim_end_matches(' ').as_bytes()),
+            Collation::User(collation) => (collation.compare)(left, right),
+        }
+    }
+
+    /// Compares two non-NULL values in the order `Value` sorts in, text being compared with the
+    /// collation.
+    pub fn compare(&self, left: &Value, right: &Value) -> Ordering {
+        match (self, left, right) {
+            (Collation::Binary, _, _) => left.cmp(right),
+            (_, Value::String(a), Value::String(b)) => self.compare_text(a, b),
+            _ => left.cmp(right),
+        }
+    }
+
+    /// Compares two non-NULL values the way the indexes of a database storing text with the
+    /// given encoding are ordered. SQLite's BINARY collation compares the stored bytes, while
+    /// the other built-in collations work on UTF-8 text whatever the encoding.
+    pub fn compare_stored(&self, left: &Value, right: &Value, encoding: TextEncoding) -> Ordering {
+        match (self, left, right) {
+            (Collation::Binary, Value::String(a), Value::String(b)) => encoding.compare(a, b),
+            _ => self.compare(left, right),
+        }
+    }
+
+    /// Text which is the same for all the text values comparing equal with the collation, when
+    /// there is such a thing: collations defined by the application only tell how values
+    /// compare.
+    pub fn key<'t>(&self, text: &'t str) -> Option<Cow<'t, str>> {
+        match self {
+            Collation::Binary => Some(Cow::Borrowed(text)),
+            Collation::NoCase if text.bytes().any(|byte| byte.is_ascii_uppercase()) => {
+                Some(Cow::Owned(text.to_ascii_lowercase()))
+            }
+            Collation::NoCase => Some(Cow::Borrowed(text)),
+            Collation::RTrim => Some(Cow::Borrowed(text.trim_end_matches(' '))),
+            Collation::User(_) => None,
+        }
+    }
+}
+
+/// A collating sequence defined by the application
+pub struct UserCollation {
+    name: String,
+    compare: CollationImplementation,
+}
+
+/// Compares two texts for a collation defined by the application
+pub type CollationImplementation = Box<dyn Fn(&str, &str) -> Ordering>;
+
+/// The collating sequences defined by the application on a database
+#[derive(Default)]
+pub struct Collations {
+    user: Vec<Rc<UserCollation>>,
+}
+
+/// The collations known where no database is at hand, as when evaluating constants
+pub const NO_COLLATIONS: &Collations = &Collations { user: Vec::new() };
+
+impl Collations {
+    /// Defines a collating sequence, replacing any collation with the same name, built-in
+    /// collations included.
+    pub fn add(&mut self, name: &str, compare: CollationImplementation) -> database::Result<()> {
+        if name.is_empty() {
+            return Err(database::Error::Misuse);
+        }
+        self.user
+            .retain(|collation| !collation.name.eq_ignore_ascii_case(name));
+        self.user.push(Rc::new(UserCollation {
+            name: name.to_string(),
+            compare,
+        }));
+        Ok(())
+    }
+
+    /// Looks up a collating sequence by name, among those defined by the application first.
+    pub fn find(&self, name: &str) -> Result<Collation> {
+        if let Some(collation) = self
+            .user
+            .iter()
+            .find(|collation| collation.name.eq_ignore_ascii_case(name))
+        {
+            return Ok(Collation::User(collation.clone()));
+        }
+        [Collation::Binary, Collation::NoCase, Collation::RTrim]
+            .into_iter()
+            .find(|collation| collation.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("no such collation sequence: {}", name))
+    }
+}
+
+/// Whether a COLLATE operator is part of the expression
+fn has_explicit_collation(expression: &Expression) -> bool {
+    matches!(expression, Expression::Collate { .. })
+        || expression
+            .children()
+            .into_iter()
+            .any(has_explicit_collation)
+}
+
+/// The name of the collating sequence of an expression, like SQLite finds it: the one given
+/// by a COLLATE operator, the leftmost one when the expression is made of several, or else the
+/// collation of the column the expression is. Casts and unary `+` keep the collation of their
+/// operand.
+pub fn explicit_collation(expression: &Expression) -> Option<&str> {
+    match expression {
+        Expression::Collate { collation, .. } => Some(collation),
+        Expression::Cast { expression, .. }
+        | Expression::Unary {
+            operator: UnaryOperator::Plus,
+            operand: expression,
+        } => explicit_collation(expression),
+        Expression::Column { .. } => None,
+        _ => expression
+            .children()
+            .into_iter()
+            .find(|child| has_explicit_collation(child))
+            .and_then(explicit_collation),
+    }
+}
+
+/// The name of the collating sequence of an expression, if it has one: see `explicit_collation`
+pub fn declared_collation(expression: &Expression, scope: &Scope) -> Option<String> {
+    explicit_collation(expression)
+        .or_else(|| column_collation(expression, scope))
+        .map(str::to_string)
+}
+
+/// The collating sequence of an expression, BINARY if it has none: see `explicit_collation`
+pub fn collation_of(expression: &Expression, scope: &Scope) -> Result<Collation> {
+    let name = explicit_collation(expression).or_else(|| column_collation(expression, scope));
+    scope.collations().find(name.unwrap_or(BINARY))
+}
+
+/// The collation of the column an expression is, through casts and unary `+`. Columns of
+/// enclosing queries bound to their value keep theirs.
+fn column_collation<'s>(expression: &'s Expression, scope: &'s Scope) -> Option<&'s str> {
+    match expression {
+        Expression::Column { table, name } => {
+            let index = scope.find(table.as_deref(), name).ok()??;
+            Some(&scope.columns[index].collation)
+        }
+        Expression::Bound { collation, .. } => Some(collation),
+        Expression::Cast { expression, .. }
+        | Expression::Unary {
+            operator: UnaryOperator::Plus,
+            operand: expression,
+        } => column_collation(expression, scope),
+        _ => None,
+    }
+}
+
+/// The collating sequence functions comparing their arguments compare them with: the one of
+/// the leftmost argument having one, BINARY if none has
+pub fn arguments_collation(arguments: &[Expression], scope: &Scope) -> Result<Collation> {
+    let name = arguments.iter().find_map(|argument| {
+        explicit_collation(argument).or_else(|| column_collation(argument, scope))
+    });
+    scope.collations().find(name.unwrap_or(BINARY))
+}
+
+/// The collating sequence two values are compared with: the one given with a COLLATE operator
+/// on the left operand, or else on the right one, or else the collation of the left operand if
+/// it is a column, or else of the right one. Columns have the BINARY collation unless declared
+/// with another one.
+pub fn comparison_collation(
+    left: &Expression,
+    right: &Expression,
+    scope: &Scope,
+) -> Result<Collation> {
+    let name = explicit_collation(left)
+        .or_else(|| explicit_collation(right))
+        .or_else(|| column_collation(left, scope))
+        .or_else(|| column_collation(right, scope));
+    scope.collations().find(name.unwrap_or(BINARY))
+}
+
+/// Encodes values like `hashable_key`, values comparing equal with the collation of their
+/// position being encoded the same way. Collations defined by the application don't tell
+/// which texts compare equal without comparing them, so text compared with one of them is
+/// encoded as the first text seen comparing equal to it.
+#[derive(Clone)]
+pub struct HashKeys {
+    collations: Vec<Collation>,
+    /// The texts seen so far at each position, for the positions compared with a collation
+    /// defined by the application
+    representatives: Vec<Vec<String>>,
+}
+
+impl HashKeys {
+    pub fn new(collations: Vec<Collation>) -> HashKeys {
+        let representatives = vec![Vec::new(); collations.len()];
+        HashKeys {
+            collations,
+            representatives,
+        }
+    }
+
+    /// The key of values being added to a hash table
+    pub fn key(&mut self, values: &[Value]) -> Vec<u8> {
+        for (i, value) in values.iter().enumerate() {
+            if let (Value::String(text), Some(Collation::User(_))) = (value, self.collations.get(i))
+            {
+                if self.representative(i, text).is_none() {
+                    self.representatives[i].push(text.clone());
+                }
+            }
+        }
+        self.probe(values)
+    }
+
+    /// The key of values being looked up in a hash table, which only matches the keys of
+    /// values added before
+    pub fn probe(&self, values: &[Value]) -> Vec<u8> {
+        if self
+            .collations
+            .iter()
+            .all(|collation| *collation == Collation::Binary)
+        {
+            return hashable_key(values);
+        }
+        let normalized: Vec<Value> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| match (value, self.collations.get(i)) {
+                (Value::String(text), Some(collation)) => Value::String(
+                    match collation.key(text) {
+                        Some(key) => key,
+                        None => {
+                            Cow::Borrowed(self.representative(i, text).unwrap_or(text).as_str())
+                        }
+                    }
+                    .into_owned(),
+                ),
+                _ => value.clone(),
+            })
+            .collect();
+        hashable_key(&normalized)
+    }
+
+    /// The text seen before at a position comparing equal to the given text
+    fn representative(&self, position: usize, text: &str) -> Option<&String> {
+        self.representatives[position]
+            .iter()
+            .find(|r| self.collations[position].compare_text(r, text) == Ordering::Equal)
+    }
+}
+
+/// The name of the collating sequence the entries of an index are ordered by in a column: the
+/// one given by the index definition, or else the one the column is declared with. `scope` is
+/// the scope of the table, as made by `Scope::for_table`.
+pub fn index_collation<'s>(column: &'s IndexedColumn, scope: &'s Scope) -> &'s str {
+    match (&column.collation, scope.find(None, &column.name)) {
+        (Some(collation), _) => collation,
+        (None, Ok(Some(position))) => &scope.columns[position].collation,
+        (None, _) => BINARY,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::Database;
+    use crate::engine::select::execute;
+    use crate::sql::sql_query;
+
+    #[test]
+    fn compares_text_with_the_built_in_collations() {
+        assert_eq!(Collation::Binary.compare_text("a", "B"), Ordering::Greater);
+        assert_eq!(Collation::NoCase.compare_text("a", "B"), Ordering::Less);
+        assert_eq!(
+            Collation::NoCase.compare_text("ABC", "abc"),
+            Ordering::Equal
+        );
+        // only ASCII letters are folded
+        assert_ne!(Collation::NoCase.compare_text("É", "é"), Ordering::Equal);
+        assert_eq!(Collation::RTrim.compare_text("a  ", "a"), Ordering::Equal);
+        assert_ne!(Collation::RTrim.compare_text(" a", "a"), Ordering::Equal);
+        // values other than text compare the same whatever the collation
+        assert_eq!(
+            Collation::NoCase.compare(&Value::Int64(2), &Value::String(String::from("1"))),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn hashes_values_comparing_equal_the_same_way() {
+        let text = |text: &str| [Value::String(text.to_string())];
+        let mut keys = HashKeys::new(vec![Collation::NoCase]);
+        assert_eq!(keys.key(&text("Abc")), keys.probe(&text("aBC")));
+        assert_ne!(keys.key(&text("Abc")), keys.probe(&text("abd")));
+
+        let mut collations = Collations::default();
+        collations
+            .add("first", Box::new(|a, b| a[..1].cmp(&b[..1])))
+            .unwrap();
+        let mut keys = HashKeys::new(vec![collations.find("FIRST").unwrap()]);
+        assert_eq!(keys.probe(&text("ab")), hashable_key(&text("ab")));
+        let key = keys.key(&text("ab"));
+        assert_eq!(keys.key(&text("ac")), key);
+        assert_eq!(keys.probe(&text("ax")), key);
+        assert_ne!(keys.probe(&text("ba")), key);
+    }
+
+    #[test]
+    fn compares_with_collations_defined_by_the_application() {
+        let mut database = Database::init_from_file("sample.db").unwrap();
+        database
+            .create_collation("reverse", |a, b| b.cmp(a))
+            .unwrap();
+        let query = |sql: &str| -> Result<Vec<String>> {
+            let statement = sql_query::select_statement(sql)?;
+            let rows = execute(&database, &statement, &Default::default())?
+                .map(|row| Ok(row?[0].to_string()))
+                .collect();
+            rows
+        };
+        assert_eq!(
+            query("SELECT name FROM apples WHERE id < 3 ORDER BY name COLLATE reverse").unwrap(),
+            vec!["Granny Smith", "Fuji"]
+        );
+        assert_eq!(
+            query("SELECT 'a' COLLATE reverse < 'b'").unwrap(),
+            vec!["0"]
+        );
+        assert_eq!(
+            query("SELECT 1 ORDER BY 'a' COLLATE nothing")
+                .unwrap_err()
+                .to_string(),
+            "no such collation sequence: nothing"
+        );
+    }
+}
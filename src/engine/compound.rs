@@ -0,0 +1,222 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, Result};
+use itertools::Itertools;
+
+use crate::database::Database;
+use crate::sql::{CompoundOperator, Expression, SelectStatement};
+
+use super::collation::{explicit_collation, Collation, HashKeys};
+use super::cte::CommonTables;
+use super::expression::Scope;
+use super::join::FromClause;
+use super::select::{
+    apply_limit, column_names, column_types, evaluate_limit, execute_select, ordinal,
+    result_column_reference, result_columns, sort_key, sorter, Projected, Rows,
+};
+use super::sort::{compare_sort_keys, SortKey, Sorter, DEFAULT_SORT_MEMORY_BUDGET};
+
+/// Executes a compound SELECT, whose WITH clause is already part of `tables`.
+///
+/// The SELECTs are combined from left to right. UNION ALL appends the rows of the SELECT on
+/// its right to the rows on its left. UNION, INTERSECT and EXCEPT only keep distinct rows, and
+/// return them sorted like SQLite does, before ORDER BY and LIMIT apply to the combined rows.
+pub fn execute<'a>(
+    database: &'a Database,
+    statement: &'a SelectStatement,
+    tables: &CommonTables,
+) -> Result<Rows<'a>> {
+    let columns = column_names(database, statement, tables)?.len();
+    for term in &statement.compound {
+        if column_names(database, &term.select, tables)?.len() != columns {
+            bail!(
+                "SELECTs to the left and right of {} do not have the same number of result \
+                 columns",
+                term.operator
+            );
+        }
+    }
+    let order_by = resolve_order_by(database, statement, tables, columns)?;
+    let (offset, limit) = evaluate_limit(statement.limit.as_ref(), &Scope::default())?;
+    // rows are compared with the collations of the result columns
+    let collations = column_types(database, statement, tables)?
+        .iter()
+        .map(|column_type| database.collations().find(&column_type.collation))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut rows = execute_select(database, statement, tables, &[], None)?;
+    for term in &statement.compound {
+        let right = execute_select(database, &term.select, tables, &[], None)?;
+        rows = match term.operator {
+            CompoundOperator::UnionAll => Box::new(rows.chain(right)),
+            CompoundOperator::Union => distinct(Box::new(rows.chain(right)), &collations)?,
+            CompoundOperator::Intersect | CompoundOperator::Except => {
+                let keep_members = term.operator == CompoundOperator::Intersect;
+                let mut keys = HashKeys::new(collations.clone());
+                let members = right
+                    .map(|row| row.map(|row| keys.key(&row)))
+                    .collect::<Result<HashSet<_>>>()?;
+                let kept = rows.filter(move |row| match row {
+                    Ok(row) => members.contains(&keys.probe(row)) == keep_members,
+                    Err(_) => true,
+                });
+                distinct(Box::new(kept), &collations)?
+            }
+        };
+    }
+
+    if order_by.is_empty() {
+        return Ok(apply_limit(rows, offset, limit));
+    }
+    let keys = order_by.iter().map(|(_, key)| key.clone()).collect();
+    let mut sorter = sorter(keys, offset, limit);
+    for row in rows {
+        let row = row?;
+        let key = order_by
+            .iter()
+            .map(|(position, _)| row[*position].clone())
+            .collect();
+        sorter.push(key, row)?;
+    }
+    Ok(apply_limit(Box::new(sorter.finish()?), offset, limit))
+}
+
+/// Sorts the rows, keeping one row of each group of rows equal with the given collations.
+/// SQLite keeps the last one.
+fn distinct<'a>(rows: Rows<'a>, collations: &[Collation]) -> Result<Rows<'a>> {
+    let keys: Vec<SortKey> = collations.iter().cloned().map(SortKey::ascending).collect();
+    let mut sorter = Sorter::new(keys.clone(), DEFAULT_SORT_MEMORY_BUDGET);
+    for row in rows {
+        let row = row?;
+        sorter.push(row.clone(), row)?;
+    }
+    Ok(Box::new(sorter.finish()?.coalesce(
+        move |previous, row| match (previous, row) {
+            (Ok(previous), Ok(row))
+                if compare_sort_keys(&keys, &previous, &row) == Ordering::Equal =>
+            {
+                Ok(Ok(row))
+            }
+            (previous, row) => Err((previous, row)),
+        },
+    )))
+}
+
+/// Resolves the ORDER BY terms of a compound SELECT to the positions of the result columns
+/// they sort by, along with how they sort. A term is either the position of a result column,
+/// or matches a result column of one of the SELECTs, looked at from left to right: it is its
+/// name, or the same expression.
+pub fn resolve_order_by(
+    database: &Database,
+    statement: &SelectStatement,
+    tables: &CommonTables,
+    columns: usize,
+) -> Result<Vec<(usize, SortKey)>> {
+    let selects: Vec<&SelectStatement> = std::iter::once(statement)
+        .chain(statement.compound.iter().map(|term| &term.select))
+        .collect();
+    let types = column_types(database, statement, tables)?;
+    let mut order_by = Vec::with_capacity(statement.order_by.len());
+    for (index, term) in statement.order_by.iter().enumerate() {
+        let position = match result_column_reference(term) {
+            Some(position) if position < 1 || position as usize > columns => bail!(
+                "{} ORDER BY term out of range - should be between 1 and {}",
+                ordinal(index + 1),
+                columns
+            ),
+            Some(position) => position as usize - 1,
+            None => {
+                let mut matching = None;
+                for select in &selects {
+                    matching = matching_column(database, select, tables, &term.expression)?;
+                    if matching.is_some() {
+                        break;
+                    }
+                }
+                matching.ok_or_else(|| {
+                    anyhow!(
+                        "{} ORDER BY term does not match any column in the result set",
+                        ordinal(index + 1)
+                    )
+                })?
+            }
+        };
+        // the result column is sorted with its own collation, unless the term gives another
+        let collation = match explicit_collation(&term.expression) {
+            Some(name) => name,
+            None => &types[position].collation,
+        };
+        let collation = database.collations().find(collation)?;
+        order_by.push((position, sort_key(term, collation)));
+    }
+    Ok(order_by)
+}
+
+/// The position of the result column of a single SELECT an ORDER BY term matches, if any
+fn matching_column(
+    database: &Database,
+    select: &SelectStatement,
+    tables: &CommonTables,
+    expression: &Expression,
+) -> Result<Option<usize>> {
+    if let Expression::Column { table: None, name } = expression {
+        let names = column_names(database, select, tables)?;
+        if let Some(position) = names.iter().position(|c| c.eq_ignore_ascii_case(name)) {
+            return Ok(Some(position));
+        }
+    }
+    let scope = FromClause::plan(database, select.from_target.as_ref(), tables)?.scope;
+    // columns match when they are the same column of the SELECT's tables, however written
+    let same = |selected: &Expression| match (selected, expression) {
+        (
+            Expression::Column { table, name },
+            Expression::Column {
+                table: other_table,
+                name: other_name,
+            },
+        ) => match scope.find(table.as_deref(), name) {
+            Ok(Some(position)) => {
+                scope.find(other_table.as_deref(), other_name).ok() == Some(Some(position))
+            }
+            _ => false,
+        },
+        _ => selected == expression,
+    };
+    Ok(result_columns(&select.selectables, &scope)?
+        .iter()
+        .position(|(_, projected)| match projected {
+            Projected::Expression(selected) => same(selected),
+            Projected::Column(_) => false,
+        }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::page::btree::data::serial_types::Value;
+
+    #[test]
+    fn keeps_the_last_of_equal_rows_in_sorted_order() {
+        let rows = vec![
+            vec![Value::String("b".to_string())],
+            vec![Value::Int64(1)],
+            vec![Value::Null],
+            vec![Value::Float64(1.0)],
+            vec![Value::String("b".to_string())],
+        ];
+        let distinct_rows: Vec<Vec<Value>> =
+            distinct(Box::new(rows.into_iter().map(Ok)), &[Collation::Binary])
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+        assert_eq!(
+            distinct_rows,
+            vec![
+                vec![Value::Null],
+                vec![Value::Float64(1.0)],
+                vec![Value::String("b".to_string())],
+            ]
+        );
+    }
+}
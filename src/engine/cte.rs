@@ -0,0 +1,447 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use anyhow::{bail, Result};
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::database::Database;
+use crate::sql::{
+    CommonTableExpression, CompoundOperator, CompoundTerm, SelectStatement, Targetable, WithClause,
+};
+
+use super::collation::HashKeys;
+use super::compound::resolve_order_by;
+use super::expression::Scope;
+use super::select::{column_names, column_types, evaluate_limit, execute, ColumnType, Rows};
+use super::sort::{PriorityQueue, SortKey};
+
+/// A table defined by a common table expression of a WITH clause
+pub struct CommonTable {
+    pub name: String,
+    columns: Vec<String>,
+    /// How the values of the columns compare
+    types: Vec<ColumnType>,
+    content: Content,
+}
+
+enum Content {
+    /// The columns of the table are being worked out from its first query, which can't read
+    /// from the table
+    Defining,
+    /// The rows of the table are those the definition yields, worked out as they are read
+    Definition {
+        definition: CommonTableExpression,
+        /// The tables the definition can read from, the table itself aside
+        tables: CommonTables,
+        production: RefCell<Option<Production>>,
+    },
+    /// The working table of a recursive common table expression: the row its recursive query
+    /// runs on
+    Rows(Vec<Vec<Value>>),
+}
+
+/// The rows of a common table worked out so far, and how to work out the following ones.
+/// A recursive common table may have no end, in which case it is only read until the query
+/// reading it stops asking for rows.
+struct Production {
+    rows: Vec<Vec<Value>>,
+    recursion: Option<Box<Recursion>>,
+}
+
+/// Works out the rows of a recursive common table expression: a compound SELECT where the
+/// SELECTs following some UNION [ALL] read from the table itself.
+///
+/// The rows of the SELECTs before them, the initial rows, go through a queue, ordered by the
+/// ORDER BY clause of the compound SELECT if there is one. Each row taken out of the queue is
+/// added to the table, until there are as many as LIMIT asks for, and the recursive SELECTs
+/// are run on it, as if the table only held that row. The rows they return are queued in turn.
+/// With UNION rather than UNION ALL, rows queued once before are left out.
+struct Recursion {
+    /// The SELECTs reading from the table
+    selects: Vec<SelectStatement>,
+    /// The common tables they can read from, the table itself aside
+    tables: CommonTables,
+    name: String,
+    columns: Vec<String>,
+    types: Vec<ColumnType>,
+    all: bool,
+    /// The positions of the columns the queue is ordered by
+    order_by: Vec<usize>,
+    offset: usize,
+    limit: Option<usize>,
+    queue: PriorityQueue,
+    queued: HashSet<Vec<u8>>,
+    queued_keys: HashKeys,
+    /// How many rows were taken out of the queue
+    taken: usize,
+}
+
+impl CommonTable {
+    pub fn columns(&self) -> Result<&[String]> {
+        match self.content {
+            Content::Defining => bail!("circular reference: {}", self.name),
+            _ => Ok(&self.columns),
+        }
+    }
+
+    pub fn types(&self) -> &[ColumnType] {
+        &self.types
+    }
+
+    /// The rows of the table
+    pub fn scan<'a>(self: &Rc<Self>, database: &'a Database) -> Rows<'a> {
+        let table = self.clone();
+        let mut index = 0;
+        Box::new(std::iter::from_fn(move || {
+            let row = table.row(database, index).transpose();
+            index += 1;
+            row
+        }))
+    }
+
+    /// The row at the given position, working out the rows up to it if need be
+    fn row(&self, database: &Database, index: usize) -> Result<Option<Vec<Value>>> {
+        let (definition, tables, production) = match &self.content {
+            Content::Defining => bail!("circular reference: {}", self.name),
+            Content::Rows(rows) => return Ok(rows.get(index).cloned()),
+            Content::Definition {
+                definition,
+                tables,
+                production,
+            } => (definition, tables, production),
+        };
+        let mut production = production.borrow_mut();
+        if production.is_none() {
+            *production = Some(self.produce(database, definition, tables)?);
+        }
+        let Some(production) = production.as_mut() else {
+            unreachable!("the production was just started");
+        };
+        while production.rows.len() <= index {
+            let Some(recursion) = &mut production.recursion else {
+                break;
+            };
+            match recursion.next(database)? {
+                Some(row) => production.rows.push(row),
+                None => production.recursion = None,
+            }
+        }
+        Ok(production.rows.get(index).cloned())
+    }
+
+    /// Runs the query defining the table, or its initial SELECTs when it is recursive
+    fn produce(
+        &self,
+        database: &Database,
+        definition: &CommonTableExpression,
+        tables: &CommonTables,
+    ) -> Result<Production> {
+        let query = definition.query.as_ref();
+        let references: Vec<usize> = query
+            .compound
+            .iter()
+            .map(|term| {
+                term.select
+                    .from_target
+                    .as_ref()
+                    .map_or(0, |target| self.references(target))
+            })
+            .collect();
+        let Some(start) = references.iter().position(|count| *count > 0) else {
+            // reading from the table anywhere else is a circular reference
+            let defining = tables.with_table(CommonTable {
+                name: self.name.clone(),
+                columns: Vec::new(),
+                types: Vec::new(),
+                content: Content::Defining,
+            });
+            return Ok(Production {
+                rows: execute(database, query, &defining)?.collect::<Result<_>>()?,
+                recursion: None,
+            });
+        };
+        let recursive_terms = &query.compound[start..];
+        for (term, count) in recursive_terms.iter().zip(&references[start..]) {
+            // only SELECTs reading the table once can follow the first recursive one
+            let union = matches!(
+                term.operator,
+                CompoundOperator::Union | CompoundOperator::UnionAll
+            );
+            if *count > 1 {
+                bail!("multiple references to recursive table: {}", self.name);
+            }
+            if !union || *count == 0 {
+                bail!("circular reference: {}", self.name);
+            }
+        }
+
+        let tables = tables.with_clause(database, query.with.as_ref())?;
+        let empty_table =
+            working_table(&tables, &self.name, &self.columns, &self.types, Vec::new());
+        for term in recursive_terms {
+            if column_names(database, &term.select, &empty_table)?.len() != self.columns.len() {
+                bail!(
+                    "SELECTs to the left and right of {} do not have the same number of result \
+                     columns",
+                    term.operator
+                );
+            }
+        }
+        let order_by = resolve_order_by(database, query, &empty_table, self.columns.len())?;
+        let (offset, limit) = evaluate_limit(query.limit.as_ref(), &Scope::default())?;
+        let initial = SelectStatement {
+            with: None,
+            compound: query.compound[..start].to_vec(),
+            order_by: vec![],
+            limit: None,
+            ..query.clone()
+        };
+
+        let keys: Vec<SortKey> = order_by.iter().map(|(_, key)| key.clone()).collect();
+        // UNION compares rows with the collations of the columns
+        let collations = self
+            .types
+            .iter()
+            .map(|column_type| database.collations().find(&column_type.collation))
+            .collect::<Result<_>>()?;
+        let mut recursion = Recursion {
+            selects: recursive_terms
+                .iter()
+                .map(|term| term.select.clone())
+                .collect(),
+            tables: tables.clone(),
+            name: self.name.clone(),
+            columns: self.columns.clone(),
+            types: self.types.clone(),
+            all: recursive_terms
+                .iter()
+                .all(|term| term.operator == CompoundOperator::UnionAll),
+            order_by: order_by.iter().map(|(position, _)| *position).collect(),
+            offset,
+            limit,
+            queue: PriorityQueue::new(keys),
+            queued: HashSet::new(),
+            queued_keys: HashKeys::new(collations),
+            taken: 0,
+        };
+        for row in execute(database, &initial, &tables)? {
+            recursion.enqueue(row?);
+        }
+        Ok(Production {
+            rows: Vec::new(),
+            recursion: Some(Box::new(recursion)),
+        })
+    }
+
+    /// How many times a FROM clause reads from the table
+    fn references(&self, target: &Targetable) -> usize {
+        references(&self.name, target)
+    }
+}
+
+impl Recursion {
+    fn enqueue(&mut self, row: Vec<Value>) {
+        if !self.all && !self.queued.insert(self.queued_keys.key(&row)) {
+            return;
+        }
+        let key = self.order_by.iter().map(|i| row[*i].clone()).collect();
+        self.queue.push(key, row);
+    }
+
+    /// The next row of the table, if any
+    fn next(&mut self, database: &Database) -> Result<Option<Vec<Value>>> {
+        loop {
+            // a recursion may go on without ever reading from the database
+            database.check_interrupt()?;
+            if self
+                .limit
+                .is_some_and(|limit| self.taken >= self.offset.saturating_add(limit))
+            {
+                return Ok(None);
+            }
+            let Some(row) = self.queue.pop() else {
+                return Ok(None);
+            };
+            self.taken += 1;
+            let tables = working_table(
+                &self.tables,
+                &self.name,
+                &self.columns,
+                &self.types,
+                vec![row.clone()],
+            );
+            let mut next_rows = Vec::new();
+            for select in &self.selects {
+                for next in execute(database, select, &tables)? {
+                    next_rows.push(next?);
+                }
+            }
+            for next in next_rows {
+                self.enqueue(next);
+            }
+            // rows skipped by OFFSET still take part in the recursion
+            if self.taken > self.offset {
+                return Ok(Some(row));
+            }
+        }
+    }
+}
+
+/// How many times a FROM clause reads from the table with the given name
+fn references(name: &str, target: &Targetable) -> usize {
+    match target {
+        Targetable::TableOrView { name: table, .. } => table.eq_ignore_ascii_case(name) as usize,
+        Targetable::Subquery { .. } | Targetable::Function { .. } => 0,
+        Targetable::Join { left, right, .. } => references(name, left) + references(name, right),
+    }
+}
+
+/// The tables the recursive SELECTs of a common table read from: the given tables, and the
+/// working table holding the given rows
+fn working_table(
+    tables: &CommonTables,
+    name: &str,
+    columns: &[String],
+    types: &[ColumnType],
+    rows: Vec<Vec<Value>>,
+) -> CommonTables {
+    tables.with_table(CommonTable {
+        name: name.to_string(),
+        columns: columns.to_vec(),
+        types: types.to_vec(),
+        content: Content::Rows(rows),
+    })
+}
+
+/// The common tables a statement can read from: those of its own WITH clause, and those of the
+/// WITH clauses of the statements it is a subquery of. Tables defined last hide the ones with
+/// the same name defined before them.
+#[derive(Clone, Default)]
+pub struct CommonTables(Rc<Vec<Rc<CommonTable>>>);
+
+impl CommonTables {
+    pub fn find(&self, name: &str) -> Option<Rc<CommonTable>> {
+        self.0
+            .iter()
+            .rev()
+            .find(|table| table.name.eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    /// The tables a statement with the given WITH clause can read from. Each common table
+    /// expression can read from the ones before it, and from itself after UNION.
+    pub fn with_clause(
+        &self,
+        database: &Database,
+        with: Option<&WithClause>,
+    ) -> Result<CommonTables> {
+        let Some(with) = with else {
+            return Ok(self.clone());
+        };
+        let mut tables = self.clone();
+        for (index, definition) in with.tables.iter().enumerate() {
+            let name = &definition.name;
+            if with.tables[..index]
+                .iter()
+                .any(|other| other.name.eq_ignore_ascii_case(name))
+            {
+                bail!("duplicate WITH table name: {}", name);
+            }
+            let defining = tables.with_table(CommonTable {
+                name: name.clone(),
+                columns: Vec::new(),
+                types: Vec::new(),
+                content: Content::Defining,
+            });
+            let mut columns = column_names(database, &definition.query, &defining)?;
+            // the SELECTs reading the table itself can't be planned until its columns are known
+            let recursive = |term: &CompoundTerm| {
+                term.select
+                    .from_target
+                    .as_ref()
+                    .is_some_and(|target| references(name, target) > 0)
+            };
+            let initial = SelectStatement {
+                compound: definition
+                    .query
+                    .compound
+                    .iter()
+                    .take_while(|term| !recursive(term))
+                    .cloned()
+                    .collect(),
+                ..definition.query.as_ref().clone()
+            };
+            let types = column_types(database, &initial, &defining)?;
+            if !definition.columns.is_empty() {
+                if definition.columns.len() != columns.len() {
+                    bail!(
+                        "table {} has {} values for {} columns",
+                        name,
+                        columns.len(),
+                        definition.columns.len()
+                    );
+                }
+                columns = definition.columns.clone();
+            }
+            tables = tables.with_table(CommonTable {
+                name: name.clone(),
+                columns,
+                types,
+                content: Content::Definition {
+                    definition: definition.clone(),
+                    tables: tables.clone(),
+                    production: RefCell::new(None),
+                },
+            });
+        }
+        Ok(tables)
+    }
+
+    fn with_table(&self, table: CommonTable) -> CommonTables {
+        let mut tables = self.0.as_ref().clone();
+        tables.push(Rc::new(table));
+        CommonTables(Rc::new(tables))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sql::sql_query;
+
+    fn working_table(name: &str, columns: &[&str]) -> CommonTable {
+        CommonTable {
+            name: name.to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            types: vec![
+                ColumnType {
+                    collation: String::from("BINARY"),
+                    affinity: None,
+                };
+                columns.len()
+            ],
+            content: Content::Rows(vec![]),
+        }
+    }
+
+    #[test]
+    fn finds_the_innermost_table_with_a_name() {
+        let tables = CommonTables::default()
+            .with_table(working_table("t", &["a"]))
+            .with_table(working_table("u", &["b"]))
+            .with_table(working_table("T", &["c", "d"]));
+        assert_eq!(tables.find("t").unwrap().columns().unwrap(), ["c", "d"]);
+        assert_eq!(tables.find("U").unwrap().columns().unwrap(), ["b"]);
+        assert!(tables.find("v").is_none());
+    }
+
+    #[test]
+    fn counts_references_of_the_from_clause_only() {
+        let table = working_table("t", &["a"]);
+        let query =
+            sql_query::select_statement("SELECT * FROM t JOIN u, (SELECT * FROM t) AS s, T AS v")
+                .unwrap();
+        assert_eq!(table.references(query.from_target.as_ref().unwrap()), 2);
+    }
+}
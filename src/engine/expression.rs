@@ -0,0 +1,841 @@
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::database;
+use crate::database::encoding::TextEncoding;
+use crate::database::page::btree::data::record::encode_record;
+use crate::database::page::btree::data::serial_types::{format_real, Value};
+use crate::database::schema::TableInformation;
+use crate::sql::{BinaryOperator, Expression, InList, Literal, UnaryOperator};
+
+use super::affinity::{
+    apply_comparison_affinity, cast, comparison_affinity, expression_affinity, Affinity,
+};
+use super::aggregate::is_aggregate_call;
+use super::collation::{
+    arguments_collation, collation_of, comparison_collation, Collation, Collations, BINARY,
+    NO_COLLATIONS,
+};
+use super::function::user::{Functions, NO_FUNCTIONS};
+use super::function::{self, json, TableFunction};
+use super::select::ColumnType;
+use super::subquery::Subqueries;
+use super::window::is_window_function;
+
+/// Names under which the rowid of a table can be referred to
+const ROWID_NAMES: [&str; 3] = ["rowid", "oid", "_rowid_"];
+
+/// A column available to expressions evaluated against a row
+#[derive(Clone)]
+pub struct ScopeColumn {
+    pub table: String,
+    pub name: String,
+    /// Hidden columns (the rowid, or the arguments of table-valued functions) can be referred
+    /// to by name but are left out of `*`
+    pub hidden: bool,
+    /// Columns merged into a column of a table to their left by a USING clause are left out of
+    /// `*` and can only be referred to along with their table name
+    pub merged: bool,
+    /// The name of the collating sequence of the column: the one it is declared with for the
+    /// columns of tables, BINARY if none
+    pub collation: String,
+    /// The affinity values of the column are converted to when compared, if any
+    pub affinity: Option<Affinity>,
+}
+
+impl ScopeColumn {
+    /// Whether the column is one of those `*` stands for
+    pub fn in_star(&self) -> bool {
+        !self.hidden && !self.merged
+    }
+
+    fn is_rowid(&self) -> bool {
+        self.hidden && self.name == "rowid"
+    }
+}
+
+/// Describes the layout of the rows expressions are evaluated against: the value of the n-th
+/// column of the scope is found at position n in the row.
+///
+/// Rows of aggregate queries hold the values of a row of each group followed by the values of
+/// the aggregate function calls listed in `aggregates`. Rows of queries calling window
+/// functions then hold the values of the window function calls listed in `windows`.
+///
+/// Subqueries are run through `subqueries`, which only scopes of queries have.
+#[derive(Clone, Default)]
+pub struct Scope<'a> {
+    pub columns: Vec<ScopeColumn>,
+    pub aggregates: Vec<Expression>,
+    pub windows: Vec<Expression>,
+    pub subqueries: Option<Subqueries<'a>>,
+}
+
+impl<'a> Scope<'a> {
+    /// The scope of a table scan: the rowid, followed by every column of the table. Columns
+    /// are qualified by `name`, the alias of the table or its name.
+    pub fn for_table(table: &TableInformation, name: &str) -> Scope<'a> {
+        let column = |column_name: &str,
+                      hidden: bool,
+                      collation: Option<&String>,
+                      affinity: Affinity| ScopeColumn {
+            table: name.to_string(),
+            name: column_name.to_string(),
+            hidden,
+            merged: false,
+            collation: collation.map_or(BINARY, String::as_str).to_string(),
+            affinity: Some(affinity),
+        };
+        let mut columns = vec![column("rowid", true, None, Affinity::Integer)];
+        columns.extend(
+            table
+                .column_names
+                .iter()
+                .zip(&table.column_collations)
+                .zip(&table.column_affinities)
+                .map(|((name, collation), affinity)| {
+                    column(name, false, collation.as_ref(), *affinity)
+                }),
+        );
+        Scope {
+            columns,
+            ..Scope::default()
+        }
+    }
+
+    /// The scope of the rows of a table-valued function: a rowid numbering them from 0,
+    /// followed by the columns of the function, its hidden columns last
+    pub fn for_function(function: &TableFunction, name: &str) -> Scope<'a> {
+        let column = |column_name: &str, hidden: bool| ScopeColumn {
+            table: name.to_string(),
+            name: column_name.to_string(),
+            hidden,
+            merged: false,
+            collation: BINARY.to_string(),
+            // the columns of table-valued functions are declared without a type
+            affinity: Some(Affinity::Blob),
+        };
+        let mut columns = vec![column("rowid", true)];
+        columns.extend(function.columns.iter().map(|name| column(name, false)));
+        columns.extend(
+            function
+                .hidden_columns
+                .iter()
+                .map(|name| column(name, true)),
+        );
+        Scope {
+            columns,
+            ..Scope::default()
+        }
+    }
+
+    /// The scope of the rows of a subquery or common table, made of the given columns of the
+    /// given types
+    pub fn for_columns(columns: &[String], types: &[ColumnType], name: &str) -> Scope<'a> {
+        Scope {
+            columns: columns
+                .iter()
+                .zip(types)
+                .map(|(column, column_type)| ScopeColumn {
+                    table: name.to_string(),
+                    name: column.clone(),
+                    hidden: false,
+                    merged: false,
+                    collation: column_type.collation.clone(),
+                    affinity: column_type.affinity,
+                })
+                .collect(),
+            ..Scope::default()
+        }
+    }
+
+    /// The scope of rows laid out like those of this scope's query but holding the given
+    /// columns
+    pub fn with_columns(&self, columns: Vec<ScopeColumn>) -> Scope<'a> {
+        Scope {
+            columns,
+            aggregates: vec![],
+            windows: vec![],
+            subqueries: self.subqueries.clone(),
+        }
+    }
+
+    /// Finds the position of a column, like `find`, failing when there is no such column.
+    pub fn resolve(&self, table: Option<&str>, name: &str) -> Result<usize> {
+        let column = match table {
+            Some(table) => format!("{}.{}", table, name),
+            None => name.to_string(),
+        };
+        self.find(table, name)?
+            .ok_or_else(|| database::Error::Schema(format!("no such column: {}", column)).into())
+    }
+
+    /// Finds the position of a column, matching names case-insensitively. Declared columns take
+    /// precedence over the rowid when a table has a column named like one of its aliases.
+    pub fn find(&self, table: Option<&str>, name: &str) -> Result<Option<usize>> {
+        let in_table =
+            |column: &ScopeColumn| table.is_none_or(|t| column.table.eq_ignore_ascii_case(t));
+        let matches: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !c.is_rowid() && (!c.merged || table.is_some()))
+            .filter(|(_, c)| in_table(c) && c.name.eq_ignore_ascii_case(name))
+            .map(|(i, _)| i)
+            .collect();
+        match matches.as_slice() {
+            [index] => return Ok(Some(*index)),
+            [] => {}
+            _ => {
+                let message = format!("ambiguous column name: {}", name);
+                return Err(database::Error::Schema(message).into());
+            }
+        }
+        if !ROWID_NAMES.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            return Ok(None);
+        }
+        Ok(self
+            .columns
+            .iter()
+            .position(|c| c.is_rowid() && in_table(c)))
+    }
+
+    /// The encoding of the database's text, which text converted to blobs or back takes.
+    /// Outside of queries, it's UTF-8.
+    pub fn encoding(&self) -> TextEncoding {
+        match &self.subqueries {
+            Some(subqueries) => subqueries.database().header.encoding(),
+            None => TextEncoding::Utf8,
+        }
+    }
+
+    /// The functions defined by the application, which only scopes of queries can call
+    pub fn functions(&self) -> &'a Functions {
+        match &self.subqueries {
+            Some(subqueries) => subqueries.database().functions(),
+            None => NO_FUNCTIONS,
+        }
+    }
+
+    /// The collating sequences defined by the application, which only scopes of queries can
+    /// use
+    pub fn collations(&self) -> &'a Collations {
+        match &self.subqueries {
+            Some(subqueries) => subqueries.database().collations(),
+            None => NO_COLLATIONS,
+        }
+    }
+
+    fn subqueries(&self) -> Result<&Subqueries<'a>> {
+        self.subqueries
+            .as_ref()
+            .ok_or_else(|| anyhow!("subqueries are not supported here"))
+    }
+}
+
+/// Evaluates an expression against a row laid out as described by `scope`.
+pub fn evaluate(expression: &Expression, scope: &Scope, row: &[Value]) -> Result<Value> {
+    match expression {
+        Expression::Literal(literal) | Expression::Bound { value: literal, .. } => {
+            Ok(literal_value(literal))
+        }
+        // parameters are replaced by the values bound to them before statements are run
+        Expression::Parameter(_) => Ok(Value::Null),
+        Expression::Column { table, name } => {
+            let index = scope.resolve(table.as_deref(), name)?;
+            Ok(row[index].clone())
+        }
+        Expression::Unary { operator, operand } => {
+            let value = evaluate(operand, scope, row)?;
+            Ok(evaluate_unary(*operator, value))
+        }
+        Expression::Binary {
+            left,
+            operator: BinaryOperator::And,
+            right,
+        } => {
+            // FALSE AND anything is FALSE, which lets us skip evaluating the right hand side
+            let left = truth_value(&evaluate(left, scope, row)?);
+            if left == Some(false) {
+                return Ok(Value::Int64(0));
+            }
+            let right = truth_value(&evaluate(right, scope, row)?);
+            Ok(match (left, right) {
+                (_, Some(false)) => Value::Int64(0),
+                (Some(true), Some(true)) => Value::Int64(1),
+                _ => Value::Null,
+            })
+        }
+        Expression::Binary {
+            left,
+            operator: BinaryOperator::Or,
+            right,
+        } => {
+            let left = truth_value(&evaluate(left, scope, row)?);
+            if left == Some(true) {
+                return Ok(Value::Int64(1));
+            }
+            let right = truth_value(&evaluate(right, scope, row)?);
+            Ok(match (left, right) {
+                (_, Some(true)) => Value::Int64(1),
+                (Some(false), Some(false)) => Value::Int64(0),
+                _ => Value::Null,
+            })
+        }
+        Expression::Binary {
+            left,
+            operator: operator @ (BinaryOperator::Extract | BinaryOperator::ExtractValue),
+            right,
+        } => {
+            let left = evaluate(left, scope, row)?;
+            let right = evaluate(right, scope, row)?;
+            json::extract_operator(&left, &right, *operator == BinaryOperator::ExtractValue)
+        }
+        Expression::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let left_value = evaluate(left, scope, row)?;
+            let right_value = evaluate(right, scope, row)?;
+            if !operator.is_comparison() {
+                return Ok(evaluate_binary(
+                    *operator,
+                    left_value,
+                    right_value,
+                    &Collation::Binary,
+                ));
+            }
+            let (left_value, right_value, collation) =
+                comparison_operands(left, right, left_value, right_value, scope)?;
+            Ok(evaluate_binary(
+                *operator,
+                left_value,
+                right_value,
+                &collation,
+            ))
+        }
+        Expression::Function {
+            name,
+            arguments,
+            over,
+            ..
+        } => match scope.aggregates.iter().position(|a| a == expression) {
+            Some(position) => Ok(row[scope.columns.len() + position].clone()),
+            None if over.is_some() => match scope.windows.iter().position(|w| w == expression) {
+                Some(position) => {
+                    Ok(row[scope.columns.len() + scope.aggregates.len() + position].clone())
+                }
+                None => bail!("misuse of window function {}()", name),
+            },
+            None if is_aggregate_call(expression, scope.functions()) => {
+                bail!("misuse of aggregate function {}()", name)
+            }
+            None if is_window_function(name) => bail!("misuse of window function {}()", name),
+            None => {
+                let function =
+                    function::lookup(name, arguments.expressions().len(), scope.functions())?;
+                let mut values = Vec::with_capacity(arguments.expressions().len());
+                for argument in arguments.expressions() {
+                    values.push(evaluate(argument, scope, row)?);
+                }
+                if function.compares() {
+                    let collation = arguments_collation(arguments.expressions(), scope)?;
+                    return function.call_with_collation(&values, &collation);
+                }
+                if function.encodes() {
+                    return function.call_with_encoding(&values, scope.encoding());
+                }
+                if !function.reads_json() {
+                    return function.call(&values);
+                }
+                let is_json: Vec<bool> = arguments
+                    .expressions()
+                    .iter()
+                    .zip(&values)
+                    .map(|(argument, value)| json::is_json(argument, value))
+                    .collect();
+                function.call_with_json(&values, &is_json)
+            }
+        },
+        Expression::In {
+            operand,
+            negated,
+            list,
+        } => {
+            let value = evaluate(operand, scope, row)?;
+            let found = match list {
+                InList::Expressions(expressions) => {
+                    let mut values = Vec::with_capacity(expressions.len());
+                    for expression in expressions {
+                        values.push(evaluate(expression, scope, row)?);
+                    }
+                    // like SQLite, a list of a single value is compared with it like `=` does,
+                    // while longer lists use the collation and affinity of the operand
+                    let (affinity, collation) = match expressions.as_slice() {
+                        _ if !is_text(&value) && !values.iter().any(is_text) => {
+                            (None, Collation::Binary)
+                        }
+                        [expression] => (
+                            comparison_affinity(
+                                expression_affinity(operand, scope)?,
+                                expression_affinity(expression, scope)?,
+                            ),
+                            comparison_collation(operand, expression, scope)?,
+                        ),
+                        _ => (
+                            comparison_affinity(expression_affinity(operand, scope)?, None),
+                            collation_of(operand, scope)?,
+                        ),
+                    };
+                    list_contains(&values, &value, affinity, &collation)
+                }
+                InList::Subquery(query) => {
+                    let subqueries = scope.subqueries()?;
+                    let collation = subqueries.members_collation(operand, query, scope)?;
+                    let affinity = subqueries.members_affinity(operand, query, scope)?;
+                    subqueries
+                        .members(query, &collation, affinity, scope, row)?
+                        .contains(&value)
+                }
+            };
+            Ok(match found {
+                Some(found) => Value::Int64((found != *negated) as i64),
+                None => Value::Null,
+            })
+        }
+        Expression::Subquery(query) => scope.subqueries()?.value(query, scope, row),
+        Expression::Exists(query) => {
+            let exists = scope.subqueries()?.exists(query, scope, row)?;
+            Ok(Value::Int64(exists as i64))
+        }
+        Expression::Case {
+            operand,
+            branches,
+            else_result,
+        } => {
+            let operand_value = match operand {
+                Some(operand) => Some(evaluate(operand, scope, row)?),
+                None => None,
+            };
+            for (when, then) in branches {
+                let when_value = evaluate(when, scope, row)?;
+                let applies = match (operand, &operand_value) {
+                    (Some(operand), Some(value)) => {
+                        let (value, when_value, collation) =
+                            comparison_operands(operand, when, value.clone(), when_value, scope)?;
+                        evaluate_binary(BinaryOperator::Equal, value, when_value, &collation)
+                    }
+                    _ => when_value,
+                };
+                if truth_value(&applies) == Some(true) {
+                    return evaluate(then, scope, row);
+                }
+            }
+            match else_result {
+                Some(else_result) => evaluate(else_result, scope, row),
+                None => Ok(Value::Null),
+            }
+        }
+        Expression::Cast {
+            expression,
+            type_name,
+        } => Ok(cast(
+            evaluate(expression, scope, row)?,
+            Affinity::of(type_name),
+            scope.encoding(),
+        )),
+        Expression::Collate { expression, .. } => evaluate(expression, scope, row),
+    }
+}
+
+/// Converts the values of the operands of a comparison to the affinity they are compared with,
+/// and finds the collation they are compared with
+fn comparison_operands(
+    left: &Expression,
+    right: &Expression,
+    left_value: Value,
+    right_value: Value,
+    scope: &Scope,
+) -> Result<(Value, Value, Collation)> {
+    // affinities only convert text, or numbers compared with text
+    if !is_text(&left_value) && !is_text(&right_value) {
+        return Ok((left_value, right_value, Collation::Binary));
+    }
+    let affinity = comparison_affinity(
+        expression_affinity(left, scope)?,
+        expression_affinity(right, scope)?,
+    );
+    let (left_value, right_value) = apply_comparison_affinity(affinity, left_value, right_value);
+    // only text comparisons depend on the collation
+    let collation = if is_text(&left_value) && is_text(&right_value) {
+        comparison_collation(left, right, scope)?
+    } else {
+        Collation::Binary
+    };
+    Ok((left_value, right_value, collation))
+}
+
+fn is_text(value: &Value) -> bool {
+    matches!(value, Value::String(_))
+}
+
+/// Whether a value is one of the values of an IN list, compared once converted to the given
+/// affinity. This is unknown when the value is NULL or when it isn't found but the list holds
+/// a NULL.
+fn list_contains(
+    values: &[Value],
+    value: &Value,
+    affinity: Option<Affinity>,
+    collation: &Collation,
+) -> Option<bool> {
+    if value.is_null() {
+        return values.is_empty().then_some(false);
+    }
+    let mut found = Some(false);
+    for candidate in values {
+        if candidate.is_null() {
+            found = None;
+            continue;
+        }
+        let (value, candidate) =
+            apply_comparison_affinity(affinity, value.clone(), candidate.clone());
+        if collation.compare(&value, &candidate) == Ordering::Equal {
+            return Some(true);
+        }
+    }
+    found
+}
+
+/// Checks that every column an expression refers to exists and that every function it calls
+/// can be called, without evaluating it.
+pub fn validate(expression: &Expression, scope: &Scope) -> Result<()> {
+    match expression {
+        Expression::Column { table, name } => {
+            scope.resolve(table.as_deref(), name)?;
+        }
+        Expression::Function {
+            name,
+            arguments,
+            over,
+            ..
+        } if !scope.aggregates.contains(expression) && !scope.windows.contains(expression) => {
+            if is_aggregate_call(expression, scope.functions()) {
+                bail!("misuse of aggregate function {}()", name);
+            }
+            if over.is_some() || is_window_function(name) {
+                bail!("misuse of window function {}()", name);
+            }
+            function::lookup(name, arguments.expressions().len(), scope.functions())?;
+        }
+        Expression::Collate { collation, .. } => {
+            scope.collations().find(collation)?;
+        }
+        _ => {}
+    }
+    expression
+        .children()
+        .into_iter()
+        .try_for_each(|child| validate(child, scope))
+}
+
+pub fn literal_value(literal: &Literal) -> Value {
+    match literal {
+        Literal::Null => Value::Null,
+        Literal::Integer(i) => Value::Int64(*i),
+        Literal::Real(r) => Value::Float64(*r),
+        Literal::String(s) => Value::String(s.clone()),
+        Literal::Blob(b) => Value::Blob(b.clone()),
+    }
+}
+
+/// Interprets a value as a boolean: NULL is unknown, numbers are true when non-zero, and text
+/// and blobs are converted to numbers first.
+pub fn truth_value(value: &Value) -> Option<bool> {
+    match to_numeric(value)? {
+        Numeric::Integer(i) => Some(i != 0),
+        Numeric::Real(r) => Some(r != 0.0),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Numeric {
+    Integer(i64),
+    Real(f64),
+}
+
+impl Numeric {
+    pub fn as_real(self) -> f64 {
+        match self {
+            Numeric::Integer(i) => i as f64,
+            Numeric::Real(r) => r,
+        }
+    }
+
+    /// The number as a value. Like in SQLite, a real which isn't a number, such as the
+    /// difference of two infinities, is NULL.
+    pub fn into_value(self) -> Value {
+        match self {
+            Numeric::Integer(i) => Value::Int64(i),
+            Numeric::Real(r) if r.is_nan() => Value::Null,
+            Numeric::Real(r) => Value::Float64(r),
+        }
+    }
+}
+
+/// Converts a value to a number the way SQLite does for arithmetic: text and blobs are read as
+/// the longest numeric prefix they start with, 0 if there is none. NULL stays NULL.
+pub fn to_numeric(value: &Value) -> Option<Numeric> {
+    match value {
+        Value::Null => None,
+        Value::Float64(r) => Some(Numeric::Real(*r)),
+        Value::String(s) => Some(parse_numeric_prefix(s)),
+        Value::Blob(b) => Some(parse_numeric_prefix(&String::from_utf8_lossy(b))),
+        other => Some(Numeric::Integer(other.as_i64().unwrap_or(0))),
+    }
+}
+
+fn parse_numeric_prefix(text: &str) -> Numeric {
+    numeric_prefix(text).map_or(Numeric::Integer(0), |(number, _)| number)
+}
+
+/// Reads a text holding nothing but a number, with optional spaces around it, like SQLite does
+/// when converting text to a numeric affinity
+pub fn parse_numeric(text: &str) -> Option<Numeric> {
+    let (number, rest) = numeric_prefix(text)?;
+    rest.trim_end().is_empty().then_some(number)
+}
+
+/// The number a text starts with after any leading spaces, along with the rest of the text
+fn numeric_prefix(text: &str) -> Option<(Numeric, &str)> {
+    let text = text.trim_start();
+    let bytes = text.as_bytes();
+    let mut end = 0;
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        end += 1;
+    }
+    let digits_start = end;
+    while bytes.get(end).is_some_and(u8::is_ascii_digit) {
+        end += 1;
+    }
+    let mut is_real = false;
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_end = end
+            + 1
+            + bytes[end + 1..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+        if fraction_end > end + 1 || end > digits_start {
+            is_real = true;
+            end = fraction_end;
+        }
+    }
+    if end == digits_start {
+        return None;
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut exponent_end = end + 1;
+        if matches!(bytes.get(exponent_end), Some(b'+' | b'-')) {
+            exponent_end += 1;
+        }
+        let exponent_digits = bytes[exponent_end..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if exponent_digits > 0 {
+            is_real = true;
+            end = exponent_end + exponent_digits;
+        }
+    }
+    let (prefix, rest) = text.split_at(end);
+    if !is_real {
+        if let Ok(i) = prefix.parse::<i64>() {
+            return Some((Numeric::Integer(i), rest));
+        }
+    }
+    Some((Numeric::Real(prefix.parse().unwrap_or(0.0)), rest))
+}
+
+/// Renders a value as text, as done when it's used as an operand of `||`.
+pub fn to_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Float64(r) => Some(format_real(*r)),
+        Value::String(s) => Some(s.clone()),
+        Value::Blob(b) => Some(String::from_utf8_lossy(b).to_string()),
+        other => Some(other.as_i64().unwrap_or(0).to_string()),
+    }
+}
+
+/// Normalizes values so that values comparing equal are encoded the same way: integers of all
+/// widths and integral reals become 64 bit integers. The encoding is used to hash group keys,
+/// the arguments of DISTINCT aggregates and the keys of hash joins.
+pub fn hashable_key(values: &[Value]) -> Vec<u8> {
+    let normalized: Vec<Value> = values
+        .iter()
+        .map(|value| match value {
+            Value::Float64(r) if r.fract() == 0.0 && r.abs() < 9.2e18 => Value::Int64(*r as i64),
+            Value::String(_) | Value::Blob(_) | Value::Float64(_) | Value::Null => value.clone(),
+            other => Value::Int64(other.as_i64().unwrap_or(0)),
+        })
+        .collect();
+    encode_record(&normalized)
+}
+
+fn evaluate_unary(operator: UnaryOperator, value: Value) -> Value {
+    if value.is_null() {
+        return Value::Null;
+    }
+    match operator {
+        UnaryOperator::Plus => value,
+        UnaryOperator::Negate => match to_numeric(&value) {
+            Some(Numeric::Integer(i)) => match i.checked_neg() {
+                Some(negated) => Value::Int64(negated),
+                None => Value::Float64(-(i as f64)),
+            },
+            Some(Numeric::Real(r)) => Value::Float64(-r),
+            None => Value::Null,
+        },
+        UnaryOperator::Not => match truth_value(&value) {
+            Some(b) => Value::Int64(!b as i64),
+            None => Value::Null,
+        },
+        UnaryOperator::BitNot => Value::Int64(!to_integer(&value)),
+    }
+}
+
+/// Converts a value to an integer, truncating reals. NULL is 0.
+pub fn to_integer(value: &Value) -> i64 {
+    match to_numeric(value) {
+        Some(Numeric::Integer(i)) => i,
+        Some(Numeric::Real(r)) => r as i64,
+        None => 0,
+    }
+}
+
+/// Applies a binary operator to two values, comparisons comparing text with the collation
+fn evaluate_binary(
+    operator: BinaryOperator,
+    left: Value,
+    right: Value,
+    collation: &Collation,
+) -> Value {
+    match operator {
+        BinaryOperator::Is | BinaryOperator::IsNot => {
+            let same = match (left.is_null(), right.is_null()) {
+                (true, true) => true,
+                (false, false) => collation.compare(&left, &right) == Ordering::Equal,
+                _ => false,
+            };
+            return Value::Int64((same == (operator == BinaryOperator::Is)) as i64);
+        }
+        _ if left.is_null() || right.is_null() => return Value::Null,
+        _ => {}
+    }
+    let comparison = |predicate: fn(Ordering) -> bool| {
+        Value::Int64(predicate(collation.compare(&left, &right)) as i64)
+    };
+    match operator {
+        BinaryOperator::Equal => comparison(Ordering::is_eq),
+        BinaryOperator::NotEqual => comparison(Ordering::is_ne),
+        BinaryOperator::Less => comparison(Ordering::is_lt),
+        BinaryOperator::LessOrEqual => comparison(Ordering::is_le),
+        BinaryOperator::Greater => comparison(Ordering::is_gt),
+        BinaryOperator::GreaterOrEqual => comparison(Ordering::is_ge),
+        BinaryOperator::Concat => {
+            let mut text = to_text(&left).unwrap_or_default();
+            text.push_str(&to_text(&right).unwrap_or_default());
+            Value::String(text)
+        }
+        BinaryOperator::BitAnd => Value::Int64(to_integer(&left) & to_integer(&right)),
+        BinaryOperator::BitOr => Value::Int64(to_integer(&left) | to_integer(&right)),
+        BinaryOperator::ShiftLeft => shift(to_integer(&left), to_integer(&right)),
+        BinaryOperator::ShiftRight => shift(to_integer(&left), to_integer(&right).saturating_neg()),
+        BinaryOperator::Add
+        | BinaryOperator::Subtract
+        | BinaryOperator::Multiply
+        | BinaryOperator::Divide
+        | BinaryOperator::Modulo => match (to_numeric(&left), to_numeric(&right)) {
+            (Some(a), Some(b)) => arithmetic(operator, a, b),
+            _ => Value::Null,
+        },
+        // handled with short-circuiting in `evaluate`
+        BinaryOperator::And | BinaryOperator::Or => Value::Null,
+        // handled above, as they don't propagate NULL
+        BinaryOperator::Is | BinaryOperator::IsNot => Value::Null,
+        // handled in `evaluate`, as reading JSON can fail
+        BinaryOperator::Extract | BinaryOperator::ExtractValue => Value::Null,
+    }
+}
+
+fn shift(value: i64, amount: i64) -> Value {
+    Value::Int64(match amount {
+        a if a >= 64 => 0,
+        a if a >= 0 => value << a,
+        a if a <= -64 => {
+            if value < 0 {
+                -1
+            } else {
+                0
+            }
+        }
+        a => value >> -a,
+    })
+}
+
+fn arithmetic(operator: BinaryOperator, left: Numeric, right: Numeric) -> Value {
+    if let (Numeric::Integer(a), Numeric::Integer(b)) = (left, right) {
+        let result = match operator {
+            BinaryOperator::Add => a.checked_add(b),
+            BinaryOperator::Subtract => a.checked_sub(b),
+            BinaryOperator::Multiply => a.checked_mul(b),
+            BinaryOperator::Divide | BinaryOperator::Modulo if b == 0 => return Value::Null,
+            BinaryOperator::Divide => a.checked_div(b),
+            _ => Some(a.checked_rem(b).unwrap_or(0)),
+        };
+        // integer overflow falls back to floating point arithmetic
+        if let Some(result) = result {
+            return Value::Int64(result);
+        }
+    }
+    let (a, b) = (left.as_real(), right.as_real());
+    match operator {
+        BinaryOperator::Add => Numeric::Real(a + b).into_value(),
+        BinaryOperator::Subtract => Numeric::Real(a - b).into_value(),
+        BinaryOperator::Multiply => Numeric::Real(a * b).into_value(),
+        BinaryOperator::Divide if b == 0.0 => Value::Null,
+        BinaryOperator::Divide => Numeric::Real(a / b).into_value(),
+        _ => {
+            // the remainder is computed on the integer parts of the operands
+            let (a, b) = (a as i64, b as i64);
+            if b == 0 {
+                Value::Null
+            } else {
+                Value::Float64(a.checked_rem(b).unwrap_or(0) as f64)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn makes_reals_which_are_not_numbers_null() {
+        let infinity = Numeric::Real(f64::INFINITY);
+        assert_eq!(
+            arithmetic(BinaryOperator::Subtract, infinity, infinity),
+            Value::Null
+        );
+        assert_eq!(
+            arithmetic(BinaryOperator::Multiply, infinity, Numeric::Integer(0)),
+            Value::Null
+        );
+        assert_eq!(
+            arithmetic(BinaryOperator::Add, infinity, Numeric::Integer(1)),
+            Value::Float64(f64::INFINITY)
+        );
+    }
+}
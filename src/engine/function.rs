@@ -0,0 +1,871 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::database::encoding::TextEncoding;
+use crate::database::page::btree::data::serial_types::{format_real, Value};
+
+use self::user::{Functions, UserScalar};
+
+use super::collation::Collation;
+
+use super::expression::{to_integer, to_numeric, to_text, truth_value, Numeric};
+
+pub mod datetime;
+pub mod json;
+pub mod math;
+pub mod pattern;
+pub mod printf;
+pub mod user;
+
+/// SQLite's limit on the size of strings and blobs
+const MAX_LENGTH: i64 = 1_000_000_000;
+
+/// A built-in scalar function
+pub struct ScalarFunction {
+    name: &'static str,
+    /// How many arguments the function can be called with
+    arguments: RangeInclusive<usize>,
+    /// Whether the function returns NULL as soon as one of its arguments is NULL, without
+    /// being called
+    null_propagating: bool,
+    implementation: Implementation,
+}
+
+enum Implementation {
+    Plain(fn(&[Value]) -> Result<Value>),
+    /// A JSON function, also told which of its arguments are JSON built by another JSON
+    /// function rather than text
+    Json(fn(&[Value], &[bool]) -> Result<Value>),
+    /// A function comparing its arguments, also told the collation to compare text with
+    Collating(fn(&[Value], &Collation) -> Result<Value>),
+    /// A function converting text to bytes, also told the encoding of the database's text
+    Encoding(fn(&[Value], TextEncoding) -> Result<Value>),
+}
+
+impl ScalarFunction {
+    pub fn call(&self, arguments: &[Value]) -> Result<Value> {
+        self.call_with_json(arguments, &vec![false; arguments.len()])
+    }
+
+    /// Calls the function, telling it which of its arguments are JSON built by another JSON
+    /// function, when it reads JSON.
+    pub fn call_with_json(&self, arguments: &[Value], json: &[bool]) -> Result<Value> {
+        if self.null_propagating && arguments.iter().any(Value::is_null) {
+            return Ok(Value::Null);
+        }
+        match self.implementation {
+            Implementation::Plain(implementation) => implementation(arguments),
+            Implementation::Json(implementation) => implementation(arguments, json),
+            Implementation::Collating(implementation) => {
+                implementation(arguments, &Collation::Binary)
+            }
+            Implementation::Encoding(implementation) => {
+                implementation(arguments, TextEncoding::Utf8)
+            }
+        }
+    }
+
+    /// Calls the function, telling it the collation to compare text with, when it compares
+    /// its arguments.
+    pub fn call_with_collation(&self, arguments: &[Value], collation: &Collation) -> Result<Value> {
+        match self.implementation {
+            Implementation::Collating(implementation)
+                if !self.null_propagating || !arguments.iter().any(Value::is_null) =>
+            {
+                implementation(arguments, collation)
+            }
+            _ => self.call(arguments),
+        }
+    }
+
+    /// Calls the function, telling it the encoding of the database's text, when it converts
+    /// text to bytes.
+    pub fn call_with_encoding(&self, arguments: &[Value], encoding: TextEncoding) -> Result<Value> {
+        match self.implementation {
+            Implementation::Encoding(implementation)
+                if !self.null_propagating || !arguments.iter().any(Value::is_null) =>
+            {
+                implementation(arguments, encoding)
+            }
+            _ => self.call(arguments),
+        }
+    }
+
+    /// Whether the function compares its arguments, which makes it depend on their collation
+    pub fn compares(&self) -> bool {
+        matches!(self.implementation, Implementation::Collating(_))
+    }
+
+    /// Whether the function converts text to bytes, which makes it depend on the encoding of
+    /// the database
+    pub fn encodes(&self) -> bool {
+        matches!(self.implementation, Implementation::Encoding(_))
+    }
+
+    /// Whether the function tells JSON arguments built by other JSON functions from text
+    pub fn reads_json(&self) -> bool {
+        matches!(self.implementation, Implementation::Json(_))
+    }
+}
+
+const fn scalar(
+    name: &'static str,
+    arguments: RangeInclusive<usize>,
+    implementation: fn(&[Value]) -> Result<Value>,
+) -> ScalarFunction {
+    ScalarFunction {
+        name,
+        arguments,
+        null_propagating: true,
+        implementation: Implementation::Plain(implementation),
+    }
+}
+
+/// A function which is called even when some of its arguments are NULL
+const fn null_handling(
+    name: &'static str,
+    arguments: RangeInclusive<usize>,
+    implementation: fn(&[Value]) -> Result<Value>,
+) -> ScalarFunction {
+    ScalarFunction {
+        null_propagating: false,
+        ..scalar(name, arguments, implementation)
+    }
+}
+
+/// A JSON function, which handles NULL arguments itself
+const fn json_function(
+    name: &'static str,
+    arguments: RangeInclusive<usize>,
+    implementation: fn(&[Value], &[bool]) -> Result<Value>,
+) -> ScalarFunction {
+    ScalarFunction {
+        name,
+        arguments,
+        null_propagating: false,
+        implementation: Implementation::Json(implementation),
+    }
+}
+
+/// A function converting text to the bytes it is stored as
+const fn encoding(
+    name: &'static str,
+    arguments: RangeInclusive<usize>,
+    null_propagating: bool,
+    implementation: fn(&[Value], TextEncoding) -> Result<Value>,
+) -> ScalarFunction {
+    ScalarFunction {
+        name,
+        arguments,
+        null_propagating,
+        implementation: Implementation::Encoding(implementation),
+    }
+}
+
+/// A function comparing its arguments
+const fn collating(
+    name: &'static str,
+    arguments: RangeInclusive<usize>,
+    null_propagating: bool,
+    implementation: fn(&[Value], &Collation) -> Result<Value>,
+) -> ScalarFunction {
+    ScalarFunction {
+        name,
+        arguments,
+        null_propagating,
+        implementation: Implementation::Collating(implementation),
+    }
+}
+
+const ANY: usize = usize::MAX;
+
+/// SQLite's built-in scalar functions: the core functions, along with the date and time, JSON
+/// and math functions. `min` and `max` are only scalar functions when called with more than one
+/// argument, otherwise they are aggregates.
+static FUNCTIONS: &[ScalarFunction] = &[
+    scalar("abs", 1..=1, abs),
+    scalar("acos", 1..=1, math::acos),
+    scalar("acosh", 1..=1, math::acosh),
+    scalar("asin", 1..=1, math::asin),
+    scalar("asinh", 1..=1, math::asinh),
+    scalar("atan", 1..=1, math::atan),
+    scalar("atan2", 2..=2, math::atan2),
+    scalar("atanh", 1..=1, math::atanh),
+    scalar("ceil", 1..=1, math::ceil),
+    scalar("ceiling", 1..=1, math::ceil),
+    null_handling("char", 0..=ANY, char),
+    null_handling("coalesce", 2..=ANY, coalesce),
+    scalar("cos", 1..=1, math::cos),
+    scalar("cosh", 1..=1, math::cosh),
+    scalar("date", 0..=ANY, datetime::date),
+    scalar("datetime", 0..=ANY, datetime::datetime),
+    scalar("degrees", 1..=1, math::degrees),
+    scalar("exp", 1..=1, math::exp),
+    scalar("floor", 1..=1, math::floor),
+    null_handling("format", 1..=ANY, printf::format),
+    scalar("glob", 2..=2, glob),
+    encoding("hex", 1..=1, false, hex),
+    null_handling("ifnull", 2..=2, coalesce),
+    null_handling("iif", 3..=3, iif),
+    scalar("instr", 2..=2, instr),
+    json_function("json", 1..=1, json::json),
+    json_function("json_array", 0..=ANY, json::json_array),
+    json_function("json_array_length", 1..=2, json::json_array_length),
+    json_function("json_extract", 1..=ANY, json::json_extract),
+    json_function("json_insert", 1..=ANY, json::json_insert),
+    json_function("json_object", 0..=ANY, json::json_object),
+    json_function("json_quote", 1..=1, json::json_quote),
+    json_function("json_remove", 1..=ANY, json::json_remove),
+    json_function("json_replace", 1..=ANY, json::json_replace),
+    json_function("json_set", 1..=ANY, json::json_set),
+    json_function("json_type", 1..=2, json::json_type),
+    json_function("json_valid", 1..=2, json::json_valid),
+    scalar("julianday", 0..=ANY, datetime::julianday),
+    scalar("length", 1..=1, length),
+    scalar("like", 2..=3, like),
+    scalar("ln", 1..=1, math::ln),
+    scalar("log", 1..=2, math::log),
+    scalar("log10", 1..=1, math::log10),
+    scalar("log2", 1..=1, math::log2),
+    scalar("lower", 1..=1, lower),
+    scalar("ltrim", 1..=2, ltrim),
+    collating("max", 2..=ANY, true, max),
+    collating("min", 2..=ANY, true, min),
+    scalar("mod", 2..=2, math::modulo),
+    collating("nullif", 2..=2, false, nullif),
+    scalar("pi", 0..=0, math::pi),
+    scalar("pow", 2..=2, math::pow),
+    scalar("power", 2..=2, math::pow),
+    null_handling("printf", 1..=ANY, printf::format),
+    null_handling("quote", 1..=1, quote),
+    scalar("radians", 1..=1, math::radians),
+    null_handling("random", 0..=0, random),
+    scalar("replace", 3..=3, replace),
+    scalar("round", 1..=2, round),
+    scalar("rtrim", 1..=2, rtrim),
+    scalar("sign", 1..=1, math::sign),
+    scalar("sin", 1..=1, math::sin),
+    scalar("sinh", 1..=1, math::sinh),
+    scalar("sqrt", 1..=1, math::sqrt),
+    scalar("strftime", 0..=ANY, datetime::strftime),
+    scalar("substr", 2..=3, substr),
+    scalar("substring", 2..=3, substr),
+    scalar("tan", 1..=1, math::tan),
+    scalar("tanh", 1..=1, math::tanh),
+    scalar("time", 0..=ANY, datetime::time),
+    scalar("timediff", 2..=2, datetime::timediff),
+    scalar("trim", 1..=2, trim),
+    scalar("trunc", 1..=1, math::trunc),
+    null_handling("typeof", 1..=1, type_of),
+    scalar("unhex", 1..=2, unhex),
+    scalar("unicode", 1..=1, unicode),
+    scalar("unixepoch", 0..=ANY, datetime::unixepoch),
+    scalar("upper", 1..=1, upper),
+    null_handling("zeroblob", 1..=1, zeroblob),
+];
+
+/// A scalar function, built-in or defined by the application
+pub enum Scalar<'a> {
+    BuiltIn(&'static ScalarFunction),
+    User(&'a UserScalar),
+}
+
+impl Scalar<'_> {
+    pub fn call(&self, arguments: &[Value]) -> Result<Value> {
+        match self {
+            Scalar::BuiltIn(function) => function.call(arguments),
+            Scalar::User(function) => function.call(arguments),
+        }
+    }
+
+    /// Calls the function, telling it which of its arguments are JSON built by another JSON
+    /// function, when it reads JSON.
+    pub fn call_with_json(&self, arguments: &[Value], json: &[bool]) -> Result<Value> {
+        match self {
+            Scalar::BuiltIn(function) => function.call_with_json(arguments, json),
+            Scalar::User(function) => function.call(arguments),
+        }
+    }
+
+    pub fn reads_json(&self) -> bool {
+        match self {
+            Scalar::BuiltIn(function) => function.reads_json(),
+            Scalar::User(_) => false,
+        }
+    }
+
+    /// Calls the function, telling it the collation to compare text with, when it compares
+    /// its arguments.
+    pub fn call_with_collation(&self, arguments: &[Value], collation: &Collation) -> Result<Value> {
+        match self {
+            Scalar::BuiltIn(function) => function.call_with_collation(arguments, collation),
+            Scalar::User(function) => function.call(arguments),
+        }
+    }
+
+    pub fn compares(&self) -> bool {
+        match self {
+            Scalar::BuiltIn(function) => function.compares(),
+            Scalar::User(_) => false,
+        }
+    }
+
+    /// Calls the function, telling it the encoding of the database's text, when it converts
+    /// text to bytes.
+    pub fn call_with_encoding(&self, arguments: &[Value], encoding: TextEncoding) -> Result<Value> {
+        match self {
+            Scalar::BuiltIn(function) => function.call_with_encoding(arguments, encoding),
+            Scalar::User(function) => function.call(arguments),
+        }
+    }
+
+    pub fn encodes(&self) -> bool {
+        match self {
+            Scalar::BuiltIn(function) => function.encodes(),
+            Scalar::User(_) => false,
+        }
+    }
+}
+
+/// Looks up the scalar function called by `name` with the given number of arguments, among the
+/// functions defined by the application first.
+pub fn lookup<'a>(name: &str, arguments: usize, functions: &'a Functions) -> Result<Scalar<'a>> {
+    if let Some(function) = functions.scalar(name, arguments) {
+        return Ok(Scalar::User(function));
+    }
+    let function = FUNCTIONS
+        .iter()
+        .find(|function| function.name.eq_ignore_ascii_case(name));
+    match function {
+        Some(function) if function.arguments.contains(&arguments) => Ok(Scalar::BuiltIn(function)),
+        Some(_) => bail!("wrong number of arguments to function {}()", name),
+        None if functions.defines(name) => {
+            bail!("wrong number of arguments to function {}()", name)
+        }
+        None => bail!("no such function: {}", name),
+    }
+}
+
+/// A built-in table-valued function, read from in the FROM clause like a table
+pub struct TableFunction {
+    pub name: &'static str,
+    pub columns: &'static [&'static str],
+    /// Columns following `columns` in the rows, which are left out of `*`
+    pub hidden_columns: &'static [&'static str],
+    /// How many arguments the function can be called with, at most
+    pub arguments: usize,
+    implementation: fn(&[Value]) -> Result<Vec<Vec<Value>>>,
+}
+
+impl TableFunction {
+    pub fn call(&self, arguments: &[Value]) -> Result<Vec<Vec<Value>>> {
+        if arguments.len() > self.arguments {
+            bail!(
+                "too many arguments on {}() - max {}",
+                self.name,
+                self.arguments
+            );
+        }
+        (self.implementation)(arguments)
+    }
+}
+
+static TABLE_FUNCTIONS: &[TableFunction] = &[
+    TableFunction {
+        name: "json_each",
+        columns: json::EACH_COLUMNS,
+        hidden_columns: json::EACH_HIDDEN_COLUMNS,
+        arguments: 2,
+        implementation: json::json_each,
+    },
+    TableFunction {
+        name: "json_tree",
+        columns: json::EACH_COLUMNS,
+        hidden_columns: json::EACH_HIDDEN_COLUMNS,
+        arguments: 2,
+        implementation: json::json_tree,
+    },
+];
+
+/// Looks up the table-valued function called by `name`.
+pub fn table_function(name: &str) -> Option<&'static TableFunction> {
+    TABLE_FUNCTIONS
+        .iter()
+        .find(|function| function.name.eq_ignore_ascii_case(name))
+}
+
+/// The text a value is read as by string functions
+fn text(value: &Value) -> String {
+    to_text(value).unwrap_or_default()
+}
+
+fn abs(arguments: &[Value]) -> Result<Value> {
+    Ok(match &arguments[0] {
+        Value::Float64(r) => Value::Float64(r.abs()),
+        // text and blobs are converted to reals, 0.0 when they aren't numbers
+        Value::String(_) | Value::Blob(_) => Value::Float64(
+            to_numeric(&arguments[0])
+                .map_or(0.0, Numeric::as_real)
+                .abs(),
+        ),
+        other => Value::Int64(
+            to_integer(other)
+                .checked_abs()
+                .ok_or_else(|| anyhow!("integer overflow"))?,
+        ),
+    })
+}
+
+/// The string made of the characters with the given code points
+fn char(arguments: &[Value]) -> Result<Value> {
+    Ok(Value::String(
+        arguments
+            .iter()
+            .map(|argument| {
+                u32::try_from(to_integer(argument))
+                    .ok()
+                    .and_then(char::from_u32)
+                    .unwrap_or(char::REPLACEMENT_CHARACTER)
+            })
+            .collect(),
+    ))
+}
+
+/// The first argument which isn't NULL, as returned by `coalesce` and `ifnull`
+fn coalesce(arguments: &[Value]) -> Result<Value> {
+    Ok(arguments
+        .iter()
+        .find(|argument| !argument.is_null())
+        .cloned()
+        .unwrap_or(Value::Null))
+}
+
+/// The bytes of a blob, or of text as the database stores it, in hexadecimal. Numbers are shown
+/// as the UTF-8 bytes of their text, whatever the encoding, as SQLite does.
+fn hex(arguments: &[Value], encoding: TextEncoding) -> Result<Value> {
+    let bytes = match &arguments[0] {
+        Value::Blob(b) => b.clone(),
+        Value::String(s) => encoding.encode(s),
+        other => text(other).into_bytes(),
+    };
+    Ok(Value::String(
+        bytes.iter().map(|byte| format!("{:02X}", byte)).collect(),
+    ))
+}
+
+fn iif(arguments: &[Value]) -> Result<Value> {
+    Ok(match truth_value(&arguments[0]) {
+        Some(true) => arguments[1].clone(),
+        _ => arguments[2].clone(),
+    })
+}
+
+/// The position of the first occurrence of the second argument in the first one, counted in
+/// characters, or in bytes when both are blobs. 0 when there is none.
+fn instr(arguments: &[Value]) -> Result<Value> {
+    let position = match (&arguments[0], &arguments[1]) {
+        (Value::Blob(haystack), Value::Blob(needle)) => {
+            if needle.is_empty() {
+                Some(0)
+            } else {
+                haystack
+                    .windows(needle.len())
+                    .position(|window| window == needle.as_slice())
+            }
+        }
+        (haystack, needle) => {
+            let haystack = text(haystack);
+            haystack
+                .find(&text(needle))
+                .map(|index| haystack[..index].chars().count())
+        }
+    };
+    Ok(Value::Int64(
+        position.map_or(0, |position| position as i64 + 1),
+    ))
+}
+
+/// The number of characters of a text, up to its first NUL character, or the number of bytes
+/// of a blob
+fn length(arguments: &[Value]) -> Result<Value> {
+    let length = match &arguments[0] {
+        Value::Blob(b) => b.len(),
+        other => text(other).split('\0').next().unwrap_or("").chars().count(),
+    };
+    Ok(Value::Int64(length as i64))
+}
+
+/// Whether the text of the second argument matches the LIKE pattern of the first one, with the
+/// escape character given by the third one
+fn like(arguments: &[Value]) -> Result<Value> {
+    let escape = match arguments.get(2) {
+        Some(escape) => match text(escape).chars().collect::<Vec<_>>()[..] {
+            [escape] => Some(escape),
+            _ => bail!("ESCAPE expression must be a single character"),
+        },
+        None => None,
+    };
+    let matches = pattern::like(&text(&arguments[0]), &text(&arguments[1]), escape);
+    Ok(Value::Int64(matches as i64))
+}
+
+/// Whether the text of the second argument matches the GLOB pattern of the first one
+fn glob(arguments: &[Value]) -> Result<Value> {
+    let matches = pattern::glob(&text(&arguments[0]), &text(&arguments[1]));
+    Ok(Value::Int64(matches as i64))
+}
+
+/// Only ASCII characters change case, like in SQLite built without ICU
+fn lower(arguments: &[Value]) -> Result<Value> {
+    Ok(Value::String(text(&arguments[0]).to_ascii_lowercase()))
+}
+
+fn upper(arguments: &[Value]) -> Result<Value> {
+    Ok(Value::String(text(&arguments[0]).to_ascii_uppercase()))
+}
+
+/// The characters removed by the trim functions: those of the second argument, spaces by
+/// default
+fn trimmed_characters(arguments: &[Value]) -> Vec<char> {
+    match arguments.get(1) {
+        Some(characters) => text(characters).chars().collect(),
+        None => vec![' '],
+    }
+}
+
+fn trim(arguments: &[Value]) -> Result<Value> {
+    let characters = trimmed_characters(arguments);
+    Ok(Value::String(
+        text(&arguments[0])
+            .trim_matches(characters.as_slice())
+            .to_string(),
+    ))
+}
+
+fn ltrim(arguments: &[Value]) -> Result<Value> {
+    let characters = trimmed_characters(arguments);
+    Ok(Value::String(
+        text(&arguments[0])
+            .trim_start_matches(characters.as_slice())
+            .to_string(),
+    ))
+}
+
+fn rtrim(arguments: &[Value]) -> Result<Value> {
+    let characters = trimmed_characters(arguments);
+    Ok(Value::String(
+        text(&arguments[0])
+            .trim_end_matches(characters.as_slice())
+            .to_string(),
+    ))
+}
+
+/// The scalar `max`, NULL as soon as one of the arguments is
+fn max(arguments: &[Value], collation: &Collation) -> Result<Value> {
+    extremum(arguments, collation, Ordering::Greater)
+}
+
+fn min(arguments: &[Value], collation: &Collation) -> Result<Value> {
+    extremum(arguments, collation, Ordering::Less)
+}
+
+/// The first of the arguments which compares to each of the others as `ordering` or equal
+fn extremum(arguments: &[Value], collation: &Collation, ordering: Ordering) -> Result<Value> {
+    let mut extremum = &arguments[0];
+    for argument in &arguments[1..] {
+        if collation.compare(argument, extremum) == ordering {
+            extremum = argument;
+        }
+    }
+    Ok(extremum.clone())
+}
+
+fn nullif(arguments: &[Value], collation: &Collation) -> Result<Value> {
+    let (first, second) = (&arguments[0], &arguments[1]);
+    let equal = !first.is_null()
+        && !second.is_null()
+        && collation.compare(first, second) == Ordering::Equal;
+    Ok(if equal { Value::Null } else { first.clone() })
+}
+
+/// The value as an SQL literal
+fn quote(arguments: &[Value]) -> Result<Value> {
+    Ok(Value::String(match &arguments[0] {
+        Value::Null => "NULL".to_string(),
+        Value::Float64(r) => format_real(*r),
+        Value::String(s) => format!("'{}'", s.replace('\'', "''")),
+        Value::Blob(b) => format!(
+            "X'{}'",
+            b.iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<String>()
+        ),
+        other => to_integer(other).to_string(),
+    }))
+}
+
+/// A pseudo-random 64 bit integer
+fn random(_: &[Value]) -> Result<Value> {
+    // each `RandomState` is seeded differently, so hashing nothing gives a new number each time
+    Ok(Value::Int64(
+        RandomState::new().build_hasher().finish() as i64
+    ))
+}
+
+fn replace(arguments: &[Value]) -> Result<Value> {
+    let pattern = text(&arguments[1]);
+    if pattern.is_empty() {
+        return Ok(arguments[0].clone());
+    }
+    Ok(Value::String(
+        text(&arguments[0]).replace(&pattern, &text(&arguments[2])),
+    ))
+}
+
+/// Rounds to the given number of digits after the decimal point, halves away from zero. The
+/// result is always a real.
+fn round(arguments: &[Value]) -> Result<Value> {
+    let digits = arguments.get(1).map_or(0, to_integer).clamp(0, 30) as usize;
+    let value = to_numeric(&arguments[0]).map_or(0.0, Numeric::as_real);
+    let largest = (i64::MAX - 1) as f64;
+    let rounded = if digits == 0 && value >= 0.0 && value < largest {
+        (value + 0.5) as i64 as f64
+    } else if digits == 0 && value < 0.0 && -value < largest {
+        -((-value + 0.5) as i64 as f64)
+    } else {
+        printf::fixed(value, digits).parse().unwrap_or(value)
+    };
+    Ok(Value::Float64(rounded))
+}
+
+/// The part of a text starting at the character at the given 1-based position, or of a blob
+/// starting at the byte at that position. A negative position counts from the end, and a
+/// negative length takes the characters before the position rather than after it.
+fn substr(arguments: &[Value]) -> Result<Value> {
+    let blob = match &arguments[0] {
+        Value::Blob(b) => Some(b),
+        _ => None,
+    };
+    let text = text(&arguments[0]);
+    let total = blob.map_or_else(|| text.chars().count(), |b| b.len()) as i64;
+    let mut start = to_integer(&arguments[1]);
+    let (mut length, negative_length) = match arguments.get(2) {
+        Some(length) => {
+            let length = to_integer(length);
+            (length.saturating_abs(), length < 0)
+        }
+        None => (MAX_LENGTH, false),
+    };
+    // SQLite's arithmetic, which makes position 0 the one before the first character
+    if start < 0 {
+        start = start.saturating_add(total);
+        if start < 0 {
+            length = length.saturating_add(start).max(0);
+            start = 0;
+        }
+    } else if start > 0 {
+        start -= 1;
+    } else if length > 0 {
+        length -= 1;
+    }
+    if negative_length {
+        start -= length;
+        if start < 0 {
+            length += start;
+            start = 0;
+        }
+    }
+    let (start, length) = (start as usize, length as usize);
+    Ok(match blob {
+        Some(b) => {
+            let start = start.min(b.len());
+            let end = start.saturating_add(length).min(b.len());
+            Value::Blob(b[start..end].to_vec())
+        }
+        None => Value::String(text.chars().skip(start).take(length).collect()),
+    })
+}
+
+fn type_of(arguments: &[Value]) -> Result<Value> {
+    let name = match &arguments[0] {
+        Value::Null => "null",
+        Value::Float64(_) => "real",
+        Value::String(_) => "text",
+        Value::Blob(_) => "blob",
+        _ => "integer",
+    };
+    Ok(Value::String(name.to_string()))
+}
+
+/// The blob written in hexadecimal by the first argument, NULL when it isn't hexadecimal. The
+/// characters of the second argument may appear between pairs of digits, and are ignored.
+fn unhex(arguments: &[Value]) -> Result<Value> {
+    let ignored: Vec<char> = arguments
+        .get(1)
+        .map(text)
+        .unwrap_or_default()
+        .chars()
+        .collect();
+    let text = text(&arguments[0]);
+    let mut characters = text.chars();
+    let mut bytes = Vec::with_capacity(text.len() / 2);
+    while let Some(character) = characters.next() {
+        if ignored.contains(&character) {
+            continue;
+        }
+        let high = character.to_digit(16);
+        let low = characters.next().and_then(|c| c.to_digit(16));
+        match (high, low) {
+            (Some(high), Some(low)) => bytes.push((high * 16 + low) as u8),
+            _ => return Ok(Value::Null),
+        }
+    }
+    Ok(Value::Blob(bytes))
+}
+
+/// The code point of the first character of a text, NULL when it is empty
+fn unicode(arguments: &[Value]) -> Result<Value> {
+    Ok(text(&arguments[0])
+        .chars()
+        .next()
+        .map_or(Value::Null, |c| Value::Int64(c as i64)))
+}
+
+fn zeroblob(arguments: &[Value]) -> Result<Value> {
+    let length = to_integer(&arguments[0]).max(0);
+    if length > MAX_LENGTH {
+        bail!("string or blob too big");
+    }
+    Ok(Value::Blob(vec![0; length as usize]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn call(name: &str, arguments: &[Value]) -> Value {
+        lookup(name, arguments.len(), &Functions::default())
+            .unwrap()
+            .call(arguments)
+            .unwrap()
+    }
+
+    fn text(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn looks_up_functions_by_name_and_argument_count() {
+        assert!(lookup("UPPER", 1, &Functions::default()).is_ok());
+        assert_eq!(
+            lookup("substr", 1, &Functions::default())
+                .err()
+                .unwrap()
+                .to_string(),
+            "wrong number of arguments to function substr()"
+        );
+        assert_eq!(
+            lookup("nope", 0, &Functions::default())
+                .err()
+                .unwrap()
+                .to_string(),
+            "no such function: nope"
+        );
+    }
+
+    #[test]
+    fn prefers_functions_defined_by_the_application() {
+        let mut functions = Functions::default();
+        let constant = |value: i64| Box::new(move |_: &[Value]| Ok(Value::Int64(value)));
+        functions.add_scalar("upper", 1, true, constant(1)).unwrap();
+        functions
+            .add_scalar("twice", -1, true, constant(2))
+            .unwrap();
+        functions.add_scalar("twice", 2, true, constant(3)).unwrap();
+        let call = |functions: &Functions, name: &str, arguments: usize| {
+            lookup(name, arguments, functions)
+                .and_then(|function| function.call(&[]))
+                .map_err(|error| error.to_string())
+        };
+        assert_eq!(call(&functions, "UPPER", 1), Ok(Value::Int64(1)));
+        assert_eq!(call(&functions, "twice", 0), Ok(Value::Int64(2)));
+        assert_eq!(call(&functions, "twice", 2), Ok(Value::Int64(3)));
+        functions.add_scalar("Twice", 2, true, constant(4)).unwrap();
+        assert_eq!(call(&functions, "twice", 2), Ok(Value::Int64(4)));
+        assert!(functions
+            .add_scalar("many", 128, true, constant(0))
+            .is_err());
+    }
+
+    #[test]
+    fn propagates_nulls_like_sqlite() {
+        assert_eq!(call("length", &[Value::Null]), Value::Null);
+        assert_eq!(call("max", &[Value::Int64(1), Value::Null]), Value::Null);
+        assert_eq!(call("hex", &[Value::Null]), text(""));
+        assert_eq!(call("quote", &[Value::Null]), text("NULL"));
+        assert_eq!(
+            call("coalesce", &[Value::Null, Value::Null, Value::Int64(3)]),
+            Value::Int64(3)
+        );
+        assert_eq!(
+            call("nullif", &[Value::Int64(1), Value::Float64(1.0)]),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn shows_text_in_hexadecimal_as_the_database_encodes_it() {
+        let hex = |value: Value, encoding: TextEncoding| {
+            lookup("hex", 1, &Functions::default())
+                .unwrap()
+                .call_with_encoding(&[value], encoding)
+                .unwrap()
+        };
+        assert_eq!(hex(text("Zed"), TextEncoding::Utf8), text("5A6564"));
+        assert_eq!(
+            hex(text("Zed"), TextEncoding::Utf16le),
+            text("5A0065006400")
+        );
+        assert_eq!(
+            hex(text("Zed"), TextEncoding::Utf16be),
+            text("005A00650064")
+        );
+        assert_eq!(
+            hex(Value::Float64(1.5), TextEncoding::Utf16le),
+            text("312E35")
+        );
+        assert_eq!(hex(Value::Null, TextEncoding::Utf16le), text(""));
+    }
+
+    #[test]
+    fn takes_substrings_like_sqlite() {
+        let substr = |start: i64, length: Option<i64>| {
+            let mut arguments = vec![text("hello"), Value::Int64(start)];
+            arguments.extend(length.map(Value::Int64));
+            call("substr", &arguments)
+        };
+        assert_eq!(substr(2, None), text("ello"));
+        assert_eq!(substr(0, Some(2)), text("h"));
+        assert_eq!(substr(-3, Some(2)), text("ll"));
+        assert_eq!(substr(4, Some(-3)), text("hel"));
+        assert_eq!(substr(-10, Some(9)), text("hell"));
+        assert_eq!(
+            call("substr", &[Value::Blob(vec![1, 2, 3]), Value::Int64(2)]),
+            Value::Blob(vec![2, 3])
+        );
+    }
+
+    #[test]
+    fn rounds_halves_away_from_zero() {
+        let round =
+            |value: f64, digits: i64| call("round", &[Value::Float64(value), Value::Int64(digits)]);
+        assert_eq!(round(2.5, 0), Value::Float64(3.0));
+        assert_eq!(round(-2.5, 0), Value::Float64(-3.0));
+        assert_eq!(round(2.675, 2), Value::Float64(2.67));
+        assert_eq!(round(0.125, 2), Value::Float64(0.13));
+    }
+}
@@ -0,0 +1,1157 @@
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::engine::expression::to_text;
+
+use super::printf;
+
+/// Milliseconds in a day
+const DAY: i64 = 86_400_000;
+/// The julian day number of 1970-01-01 00:00:00, in milliseconds
+const UNIX_EPOCH_DAY: i64 = 210_866_760_000_000;
+/// The largest supported julian day number, 9999-12-31 23:59:59.999, in milliseconds
+const MAX_DAY: i64 = 464_269_060_799_999;
+
+/// The units of the `+NNN unit` modifiers: their name, the largest amount which can be added
+/// and how many seconds one of them lasts. Months and years are added to the calendar fields
+/// instead, only their fractional part being added as seconds.
+const UNITS: [(&str, f32, f32); 6] = [
+    ("second", 4.6427e14, 1.0),
+    ("minute", 7.7379e12, 60.0),
+    ("hour", 1.2897e11, 3600.0),
+    ("day", 5373485.0, 86400.0),
+    ("month", 176546.0, 2592000.0),
+    ("year", 14713.0, 31536000.0),
+];
+
+/// A point in time being computed by a date and time function. It is known as a julian day
+/// number, as calendar fields or as both, and each form is computed from the other when
+/// needed, in the same order as SQLite does so that out of range fields are normalized the
+/// same way.
+#[derive(Debug, Clone, Copy, Default)]
+struct DateTime {
+    /// The julian day number, in milliseconds
+    day_number: i64,
+    year: i32,
+    month: i32,
+    day: i32,
+    hour: i32,
+    minute: i32,
+    second: f64,
+    /// The offset in minutes of the time zone the fields were given in
+    time_zone: i32,
+    /// The number the time value was given as, until a modifier tells what it counts
+    raw: Option<f64>,
+    valid_day_number: bool,
+    valid_date: bool,
+    valid_time: bool,
+    is_local: bool,
+    is_utc: bool,
+    /// Whether seconds are written with milliseconds, as asked by the `subsec` modifier
+    subsecond: bool,
+    /// How many days the day of month overflowed the month by, which the `floor` modifier
+    /// takes back
+    overflow: i32,
+    error: bool,
+}
+
+impl DateTime {
+    fn now() -> DateTime {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as i64);
+        DateTime {
+            day_number: UNIX_EPOCH_DAY + elapsed,
+            valid_day_number: true,
+            is_utc: true,
+            ..DateTime::default()
+        }
+    }
+
+    /// A time value given as a number, which is a julian day number unless a modifier says
+    /// otherwise
+    fn from_number(number: f64) -> DateTime {
+        let mut date_time = DateTime {
+            raw: Some(number),
+            ..DateTime::default()
+        };
+        if (0.0..5373484.5).contains(&number) {
+            date_time.day_number = (number * DAY as f64 + 0.5) as i64;
+            date_time.valid_day_number = true;
+        }
+        date_time
+    }
+
+    /// Parses a time value given as text: `YYYY-MM-DD` followed by an optional time,
+    /// `HH:MM[:SS[.SSS]]` followed by an optional time zone, `now`, or a number.
+    fn parse(text: &str) -> Option<DateTime> {
+        let mut date_time = DateTime::default();
+        if date_time.parse_date(text.as_bytes()).is_some() {
+            return Some(date_time);
+        }
+        let mut date_time = DateTime::default();
+        if date_time.parse_time(text.as_bytes()).is_some() {
+            Some(date_time)
+        } else if text.eq_ignore_ascii_case("now") {
+            Some(DateTime::now())
+        } else if let Some(number) = parse_real(text.as_bytes()) {
+            Some(DateTime::from_number(number))
+        } else if text.eq_ignore_ascii_case("subsec") || text.eq_ignore_ascii_case("subsecond") {
+            Some(DateTime {
+                subsecond: true,
+                ..DateTime::now()
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Parses `[-]YYYY-MM-DD`, optionally followed by spaces or a `T` and a time
+    fn parse_date(&mut self, text: &[u8]) -> Option<()> {
+        let (negative, text) = match text.split_first() {
+            Some((b'-', text)) => (true, text),
+            _ => (false, text),
+        };
+        let year = digits(text, 0, 4, 0..=14712)?;
+        separator(text, 4, b'-')?;
+        let month = digits(text, 5, 2, 1..=12)?;
+        separator(text, 7, b'-')?;
+        let day = digits(text, 8, 2, 1..=31)?;
+        let mut rest = &text[10..];
+        while let Some((b'T' | b' ' | b'\t'..=b'\r', tail)) = rest.split_first() {
+            rest = tail;
+        }
+        if self.parse_time(rest).is_none() {
+            if !rest.is_empty() {
+                return None;
+            }
+            self.valid_time = false;
+        }
+        self.valid_day_number = false;
+        self.valid_date = true;
+        self.year = if negative { -year } else { year };
+        self.month = month;
+        self.day = day;
+        self.compute_overflow();
+        if self.time_zone != 0 {
+            self.compute_day_number();
+        }
+        Some(())
+    }
+
+    /// Parses `HH:MM[:SS[.SSS]]` followed by an optional time zone
+    fn parse_time(&mut self, text: &[u8]) -> Option<()> {
+        let hour = digits(text, 0, 2, 0..=24)?;
+        separator(text, 2, b':')?;
+        let minute = digits(text, 3, 2, 0..=59)?;
+        let mut rest = &text[5..];
+        let mut second = 0.0;
+        if rest.first() == Some(&b':') {
+            second = digits(rest, 1, 2, 0..=59)? as f64;
+            rest = &rest[3..];
+            if rest.first() == Some(&b'.') && rest.get(1).is_some_and(u8::is_ascii_digit) {
+                let count = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let (fraction, scale) =
+                    rest[1..=count]
+                        .iter()
+                        .fold((0.0, 1.0), |(fraction, scale), digit| {
+                            (fraction * 10.0 + (digit - b'0') as f64, scale * 10.0)
+                        });
+                // truncated rather than rounded, so that it can't round up to the next second
+                second += (fraction / scale).min(0.999);
+                rest = &rest[count + 1..];
+            }
+        }
+        self.valid_day_number = false;
+        self.raw = None;
+        self.valid_time = true;
+        self.hour = hour;
+        self.minute = minute;
+        self.second = second;
+        self.parse_time_zone(rest)
+    }
+
+    /// Parses the optional `[+-]HH:MM` or `Z` after a time, which may be surrounded by spaces
+    fn parse_time_zone(&mut self, text: &[u8]) -> Option<()> {
+        let mut text = trim_spaces(text);
+        self.time_zone = 0;
+        let sign = match text.first() {
+            None => return Some(()),
+            Some(b'-') => -1,
+            Some(b'+') => 1,
+            Some(b'Z' | b'z') => {
+                self.is_local = false;
+                self.is_utc = true;
+                0
+            }
+            Some(_) => return None,
+        };
+        if sign == 0 {
+            text = &text[1..];
+        } else {
+            let hours = digits(text, 1, 2, 0..=14)?;
+            separator(text, 3, b':')?;
+            let minutes = digits(text, 4, 2, 0..=59)?;
+            self.time_zone = sign * (minutes + hours * 60);
+            text = &text[6..];
+        }
+        trim_spaces(text).is_empty().then_some(())
+    }
+
+    fn set_error(&mut self) {
+        *self = DateTime {
+            error: true,
+            ..DateTime::default()
+        };
+    }
+
+    fn clear_fields(&mut self) {
+        self.valid_date = false;
+        self.valid_time = false;
+        self.time_zone = 0;
+    }
+
+    /// Computes how many days the day of month goes past the end of the month
+    fn compute_overflow(&mut self) {
+        let leap_year = self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0);
+        self.overflow = match self.month {
+            _ if self.day <= 28 => 0,
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 0,
+            2 if leap_year => self.day - 29,
+            2 => self.day - 28,
+            _ => (self.day == 31) as i32,
+        };
+    }
+
+    /// Brings the month back within 1 to 12, carrying the excess to the year
+    fn normalize_month(&mut self) {
+        let years = if self.month > 0 {
+            (self.month - 1) / 12
+        } else {
+            (self.month - 12) / 12
+        };
+        self.year += years;
+        self.month -= years * 12;
+    }
+
+    /// Computes the julian day number from the calendar fields, applying the time zone. A
+    /// value without a date is on 2000-01-01.
+    fn compute_day_number(&mut self) {
+        if self.valid_day_number {
+            return;
+        }
+        let (mut year, mut month, day) = if self.valid_date {
+            (self.year, self.month, self.day)
+        } else {
+            (2000, 1, 1)
+        };
+        if !(-4713..=9999).contains(&year) || self.raw.is_some() {
+            self.set_error();
+            return;
+        }
+        if month <= 2 {
+            year -= 1;
+            month += 12;
+        }
+        let a = (year + 4800) / 100;
+        let b = 38 - a + a / 4;
+        let x1 = 36525 * (year + 4716) / 100;
+        let x2 = 306001 * (month + 1) / 10000;
+        self.day_number = (((x1 + x2 + day + b) as f64 - 1524.5) * DAY as f64) as i64;
+        self.valid_day_number = true;
+        if self.valid_time {
+            self.day_number += self.hour as i64 * 3_600_000
+                + self.minute as i64 * 60_000
+                + (self.second * 1000.0 + 0.5) as i64;
+            if self.time_zone != 0 {
+                self.day_number -= self.time_zone as i64 * 60_000;
+                self.valid_date = false;
+                self.valid_time = false;
+                self.time_zone = 0;
+                self.is_utc = true;
+                self.is_local = false;
+            }
+        }
+    }
+
+    /// Computes the date from the julian day number
+    fn compute_date(&mut self) {
+        if self.valid_date {
+            return;
+        }
+        if !self.valid_day_number {
+            (self.year, self.month, self.day) = (2000, 1, 1);
+        } else if !(0..=MAX_DAY).contains(&self.day_number) {
+            self.set_error();
+            return;
+        } else {
+            let z = ((self.day_number + DAY / 2) / DAY) as i32;
+            let alpha = ((z as f64 + 32044.75) / 36524.25) as i32 - 52;
+            let a = z + 1 + alpha - (alpha + 100) / 4 + 25;
+            let b = a + 1524;
+            let c = ((b as f64 - 122.1) / 365.25) as i32;
+            let d = (36525 * (c & 32767)) / 100;
+            let e = ((b - d) as f64 / 30.6001) as i32;
+            let x1 = (30.6001 * e as f64) as i32;
+            self.day = b - d - x1;
+            self.month = if e < 14 { e - 1 } else { e - 13 };
+            self.year = if self.month > 2 { c - 4716 } else { c - 4715 };
+        }
+        self.valid_date = true;
+    }
+
+    /// Computes the time of day from the julian day number
+    fn compute_time(&mut self) {
+        if self.valid_time {
+            return;
+        }
+        self.compute_day_number();
+        let milliseconds = ((self.day_number + DAY / 2) % DAY) as i32;
+        self.second = (milliseconds % 60_000) as f64 / 1000.0;
+        let minutes = milliseconds / 60_000;
+        self.minute = minutes % 60;
+        self.hour = minutes / 60;
+        self.raw = None;
+        self.valid_time = true;
+    }
+
+    fn compute_fields(&mut self) {
+        self.compute_date();
+        self.compute_time();
+    }
+
+    /// Days since the first of January of the year
+    fn days_in_year(&self) -> i64 {
+        let mut january_first = DateTime {
+            valid_day_number: false,
+            month: 1,
+            day: 1,
+            ..*self
+        };
+        january_first.compute_day_number();
+        (self.day_number - january_first.day_number + DAY / 2) / DAY
+    }
+
+    /// Days since the last monday, 0 on mondays
+    fn days_after_monday(&self) -> i64 {
+        ((self.day_number + DAY / 2) / DAY) % 7
+    }
+
+    /// Days since the last sunday, 0 on sundays
+    fn days_after_sunday(&self) -> i64 {
+        ((self.day_number + DAY + DAY / 2) / DAY) % 7
+    }
+
+    /// The thursday of the same ISO 8601 week, whose year is the week's year
+    fn thursday(&self) -> DateTime {
+        let mut thursday = DateTime {
+            day_number: self.day_number + (3 - self.days_after_monday()) * DAY,
+            valid_date: false,
+            ..*self
+        };
+        thursday.compute_date();
+        thursday
+    }
+
+    /// Applies a modifier, the first one being at index 1. Returns false when the modifier is
+    /// unknown or can't be applied, which makes the function return NULL.
+    fn modify(&mut self, modifier: &str, index: usize) -> Result<bool> {
+        let lowercase = modifier.to_ascii_lowercase();
+        Ok(match lowercase.as_str() {
+            // the number is a julian day number if it can be one, and a unix time otherwise
+            "auto" => {
+                if index > 1 {
+                    return Ok(false);
+                }
+                match self.raw {
+                    Some(_) if self.valid_day_number => {
+                        self.raw = None;
+                        true
+                    }
+                    None => true,
+                    Some(seconds) => {
+                        if (-210_866_760_000.0..=253_402_300_799.0).contains(&seconds) {
+                            self.set_unix_time(seconds);
+                        }
+                        self.raw.is_none()
+                    }
+                }
+            }
+            // rolls days past the end of the month over to the next month, as done by default
+            "ceiling" => {
+                self.compute_day_number();
+                self.clear_fields();
+                self.overflow = 0;
+                true
+            }
+            // takes days past the end of the month back to its last day
+            "floor" => {
+                self.compute_day_number();
+                self.day_number -= self.overflow as i64 * DAY;
+                self.clear_fields();
+                true
+            }
+            "julianday" => {
+                if index > 1 || !self.valid_day_number || self.raw.is_none() {
+                    return Ok(false);
+                }
+                self.raw = None;
+                true
+            }
+            "localtime" => {
+                if !self.is_local {
+                    self.convert_to_local_time()?;
+                }
+                self.is_utc = false;
+                self.is_local = true;
+                true
+            }
+            "unixepoch" if self.raw.is_some() => {
+                if index > 1 {
+                    return Ok(false);
+                }
+                let seconds = self.raw.unwrap_or_default();
+                let day_number = seconds * 1000.0 + UNIX_EPOCH_DAY as f64;
+                if (0.0..(MAX_DAY + 1) as f64).contains(&day_number) {
+                    self.set_unix_time(seconds);
+                }
+                self.raw.is_none()
+            }
+            "utc" => {
+                if !self.is_utc {
+                    self.convert_to_utc()?;
+                }
+                true
+            }
+            "subsec" | "subsecond" => {
+                self.subsecond = true;
+                true
+            }
+            _ => {
+                if let Some(unit) = lowercase.strip_prefix("start of ") {
+                    self.start_of(unit)
+                } else if let Some(weekday) = lowercase.strip_prefix("weekday ") {
+                    self.next_weekday(weekday)
+                } else if modifier.starts_with(['+', '-'])
+                    || modifier.starts_with(|c: char| c.is_ascii_digit())
+                {
+                    self.shift(modifier.as_bytes())
+                } else {
+                    false
+                }
+            }
+        })
+    }
+
+    fn set_unix_time(&mut self, seconds: f64) {
+        self.clear_fields();
+        self.day_number = (seconds * 1000.0 + UNIX_EPOCH_DAY as f64 + 0.5) as i64;
+        self.valid_day_number = true;
+        self.raw = None;
+    }
+
+    /// `start of day`, `start of month` and `start of year`
+    fn start_of(&mut self, unit: &str) -> bool {
+        if !self.valid_day_number && !self.valid_date && !self.valid_time {
+            return false;
+        }
+        self.compute_date();
+        self.valid_time = true;
+        (self.hour, self.minute, self.second) = (0, 0, 0.0);
+        self.raw = None;
+        self.time_zone = 0;
+        self.valid_day_number = false;
+        match unit {
+            "day" => {}
+            "month" => self.day = 1,
+            "year" => (self.month, self.day) = (1, 1),
+            _ => return false,
+        }
+        true
+    }
+
+    /// `weekday N`, which moves forward to the next day which is the Nth of the week, sunday
+    /// being 0, unless it already is
+    fn next_weekday(&mut self, weekday: &str) -> bool {
+        let Some(weekday) = parse_real(weekday.as_bytes())
+            .filter(|weekday| (0.0..7.0).contains(weekday) && weekday.fract() == 0.0)
+        else {
+            return false;
+        };
+        let weekday = weekday as i64;
+        self.compute_fields();
+        self.time_zone = 0;
+        self.valid_day_number = false;
+        self.compute_day_number();
+        let mut current = self.days_after_sunday();
+        if current > weekday {
+            current -= 7;
+        }
+        self.day_number += (weekday - current) * DAY;
+        self.clear_fields();
+        true
+    }
+
+    /// The modifiers starting with a number: `±NNN unit`, `±HH:MM[:SS[.SSS]]` and
+    /// `±YYYY-MM-DD[ HH:MM[:SS[.SSS]]]`
+    fn shift(&mut self, modifier: &[u8]) -> bool {
+        let sign = modifier[0];
+        let mut length = 1;
+        while let Some(&c) = modifier.get(length) {
+            if c == b':' || is_space(c) {
+                break;
+            }
+            if c == b'-'
+                && ((length == 5 && digits(modifier, 1, 4, 0..=14712).is_some())
+                    || (length == 6 && digits(modifier, 1, 5, 0..=14712).is_some()))
+            {
+                break;
+            }
+            length += 1;
+        }
+        let Some(amount) = parse_real(&modifier[..length]) else {
+            return false;
+        };
+        let mut time = &modifier[1..];
+        if modifier.get(length) == Some(&b'-') {
+            // years, months and days added to the calendar fields
+            if sign != b'+' && sign != b'-' {
+                return false;
+            }
+            let (Some(years), Some(months), Some(days)) = (
+                digits(modifier, 1, length - 1, 0..=14712),
+                separator(modifier, length, b'-').and(digits(modifier, length + 1, 2, 0..=12)),
+                separator(modifier, length + 3, b'-').and(digits(modifier, length + 4, 2, 0..=31)),
+            ) else {
+                return false;
+            };
+            if months >= 12 || days >= 31 {
+                return false;
+            }
+            self.compute_fields();
+            self.valid_day_number = false;
+            let days = if sign == b'-' {
+                self.year -= years;
+                self.month -= months;
+                -days
+            } else {
+                self.year += years;
+                self.month += months;
+                days
+            };
+            self.normalize_month();
+            self.compute_overflow();
+            self.compute_day_number();
+            self.valid_time = false;
+            self.valid_date = false;
+            self.day_number += days as i64 * DAY;
+            let end = length + 6;
+            let Some(&c) = modifier.get(end) else {
+                return true;
+            };
+            if !is_space(c)
+                || digits(modifier, end + 1, 2, 0..=24).is_none()
+                || separator(modifier, end + 3, b':').is_none()
+                || digits(modifier, end + 4, 2, 0..=59).is_none()
+            {
+                return false;
+            }
+            time = &modifier[end + 1..];
+        } else if modifier.get(length) != Some(&b':') {
+            let unit = &modifier[length..];
+            let start = unit
+                .iter()
+                .position(|&c| !is_space(c))
+                .unwrap_or(unit.len());
+            return self.add(amount, &unit[start..]);
+        } else if modifier[0].is_ascii_digit() {
+            time = modifier;
+        }
+        // a time of day added to the time
+        let mut offset = DateTime::default();
+        if offset.parse_time(time).is_none() {
+            return false;
+        }
+        offset.compute_day_number();
+        offset.day_number = (offset.day_number - DAY / 2) % DAY;
+        if sign == b'-' {
+            offset.day_number = -offset.day_number;
+        }
+        self.compute_day_number();
+        self.clear_fields();
+        self.day_number += offset.day_number;
+        true
+    }
+
+    /// `±NNN unit`, where the unit may be plural
+    fn add(&mut self, mut amount: f64, unit: &[u8]) -> bool {
+        if !(3..=10).contains(&unit.len()) {
+            return false;
+        }
+        let unit = match unit.split_last() {
+            Some((b's' | b'S', singular)) => singular,
+            _ => unit,
+        };
+        self.compute_day_number();
+        let rounder = if amount < 0.0 { -0.5 } else { 0.5 };
+        self.overflow = 0;
+        let added = UNITS.iter().find(|(name, limit, _)| {
+            name.as_bytes().eq_ignore_ascii_case(unit)
+                && amount > -(*limit as f64)
+                && amount < *limit as f64
+        });
+        if let Some((name, _, seconds)) = added {
+            if *name == "month" || *name == "year" {
+                self.compute_fields();
+                if *name == "month" {
+                    self.month += amount as i32;
+                    self.normalize_month();
+                } else {
+                    self.year += amount as i32;
+                }
+                self.compute_overflow();
+                self.valid_day_number = false;
+                amount -= (amount as i32) as f64;
+            }
+            self.compute_day_number();
+            self.day_number += (amount * 1000.0 * *seconds as f64 + rounder) as i64;
+        }
+        self.clear_fields();
+        added.is_some()
+    }
+
+    /// Converts the time, taken to be UTC, to the local time. The C library only knows about
+    /// the years from 1970 to 2037, so other years are converted as a year within that range
+    /// which starts on the same day of the week.
+    fn convert_to_local_time(&mut self) -> Result<()> {
+        self.compute_day_number();
+        let mut shifted = *self;
+        let mut years = 0;
+        if !(UNIX_EPOCH_DAY..=213_014_145_600_000).contains(&self.day_number) {
+            shifted.compute_fields();
+            years = (2000 + shifted.year % 4) - shifted.year;
+            shifted.year += years;
+            shifted.valid_day_number = false;
+            shifted.compute_day_number();
+        }
+        let local = local_time(shifted.day_number / 1000 - UNIX_EPOCH_DAY / 1000)
+            .ok_or_else(|| anyhow!("local time unavailable"))?;
+        self.year = local.year - years;
+        self.month = local.month;
+        self.day = local.day;
+        self.hour = local.hour;
+        self.minute = local.minute;
+        self.second = local.second as f64 + (self.day_number % 1000) as f64 * 0.001;
+        self.valid_date = true;
+        self.valid_time = true;
+        self.valid_day_number = false;
+        self.raw = None;
+        self.time_zone = 0;
+        self.error = false;
+        Ok(())
+    }
+
+    /// Converts the time, taken to be local, to UTC, by guessing the UTC time whose local time
+    /// it is and correcting the guess a few times
+    fn convert_to_utc(&mut self) -> Result<()> {
+        self.compute_day_number();
+        let original = self.day_number;
+        let mut guess = original;
+        let mut error = 0;
+        for _ in 0..4 {
+            guess -= error;
+            let mut local = DateTime {
+                day_number: guess,
+                valid_day_number: true,
+                ..DateTime::default()
+            };
+            local.convert_to_local_time()?;
+            local.compute_day_number();
+            error = local.day_number - original;
+            if error == 0 {
+                break;
+            }
+        }
+        *self = DateTime {
+            day_number: guess,
+            valid_day_number: true,
+            is_utc: true,
+            ..DateTime::default()
+        };
+        Ok(())
+    }
+
+    fn date(&self) -> String {
+        format!(
+            "{}{:04}-{:02}-{:02}",
+            if self.year < 0 { "-" } else { "" },
+            self.year.abs() % 10000,
+            self.month,
+            self.day
+        )
+    }
+
+    fn time(&self) -> String {
+        if self.subsecond {
+            let milliseconds = (1000.0 * self.second + 0.5) as i32;
+            format!(
+                "{:02}:{:02}:{:02}.{:03}",
+                self.hour,
+                self.minute,
+                milliseconds / 1000 % 100,
+                milliseconds % 1000
+            )
+        } else {
+            format!(
+                "{:02}:{:02}:{:02}",
+                self.hour, self.minute, self.second as i32
+            )
+        }
+    }
+
+    fn unix_time(&self) -> Value {
+        if self.subsecond {
+            Value::Float64((self.day_number - UNIX_EPOCH_DAY) as f64 / 1000.0)
+        } else {
+            Value::Int64(self.day_number / 1000 - UNIX_EPOCH_DAY / 1000)
+        }
+    }
+}
+
+/// The calendar fields of a time in the local time zone
+struct LocalTime {
+    year: i32,
+    month: i32,
+    day: i32,
+    hour: i32,
+    minute: i32,
+    second: i32,
+}
+
+/// Asks the C library for the local time of a unix time, which follows the `TZ` environment
+/// variable or the system's time zone
+#[cfg(unix)]
+fn local_time(seconds: i64) -> Option<LocalTime> {
+    use std::os::raw::{c_char, c_int, c_long};
+
+    #[repr(C)]
+    struct Tm {
+        tm_sec: c_int,
+        tm_min: c_int,
+        tm_hour: c_int,
+        tm_mday: c_int,
+        tm_mon: c_int,
+        tm_year: c_int,
+        tm_wday: c_int,
+        tm_yday: c_int,
+        tm_isdst: c_int,
+        tm_gmtoff: c_long,
+        tm_zone: *const c_char,
+    }
+
+    extern "C" {
+        fn localtime_r(time: *const c_long, result: *mut Tm) -> *mut Tm;
+    }
+
+    let time = c_long::try_from(seconds).ok()?;
+    // SAFETY: `localtime_r` only writes to the given `tm`, which all zeros is a valid value of
+    let tm = unsafe {
+        let mut tm: Tm = std::mem::zeroed();
+        if localtime_r(&time, &mut tm).is_null() {
+            return None;
+        }
+        tm
+    };
+    Some(LocalTime {
+        year: tm.tm_year + 1900,
+        month: tm.tm_mon + 1,
+        day: tm.tm_mday,
+        hour: tm.tm_hour,
+        minute: tm.tm_min,
+        second: tm.tm_sec,
+    })
+}
+
+#[cfg(not(unix))]
+fn local_time(_seconds: i64) -> Option<LocalTime> {
+    None
+}
+
+fn is_space(c: u8) -> bool {
+    c == b' ' || (b'\t'..=b'\r').contains(&c)
+}
+
+fn trim_spaces(text: &[u8]) -> &[u8] {
+    let start = text
+        .iter()
+        .position(|&c| !is_space(c))
+        .unwrap_or(text.len());
+    let end = text
+        .iter()
+        .rposition(|&c| !is_space(c))
+        .map_or(start, |end| end + 1);
+    &text[start..end]
+}
+
+/// Reads a number written with exactly `count` digits at `position`, if it is within `range`
+fn digits(text: &[u8], position: usize, count: usize, range: RangeInclusive<i32>) -> Option<i32> {
+    let digits = text.get(position..position + count)?;
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let number = digits
+        .iter()
+        .fold(0, |number, digit| number * 10 + (digit - b'0') as i32);
+    range.contains(&number).then_some(number)
+}
+
+fn separator(text: &[u8], position: usize, separator: u8) -> Option<()> {
+    (text.get(position) == Some(&separator)).then_some(())
+}
+
+/// The number a whole text is written as, which may be surrounded by spaces
+fn parse_real(text: &[u8]) -> Option<f64> {
+    let text = trim_spaces(text);
+    if !text
+        .iter()
+        .all(|c| c.is_ascii_digit() || matches!(c, b'+' | b'-' | b'.' | b'e' | b'E'))
+    {
+        return None;
+    }
+    std::str::from_utf8(text).ok()?.parse().ok()
+}
+
+/// Reads a time value and applies its modifiers, giving None when the time value can't be
+/// read, a modifier can't be applied or the result is out of range. No arguments at all means
+/// the current time.
+fn date_time(arguments: &[Value]) -> Result<Option<DateTime>> {
+    let Some((value, modifiers)) = arguments.split_first() else {
+        return Ok(Some(DateTime::now()));
+    };
+    let mut date_time = match value {
+        Value::Null => return Ok(None),
+        Value::Float64(number) => DateTime::from_number(*number),
+        Value::String(_) | Value::Blob(_) => {
+            match to_text(value).as_deref().and_then(DateTime::parse) {
+                Some(date_time) => date_time,
+                None => return Ok(None),
+            }
+        }
+        other => DateTime::from_number(other.as_i64().unwrap_or(0) as f64),
+    };
+    for (index, modifier) in modifiers.iter().enumerate() {
+        let Some(modifier) = to_text(modifier) else {
+            return Ok(None);
+        };
+        if !date_time.modify(&modifier, index + 1)? {
+            return Ok(None);
+        }
+    }
+    date_time.compute_day_number();
+    if date_time.error || !(0..=MAX_DAY).contains(&date_time.day_number) {
+        return Ok(None);
+    }
+    if modifiers.is_empty() && date_time.valid_date && date_time.day > 28 {
+        // the date is computed again so that days past the end of the month roll over
+        date_time.valid_date = false;
+    }
+    Ok(Some(date_time))
+}
+
+/// Computes the result of a date and time function from the time its arguments give, NULL
+/// when they don't give one
+fn with_date_time(arguments: &[Value], result: impl FnOnce(DateTime) -> Value) -> Result<Value> {
+    Ok(date_time(arguments)?.map_or(Value::Null, result))
+}
+
+/// `date(time-value, modifier, ...)`, as `YYYY-MM-DD`
+pub fn date(arguments: &[Value]) -> Result<Value> {
+    with_date_time(arguments, |mut date_time| {
+        date_time.compute_date();
+        Value::String(date_time.date())
+    })
+}
+
+/// `time(time-value, modifier, ...)`, as `HH:MM:SS`
+pub fn time(arguments: &[Value]) -> Result<Value> {
+    with_date_time(arguments, |mut date_time| {
+        date_time.compute_time();
+        Value::String(date_time.time())
+    })
+}
+
+/// `datetime(time-value, modifier, ...)`, as `YYYY-MM-DD HH:MM:SS`
+pub fn datetime(arguments: &[Value]) -> Result<Value> {
+    with_date_time(arguments, |mut date_time| {
+        date_time.compute_fields();
+        Value::String(format!("{} {}", date_time.date(), date_time.time()))
+    })
+}
+
+/// `julianday(time-value, modifier, ...)`, the fractional number of days since noon in
+/// Greenwich on November 24, 4714 B.C.
+pub fn julianday(arguments: &[Value]) -> Result<Value> {
+    with_date_time(arguments, |date_time| {
+        Value::Float64(date_time.day_number as f64 / DAY as f64)
+    })
+}
+
+/// `unixepoch(time-value, modifier, ...)`, the number of seconds since 1970-01-01 00:00:00
+pub fn unixepoch(arguments: &[Value]) -> Result<Value> {
+    with_date_time(arguments, |date_time| date_time.unix_time())
+}
+
+/// `strftime(format, time-value, modifier, ...)`, which writes the time as told by the format.
+/// An unknown conversion makes it return NULL.
+pub fn strftime(arguments: &[Value]) -> Result<Value> {
+    let Some((format, arguments)) = arguments.split_first() else {
+        return Ok(Value::Null);
+    };
+    let Some(format) = to_text(format) else {
+        return Ok(Value::Null);
+    };
+    let Some(mut date_time) = date_time(arguments)? else {
+        return Ok(Value::Null);
+    };
+    date_time.compute_day_number();
+    date_time.compute_fields();
+    let twelve_hour = match date_time.hour {
+        0 => 12,
+        hour if hour > 12 => hour - 12,
+        hour => hour,
+    };
+    let mut output = String::new();
+    let mut characters = format.chars();
+    while let Some(c) = characters.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        let conversion = match characters.next() {
+            Some('d') => format!("{:02}", date_time.day),
+            Some('e') => format!("{:2}", date_time.day),
+            Some('f') => format!("{:06.3}", date_time.second.min(59.999)),
+            Some('F') => format!(
+                "{:04}-{:02}-{:02}",
+                date_time.year, date_time.month, date_time.day
+            ),
+            Some('G') => format!("{:04}", date_time.thursday().year),
+            Some('g') => format!("{:02}", date_time.thursday().year % 100),
+            Some('H') => format!("{:02}", date_time.hour),
+            Some('k') => format!("{:2}", date_time.hour),
+            Some('I') => format!("{:02}", twelve_hour),
+            Some('l') => format!("{:2}", twelve_hour),
+            Some('j') => format!("{:03}", date_time.days_in_year() + 1),
+            Some('J') => printf::general(date_time.day_number as f64 / DAY as f64, 16),
+            Some('m') => format!("{:02}", date_time.month),
+            Some('M') => format!("{:02}", date_time.minute),
+            Some('p') => (if date_time.hour >= 12 { "PM" } else { "AM" }).to_string(),
+            Some('P') => (if date_time.hour >= 12 { "pm" } else { "am" }).to_string(),
+            Some('R') => format!("{:02}:{:02}", date_time.hour, date_time.minute),
+            Some('s') => match date_time.unix_time() {
+                Value::Float64(seconds) => format!("{:.3}", seconds),
+                seconds => seconds.as_i64().unwrap_or_default().to_string(),
+            },
+            Some('S') => format!("{:02}", date_time.second as i32),
+            Some('T') => format!(
+                "{:02}:{:02}:{:02}",
+                date_time.hour, date_time.minute, date_time.second as i32
+            ),
+            Some('u') => match date_time.days_after_sunday() {
+                0 => "7".to_string(),
+                weekday => weekday.to_string(),
+            },
+            Some('w') => date_time.days_after_sunday().to_string(),
+            Some('U') => format!(
+                "{:02}",
+                (date_time.days_in_year() - date_time.days_after_sunday() + 7) / 7
+            ),
+            Some('V') => format!("{:02}", date_time.thursday().days_in_year() / 7 + 1),
+            Some('W') => format!(
+                "{:02}",
+                (date_time.days_in_year() - date_time.days_after_monday() + 7) / 7
+            ),
+            Some('Y') => format!("{:04}", date_time.year),
+            Some('%') => "%".to_string(),
+            _ => return Ok(Value::Null),
+        };
+        output.push_str(&conversion);
+    }
+    Ok(Value::String(output))
+}
+
+/// `timediff(time-value, time-value)`, the time from the second time to the first as
+/// `±YYYY-MM-DD HH:MM:SS.SSS`, counted in whole years and months first
+pub fn timediff(arguments: &[Value]) -> Result<Value> {
+    let (Some(mut first), Some(mut second)) =
+        (date_time(&arguments[..1])?, date_time(&arguments[1..])?)
+    else {
+        return Ok(Value::Null);
+    };
+    first.compute_fields();
+    second.compute_fields();
+    let sign = if first.day_number >= second.day_number {
+        '+'
+    } else {
+        '-'
+    };
+    let (mut years, mut months) = if sign == '+' {
+        (first.year - second.year, first.month - second.month)
+    } else {
+        (second.year - first.year, second.month - first.month)
+    };
+    if years != 0 {
+        second.year = first.year;
+        second.valid_day_number = false;
+        second.compute_day_number();
+    }
+    if months < 0 {
+        years -= 1;
+        months += 12;
+    }
+    if months != 0 {
+        second.month = first.month;
+        second.valid_day_number = false;
+        second.compute_day_number();
+    }
+    // goes back a month at a time while the second time went past the first one
+    let step = if sign == '+' { -1 } else { 1 };
+    while (sign == '+' && first.day_number < second.day_number)
+        || (sign == '-' && first.day_number > second.day_number)
+    {
+        months -= 1;
+        if months < 0 {
+            months = 11;
+            years -= 1;
+        }
+        second.month += step;
+        if second.month < 1 {
+            second.month = 12;
+            second.year -= 1;
+        } else if second.month > 12 {
+            second.month = 1;
+            second.year += 1;
+        }
+        second.valid_day_number = false;
+        second.compute_day_number();
+    }
+    let mut difference = DateTime {
+        // the julian day number of 0000-01-01 00:00:00, so that the days count from 1
+        day_number: (first.day_number - second.day_number).abs() + 148_699_540_800_000,
+        valid_day_number: true,
+        ..DateTime::default()
+    };
+    difference.compute_fields();
+    Ok(Value::String(format!(
+        "{}{:04}-{:02}-{:02} {:02}:{:02}:{:06.3}",
+        sign,
+        years,
+        months,
+        difference.day - 1,
+        difference.hour,
+        difference.minute,
+        difference.second
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn call(function: fn(&[Value]) -> Result<Value>, arguments: &[&str]) -> Value {
+        let arguments: Vec<Value> = arguments
+            .iter()
+            .map(|argument| Value::String(argument.to_string()))
+            .collect();
+        function(&arguments).unwrap()
+    }
+
+    fn text(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn parses_time_values() {
+        assert_eq!(
+            call(datetime, &["2024-02-29T13:45:30.5+02:00"]),
+            text("2024-02-29 11:45:30")
+        );
+        assert_eq!(call(date, &["2023-02-31"]), text("2023-03-03"));
+        assert_eq!(call(time, &["12:30"]), text("12:30:00"));
+        assert_eq!(call(datetime, &["2460000.5"]), text("2023-02-25 00:00:00"));
+        assert_eq!(call(date, &["2024-13-01"]), Value::Null);
+        assert_eq!(call(date, &["2024-01-01 junk"]), Value::Null);
+        assert_eq!(
+            julianday(&[text("2000-01-01 12:00")]).unwrap(),
+            Value::Float64(2451545.0)
+        );
+    }
+
+    #[test]
+    fn applies_modifiers() {
+        assert_eq!(
+            datetime(&[Value::Int64(1700000000), text("unixepoch")]).unwrap(),
+            text("2023-11-14 22:13:20")
+        );
+        assert_eq!(call(date, &["2024-01-31", "+1 month"]), text("2024-03-02"));
+        assert_eq!(
+            call(date, &["2024-01-31", "+1 month", "floor"]),
+            text("2024-02-29")
+        );
+        assert_eq!(
+            call(date, &["2024-03-15", "start of month", "-1 day"]),
+            text("2024-02-29")
+        );
+        assert_eq!(call(date, &["2024-01-01", "weekday 0"]), text("2024-01-07"));
+        assert_eq!(
+            call(datetime, &["2024-01-01", "-01:30"]),
+            text("2023-12-31 22:30:00")
+        );
+        assert_eq!(
+            call(date, &["2024-02-29", "+0001-01-01"]),
+            text("2025-03-30")
+        );
+        assert_eq!(
+            call(time, &["10:00", "subsec", "+1.25 seconds"]),
+            text("10:00:01.250")
+        );
+        assert_eq!(call(date, &["2024-01-01", "+1 fortnight"]), Value::Null);
+        // unixepoch only applies to a number given as the time value
+        assert_eq!(call(date, &["2024-01-01", "unixepoch"]), Value::Null);
+    }
+
+    #[test]
+    fn formats_like_strftime() {
+        assert_eq!(
+            call(
+                strftime,
+                &["%Y/%m/%d %H:%M:%S %j %w %u", "2024-03-10 08:05:09"]
+            ),
+            text("2024/03/10 08:05:09 070 0 7")
+        );
+        assert_eq!(
+            call(strftime, &["%G-W%V %U %W %I%p", "2021-01-03 15:00"]),
+            text("2020-W53 01 00 03PM")
+        );
+        assert_eq!(
+            call(strftime, &["%s", "2001-09-09 01:46:40"]),
+            text("1000000000")
+        );
+        assert_eq!(call(strftime, &["%Q", "2024-01-01"]), Value::Null);
+    }
+
+    #[test]
+    fn computes_time_differences() {
+        assert_eq!(
+            call(timediff, &["2024-03-01", "2023-01-31 12:00"]),
+            text("+0001-00-29 12:00:00.000")
+        );
+        assert_eq!(
+            call(timediff, &["2023-01-01", "2024-01-01"]),
+            text("-0001-00-00 00:00:00.000")
+        );
+    }
+}
@@ -0,0 +1,1170 @@
+use std::fmt::{self, Write};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::database::page::btree::data::serial_types::{format_real, Value};
+use crate::engine::expression::{to_integer, to_text};
+use crate::sql::{BinaryOperator, Expression};
+
+/// How deeply arrays and objects can be nested
+const MAX_DEPTH: usize = 1000;
+
+/// A JSON value. Numbers and strings keep the text they were written with, escapes included,
+/// so that they are written back the way they were read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    True,
+    False,
+    Integer(String),
+    Real(String),
+    /// The text between the quotes
+    String(String),
+    Array(Vec<Json>),
+    /// The members in the order they were written, duplicates included. Labels are held like
+    /// strings.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses JSON text as defined by RFC 8259.
+    pub fn parse(text: &str) -> Result<Json> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+        parser
+            .value(0)
+            .filter(|_| {
+                parser.skip_whitespace();
+                parser.position == parser.text.len()
+            })
+            .ok_or_else(|| anyhow!("malformed JSON"))
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::True => "true",
+            Json::False => "false",
+            Json::Integer(_) => "integer",
+            Json::Real(_) => "real",
+            Json::String(_) => "text",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    fn is_container(&self) -> bool {
+        matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    /// The size of the value in JSONB, SQLite's binary format for JSON, where each value is a
+    /// header giving its type and the size of its payload, followed by the payload
+    fn jsonb_size(&self) -> usize {
+        let payload = self.jsonb_payload();
+        header_size(payload) + payload
+    }
+
+    fn jsonb_payload(&self) -> usize {
+        match self {
+            Json::Null | Json::True | Json::False => 0,
+            Json::Integer(text) | Json::Real(text) | Json::String(text) => text.len(),
+            Json::Array(elements) => elements.iter().map(Json::jsonb_size).sum(),
+            Json::Object(members) => members
+                .iter()
+                .map(|(label, value)| header_size(label.len()) + label.len() + value.jsonb_size())
+                .sum(),
+        }
+    }
+}
+
+/// Writes the value as minified JSON text.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::True => f.write_str("true"),
+            Json::False => f.write_str("false"),
+            Json::Integer(text) | Json::Real(text) => f.write_str(text),
+            Json::String(text) => write!(f, "\"{}\"", text),
+            Json::Array(elements) => {
+                f.write_char('[')?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (i, (label, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "\"{}\":{}", label, value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// The size of the header of a JSONB value with a payload of the given size
+fn header_size(payload: usize) -> usize {
+    match payload {
+        0..=11 => 1,
+        12..=0xff => 2,
+        0x100..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.position).copied()
+    }
+
+    /// Skips the given character if it comes next, after whitespace.
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_whitespace();
+        let next = self.peek() == Some(c);
+        if next {
+            self.position += 1;
+        }
+        next
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Json> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'[' | b'{' if depth >= MAX_DEPTH => None,
+            b'[' => {
+                self.position += 1;
+                let mut elements = Vec::new();
+                if self.eat(b']') {
+                    return Some(Json::Array(elements));
+                }
+                loop {
+                    elements.push(self.value(depth + 1)?);
+                    if self.eat(b']') {
+                        return Some(Json::Array(elements));
+                    }
+                    if !self.eat(b',') {
+                        return None;
+                    }
+                }
+            }
+            b'{' => {
+                self.position += 1;
+                let mut members = Vec::new();
+                if self.eat(b'}') {
+                    return Some(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let label = self.string()?;
+                    if !self.eat(b':') {
+                        return None;
+                    }
+                    members.push((label, self.value(depth + 1)?));
+                    if self.eat(b'}') {
+                        return Some(Json::Object(members));
+                    }
+                    if !self.eat(b',') {
+                        return None;
+                    }
+                }
+            }
+            b'"' => self.string().map(Json::String),
+            b't' => self.keyword("true", Json::True),
+            b'f' => self.keyword("false", Json::False),
+            b'n' => self.keyword("null", Json::Null),
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => None,
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Option<Json> {
+        if !self.text[self.position..].starts_with(keyword.as_bytes()) {
+            return None;
+        }
+        self.position += keyword.len();
+        Some(value)
+    }
+
+    /// Reads a string, returning the text between its quotes.
+    fn string(&mut self) -> Option<String> {
+        if self.peek() != Some(b'"') {
+            return None;
+        }
+        let start = self.position + 1;
+        let mut end = start;
+        loop {
+            match *self.text.get(end)? {
+                b'"' => break,
+                b'\\' => match *self.text.get(end + 1)? {
+                    b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => end += 2,
+                    b'u' if self
+                        .text
+                        .get(end + 2..end + 6)?
+                        .iter()
+                        .all(u8::is_ascii_hexdigit) =>
+                    {
+                        end += 6
+                    }
+                    _ => return None,
+                },
+                c if c < 0x20 => return None,
+                _ => end += 1,
+            }
+        }
+        self.position = end + 1;
+        std::str::from_utf8(&self.text[start..end])
+            .ok()
+            .map(str::to_string)
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.position;
+        let digits = |parser: &mut Parser| {
+            let first = parser.position;
+            while parser.peek().is_some_and(|c| c.is_ascii_digit()) {
+                parser.position += 1;
+            }
+            parser.position > first
+        };
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        match self.peek()? {
+            b'0' => self.position += 1,
+            b'1'..=b'9' => {
+                digits(self);
+            }
+            _ => return None,
+        }
+        let mut real = false;
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            if !digits(self) {
+                return None;
+            }
+            real = true;
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.position += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.position += 1;
+            }
+            if !digits(self) {
+                return None;
+            }
+            real = true;
+        }
+        let text = std::str::from_utf8(&self.text[start..self.position])
+            .ok()?
+            .to_string();
+        Some(if real {
+            Json::Real(text)
+        } else {
+            Json::Integer(text)
+        })
+    }
+}
+
+/// Escapes text to be written between the quotes of a JSON string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The text of a JSON string, given the text between its quotes.
+pub fn unescape(raw: &str) -> String {
+    if !raw.contains('\\') {
+        return raw.to_string();
+    }
+    let hex = |chars: &mut std::str::Chars| {
+        let digits: String = chars.take(4).collect();
+        u32::from_str_radix(&digits, 16).unwrap_or(0xfffd)
+    };
+    let mut text = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('b') => text.push('\u{8}'),
+            Some('f') => text.push('\u{c}'),
+            Some('n') => text.push('\n'),
+            Some('r') => text.push('\r'),
+            Some('t') => text.push('\t'),
+            Some('u') => {
+                let mut code = hex(&mut chars);
+                // characters outside of the basic plane are written as a surrogate pair
+                if (0xd800..0xdc00).contains(&code) {
+                    let mut ahead = chars.clone();
+                    if ahead.next() == Some('\\') && ahead.next() == Some('u') {
+                        let low = hex(&mut ahead);
+                        if (0xdc00..0xe000).contains(&low) {
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            chars = ahead;
+                        }
+                    }
+                }
+                text.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+            Some(other) => text.push(other),
+            None => {}
+        }
+    }
+    text
+}
+
+/// Converts an SQL value to JSON: text becomes a string, unless it is JSON built by another
+/// JSON function (`is_json`), which is embedded as is.
+fn from_value(value: &Value, is_json: bool) -> Result<Json> {
+    Ok(match value {
+        Value::Null => Json::Null,
+        Value::Float64(r) if r.is_nan() => Json::Null,
+        Value::Float64(r) if r.is_infinite() => {
+            let text = if *r > 0.0 { "9.0e+999" } else { "-9.0e+999" };
+            Json::Real(text.to_string())
+        }
+        Value::Float64(r) => Json::Real(format_real(*r)),
+        Value::String(text) if is_json => Json::parse(text)?,
+        Value::String(text) => Json::String(escape(text)),
+        Value::Blob(_) => bail!("JSON cannot hold BLOB values"),
+        other => Json::Integer(other.as_i64().unwrap_or(0).to_string()),
+    })
+}
+
+/// Converts JSON to an SQL value: arrays and objects are returned as JSON text.
+pub fn to_value(json: &Json) -> Value {
+    match json {
+        Json::Null => Value::Null,
+        Json::True => Value::Int64(1),
+        Json::False => Value::Int64(0),
+        // integers too large for 64 bits are read as reals
+        Json::Integer(text) => match text.parse() {
+            Ok(i) => Value::Int64(i),
+            Err(_) => Value::Float64(text.parse().unwrap_or(0.0)),
+        },
+        Json::Real(text) => Value::Float64(text.parse().unwrap_or(0.0)),
+        Json::String(raw) => Value::String(unescape(raw)),
+        container => Value::String(container.to_string()),
+    }
+}
+
+/// Reads the JSON document a function works on: text is parsed while numbers are taken as
+/// they are. There is no document when the value is NULL.
+fn document(value: &Value) -> Result<Option<Json>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(text) => Json::parse(text).map(Some),
+        Value::Blob(_) => bail!("malformed JSON"),
+        other => from_value(other, false).map(Some),
+    }
+}
+
+/// Whether a value an expression evaluated to is JSON built by a JSON function, which other
+/// JSON functions embed as is rather than as a string. SQLite tells such values apart by a
+/// subtype which isn't kept once they are stored, or returned by a subquery.
+pub fn is_json(expression: &Expression, value: &Value) -> bool {
+    let Value::String(text) = value else {
+        return false;
+    };
+    match expression {
+        Expression::Binary {
+            operator: BinaryOperator::Extract,
+            ..
+        } => true,
+        Expression::Function {
+            name, arguments, ..
+        } => match name.to_ascii_lowercase().as_str() {
+            "json" | "json_array" | "json_group_array" | "json_group_object" | "json_insert"
+            | "json_object" | "json_quote" | "json_remove" | "json_replace" | "json_set" => true,
+            // only arrays and objects are extracted as JSON, along with several values at once
+            "json_extract" => {
+                arguments.expressions().len() > 2
+                    || (text.starts_with(['[', '{']) && Json::parse(text).is_ok())
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The path of a JSON function doesn't follow the syntax of paths
+struct BadPath;
+
+fn bad_path(path: &str) -> anyhow::Error {
+    anyhow!("bad JSON path: '{}'", path)
+}
+
+/// The text of a path argument, the `$` it starts with removed. NULL paths have none.
+fn path_argument(value: &Value) -> Result<Option<(String, String)>> {
+    let Some(path) = to_text(value) else {
+        return Ok(None);
+    };
+    match path.strip_prefix('$') {
+        Some(rest) => {
+            let rest = rest.to_string();
+            Ok(Some((path, rest)))
+        }
+        None => Err(bad_path(&path)),
+    }
+}
+
+/// A step of a path, applied to an array or an object
+enum Step {
+    /// `.label` or `."label"`. `key` is compared to the labels of the object, while `label` is
+    /// the label of the members the step creates.
+    Member { key: String, label: String },
+    /// `[N]`, `[#]` (past the last element) or `[#-N]`
+    Element(usize),
+    /// A step into a value of the wrong type, or before the first element of an array
+    NotFound,
+}
+
+/// Splits the first step off a path, as applied to `node`. Indices are only parsed when the
+/// node is an array, like SQLite does, so that malformed steps into anything else find
+/// nothing.
+fn next_step<'p>(node: &Json, path: &'p str) -> Result<(Step, &'p str), BadPath> {
+    if let Some(rest) = path.strip_prefix('.') {
+        let (key, label, rest) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').ok_or(BadPath)?;
+                let label = &quoted[..end];
+                (unescape(label), label.to_string(), &quoted[end + 1..])
+            }
+            None => {
+                let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                if end == 0 {
+                    return Err(BadPath);
+                }
+                let key = &rest[..end];
+                (key.to_string(), escape(key), &rest[end..])
+            }
+        };
+        if !matches!(node, Json::Object(_)) {
+            return Ok((Step::NotFound, rest));
+        }
+        return Ok((Step::Member { key, label }, rest));
+    }
+    if !path.starts_with('[') {
+        return Err(BadPath);
+    }
+    let Json::Array(elements) = node else {
+        return Ok((Step::NotFound, ""));
+    };
+    let bytes = path.as_bytes();
+    let number = |position: &mut usize| {
+        let mut n: usize = 0;
+        while let Some(digit) = bytes.get(*position).filter(|c| c.is_ascii_digit()) {
+            n = n.saturating_mul(10).saturating_add((digit - b'0') as usize);
+            *position += 1;
+        }
+        n
+    };
+    let mut end = 1;
+    let mut index = number(&mut end);
+    if end < 2 || bytes.get(end) != Some(&b']') {
+        if bytes.get(1) != Some(&b'#') {
+            return Err(BadPath);
+        }
+        index = elements.len();
+        end = 2;
+        if bytes.get(2) == Some(&b'-') && bytes.get(3).is_some_and(u8::is_ascii_digit) {
+            end = 3;
+            let from_end = number(&mut end);
+            if from_end > index {
+                return Ok((Step::NotFound, ""));
+            }
+            index -= from_end;
+        }
+        if bytes.get(end) != Some(&b']') {
+            return Err(BadPath);
+        }
+    }
+    Ok((Step::Element(index), &path[end + 1..]))
+}
+
+/// A value found at the end of a path
+struct Found<'j> {
+    node: &'j Json,
+    /// The offset of the value in the JSONB encoding of the document
+    offset: usize,
+    /// The offset of its label in the JSONB encoding when it is a member, of the value otherwise
+    id: usize,
+    /// Its label or index in its container, NULL for the root of the document
+    key: Value,
+    /// The length of the part of the path leading to its container
+    container_path: usize,
+}
+
+/// Follows a path (without its leading `$`) from the root of a document.
+fn locate<'j>(root: &'j Json, path: &str) -> Result<Option<Found<'j>>, BadPath> {
+    let mut found = Found {
+        node: root,
+        offset: 0,
+        id: 0,
+        key: Value::Null,
+        container_path: 0,
+    };
+    let mut rest = path;
+    while !rest.is_empty() {
+        let (step, next) = next_step(found.node, rest)?;
+        let container_path = path.len() - rest.len();
+        let child = children(found.node, found.offset)
+            .into_iter()
+            .enumerate()
+            .find(|(i, child)| match &step {
+                Step::Member { key, .. } => {
+                    matches!(&child.key, Value::String(label) if label == key)
+                }
+                Step::Element(index) => i == index,
+                Step::NotFound => false,
+            });
+        let Some((_, child)) = child else {
+            return Ok(None);
+        };
+        found = Found {
+            node: child.node,
+            offset: child.offset,
+            id: child.id,
+            key: child.key,
+            container_path,
+        };
+        rest = next;
+    }
+    Ok(Some(found))
+}
+
+/// A member of an object or an element of an array
+struct Child<'j> {
+    node: &'j Json,
+    /// Its label, or its index
+    key: Value,
+    /// The step leading to it from its container, as written in the paths of `json_each`
+    step: String,
+    /// The JSONB offset of its label, or of itself for elements
+    id: usize,
+    /// The JSONB offset of the value
+    offset: usize,
+}
+
+/// The members or elements of an array or object found at the given JSONB offset
+fn children(container: &Json, offset: usize) -> Vec<Child<'_>> {
+    let mut position = offset + header_size(container.jsonb_payload());
+    match container {
+        Json::Array(elements) => elements
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let child = Child {
+                    node,
+                    key: Value::Int64(i as i64),
+                    step: format!("[{}]", i),
+                    id: position,
+                    offset: position,
+                };
+                position += node.jsonb_size();
+                child
+            })
+            .collect(),
+        Json::Object(members) => members
+            .iter()
+            .map(|(label, node)| {
+                let bytes = label.as_bytes();
+                let bare = bytes.first().is_some_and(u8::is_ascii_alphabetic)
+                    && bytes.iter().all(u8::is_ascii_alphanumeric);
+                let id = position;
+                let offset = position + header_size(label.len()) + label.len();
+                position = offset + node.jsonb_size();
+                Child {
+                    node,
+                    key: Value::String(unescape(label)),
+                    step: if bare {
+                        format!(".{}", label)
+                    } else {
+                        format!(".\"{}\"", label)
+                    },
+                    id,
+                    offset,
+                }
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Looks up the value at a path argument of a function.
+fn lookup<'j>(document: &'j Json, path: &Value) -> Result<Option<&'j Json>> {
+    let Some((path, rest)) = path_argument(path)? else {
+        return Ok(None);
+    };
+    let found = locate(document, &rest).map_err(|_| bad_path(&path))?;
+    Ok(found.map(|found| found.node))
+}
+
+/// How an edit changes the value at a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Remove,
+    /// Replaces existing values only
+    Replace,
+    /// Creates missing values only
+    Insert,
+    /// Replaces existing values and creates missing ones
+    Set,
+}
+
+impl Edit {
+    fn creates(self) -> bool {
+        matches!(self, Edit::Insert | Edit::Set)
+    }
+}
+
+/// Edits the value at a path (without its leading `$`, and not empty) inside a node. Inserting
+/// at a path whose containers are missing creates them.
+fn edit(node: &mut Json, path: &str, mode: Edit, value: &Json) -> Result<(), BadPath> {
+    let (step, rest) = next_step(node, path)?;
+    match (step, node) {
+        (Step::Member { key, label }, Json::Object(members)) => {
+            match members.iter().position(|(l, _)| unescape(l) == key) {
+                Some(i) if rest.is_empty() => match mode {
+                    Edit::Remove => {
+                        members.remove(i);
+                    }
+                    Edit::Insert => {}
+                    Edit::Replace | Edit::Set => members[i].1 = value.clone(),
+                },
+                Some(i) => edit(&mut members[i].1, rest, mode, value)?,
+                None if mode.creates() => {
+                    if let Some(created) = create(rest, mode, value)? {
+                        members.push((label, created));
+                    }
+                }
+                None => {}
+            }
+        }
+        (Step::Element(index), Json::Array(elements)) => {
+            if index < elements.len() {
+                if !rest.is_empty() {
+                    edit(&mut elements[index], rest, mode, value)?;
+                } else {
+                    match mode {
+                        Edit::Remove => {
+                            elements.remove(index);
+                        }
+                        Edit::Insert => {}
+                        Edit::Replace | Edit::Set => elements[index] = value.clone(),
+                    }
+                }
+            } else if index == elements.len() && mode.creates() {
+                // indexing one past the last element appends to the array
+                if let Some(created) = create(rest, mode, value)? {
+                    elements.push(created);
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// What inserting a value at a path into a missing member or element creates: the value, or
+/// the containers the rest of the path leads through. Nothing is created when the path indexes
+/// past the end of a new array.
+fn create(path: &str, mode: Edit, value: &Json) -> Result<Option<Json>, BadPath> {
+    if path.is_empty() {
+        return Ok(Some(value.clone()));
+    }
+    let mut container = if path.starts_with('.') {
+        Json::Object(vec![])
+    } else {
+        Json::Array(vec![])
+    };
+    edit(&mut container, path, mode, value)?;
+    Ok(match &container {
+        Json::Object(members) if members.is_empty() => None,
+        Json::Array(elements) if elements.is_empty() => None,
+        _ => Some(container),
+    })
+}
+
+/// `json(X)`: the minified JSON text
+pub fn json(arguments: &[Value], _: &[bool]) -> Result<Value> {
+    Ok(match document(&arguments[0])? {
+        Some(document) => Value::String(document.to_string()),
+        None => Value::Null,
+    })
+}
+
+pub fn json_array(arguments: &[Value], json: &[bool]) -> Result<Value> {
+    let elements = arguments
+        .iter()
+        .zip(json)
+        .map(|(value, is_json)| from_value(value, *is_json))
+        .collect::<Result<_>>()?;
+    Ok(Value::String(Json::Array(elements).to_string()))
+}
+
+pub fn json_array_length(arguments: &[Value], _: &[bool]) -> Result<Value> {
+    let Some(document) = document(&arguments[0])? else {
+        return Ok(Value::Null);
+    };
+    let found = match arguments.get(1) {
+        Some(path) => lookup(&document, path)?,
+        None => Some(&document),
+    };
+    Ok(match found {
+        Some(Json::Array(elements)) => Value::Int64(elements.len() as i64),
+        Some(_) => Value::Int64(0),
+        None => Value::Null,
+    })
+}
+
+/// `json_extract(X, P1, P2, ...)`: the value at a path, or a JSON array of the values at each
+/// path when given several
+pub fn json_extract(arguments: &[Value], _: &[bool]) -> Result<Value> {
+    let Some(document) = document(&arguments[0])? else {
+        return Ok(Value::Null);
+    };
+    let paths = &arguments[1..];
+    if paths.len() == 1 {
+        return Ok(lookup(&document, &paths[0])?.map_or(Value::Null, to_value));
+    }
+    let mut values = Vec::with_capacity(paths.len());
+    for path in paths {
+        if path.is_null() {
+            return Ok(Value::Null);
+        }
+        values.push(lookup(&document, path)?.cloned().unwrap_or(Json::Null));
+    }
+    if values.is_empty() {
+        return Ok(Value::Null);
+    }
+    Ok(Value::String(Json::Array(values).to_string()))
+}
+
+/// The `->` and `->>` operators, which extract the value at a path as JSON or as an SQL value
+/// respectively. Besides a path, the right operand can be an array index, or an object label.
+pub fn extract_operator(document: &Value, path: &Value, as_value: bool) -> Result<Value> {
+    let Some(document) = self::document(document)? else {
+        return Ok(Value::Null);
+    };
+    let path = match path {
+        Value::Null => return Ok(Value::Null),
+        Value::Int8(_) | Value::Int16(_) | Value::Int32(_) | Value::Int64(_) | Value::Bool(_) => {
+            match to_integer(path) {
+                index if index < 0 => format!("$[#{}]", index),
+                index => format!("$[{}]", index),
+            }
+        }
+        other => {
+            let text = to_text(other).unwrap_or_default();
+            if text.starts_with('$') {
+                text
+            } else if text.starts_with('[') {
+                format!("${}", text)
+            } else if text.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+                format!("$.{}", text)
+            } else {
+                format!("$.\"{}\"", text)
+            }
+        }
+    };
+    let found = lookup(&document, &Value::String(path))?;
+    Ok(match found {
+        Some(found) if as_value => to_value(found),
+        Some(found) => Value::String(found.to_string()),
+        None => Value::Null,
+    })
+}
+
+pub fn json_object(arguments: &[Value], json: &[bool]) -> Result<Value> {
+    if !arguments.len().is_multiple_of(2) {
+        bail!("json_object() requires an even number of arguments");
+    }
+    let mut members = Vec::with_capacity(arguments.len() / 2);
+    for i in (0..arguments.len()).step_by(2) {
+        let Value::String(label) = &arguments[i] else {
+            bail!("json_object() labels must be TEXT");
+        };
+        members.push((escape(label), from_value(&arguments[i + 1], json[i + 1])?));
+    }
+    Ok(Value::String(Json::Object(members).to_string()))
+}
+
+pub fn json_quote(arguments: &[Value], json: &[bool]) -> Result<Value> {
+    Ok(Value::String(
+        from_value(&arguments[0], json[0])?.to_string(),
+    ))
+}
+
+/// Edits a document at each of the given paths in turn.
+fn edit_document(name: &str, mode: Edit, arguments: &[Value], json: &[bool]) -> Result<Value> {
+    let pair_count = match mode {
+        Edit::Remove => 1,
+        _ => 2,
+    };
+    if !(arguments.len() - 1).is_multiple_of(pair_count) {
+        bail!("{}() needs an odd number of arguments", name);
+    }
+    let Some(mut document) = document(&arguments[0])? else {
+        return Ok(Value::Null);
+    };
+    for i in (1..arguments.len()).step_by(pair_count) {
+        let value = match mode {
+            Edit::Remove => Json::Null,
+            _ => from_value(&arguments[i + 1], json[i + 1])?,
+        };
+        let Some((path, rest)) = path_argument(&arguments[i])? else {
+            if mode == Edit::Remove {
+                return Ok(Value::Null);
+            }
+            continue;
+        };
+        if rest.is_empty() {
+            match mode {
+                Edit::Remove => return Ok(Value::Null),
+                Edit::Insert => {}
+                Edit::Replace | Edit::Set => document = value,
+            }
+            continue;
+        }
+        edit(&mut document, &rest, mode, &value).map_err(|_| bad_path(&path))?;
+    }
+    Ok(Value::String(document.to_string()))
+}
+
+pub fn json_insert(arguments: &[Value], json: &[bool]) -> Result<Value> {
+    edit_document("json_insert", Edit::Insert, arguments, json)
+}
+
+pub fn json_remove(arguments: &[Value], json: &[bool]) -> Result<Value> {
+    edit_document("json_remove", Edit::Remove, arguments, json)
+}
+
+pub fn json_replace(arguments: &[Value], json: &[bool]) -> Result<Value> {
+    edit_document("json_replace", Edit::Replace, arguments, json)
+}
+
+pub fn json_set(arguments: &[Value], json: &[bool]) -> Result<Value> {
+    edit_document("json_set", Edit::Set, arguments, json)
+}
+
+pub fn json_type(arguments: &[Value], _: &[bool]) -> Result<Value> {
+    let Some(document) = document(&arguments[0])? else {
+        return Ok(Value::Null);
+    };
+    let found = match arguments.get(1) {
+        Some(path) => lookup(&document, path)?,
+        None => Some(&document),
+    };
+    Ok(found.map_or(Value::Null, |found| {
+        Value::String(found.type_name().to_string())
+    }))
+}
+
+/// `json_valid(X, FLAGS)`: whether X is well-formed JSON. Of the flags, 1 and 2 accept JSON
+/// text while 4 and 8 accept JSONB, which isn't supported.
+pub fn json_valid(arguments: &[Value], _: &[bool]) -> Result<Value> {
+    let flags = arguments.get(1).map_or(1, to_integer);
+    if !(1..=15).contains(&flags) {
+        bail!("FLAGS parameter to json_valid() must be between 1 and 15");
+    }
+    let text = flags & 3 != 0;
+    Ok(match &arguments[0] {
+        Value::Null => Value::Null,
+        Value::String(s) => Value::Int64((text && Json::parse(s).is_ok()) as i64),
+        Value::Blob(_) => Value::Int64(0),
+        _ => Value::Int64(text as i64),
+    })
+}
+
+/// The element `json_group_array` adds to its array for a value
+pub fn array_element(value: &Value, is_json: bool) -> Result<String> {
+    Ok(from_value(value, is_json)?.to_string())
+}
+
+/// The member `json_group_object` adds to its object for a label and a value, none when the
+/// label is NULL
+pub fn object_member(label: &Value, value: &Value, is_json: bool) -> Result<Option<String>> {
+    let Some(label) = to_text(label) else {
+        return Ok(None);
+    };
+    let value = from_value(value, is_json)?;
+    Ok(Some(format!("\"{}\":{}", escape(&label), value)))
+}
+
+/// The columns of the rows of `json_each` and `json_tree`
+pub const EACH_COLUMNS: &[&str] = &[
+    "key", "value", "type", "atom", "id", "parent", "fullkey", "path",
+];
+/// Their hidden columns: the document and the path they were given
+pub const EACH_HIDDEN_COLUMNS: &[&str] = &["json", "root"];
+
+/// The rows of `json_each` and `json_tree`
+struct EachRows {
+    rows: Vec<Vec<Value>>,
+    document: Value,
+    root: String,
+}
+
+impl EachRows {
+    fn push(
+        &mut self,
+        key: Value,
+        node: &Json,
+        id: usize,
+        parent: Option<usize>,
+        fullkey: &str,
+        path: &str,
+    ) {
+        let value = to_value(node);
+        let atom = if node.is_container() {
+            Value::Null
+        } else {
+            value.clone()
+        };
+        self.rows.push(vec![
+            key,
+            value,
+            Value::String(node.type_name().to_string()),
+            atom,
+            Value::Int64(id as i64),
+            parent.map_or(Value::Null, |parent| Value::Int64(parent as i64)),
+            Value::String(fullkey.to_string()),
+            Value::String(path.to_string()),
+            self.document.clone(),
+            Value::String(self.root.clone()),
+        ]);
+    }
+
+    /// Adds rows for every descendant of a container, depth first.
+    fn descendants(&mut self, container: &Json, offset: usize, id: usize, fullkey: &str) {
+        for child in children(container, offset) {
+            let child_fullkey = format!("{}{}", fullkey, child.step);
+            self.push(
+                child.key,
+                child.node,
+                child.id,
+                Some(id),
+                &child_fullkey,
+                fullkey,
+            );
+            self.descendants(child.node, child.offset, child.id, &child_fullkey);
+        }
+    }
+}
+
+fn each_rows(arguments: &[Value], recursive: bool) -> Result<Vec<Vec<Value>>> {
+    let Some(input) = arguments.first() else {
+        return Ok(vec![]);
+    };
+    let Some(document) = document(input)? else {
+        return Ok(vec![]);
+    };
+    let (root, rest) = match arguments.get(1) {
+        Some(path) => match path_argument(path)? {
+            Some(path) => path,
+            None => return Ok(vec![]),
+        },
+        None => (String::from("$"), String::new()),
+    };
+    let Some(found) = locate(&document, &rest).map_err(|_| bad_path(&root))? else {
+        return Ok(vec![]);
+    };
+    let mut rows = EachRows {
+        rows: Vec::new(),
+        document: input.clone(),
+        root: root.clone(),
+    };
+    if recursive {
+        let container_path = &root[..found.container_path + 1];
+        rows.push(found.key, found.node, found.id, None, &root, container_path);
+        rows.descendants(found.node, found.offset, found.id, &root);
+    } else if found.node.is_container() {
+        for child in children(found.node, found.offset) {
+            let fullkey = format!("{}{}", root, child.step);
+            rows.push(child.key, child.node, child.id, None, &fullkey, &root);
+        }
+    } else {
+        rows.push(Value::Null, found.node, found.id, None, &root, &root);
+    }
+    Ok(rows.rows)
+}
+
+/// `json_each(X, P)`: a row for each member or element of the array or object at path P
+pub fn json_each(arguments: &[Value]) -> Result<Vec<Vec<Value>>> {
+    each_rows(arguments, false)
+}
+
+/// `json_tree(X, P)`: a row for the value at path P and for each value it contains, at any
+/// depth
+pub fn json_tree(arguments: &[Value]) -> Result<Vec<Vec<Value>>> {
+    each_rows(arguments, true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn minified(json: &str) -> String {
+        Json::parse(json).unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_and_minifies_json() {
+        assert_eq!(
+            minified(r#" {"a" : "\u00e9\n", "b":1.0e5, "c":[ 1 , -0, 1E2 ] } "#),
+            r#"{"a":"\u00e9\n","b":1.0e5,"c":[1,-0,1E2]}"#
+        );
+        for malformed in ["", "[1,", "01", "{a:1}", "[1] x", "\"\\x\"", "1.", "-"] {
+            assert!(Json::parse(malformed).is_err(), "{}", malformed);
+        }
+        let nested = "[".repeat(MAX_DEPTH + 1) + &"]".repeat(MAX_DEPTH + 1);
+        assert!(Json::parse(&nested).is_err());
+    }
+
+    #[test]
+    fn converts_between_json_and_sql_values() {
+        assert_eq!(
+            to_value(&Json::parse(r#""\ud83d\ude00\t""#).unwrap()),
+            text("😀\t")
+        );
+        assert_eq!(
+            to_value(&Json::Integer("99999999999999999999".to_string())),
+            Value::Float64(1e20)
+        );
+        assert_eq!(
+            from_value(&text("say \"hi\"\u{1}"), false)
+                .unwrap()
+                .to_string(),
+            r#""say \"hi\"\u0001""#
+        );
+        assert_eq!(
+            from_value(&Value::Float64(f64::INFINITY), false).unwrap(),
+            Json::Real("9.0e+999".to_string())
+        );
+        assert_eq!(
+            from_value(&text("[1, 2]"), true).unwrap().to_string(),
+            "[1,2]"
+        );
+    }
+
+    #[test]
+    fn follows_paths() {
+        let document = Json::parse(r#"{"a":[1,{"b c":2}],"d":null}"#).unwrap();
+        let find = |path: &str| {
+            lookup(&document, &text(path))
+                .map(|found| found.map(ToString::to_string))
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(find("$.a[1].\"b c\""), Ok(Some("2".to_string())));
+        assert_eq!(find("$.a[#-1]"), Ok(Some(r#"{"b c":2}"#.to_string())));
+        assert_eq!(find("$.a[#-3]"), Ok(None));
+        assert_eq!(find("$.d[0"), Ok(None));
+        assert_eq!(find("$.a[0"), Err("bad JSON path: '$.a[0'".to_string()));
+        assert_eq!(find("$x"), Err("bad JSON path: '$x'".to_string()));
+        assert_eq!(find("a"), Err("bad JSON path: 'a'".to_string()));
+    }
+
+    #[test]
+    fn edits_documents() {
+        let call = |function: fn(&[Value], &[bool]) -> Result<Value>, arguments: &[&str]| {
+            let arguments: Vec<Value> = arguments.iter().map(|a| text(a)).collect();
+            function(&arguments, &vec![false; arguments.len()]).unwrap()
+        };
+        assert_eq!(
+            call(json_set, &["{}", "$.a.b", "1"]),
+            text(r#"{"a":{"b":"1"}}"#)
+        );
+        assert_eq!(
+            call(json_set, &["{}", "$.a[0]", "1"]),
+            text(r#"{"a":["1"]}"#)
+        );
+        assert_eq!(call(json_set, &["{}", "$.a[3]", "1"]), text("{}"));
+        assert_eq!(
+            call(json_set, &["[1,2]", "$[2]", "3"]),
+            text(r#"[1,2,"3"]"#)
+        );
+        assert_eq!(call(json_insert, &["[1,2]", "$[0]", "3"]), text("[1,2]"));
+        assert_eq!(
+            call(json_replace, &["{\"a\":1}", "$.b", "3"]),
+            text(r#"{"a":1}"#)
+        );
+        assert_eq!(call(json_remove, &["[1,2,3]", "$[0]", "$[0]"]), text("[3]"));
+        assert_eq!(call(json_remove, &["[1,2]", "$"]), Value::Null);
+    }
+
+    #[test]
+    fn lists_the_values_of_documents() {
+        let rows = json_tree(&[text(r#"{"a":[true,"x"]}"#)]).unwrap();
+        let columns: Vec<(Value, i64, Value, &str)> = rows
+            .iter()
+            .map(|row| {
+                let Value::String(fullkey) = &row[6] else {
+                    panic!("fullkey is text");
+                };
+                (
+                    row[0].clone(),
+                    row[4].as_i64().unwrap(),
+                    row[5].clone(),
+                    fullkey.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                (Value::Null, 0, Value::Null, "$"),
+                (text("a"), 1, Value::Int64(0), "$.a"),
+                (Value::Int64(0), 4, Value::Int64(1), "$.a[0]"),
+                (Value::Int64(1), 5, Value::Int64(1), "$.a[1]"),
+            ]
+        );
+        let rows = json_each(&[text("[1,2]"), text("$[1]")]).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], Value::Null);
+        assert_eq!(rows[0][6], text("$[1]"));
+    }
+}
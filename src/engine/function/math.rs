@@ -0,0 +1,206 @@
+use std::f64::consts::PI;
+
+use anyhow::Result;
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::engine::expression::Numeric;
+
+/// The number an argument holds, when it is a number or text made of nothing but a number
+/// (and spaces around it). Math functions return NULL for anything else.
+pub fn number(value: &Value) -> Option<Numeric> {
+    match value {
+        Value::Null | Value::Blob(_) => None,
+        Value::Float64(r) => Some(Numeric::Real(*r)),
+        Value::String(text) => {
+            let text = text.trim_matches(|c: char| c.is_ascii_whitespace());
+            if let Ok(i) = text.parse() {
+                return Some(Numeric::Integer(i));
+            }
+            // unlike SQL, Rust also reads words such as `inf` and `NaN` as reals
+            let numeric = text
+                .bytes()
+                .all(|c| c.is_ascii_digit() || matches!(c, b'.' | b'e' | b'E' | b'+' | b'-'));
+            text.parse().ok().filter(|_| numeric).map(Numeric::Real)
+        }
+        other => other.as_i64().map(Numeric::Integer),
+    }
+}
+
+/// A real result, NULL when it isn't a number because the arguments were out of the domain of
+/// the function
+fn real(r: f64) -> Value {
+    if r.is_nan() {
+        Value::Null
+    } else {
+        Value::Float64(r)
+    }
+}
+
+fn unary(arguments: &[Value], function: fn(f64) -> f64) -> Result<Value> {
+    Ok(number(&arguments[0]).map_or(Value::Null, |x| real(function(x.as_real()))))
+}
+
+fn binary(arguments: &[Value], function: fn(f64, f64) -> f64) -> Result<Value> {
+    Ok(match (number(&arguments[0]), number(&arguments[1])) {
+        (Some(x), Some(y)) => real(function(x.as_real(), y.as_real())),
+        _ => Value::Null,
+    })
+}
+
+/// Defines functions of a single real argument returning a real.
+macro_rules! unary_functions {
+    ($($name:ident => $function:expr),* $(,)?) => {
+        $(
+            pub fn $name(arguments: &[Value]) -> Result<Value> {
+                unary(arguments, $function)
+            }
+        )*
+    };
+}
+
+unary_functions! {
+    acos => f64::acos,
+    acosh => f64::acosh,
+    asin => f64::asin,
+    asinh => f64::asinh,
+    atan => f64::atan,
+    atanh => f64::atanh,
+    cos => f64::cos,
+    cosh => f64::cosh,
+    degrees => f64::to_degrees,
+    exp => f64::exp,
+    radians => f64::to_radians,
+    sin => f64::sin,
+    sinh => f64::sinh,
+    sqrt => f64::sqrt,
+    tan => f64::tan,
+    tanh => f64::tanh,
+}
+
+pub fn atan2(arguments: &[Value]) -> Result<Value> {
+    binary(arguments, f64::atan2)
+}
+
+/// `mod(X, Y)`: the remainder of dividing X by Y, which unlike `%` keeps the fractional part
+pub fn modulo(arguments: &[Value]) -> Result<Value> {
+    binary(arguments, |x, y| x % y)
+}
+
+pub fn pow(arguments: &[Value]) -> Result<Value> {
+    binary(arguments, f64::powf)
+}
+
+pub fn pi(_: &[Value]) -> Result<Value> {
+    Ok(Value::Float64(PI))
+}
+
+/// Logarithms are only defined for positive numbers
+fn positive(value: &Value) -> Option<f64> {
+    number(value).map(Numeric::as_real).filter(|x| *x > 0.0)
+}
+
+pub fn ln(arguments: &[Value]) -> Result<Value> {
+    Ok(positive(&arguments[0]).map_or(Value::Null, |x| real(x.ln())))
+}
+
+pub fn log2(arguments: &[Value]) -> Result<Value> {
+    Ok(positive(&arguments[0]).map_or(Value::Null, |x| real(x.log2())))
+}
+
+pub fn log10(arguments: &[Value]) -> Result<Value> {
+    Ok(positive(&arguments[0]).map_or(Value::Null, |x| real(x.log10())))
+}
+
+/// `log(X)` is the base 10 logarithm of X, and `log(B, X)` its base B logarithm.
+pub fn log(arguments: &[Value]) -> Result<Value> {
+    let logarithm = match arguments {
+        [x] => positive(x).map(f64::log10),
+        [base, x] => match (positive(base), positive(x)) {
+            (Some(base), Some(x)) if base != 1.0 => Some(x.ln() / base.ln()),
+            _ => None,
+        },
+        _ => None,
+    };
+    Ok(logarithm.map_or(Value::Null, real))
+}
+
+/// Rounds reals with the given function, leaving integers as they are.
+fn rounding(arguments: &[Value], function: fn(f64) -> f64) -> Result<Value> {
+    Ok(match number(&arguments[0]) {
+        Some(Numeric::Integer(i)) => Value::Int64(i),
+        Some(Numeric::Real(r)) => real(function(r)),
+        None => Value::Null,
+    })
+}
+
+pub fn ceil(arguments: &[Value]) -> Result<Value> {
+    rounding(arguments, f64::ceil)
+}
+
+pub fn floor(arguments: &[Value]) -> Result<Value> {
+    rounding(arguments, f64::floor)
+}
+
+pub fn trunc(arguments: &[Value]) -> Result<Value> {
+    rounding(arguments, f64::trunc)
+}
+
+/// `sign(X)`: -1, 0 or 1 as an integer, depending on the sign of X
+pub fn sign(arguments: &[Value]) -> Result<Value> {
+    Ok(match number(&arguments[0]) {
+        Some(x) => {
+            let x = x.as_real();
+            Value::Int64(if x < 0.0 {
+                -1
+            } else if x > 0.0 {
+                1
+            } else {
+                0
+            })
+        }
+        None => Value::Null,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn reads_numbers_like_numeric_affinity() {
+        assert_eq!(number(&text(" 3 ")), Some(Numeric::Integer(3)));
+        assert_eq!(number(&text("2.0")), Some(Numeric::Real(2.0)));
+        assert_eq!(number(&text("1e5")), Some(Numeric::Real(1e5)));
+        assert_eq!(number(&text("4abc")), None);
+        assert_eq!(number(&text("inf")), None);
+        assert_eq!(number(&Value::Blob(b"3".to_vec())), None);
+    }
+
+    #[test]
+    fn keeps_integers_and_returns_null_outside_of_domains() {
+        assert_eq!(ceil(&[Value::Int64(5)]).unwrap(), Value::Int64(5));
+        assert_eq!(
+            trunc(&[Value::Float64(-2.7)]).unwrap(),
+            Value::Float64(-2.0)
+        );
+        assert_eq!(sign(&[text("-3")]).unwrap(), Value::Int64(-1));
+        assert_eq!(sqrt(&[Value::Int64(-1)]).unwrap(), Value::Null);
+        assert_eq!(ln(&[Value::Int64(0)]).unwrap(), Value::Null);
+        assert_eq!(
+            log(&[Value::Int64(1), Value::Int64(8)]).unwrap(),
+            Value::Null
+        );
+        assert_eq!(
+            modulo(&[Value::Float64(7.5), Value::Int64(2)]).unwrap(),
+            Value::Float64(1.5)
+        );
+        assert_eq!(
+            modulo(&[Value::Int64(7), Value::Int64(0)]).unwrap(),
+            Value::Null
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A part of a LIKE or GLOB pattern
+enum Token {
+    /// Any text, `%` or `*`
+    Any,
+    /// Any character, `_` or `?`
+    One,
+    Char(char),
+    /// `[...]`, any of the characters of the ranges, or any other when negated by `[^...]`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Any | Token::One => true,
+            Token::Char(expected) => c == *expected,
+            Token::Class { negated, ranges } => {
+                ranges
+                    .iter()
+                    .any(|(first, last)| (*first..=*last).contains(&c))
+                    != *negated
+            }
+        }
+    }
+}
+
+/// Whether text matches a LIKE pattern, where `%` stands for any text and `_` for any
+/// character, ASCII letters matching regardless of case. The character following the escape
+/// character stands for itself.
+pub fn like(pattern: &str, text: &str, escape: Option<char>) -> bool {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            c if Some(c) == escape => match chars.next() {
+                Some(c) => Token::Char(c.to_ascii_lowercase()),
+                // like in SQLite, a pattern ending with the escape character matches nothing
+                None => return false,
+            },
+            '%' => Token::Any,
+            '_' => Token::One,
+            c => Token::Char(c.to_ascii_lowercase()),
+        });
+    }
+    matches(&tokens, &text.to_ascii_lowercase())
+}
+
+/// Whether text matches a GLOB pattern, where `*` stands for any text, `?` for any character,
+/// and `[...]` for any of the characters listed in it, or any other with `[^...]`. Ranges of
+/// characters are written `a-z`, and `]` is listed first. Letters only match in the same case.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '*' => Token::Any,
+            '?' => Token::One,
+            '[' => match class(&mut chars) {
+                Some(class) => class,
+                // an unterminated class matches nothing
+                None => return false,
+            },
+            c => Token::Char(c),
+        });
+    }
+    matches(&tokens, text)
+}
+
+/// Reads the characters of a class following its `[`, up to its `]`
+fn class(chars: &mut Peekable<Chars>) -> Option<Token> {
+    let negated = chars.next_if_eq(&'^').is_some();
+    let mut ranges: Vec<(char, char)> = Vec::new();
+    // whether the last character read can start a range
+    let mut single = false;
+    loop {
+        match chars.next()? {
+            ']' if !ranges.is_empty() => break,
+            '-' if single && chars.peek().is_some_and(|c| *c != ']') => {
+                let last = chars.next()?;
+                if let Some(range) = ranges.last_mut() {
+                    range.1 = last;
+                }
+                single = false;
+            }
+            c => {
+                ranges.push((c, c));
+                single = true;
+            }
+        }
+    }
+    Some(Token::Class { negated, ranges })
+}
+
+fn matches(tokens: &[Token], text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    // the positions in the text the part of the pattern matched so far can end at
+    let mut ends = vec![false; text.len() + 1];
+    ends[0] = true;
+    for token in tokens {
+        ends = match token {
+            Token::Any => {
+                // any text can follow a position which matched
+                let first = ends.iter().position(|end| *end).unwrap_or(ends.len());
+                (0..=text.len()).map(|i| i >= first).collect()
+            }
+            token => (0..=text.len())
+                .map(|i| i > 0 && ends[i - 1] && token.matches(text[i - 1]))
+                .collect(),
+        };
+    }
+    ends[text.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_like_patterns() {
+        assert!(like("ORD%", "orders", None));
+        assert!(like("o_ders", "Orders", None));
+        assert!(!like("o_ders", "Ordres", None));
+        assert!(like("%", "", None));
+        assert!(!like("_", "", None));
+        assert!(like("100\\%", "100%", Some('\\')));
+        assert!(!like("100\\%", "1000", Some('\\')));
+        assert!(!like("a\\", "a", Some('\\')));
+        assert!(like("É%", "É!", None));
+        assert!(!like("é%", "É!", None));
+    }
+
+    #[test]
+    fn matches_glob_patterns() {
+        assert!(glob("*der*", "orders"));
+        assert!(!glob("*DER*", "orders"));
+        assert!(glob("a?c", "abc"));
+        assert!(glob("[a-c]x", "bx"));
+        assert!(!glob("[^a-c]x", "bx"));
+        assert!(glob("[]]", "]"));
+        assert!(glob("[a-]", "-"));
+        assert!(glob("[a-c-e]", "-"));
+        assert!(!glob("[a-c-e]", "d"));
+        assert!(!glob("[abc", "a"));
+    }
+}
@@ -0,0 +1,475 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use anyhow::Result;
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::engine::expression::{to_integer, to_numeric, to_text, Numeric};
+
+/// How many significant digits of a real are written, the following ones being zeros. The `!`
+/// flag raises it to `EXTENDED_SIGNIFICANT_DIGITS`.
+const SIGNIFICANT_DIGITS: usize = 16;
+const EXTENDED_SIGNIFICANT_DIGITS: usize = 26;
+
+/// How a value is written, as given between the `%` and the conversion character
+#[derive(Default)]
+struct Specification {
+    /// `-`: pad on the right rather than on the left
+    left: bool,
+    /// `+`: write the sign of positive numbers
+    plus: bool,
+    /// ` `: write a space before positive numbers
+    space: bool,
+    /// `0`: pad numbers with zeros rather than spaces
+    zero: bool,
+    /// `#`: prefix hexadecimal and octal numbers, and keep the trailing zeros of `%g`
+    alternate: bool,
+    /// `,`: separate the thousands of decimal integers
+    thousands: bool,
+    /// `!`: write more digits of reals, without trailing zeros, and measure text in characters
+    /// rather than in bytes
+    extended: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+/// `printf(format, ...)`, which writes its arguments as told by the format like the C function
+/// does, with SQLite's extensions: `%q`, `%Q` and `%w` write the argument escaped as an SQL
+/// string literal or identifier. Missing arguments are taken to be NULL, and formatting stops
+/// at an unknown conversion.
+pub fn format(arguments: &[Value]) -> Result<Value> {
+    let Some(format) = to_text(&arguments[0]) else {
+        return Ok(Value::Null);
+    };
+    let mut values = arguments[1..].iter();
+    let mut next = || values.next().cloned().unwrap_or(Value::Null);
+    let mut output = String::new();
+    let mut characters = format.chars().peekable();
+    while let Some(character) = characters.next() {
+        if character != '%' {
+            output.push(character);
+            continue;
+        }
+        let mut specification = Specification::default();
+        while let Some(flag) = characters.peek() {
+            match flag {
+                '-' => specification.left = true,
+                '+' => specification.plus = true,
+                ' ' => specification.space = true,
+                '0' => specification.zero = true,
+                '#' => specification.alternate = true,
+                ',' => specification.thousands = true,
+                '!' => specification.extended = true,
+                _ => break,
+            }
+            characters.next();
+        }
+        if characters.next_if_eq(&'*').is_some() {
+            let width = to_integer(&next());
+            specification.left |= width < 0;
+            specification.width = width.unsigned_abs() as usize;
+        } else {
+            specification.width = number(&mut characters);
+        }
+        if characters.next_if_eq(&'.').is_some() {
+            specification.precision = Some(if characters.next_if_eq(&'*').is_some() {
+                to_integer(&next()).max(0) as usize
+            } else {
+                number(&mut characters)
+            });
+        }
+        while characters.next_if_eq(&'l').is_some() {}
+        let Some(conversion) = characters.next() else {
+            output.push('%');
+            break;
+        };
+        let text = match conversion {
+            '%' => "%".to_string(),
+            'd' | 'i' => signed(to_integer(&next()), &specification),
+            'u' | 'x' | 'X' | 'o' => {
+                unsigned(to_integer(&next()) as u64, conversion, &specification)
+            }
+            'f' | 'e' | 'E' | 'g' | 'G' => {
+                let value = to_numeric(&next()).map_or(0.0, Numeric::as_real);
+                let text = real(value, conversion, &specification);
+                let sign = text.starts_with(['-', '+', ' ']) as usize;
+                let length = text.len();
+                if specification.zero && !specification.left && length < specification.width {
+                    let padding = "0".repeat(specification.width - length);
+                    format!("{}{}{}", &text[..sign], padding, &text[sign..])
+                } else {
+                    text
+                }
+            }
+            'c' => {
+                let character = to_text(&next()).and_then(|text| text.chars().next());
+                let count = specification.precision.unwrap_or(1);
+                character.map_or_else(String::new, |c| c.to_string().repeat(count))
+            }
+            's' | 'z' => truncate(to_text(&next()).unwrap_or_default(), &specification),
+            'q' | 'Q' | 'w' => {
+                let (quote, null) = match conversion {
+                    'w' => ('"', "(NULL)"),
+                    'q' => ('\'', "(NULL)"),
+                    _ => ('\'', "NULL"),
+                };
+                match to_text(&next()) {
+                    None => null.to_string(),
+                    Some(text) => {
+                        let text = truncate(text, &specification);
+                        let escaped = text.replace(quote, &format!("{}{}", quote, quote));
+                        if conversion == 'Q' {
+                            format!("'{}'", escaped)
+                        } else {
+                            escaped
+                        }
+                    }
+                }
+            }
+            _ => break,
+        };
+        let length = if specification.extended {
+            text.chars().count()
+        } else {
+            text.len()
+        };
+        let padding = " ".repeat(specification.width.saturating_sub(length));
+        if specification.left {
+            output.push_str(&text);
+            output.push_str(&padding);
+        } else {
+            output.push_str(&padding);
+            output.push_str(&text);
+        }
+    }
+    Ok(Value::String(output))
+}
+
+/// Writes a real with the given number of digits after the decimal point, as `%!.*f` does
+pub fn fixed(value: f64, digits: usize) -> String {
+    let specification = Specification {
+        extended: true,
+        precision: Some(digits),
+        ..Specification::default()
+    };
+    real(value, 'f', &specification)
+}
+
+/// Writes a real with at most the given number of significant digits, as `%.*g` does
+pub fn general(value: f64, significant: usize) -> String {
+    let specification = Specification {
+        precision: Some(significant),
+        ..Specification::default()
+    };
+    real(value, 'g', &specification)
+}
+
+/// Reads the decimal number a width or precision is written as, 0 if there is none
+fn number(characters: &mut Peekable<Chars>) -> usize {
+    let mut number: usize = 0;
+    while let Some(digit) = characters.peek().and_then(|c| c.to_digit(10)) {
+        number = number.saturating_mul(10).saturating_add(digit as usize);
+        characters.next();
+    }
+    number
+}
+
+/// Keeps the bytes of a text up to the precision, or its characters with the `!` flag
+fn truncate(text: String, specification: &Specification) -> String {
+    let Some(precision) = specification.precision else {
+        return text;
+    };
+    if specification.extended {
+        return text.chars().take(precision).collect();
+    }
+    let mut end = precision.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].to_string()
+}
+
+/// The sign written before a number
+fn sign(negative: bool, specification: &Specification) -> &'static str {
+    if negative {
+        "-"
+    } else if specification.plus {
+        "+"
+    } else if specification.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+/// Pads the digits of an integer with zeros up to the precision. The `0` flag pads them up to
+/// the width, even when padding on the right.
+fn zero_padded(digits: String, prefix: &str, specification: &Specification) -> String {
+    let mut precision = specification.precision.unwrap_or(0);
+    if specification.zero {
+        precision = precision.max(specification.width.saturating_sub(prefix.len()));
+    }
+    let padding = "0".repeat(precision.saturating_sub(digits.len()));
+    format!("{}{}{}", prefix, padding, digits)
+}
+
+fn signed(value: i64, specification: &Specification) -> String {
+    let mut digits = value.unsigned_abs().to_string();
+    if specification.thousands {
+        digits = separate_thousands(&digits);
+    }
+    zero_padded(digits, sign(value < 0, specification), specification)
+}
+
+fn unsigned(value: u64, conversion: char, specification: &Specification) -> String {
+    let (digits, prefix) = match conversion {
+        'x' => (format!("{:x}", value), "0x"),
+        'X' => (format!("{:X}", value), "0X"),
+        'o' => (format!("{:o}", value), "0"),
+        _ if specification.thousands => (separate_thousands(&value.to_string()), ""),
+        _ => (value.to_string(), ""),
+    };
+    let digits = zero_padded(digits, "", specification);
+    if specification.alternate && value != 0 {
+        format!("{}{}", prefix, digits)
+    } else {
+        digits
+    }
+}
+
+fn separate_thousands(digits: &str) -> String {
+    let mut separated = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            separated.push(',');
+        }
+        separated.push(digit);
+    }
+    separated
+}
+
+/// Where the digits of a real are rounded
+enum Rounding {
+    /// After the given number of digits after the decimal point
+    Fractional(usize),
+    /// After the given number of significant digits
+    Significant(usize),
+}
+
+/// The significant decimal digits of a positive real, rounded half away from zero as told,
+/// along with the position of the decimal point relative to the first of them. At most
+/// `max_digits` digits are kept: the following ones are taken to be zeros.
+fn decimal_digits(value: f64, rounding: Rounding, max_digits: usize) -> (Vec<u8>, i32) {
+    // enough digits of the exact decimal expansion for halves to be told apart
+    let exact = format!("{:.60e}", value);
+    let (mantissa, exponent) = exact.split_once('e').unwrap_or((&exact, "0"));
+    let mut digits: Vec<u8> = mantissa
+        .bytes()
+        .filter(u8::is_ascii_digit)
+        .map(|digit| digit - b'0')
+        .collect();
+    let mut point = exponent.parse::<i32>().unwrap_or(0) + 1;
+    let kept = match rounding {
+        Rounding::Fractional(precision) => point as i64 + precision as i64,
+        Rounding::Significant(count) => count as i64,
+    }
+    .min(max_digits as i64);
+    let Ok(kept) = usize::try_from(kept) else {
+        return (vec![], point);
+    };
+    let round_up = digits.get(kept).is_some_and(|digit| *digit >= 5);
+    digits.truncate(kept);
+    if round_up {
+        let carried = digits.iter().rposition(|digit| *digit != 9);
+        match carried {
+            Some(position) => {
+                digits[position] += 1;
+                digits[position + 1..].fill(0);
+            }
+            None => {
+                digits.fill(0);
+                digits.insert(0, 1);
+                point += 1;
+            }
+        }
+    }
+    (digits, point)
+}
+
+/// Writes a real as `%f`, `%e` or `%g` do, along with its sign
+fn real(value: f64, conversion: char, specification: &Specification) -> String {
+    let sign = sign(value < 0.0, specification);
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return format!("{}Inf", sign);
+    }
+    let value = value.abs();
+    let max_digits = if specification.extended {
+        EXTENDED_SIGNIFICANT_DIGITS
+    } else {
+        SIGNIFICANT_DIGITS
+    };
+    let precision = specification.precision.unwrap_or(6);
+    let generic = matches!(conversion, 'g' | 'G');
+    let upper = conversion.is_ascii_uppercase();
+
+    let (digits, point, precision, exponential) = if generic {
+        let significant = precision.max(1);
+        let (digits, point) = decimal_digits(value, Rounding::Significant(significant), max_digits);
+        let exponent = point - 1;
+        if exponent < -4 || exponent >= significant as i32 {
+            (digits, point, significant - 1, true)
+        } else {
+            let precision = (significant as i32 - 1 - exponent) as usize;
+            (digits, point, precision, false)
+        }
+    } else if matches!(conversion, 'e' | 'E') {
+        let (digits, point) =
+            decimal_digits(value, Rounding::Significant(precision + 1), max_digits);
+        (digits, point, precision, true)
+    } else {
+        let (digits, point) = decimal_digits(value, Rounding::Fractional(precision), max_digits);
+        (digits, point, precision, false)
+    };
+
+    let digit = |position: i32| -> char {
+        let digit = usize::try_from(position)
+            .ok()
+            .and_then(|position| digits.get(position))
+            .copied()
+            .unwrap_or(0);
+        (b'0' + digit) as char
+    };
+    let decimal_point = precision > 0 || specification.alternate || specification.extended;
+    let mut mantissa = String::new();
+    let exponent = point - 1;
+    let first_fraction_digit = if exponential {
+        mantissa.push(digit(0));
+        1
+    } else if point <= 0 {
+        mantissa.push('0');
+        point
+    } else {
+        mantissa.extend((0..point).map(digit));
+        point
+    };
+    if decimal_point {
+        mantissa.push('.');
+    }
+    mantissa.extend((first_fraction_digit..first_fraction_digit + precision as i32).map(digit));
+
+    // `%g` drops the trailing zeros of the fraction unless told otherwise, `!` drops them always
+    let trim_zeros = if generic {
+        !specification.alternate
+    } else {
+        specification.extended
+    };
+    if trim_zeros && decimal_point {
+        mantissa.truncate(mantissa.trim_end_matches('0').len());
+        if mantissa.ends_with('.') {
+            if specification.extended {
+                mantissa.push('0');
+            } else {
+                mantissa.pop();
+            }
+        }
+    }
+    if exponential {
+        let exponent = if digits.iter().all(|digit| *digit == 0) {
+            0
+        } else {
+            exponent
+        };
+        format!(
+            "{}{}{}{}{:02}",
+            sign,
+            mantissa,
+            if upper { 'E' } else { 'e' },
+            if exponent < 0 { '-' } else { '+' },
+            exponent.abs()
+        )
+    } else {
+        format!("{}{}", sign, mantissa)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn printf(format_string: &str, arguments: &[Value]) -> String {
+        let mut all = vec![Value::String(format_string.to_string())];
+        all.extend_from_slice(arguments);
+        match format(&all).unwrap() {
+            Value::String(s) => s,
+            other => panic!("expected text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn formats_integers() {
+        let n = |i: i64| Value::Int64(i);
+        assert_eq!(
+            printf("%05d|%-05d|%+.3d", &[n(-42), n(7), n(5)]),
+            "-0042|00007|+005"
+        );
+        assert_eq!(
+            printf("%,d|%x|%#X|%o", &[n(-1234567), n(255), n(255), n(8)]),
+            "-1,234,567|ff|0XFF|10"
+        );
+        assert_eq!(
+            printf("%u|%*d|", &[n(-1), n(-3), n(1)]),
+            "18446744073709551615|1  |"
+        );
+    }
+
+    #[test]
+    fn formats_reals_like_sqlite() {
+        let r = |r: f64| Value::Float64(r);
+        assert_eq!(
+            printf("%.2f|%.0f|%.1f", &[r(2.675), r(2.5), r(2.25)]),
+            "2.67|3|2.3"
+        );
+        assert_eq!(printf("%.20f", &[r(0.1)]), "0.10000000000000000000");
+        assert_eq!(
+            printf("%e|%10.3e|%E", &[r(100.0), r(12345.678), r(12.0)]),
+            "1.000000e+02| 1.235e+04|1.200000E+01"
+        );
+        assert_eq!(
+            printf("%g|%g|%g|%#g", &[r(100000.0), r(1e6), r(0.0001), r(1.0)]),
+            "100000|1e+06|0.0001|1.00000"
+        );
+        assert_eq!(
+            printf("%!g|%!.15g|%!e", &[r(1.0), r(1e100), r(1.0)]),
+            "1.0|1.0e+100|1.0e+00"
+        );
+        assert_eq!(
+            printf("%08.2f|%+f", &[r(2.71), r(2.0)]),
+            "00002.71|+2.000000"
+        );
+    }
+
+    #[test]
+    fn formats_text() {
+        let t = |s: &str| Value::String(s.to_string());
+        assert_eq!(
+            printf("%5s|%-5s|%.2s", &[t("ab"), t("ab"), t("abc")]),
+            "   ab|ab   |ab"
+        );
+        assert_eq!(
+            printf(
+                "%q|%Q|%Q|%w",
+                &[t("it's"), t("it's"), Value::Null, t("a\"b")]
+            ),
+            "it''s|'it''s'|NULL|a\"\"b"
+        );
+        assert_eq!(
+            printf("%c|%.3c|%%|%s|%d", &[t("xyz"), t("x")]),
+            "x|xxx|%||0"
+        );
+        assert_eq!(printf("abc%", &[]), "abc%");
+        assert_eq!(printf("a%yb", &[]), "a");
+    }
+}
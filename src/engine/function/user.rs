@@ -0,0 +1,315 @@
+use std::rc::Rc;
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::database::{Error, Result};
+use crate::sql::Expression;
+
+/// The most arguments an application-defined function can take, like SQLite's default
+/// `SQLITE_MAX_FUNCTION_ARG`
+const MAX_ARGUMENTS: i32 = 127;
+
+/// The longest name an application-defined function can have, in bytes
+const MAX_NAME_LENGTH: usize = 255;
+
+/// An aggregate function defined by the application. An instance is created for each group
+/// of rows, or for each window frame, and fed the arguments of its rows in turn. Errors are
+/// reported as they are, `Error::Function` holding those of the application.
+pub trait Aggregate {
+    /// Feeds the arguments the function is called with for a row.
+    fn step(&mut self, arguments: &[Value]) -> Result<()>;
+
+    /// The value of the aggregate over the rows fed so far. Window functions read it once per
+    /// row, so it may be called more than once.
+    fn finalize(&self) -> Result<Value>;
+
+    /// Whether `inverse` is implemented, which lets window functions take rows leaving the
+    /// frame back out of the aggregate rather than computing every frame from scratch
+    fn has_inverse(&self) -> bool {
+        false
+    }
+
+    /// Takes the arguments of a row fed to `step` back out of the aggregate.
+    fn inverse(&mut self, _arguments: &[Value]) -> Result<()> {
+        Err(Error::Function(String::from(
+            "inverse is not implemented by this aggregate",
+        )))
+    }
+}
+
+/// The implementation of a scalar function defined by the application, which reports its own
+/// errors as `Error::Function`
+pub type ScalarImplementation = Box<dyn Fn(&[Value]) -> Result<Value>>;
+
+/// Creates instances of an aggregate function defined by the application
+pub type AggregateFactory = Rc<dyn Fn() -> Box<dyn Aggregate>>;
+
+/// The number of arguments an application-defined function takes, any number when `None`
+type Arity = Option<usize>;
+
+/// Checks the name and number of arguments of a function being defined. Like SQLite, a
+/// negative number of arguments means the function takes any number of them.
+fn arity(name: &str, arguments: i32) -> Result<Arity> {
+    // SQLite reports this as API misuse
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH || arguments > MAX_ARGUMENTS {
+        return Err(Error::Misuse);
+    }
+    Ok(usize::try_from(arguments).ok())
+}
+
+/// A scalar function defined by the application
+pub struct UserScalar {
+    name: String,
+    arguments: Arity,
+    /// Whether the function always returns the same result given the same arguments
+    deterministic: bool,
+    implementation: ScalarImplementation,
+}
+
+impl UserScalar {
+    pub fn call(&self, arguments: &[Value]) -> anyhow::Result<Value> {
+        Ok((self.implementation)(arguments)?)
+    }
+}
+
+/// An aggregate function defined by the application
+pub struct UserAggregate {
+    name: String,
+    arguments: Arity,
+    factory: AggregateFactory,
+}
+
+impl UserAggregate {
+    /// The function creating instances of the aggregate, which accumulators keep
+    pub fn factory(&self) -> AggregateFactory {
+        self.factory.clone()
+    }
+}
+
+/// The functions defined by the application on a database, looked up by name and number of
+/// arguments before the built-in ones, which they override.
+#[derive(Default)]
+pub struct Functions {
+    scalars: Vec<UserScalar>,
+    aggregates: Vec<UserAggregate>,
+}
+
+/// The functions known where no database is at hand, as when evaluating constants
+pub const NO_FUNCTIONS: &Functions = &Functions {
+    scalars: Vec::new(),
+    aggregates: Vec::new(),
+};
+
+/// The name and number of arguments functions are looked up by
+trait Signature {
+    fn name(&self) -> &str;
+    fn arguments(&self) -> Arity;
+}
+
+impl Signature for UserScalar {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arguments(&self) -> Arity {
+        self.arguments
+    }
+}
+
+impl Signature for UserAggregate {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arguments(&self) -> Arity {
+        self.arguments
+    }
+}
+
+/// Finds the function called by `name` taking exactly `arguments` arguments, or else one
+/// taking any number of them.
+fn find<'a, F: Signature>(functions: &'a [F], name: &str, arguments: usize) -> Option<&'a F> {
+    let named = || {
+        functions
+            .iter()
+            .filter(|f| f.name().eq_ignore_ascii_case(name))
+    };
+    named()
+        .find(|f| f.arguments() == Some(arguments))
+        .or_else(|| named().find(|f| f.arguments().is_none()))
+}
+
+impl Functions {
+    /// Defines a scalar function, replacing any function with the same name and number of
+    /// arguments.
+    pub fn add_scalar(
+        &mut self,
+        name: &str,
+        arguments: i32,
+        deterministic: bool,
+        implementation: ScalarImplementation,
+    ) -> Result<()> {
+        let arguments = arity(name, arguments)?;
+        self.remove(name, arguments);
+        self.scalars.push(UserScalar {
+            name: name.to_string(),
+            arguments,
+            deterministic,
+            implementation,
+        });
+        Ok(())
+    }
+
+    /// Defines an aggregate function, replacing any function with the same name and number of
+    /// arguments.
+    pub fn add_aggregate(
+        &mut self,
+        name: &str,
+        arguments: i32,
+        factory: AggregateFactory,
+    ) -> Result<()> {
+        let arguments = arity(name, arguments)?;
+        self.remove(name, arguments);
+        self.aggregates.push(UserAggregate {
+            name: name.to_string(),
+            arguments,
+            factory,
+        });
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str, arguments: Arity) {
+        fn other(function: &impl Signature, name: &str, arguments: Arity) -> bool {
+            !function.name().eq_ignore_ascii_case(name) || function.arguments() != arguments
+        }
+        self.scalars.retain(|f| other(f, name, arguments));
+        self.aggregates.retain(|f| other(f, name, arguments));
+    }
+
+    /// The scalar function called by `name` with the given number of arguments, unless an
+    /// aggregate takes precedence
+    pub fn scalar(&self, name: &str, arguments: usize) -> Option<&UserScalar> {
+        let scalar = find(&self.scalars, name, arguments)?;
+        match self.aggregate(name, arguments) {
+            Some(aggregate) if aggregate.arguments == Some(arguments) => None,
+            _ => Some(scalar),
+        }
+    }
+
+    /// The aggregate function called by `name` with the given number of arguments, unless a
+    /// scalar function takes precedence
+    pub fn aggregate(&self, name: &str, arguments: usize) -> Option<&UserAggregate> {
+        let aggregate = find(&self.aggregates, name, arguments)?;
+        match find(&self.scalars, name, arguments) {
+            Some(scalar) if aggregate.arguments.is_none() && scalar.arguments.is_some() => None,
+            _ => Some(aggregate),
+        }
+    }
+
+    /// Whether the expression only calls application-defined functions which are deterministic
+    pub fn is_deterministic(&self, expression: &Expression) -> bool {
+        let deterministic = match expression {
+            Expression::Function {
+                name, arguments, ..
+            } => self
+                .scalar(name, arguments.expressions().len())
+                .is_none_or(|function| function.deterministic),
+            _ => true,
+        };
+        deterministic
+            && expression
+                .children()
+                .into_iter()
+                .all(|child| self.is_deterministic(child))
+    }
+
+    /// Whether a function called by `name` is defined, whatever its number of arguments
+    pub fn defines(&self, name: &str) -> bool {
+        let named = |f: &dyn Signature| f.name().eq_ignore_ascii_case(name);
+        self.scalars.iter().any(|f| named(f)) || self.aggregates.iter().any(|f| named(f))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::Database;
+    use crate::engine::select::execute;
+    use crate::sql::sql_query;
+
+    /// Concatenates its arguments, and can take them back out
+    #[derive(Default)]
+    struct Concat(Vec<String>);
+
+    impl Aggregate for Concat {
+        fn step(&mut self, arguments: &[Value]) -> Result<()> {
+            self.0.push(arguments[0].to_string());
+            Ok(())
+        }
+
+        fn finalize(&self) -> Result<Value> {
+            Ok(Value::String(self.0.join("+")))
+        }
+
+        fn has_inverse(&self) -> bool {
+            true
+        }
+
+        fn inverse(&mut self, _arguments: &[Value]) -> Result<()> {
+            self.0.remove(0);
+            Ok(())
+        }
+    }
+
+    fn query(database: &Database, sql: &str) -> anyhow::Result<Vec<String>> {
+        let statement = sql_query::select_statement(sql)?;
+        let rows = execute(database, &statement, &Default::default())?
+            .map(|row| {
+                Ok(row?
+                    .iter()
+                    .map(Value::to_string)
+                    .collect::<Vec<_>>()
+                    .join("|"))
+            })
+            .collect();
+        rows
+    }
+
+    #[test]
+    fn calls_functions_defined_by_the_application() {
+        let mut database = Database::init_from_file("sample.db").unwrap();
+        database
+            .create_scalar_function("shout", 1, true, |arguments| {
+                Ok(Value::String(format!("{}!", arguments[0])))
+            })
+            .unwrap();
+        database
+            .create_aggregate_function("concat", 1, Concat::default)
+            .unwrap();
+        assert_eq!(
+            query(&database, "SELECT shout(name) FROM apples WHERE id < 3").unwrap(),
+            vec!["Granny Smith!", "Fuji!"]
+        );
+        assert_eq!(
+            query(
+                &database,
+                "SELECT concat(id), concat(DISTINCT 1) FROM apples"
+            )
+            .unwrap(),
+            vec!["1+2+3+4|1"]
+        );
+        assert_eq!(
+            query(
+                &database,
+                "SELECT concat(id) OVER (ORDER BY id ROWS 1 PRECEDING) FROM apples"
+            )
+            .unwrap(),
+            vec!["1", "1+2", "2+3", "3+4"]
+        );
+        assert_eq!(
+            query(&database, "SELECT shout(1, 2)")
+                .unwrap_err()
+                .to_string(),
+            "wrong number of arguments to function shout()"
+        );
+    }
+}
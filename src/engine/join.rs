@@ -0,0 +1,1086 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::database::schema::TableInformation;
+use crate::database::{self, Database};
+use crate::sql::{
+    BinaryOperator, Expression, JoinConstraint, JoinOperator, Literal, SelectStatement, SortOrder,
+    Targetable,
+};
+
+use super::affinity::{apply, comparison_affinity, expression_affinity, Affinity};
+use super::collation::{comparison_collation, index_collation, Collation, HashKeys};
+use super::cte::{CommonTable, CommonTables};
+use super::expression::{
+    evaluate, parse_numeric, truth_value, validate, Numeric, Scope, ScopeColumn,
+};
+use super::function::{table_function, TableFunction};
+use super::select::{column_names, column_types, execute, filter_rows, Rows};
+use super::subquery::{column_references, Subqueries};
+
+/// How the rows of the table are read
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessPath {
+    /// Walk the table b-tree, in rowid order or in reverse rowid order
+    TableScan { reverse: bool },
+    /// Walk an index b-tree in key order (or in reverse), looking up each row by its rowid
+    IndexScan { root_page: u32, reverse: bool },
+}
+
+/// What the rows of a source of the FROM clause are read from
+pub enum Relation {
+    Table(TableInformation),
+    /// A subquery, run each time the FROM clause is, whose result columns are named `columns`
+    Subquery {
+        query: Box<SelectStatement>,
+        columns: Vec<String>,
+    },
+    CommonTable(Rc<CommonTable>),
+    /// A table-valued function, called for each row of the sources on its left, which its
+    /// arguments can refer to
+    Function {
+        function: &'static TableFunction,
+        arguments: Vec<Expression>,
+    },
+}
+
+/// A table or subquery of the FROM clause
+pub struct Source {
+    pub relation: Relation,
+    /// The name the columns of the source are qualified with: its alias, or the table name
+    pub name: String,
+    /// Whether rows of the tables on the left are kept, completed with NULLs, when no row of
+    /// this table matches them
+    pub left_join: bool,
+    /// The ON condition joining the table to the tables on its left, USING clauses included
+    pub constraint: Option<Expression>,
+}
+
+impl Source {
+    /// The number of values the source contributes to the rows of the FROM clause
+    fn width(&self) -> usize {
+        match &self.relation {
+            Relation::Table(table) => table.column_names.len() + 1,
+            Relation::Subquery { columns, .. } => columns.len(),
+            // the columns were checked to be readable when the source was added
+            Relation::CommonTable(table) => table.columns().map_or(0, <[String]>::len),
+            Relation::Function { function, .. } => {
+                function.columns.len() + function.hidden_columns.len() + 1
+            }
+        }
+    }
+}
+
+/// The sources of a FROM clause in join order, and the scope of the rows they produce: the
+/// columns of each source one after the other, those of a table starting with its rowid.
+pub struct FromClause<'a> {
+    pub sources: Vec<Source>,
+    pub scope: Scope<'a>,
+    /// The common tables the FROM clause and its subqueries can read from
+    pub tables: CommonTables,
+}
+
+impl<'a> FromClause<'a> {
+    /// Lists the sources of a FROM clause. Without a FROM clause, there are none.
+    pub fn plan(
+        database: &'a Database,
+        target: Option<&Targetable>,
+        tables: &CommonTables,
+    ) -> Result<FromClause<'a>> {
+        let mut from = FromClause {
+            sources: Vec::new(),
+            scope: Scope {
+                subqueries: Some(Subqueries::new(database, tables.clone())),
+                ..Scope::default()
+            },
+            tables: tables.clone(),
+        };
+        if let Some(target) = target {
+            from.add(database, target, JoinOperator::Inner, None)?;
+        }
+        Ok(from)
+    }
+
+    fn add(
+        &mut self,
+        database: &Database,
+        target: &Targetable,
+        operator: JoinOperator,
+        constraint: Option<&JoinConstraint>,
+    ) -> Result<()> {
+        let (relation, name, mut source_scope) = match target {
+            // common tables hide the tables of the database with the same name
+            Targetable::TableOrView { name, alias } => match self.tables.find(name) {
+                Some(table) => {
+                    let name = alias.clone().unwrap_or_else(|| table.name.clone());
+                    let scope = Scope::for_columns(table.columns()?, table.types(), &name);
+                    (Relation::CommonTable(table), name, scope)
+                }
+                None => {
+                    let table = database.table_information(name)?;
+                    if table.without_rowid {
+                        bail!("WITHOUT ROWID tables are not supported");
+                    }
+                    let name = alias.clone().unwrap_or_else(|| table.table_name.clone());
+                    let scope = Scope::for_table(&table, &name);
+                    (Relation::Table(table), name, scope)
+                }
+            },
+            Targetable::Subquery { query, alias } => {
+                let name = alias.clone().unwrap_or_default();
+                let columns = column_names(database, query, &self.tables)?;
+                let types = column_types(database, query, &self.tables)?;
+                let scope = Scope::for_columns(&columns, &types, &name);
+                let relation = Relation::Subquery {
+                    query: query.clone(),
+                    columns,
+                };
+                (relation, name, scope)
+            }
+            Targetable::Function {
+                name,
+                arguments,
+                alias,
+            } => {
+                let function = table_function(name)
+                    .ok_or_else(|| database::Error::Schema(format!("no such table: {}", name)))?;
+                let name = alias.clone().unwrap_or_else(|| name.clone());
+                let scope = Scope::for_function(function, &name);
+                let relation = Relation::Function {
+                    function,
+                    arguments: arguments.clone(),
+                };
+                (relation, name, scope)
+            }
+            Targetable::Join {
+                left,
+                operator,
+                right,
+                constraint,
+            } => {
+                self.add(database, left, JoinOperator::Inner, None)?;
+                self.add(database, right, *operator, constraint.as_ref())?;
+                return Ok(());
+            }
+        };
+        let constraint = match constraint {
+            Some(JoinConstraint::On(expression)) => Some(expression.clone()),
+            Some(JoinConstraint::Using(columns)) => {
+                Some(self.using_condition(columns, &mut source_scope)?)
+            }
+            None => None,
+        };
+        self.scope.columns.extend(source_scope.columns);
+        self.sources.push(Source {
+            relation,
+            name,
+            left_join: operator == JoinOperator::Left,
+            constraint,
+        });
+        Ok(())
+    }
+
+    /// Turns `USING (a, b)` into `left.a = right.a AND left.b = right.b`, merging the columns of
+    /// the right table into those of the left one.
+    fn using_condition(&self, columns: &[String], table_scope: &mut Scope) -> Result<Expression> {
+        let mut conditions = Vec::new();
+        for column in columns {
+            let missing = || {
+                anyhow!(
+                    "cannot join using column {} - column not present in both tables",
+                    column
+                )
+            };
+            let left = self.scope.resolve(None, column).map_err(|_| missing())?;
+            let right = table_scope.resolve(None, column).map_err(|_| missing())?;
+            table_scope.columns[right].merged = true;
+            let reference = |scope_column: &ScopeColumn| Expression::Column {
+                table: Some(scope_column.table.clone()),
+                name: scope_column.name.clone(),
+            };
+            conditions.push(Expression::Binary {
+                left: Box::new(reference(&self.scope.columns[left])),
+                operator: BinaryOperator::Equal,
+                right: Box::new(reference(&table_scope.columns[right])),
+            });
+        }
+        Ok(conjunction(conditions).unwrap_or(Expression::Literal(Literal::Integer(1))))
+    }
+
+    /// The position in the scope of the first column (the rowid) of each source, followed by
+    /// the total number of columns
+    fn offsets(&self) -> Vec<usize> {
+        let mut offsets = vec![0];
+        for source in &self.sources {
+            offsets.push(offsets.last().unwrap_or(&0) + source.width());
+        }
+        offsets
+    }
+
+    /// The scope of the rows made of the sources up to `index`, included
+    fn partial_scope(&self, offsets: &[usize], index: usize) -> Scope<'a> {
+        self.scope
+            .with_columns(self.scope.columns[..offsets[index + 1]].to_vec())
+    }
+
+    /// The sources an expression refers to columns of. Columns of a subquery's own tables
+    /// named like columns of the sources may be taken for references to them.
+    fn referenced_sources(&self, offsets: &[usize], expression: &Expression) -> Vec<usize> {
+        let mut sources = Vec::new();
+        let mut references = Vec::new();
+        match expression {
+            Expression::Column { table, name } => {
+                references.push((table.as_deref(), name.as_str()))
+            }
+            _ => references.extend(
+                expression
+                    .subquery()
+                    .map(column_references)
+                    .unwrap_or_default(),
+            ),
+        }
+        for (table, name) in references {
+            if let Ok(Some(position)) = self.scope.find(table, name) {
+                sources.push(offsets.partition_point(|offset| *offset <= position) - 1);
+            }
+        }
+        for child in expression.children() {
+            sources.extend(self.referenced_sources(offsets, child));
+        }
+        sources
+    }
+}
+
+/// Splits an expression into the terms of its top-level AND.
+fn conjuncts(expression: &Expression) -> Vec<Expression> {
+    match expression {
+        Expression::Binary {
+            left,
+            operator: BinaryOperator::And,
+            right,
+        } => {
+            let mut terms = conjuncts(left);
+            terms.extend(conjuncts(right));
+            terms
+        }
+        other => vec![other.clone()],
+    }
+}
+
+fn conjunction(terms: Vec<Expression>) -> Option<Expression> {
+    terms.into_iter().reduce(|left, right| Expression::Binary {
+        left: Box::new(left),
+        operator: BinaryOperator::And,
+        right: Box::new(right),
+    })
+}
+
+/// Rows of a table keyed by the encoded values of the join columns
+type HashTable = HashMap<Vec<u8>, Vec<Vec<Value>>>;
+
+/// How the rows of a table matching a row of the tables on its left are found
+enum Lookup {
+    /// Every row of the table is a candidate
+    Scan,
+    /// The row whose rowid is the value of the expression
+    Rowid(Expression),
+    /// The rows found through an index whose first column equals the value of the expression,
+    /// once converted to the affinity they are compared with. Entries are sorted in descending
+    /// order of that column when `descending` is set, and compared with the collation the index
+    /// is ordered by.
+    Index {
+        root_page: u32,
+        descending: bool,
+        collation: Collation,
+        affinity: Option<Affinity>,
+        key: Expression,
+    },
+    /// The rows whose `columns` equal the values of the `keys` expressions, compared with the
+    /// given collations once converted to the given affinities, found in a hash table of the
+    /// whole table built on first use
+    Hash {
+        keys: Vec<Expression>,
+        columns: Vec<usize>,
+        collations: Vec<Collation>,
+        affinities: Vec<Option<Affinity>>,
+        table: RefCell<Option<(HashKeys, HashTable)>>,
+    },
+}
+
+/// Picks how to find the rows of a table given `column = expression` equalities, where the
+/// expressions only depend on the tables on its left: through the rowid, through an index,
+/// through a hash table, or by scanning the whole table.
+fn choose_lookup(
+    database: &Database,
+    source: &Source,
+    equalities: Vec<Equality>,
+    allow_hash: bool,
+) -> Result<Lookup> {
+    // functions are called again for every row on their left, whose values they may depend on
+    if let Relation::Function { .. } = &source.relation {
+        return Ok(Lookup::Scan);
+    }
+    if let Relation::Table(table) = &source.relation {
+        if let Some(lookup) = choose_table_lookup(database, table, &source.name, &equalities)? {
+            return Ok(lookup);
+        }
+    }
+    if allow_hash && !equalities.is_empty() {
+        let (mut keys, mut columns) = (Vec::new(), Vec::new());
+        let (mut collations, mut affinities) = (Vec::new(), Vec::new());
+        for equality in equalities {
+            keys.push(equality.key);
+            columns.push(equality.column);
+            collations.push(equality.collation);
+            affinities.push(equality.affinity);
+        }
+        return Ok(Lookup::Hash {
+            keys,
+            columns,
+            collations,
+            affinities,
+            table: RefCell::new(None),
+        });
+    }
+    Ok(Lookup::Scan)
+}
+
+/// Finds a way to look up the rows of a table through its rowid or one of its indexes.
+fn choose_table_lookup(
+    database: &Database,
+    table: &TableInformation,
+    name: &str,
+    equalities: &[Equality],
+) -> Result<Option<Lookup>> {
+    let rowid_columns = [Some(0), table.rowid_alias.map(|alias| alias + 1)];
+    if let Some(equality) = equalities
+        .iter()
+        .find(|equality| rowid_columns.contains(&Some(equality.column)))
+    {
+        return Ok(Some(Lookup::Rowid(equality.key.clone())));
+    }
+    let table_scope = Scope::for_table(table, name);
+    for index in database.list_indexes()? {
+        if !index.table_name.eq_ignore_ascii_case(&table.table_name) {
+            continue;
+        }
+        let first_column = &index.columns[0];
+        let Ok(indexed) = table_scope.resolve(None, &first_column.name) else {
+            continue;
+        };
+        // the index can only be searched for values compared with the collation it is
+        // ordered by, and converted to an affinity leaving its entries as they are
+        let collation = index_collation(first_column, &table_scope);
+        let affinity = table_scope.columns[indexed].affinity;
+        if let Some(equality) = equalities.iter().find(|equality| {
+            equality.column == indexed
+                && equality.collation.name().eq_ignore_ascii_case(collation)
+                && match equality.affinity {
+                    None => true,
+                    Some(Affinity::Text) => affinity == Some(Affinity::Text),
+                    Some(_) => affinity.is_some_and(Affinity::is_numeric),
+                }
+        }) {
+            return Ok(Some(Lookup::Index {
+                root_page: index.root_page as u32,
+                descending: first_column.order == SortOrder::Descending,
+                collation: equality.collation.clone(),
+                affinity: equality.affinity,
+                key: equality.key.clone(),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// A value used as a rowid, if it is (or converts losslessly to) an integer. Like the rowid,
+/// text is compared as the number it holds.
+fn as_rowid(value: &Value) -> Option<i64> {
+    let number = match value {
+        Value::Null | Value::Blob(_) => return None,
+        Value::String(text) => parse_numeric(text)?,
+        Value::Float64(r) => Numeric::Real(*r),
+        other => return other.as_i64(),
+    };
+    match number {
+        Numeric::Integer(i) => Some(i),
+        // i64::MIN is -2^63, whose opposite is one past the largest integer
+        Numeric::Real(r) if r.fract() == 0.0 && r >= i64::MIN as f64 && r < -(i64::MIN as f64) => {
+            Some(r as i64)
+        }
+        Numeric::Real(_) => None,
+    }
+}
+
+/// The rows a join step reads: those of a table, the rows a subquery returned, those of a
+/// common table, or those returned by a table-valued function
+#[derive(Clone)]
+enum Input {
+    Table(TableInformation),
+    Rows(Rc<Vec<Vec<Value>>>),
+    CommonTable(Rc<CommonTable>),
+    Function {
+        function: &'static TableFunction,
+        arguments: Vec<Expression>,
+    },
+}
+
+impl Input {
+    fn scan<'a>(&self, database: &'a Database) -> Rows<'a> {
+        match self {
+            Input::Table(table) => scan(database, table, AccessPath::TableScan { reverse: false }),
+            Input::Rows(rows) => {
+                let rows = rows.clone();
+                Box::new((0..rows.len()).map(move |i| Ok(rows[i].clone())))
+            }
+            Input::CommonTable(table) => table.scan(database),
+            Input::Function { .. } => {
+                unreachable!("functions are called with the values of the rows on their left")
+            }
+        }
+    }
+}
+
+/// A step of the join: the rows produced so far are combined with the matching rows of
+/// one more source.
+struct JoinStep<'a> {
+    database: &'a Database,
+    input: Input,
+    lookup: Lookup,
+    left_join: bool,
+    /// The condition a combined row must satisfy for the rows to match
+    matching: Option<Expression>,
+    /// The scope of the rows produced so far
+    left_scope: Scope<'a>,
+    /// The scope of the combined rows
+    scope: Scope<'a>,
+}
+
+impl<'a> JoinStep<'a> {
+    /// The rows of the source which may match a row of the sources on its left, as laid out by
+    /// `Scope::for_table` for a table
+    fn candidates(&self, left_row: &[Value]) -> Result<Rows<'a>> {
+        let table = match (&self.lookup, &self.input) {
+            (
+                _,
+                Input::Function {
+                    function,
+                    arguments,
+                },
+            ) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| evaluate(argument, &self.left_scope, left_row))
+                    .collect::<Result<Vec<_>>>()?;
+                let rows = function.call(&arguments)?.into_iter().enumerate();
+                return Ok(Box::new(rows.map(|(rowid, values)| {
+                    let mut row = vec![Value::Int64(rowid as i64)];
+                    row.extend(values);
+                    Ok(row)
+                })));
+            }
+            (Lookup::Scan, input) => return Ok(input.scan(self.database)),
+            (Lookup::Hash { .. }, _) => None,
+            (_, Input::Table(table)) => Some(table),
+            (_, Input::Rows(_) | Input::CommonTable(_)) => {
+                bail!("Subqueries and common tables can't be looked up by rowid or index")
+            }
+        };
+        match &self.lookup {
+            Lookup::Scan => unreachable!("scans are handled above"),
+            Lookup::Rowid(key) => {
+                let table = table.expect("rowid lookups are done on tables");
+                let key = evaluate(key, &self.left_scope, left_row)?;
+                let row = match as_rowid(&key) {
+                    Some(rowid) => self
+                        .database
+                        .find_row(table.root_page as u32, rowid as u64)?
+                        .map(|values| table_row(self.database, table, rowid as u64, values)),
+                    None => None,
+                };
+                Ok(Box::new(row.into_iter()))
+            }
+            Lookup::Index {
+                root_page,
+                descending,
+                collation,
+                affinity,
+                key,
+            } => {
+                let table = table.expect("index lookups are done on tables");
+                let mut key = evaluate(key, &self.left_scope, left_row)?;
+                if let Some(affinity) = affinity {
+                    key = apply(key, *affinity);
+                }
+                if key.is_null() {
+                    return Ok(Box::new(std::iter::empty()));
+                }
+                let encoding = self.database.header.encoding();
+                let entries = self.database.find_index_entries(*root_page, &|entry| {
+                    let ordering = match entry.first() {
+                        Some(value) if !value.is_null() => {
+                            collation.compare_stored(value, &key, encoding)
+                        }
+                        // NULLs are smaller than any other value
+                        _ => Ordering::Less,
+                    };
+                    if *descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })?;
+                Ok(lookup_rows(self.database, table, entries))
+            }
+            Lookup::Hash {
+                keys,
+                columns,
+                collations,
+                affinities,
+                table,
+            } => {
+                let key = keys
+                    .iter()
+                    .zip(affinities)
+                    .map(|(key, affinity)| {
+                        let key = evaluate(key, &self.left_scope, left_row)?;
+                        Ok(match affinity {
+                            Some(affinity) => apply(key, *affinity),
+                            None => key,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                if key.iter().any(Value::is_null) {
+                    return Ok(Box::new(std::iter::empty()));
+                }
+                if table.borrow().is_none() {
+                    *table.borrow_mut() =
+                        Some(self.build_hash_table(columns, collations, affinities)?);
+                }
+                let rows = table
+                    .borrow()
+                    .as_ref()
+                    .and_then(|(hash_keys, table)| table.get(&hash_keys.probe(&key)).cloned())
+                    .unwrap_or_default();
+                Ok(Box::new(rows.into_iter().map(Ok)))
+            }
+        }
+    }
+
+    fn build_hash_table(
+        &self,
+        columns: &[usize],
+        collations: &[Collation],
+        affinities: &[Option<Affinity>],
+    ) -> Result<(HashKeys, HashTable)> {
+        let mut hash_keys = HashKeys::new(collations.to_vec());
+        let mut table: HashTable = HashMap::new();
+        for row in self.input.scan(self.database) {
+            let row = row?;
+            let key: Vec<Value> = columns
+                .iter()
+                .zip(affinities)
+                .map(|(column, affinity)| match affinity {
+                    Some(affinity) => apply(row[*column].clone(), *affinity),
+                    None => row[*column].clone(),
+                })
+                .collect();
+            // NULL never equals anything
+            if key.iter().any(Value::is_null) {
+                continue;
+            }
+            table.entry(hash_keys.key(&key)).or_default().push(row);
+        }
+        Ok((hash_keys, table))
+    }
+}
+
+/// The rows produced by a join step
+struct JoinRows<'a> {
+    left: Rows<'a>,
+    step: JoinStep<'a>,
+    /// The left row being joined, the candidate rows it is still to be combined with, and
+    /// whether it matched any row so far
+    current: Option<(Vec<Value>, Rows<'a>, bool)>,
+}
+
+impl<'a> JoinRows<'a> {
+    fn next_row(&mut self) -> Result<Option<Vec<Value>>> {
+        loop {
+            let Some((left_row, candidates, matched)) = &mut self.current else {
+                let Some(left_row) = self.left.next().transpose()? else {
+                    return Ok(None);
+                };
+                let candidates = self.step.candidates(&left_row)?;
+                self.current = Some((left_row, candidates, false));
+                continue;
+            };
+            match candidates.next().transpose()? {
+                Some(right_row) => {
+                    let mut row = left_row.clone();
+                    row.extend(right_row);
+                    let matches = match &self.step.matching {
+                        Some(matching) => {
+                            truth_value(&evaluate(matching, &self.step.scope, &row)?) == Some(true)
+                        }
+                        None => true,
+                    };
+                    if matches {
+                        *matched = true;
+                        return Ok(Some(row));
+                    }
+                }
+                None => {
+                    let unmatched = !*matched;
+                    let mut row = std::mem::take(left_row);
+                    self.current = None;
+                    if self.step.left_join && unmatched {
+                        row.resize(self.step.scope.columns.len(), Value::Null);
+                        return Ok(Some(row));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for JoinRows<'a> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_row().transpose()
+    }
+}
+
+/// Produces the rows of a FROM clause for which `filter` holds, as laid out by its scope.
+///
+/// Tables are joined in the order they appear in. The table on the left of the first join is
+/// read with `access_path` when one is given; otherwise, like every following table, it is
+/// read through the rowid or an index when a condition sets the value of the rowid or of an
+/// indexed column. Remaining tables with an equality to the tables on their left are joined
+/// through a hash table, and the others by scanning them again for every row.
+///
+/// Each term of the WHERE clause (and of the ON clauses of inner joins) is checked as soon as
+/// the tables it refers to have been joined.
+pub fn execute_from<'a>(
+    database: &'a Database,
+    from: &FromClause<'a>,
+    filter: Option<&Expression>,
+    mut access_path: Option<AccessPath>,
+) -> Result<Rows<'a>> {
+    if from.sources.is_empty() {
+        // without a FROM clause, a single row made of no columns is produced
+        let row = std::iter::once(Ok(Vec::new()));
+        return Ok(filter_rows(
+            Box::new(row),
+            filter.cloned(),
+            from.scope.clone(),
+        ));
+    }
+    let offsets = from.offsets();
+    let mut filters: Vec<Vec<Expression>> = vec![Vec::new(); from.sources.len()];
+    let mut matching: Vec<Vec<Expression>> = vec![Vec::new(); from.sources.len()];
+    let mut pooled: Vec<Expression> = filter.map(conjuncts).unwrap_or_default();
+    for (index, source) in from.sources.iter().enumerate() {
+        let Some(constraint) = &source.constraint else {
+            continue;
+        };
+        validate(constraint, &from.partial_scope(&offsets, index))?;
+        if source.left_join {
+            matching[index] = conjuncts(constraint);
+        } else {
+            pooled.extend(conjuncts(constraint));
+        }
+    }
+    for term in pooled {
+        let step = from
+            .referenced_sources(&offsets, &term)
+            .into_iter()
+            .max()
+            .unwrap_or(0);
+        filters[step].push(term);
+    }
+
+    let mut rows: Option<Rows<'a>> = None;
+    for (index, source) in from.sources.iter().enumerate() {
+        // the terms which can be used to look up rows of the table
+        let candidate_terms = if source.left_join {
+            &matching[index]
+        } else {
+            &filters[index]
+        };
+        let equalities = candidate_terms
+            .iter()
+            .filter_map(|term| equality(from, &offsets, index, term))
+            .collect();
+        let scope = from.partial_scope(&offsets, index);
+        let input = match &source.relation {
+            Relation::Table(table) => Input::Table(table.clone()),
+            Relation::Subquery { query, .. } => Input::Rows(Rc::new(
+                execute(database, query, &from.tables)?.collect::<Result<_>>()?,
+            )),
+            Relation::CommonTable(table) => Input::CommonTable(table.clone()),
+            Relation::Function {
+                function,
+                arguments,
+            } => Input::Function {
+                function,
+                arguments: arguments.clone(),
+            },
+        };
+        let step_rows: Rows<'a> = match rows.take() {
+            None => match (access_path.take(), &source.relation) {
+                (Some(access_path), Relation::Table(table))
+                    if access_path != AccessPath::TableScan { reverse: false } =>
+                {
+                    scan(database, table, access_path)
+                }
+                _ => JoinStep {
+                    database,
+                    input,
+                    lookup: choose_lookup(database, source, equalities, false)?,
+                    left_join: false,
+                    matching: None,
+                    left_scope: from.scope.with_columns(vec![]),
+                    scope: scope.clone(),
+                }
+                .candidates(&[])?,
+            },
+            Some(left) => Box::new(JoinRows {
+                left,
+                step: JoinStep {
+                    database,
+                    input,
+                    lookup: choose_lookup(database, source, equalities, true)?,
+                    left_join: source.left_join,
+                    matching: conjunction(matching[index].clone()),
+                    left_scope: from.partial_scope(&offsets, index - 1),
+                    scope: scope.clone(),
+                },
+                current: None,
+            }),
+        };
+        rows = Some(filter_rows(
+            step_rows,
+            conjunction(filters[index].clone()),
+            scope,
+        ));
+    }
+    rows.ok_or_else(|| anyhow!("FROM clause without tables"))
+}
+
+/// A `column = key` term of a condition, where the column belongs to a source and the key only
+/// depends on the sources on its left
+struct Equality {
+    /// The position of the column in the rows of the source
+    column: usize,
+    key: Expression,
+    /// The collation the column and the key are compared with
+    collation: Collation,
+    /// The affinity the column and the key are converted to before being compared
+    affinity: Option<Affinity>,
+}
+
+/// Recognizes a `column = expression` term where the column belongs to the source at `index`
+/// and the expression only depends on the sources on its left.
+fn equality(
+    from: &FromClause,
+    offsets: &[usize],
+    index: usize,
+    term: &Expression,
+) -> Option<Equality> {
+    let Expression::Binary {
+        left,
+        operator: BinaryOperator::Equal,
+        right,
+    } = term
+    else {
+        return None;
+    };
+    let column_of_source = |expression: &Expression| match expression {
+        Expression::Column { table, name } => {
+            let position = from.scope.resolve(table.as_deref(), name).ok()?;
+            (offsets[index]..offsets[index + 1])
+                .contains(&position)
+                .then(|| position - offsets[index])
+        }
+        _ => None,
+    };
+    // a key calling a function which isn't deterministic is compared to every row rather
+    // than evaluated once to look rows up
+    let depends_on_left = |expression: &Expression| {
+        from.scope.functions().is_deterministic(expression)
+            && from
+                .referenced_sources(offsets, expression)
+                .iter()
+                .all(|source| *source < index)
+    };
+    let (column, key) = match (column_of_source(left), column_of_source(right)) {
+        (Some(column), _) if depends_on_left(right) => (column, right),
+        (_, Some(column)) if depends_on_left(left) => (column, left),
+        _ => return None,
+    };
+    Some(Equality {
+        column,
+        key: (**key).clone(),
+        collation: comparison_collation(left, right, &from.scope).ok()?,
+        affinity: comparison_affinity(
+            expression_affinity(left, &from.scope).ok()?,
+            expression_affinity(right, &from.scope).ok()?,
+        ),
+    })
+}
+
+/// Completes the values of a table record into a row laid out as in `Scope::for_table`: the
+/// rowid followed by the table columns, VIRTUAL generated columns being evaluated.
+fn table_row(
+    database: &Database,
+    table: &TableInformation,
+    rowid: u64,
+    values: Vec<Value>,
+) -> Result<Vec<Value>> {
+    let mut row = vec![Value::Int64(rowid as i64)];
+    row.extend(table.row_values(rowid, values));
+    let virtual_columns = (0..table.column_names.len())
+        .filter(|column| table.is_virtual(*column))
+        .collect::<Vec<_>>();
+    if virtual_columns.is_empty() {
+        return Ok(row);
+    }
+    let scope = Scope {
+        subqueries: Some(Subqueries::new(database, CommonTables::default())),
+        ..Scope::for_table(table, &table.table_name)
+    };
+    // generated columns can refer to those declared after them: evaluating them all as many
+    // times as there are leaves each evaluated after those it refers to
+    for _ in 0..virtual_columns.len() {
+        for &column in &virtual_columns {
+            let expression = table.generated_columns[column]
+                .as_ref()
+                .and_then(|generated| generated.expression.as_ref())
+                .ok_or_else(|| {
+                    database::Error::Unsupported(format!(
+                        "unsupported expression for generated column {}.{}",
+                        table.table_name, table.column_names[column]
+                    ))
+                })?;
+            let value = evaluate(expression, &scope, &row)?;
+            row[column + 1] = apply(value, table.column_affinities[column]);
+        }
+    }
+    Ok(row)
+}
+
+/// Looks up the rows the given index entries point to.
+fn lookup_rows<'a>(
+    database: &'a Database,
+    table: &TableInformation,
+    entries: Vec<Vec<Value>>,
+) -> Rows<'a> {
+    let table = table.clone();
+    Box::new(entries.into_iter().map(move |entry| {
+        // the rowid is the last value of an index record
+        let rowid = entry
+            .last()
+            .and_then(Value::as_i64)
+            .ok_or_else(|| anyhow!("Index record has no rowid"))? as u64;
+        match database.find_row(table.root_page as u32, rowid)? {
+            Some(values) => table_row(database, &table, rowid, values),
+            None => bail!("Index points to missing row {}", rowid),
+        }
+    }))
+}
+
+/// Reads the rows of a table laid out as in `Scope::for_table`: the rowid followed by the
+/// table columns.
+pub fn scan<'a>(
+    database: &'a Database,
+    table: &TableInformation,
+    access_path: AccessPath,
+) -> Rows<'a> {
+    let table_root = table.root_page as u32;
+    let table = table.clone();
+    match access_path {
+        AccessPath::TableScan { reverse } => Box::new(
+            database
+                .table_cursor(table_root, reverse)
+                .map(move |entry| {
+                    let (rowid, values) = entry?;
+                    table_row(database, &table, rowid, values)
+                }),
+        ),
+        AccessPath::IndexScan { root_page, reverse } => {
+            let entries = database.index_cursor(root_page, reverse);
+            Box::new(entries.map(move |entry| {
+                let rowid = entry?
+                    .last()
+                    .and_then(Value::as_i64)
+                    .ok_or_else(|| anyhow!("Index record has no rowid"))?
+                    as u64;
+                match database.find_row(table_root, rowid)? {
+                    Some(values) => table_row(database, &table, rowid, values),
+                    None => bail!("Index points to missing row {}", rowid),
+                }
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::engine::select::column_names;
+    use crate::sql::sql_query;
+
+    // built by tests/fixtures/join.sql, with emp(dept_id) indexed in descending order
+    const FIXTURE: &str = "tests/fixtures/join.db";
+
+    fn query(database: &Database, sql: &str) -> Vec<String> {
+        let statement = sql_query::select_statement(sql).unwrap();
+        execute(database, &statement, &Default::default())
+            .unwrap()
+            .map(|row| row.unwrap().iter().map(Value::to_string).join("|"))
+            .collect()
+    }
+
+    /// How the rows of the last source of the FROM clause of a query are looked up
+    fn last_lookup(database: &Database, sql: &str) -> Lookup {
+        let statement = sql_query::select_statement(sql).unwrap();
+        let tables = Default::default();
+        let from = FromClause::plan(database, statement.from_target.as_ref(), &tables).unwrap();
+        let offsets = from.offsets();
+        let index = from.sources.len() - 1;
+        let constraint = from.sources[index].constraint.as_ref();
+        let terms = constraint.map(conjuncts).unwrap_or_default();
+        let equalities = terms
+            .iter()
+            .filter_map(|term| equality(&from, &offsets, index, term))
+            .collect();
+        choose_lookup(database, &from.sources[index], equalities, true).unwrap()
+    }
+
+    #[test]
+    fn splits_and_rejoins_conjuncts() {
+        let expression = sql_query::expression("a = 1 and (b = 2 or c = 3) and d").unwrap();
+        let terms = conjuncts(&expression);
+        assert_eq!(
+            terms,
+            vec![
+                sql_query::expression("a = 1").unwrap(),
+                sql_query::expression("b = 2 or c = 3").unwrap(),
+                sql_query::expression("d").unwrap(),
+            ]
+        );
+        assert_eq!(conjunction(terms), Some(expression));
+        assert_eq!(conjunction(vec![]), None);
+    }
+
+    #[test]
+    fn converts_values_to_rowids() {
+        assert_eq!(as_rowid(&Value::Int8(7)), Some(7));
+        assert_eq!(as_rowid(&Value::Float64(3.0)), Some(3));
+        assert_eq!(as_rowid(&Value::Float64(3.5)), None);
+        assert_eq!(as_rowid(&Value::String("12".to_string())), Some(12));
+        assert_eq!(as_rowid(&Value::Null), None);
+    }
+
+    #[test]
+    fn evaluates_virtual_generated_columns() {
+        // built by tests/fixtures/generated.sql
+        let database = Database::init_from_file("tests/fixtures/generated.db").unwrap();
+        let query = |sql| query(&database, sql);
+        assert_eq!(query("SELECT * FROM a"), ["1|q|2.5|qz|1", "2|p|3.0|pz|"]);
+        assert_eq!(query("SELECT x FROM a WHERE w = 'pz'"), ["2"]);
+        assert_eq!(query("SELECT * FROM g"), ["3|6|x"]);
+        // b refers to c, declared after it
+        assert_eq!(query("SELECT *, typeof(c) FROM f"), ["5|51|50|e|integer"]);
+        assert_eq!(
+            query("SELECT a.w, f.b FROM a JOIN f ON f.a = a.x + 4"),
+            ["qz|51"]
+        );
+    }
+
+    #[test]
+    fn chooses_how_to_look_up_joined_rows() {
+        let database = Database::init_from_file(FIXTURE).unwrap();
+        let lookup = |sql| last_lookup(&database, sql);
+        assert!(matches!(
+            lookup("SELECT * FROM emp JOIN dept ON dept.id = emp.dept_id"),
+            Lookup::Rowid(_)
+        ));
+        assert!(matches!(
+            lookup("SELECT * FROM dept JOIN emp ON emp.dept_id = dept.id"),
+            Lookup::Index {
+                descending: true,
+                ..
+            }
+        ));
+        assert!(matches!(
+            lookup("SELECT * FROM emp JOIN project ON project.emp_id = emp.id"),
+            Lookup::Hash { .. }
+        ));
+        assert!(matches!(
+            lookup("SELECT * FROM emp JOIN project ON project.emp_id < emp.id"),
+            Lookup::Scan
+        ));
+    }
+
+    #[test]
+    fn joins_rows() {
+        let database = Database::init_from_file(FIXTURE).unwrap();
+        let query = |sql| query(&database, sql);
+        // through the descending index of emp
+        assert_eq!(
+            query(
+                "SELECT dept.name, emp.name FROM dept JOIN emp ON emp.dept_id = dept.id \
+                 ORDER BY emp.id"
+            ),
+            vec!["Sales|Ann", "Research|Bob", "Research|Cid"]
+        );
+        assert_eq!(
+            query("SELECT name FROM emp WHERE dept_id = 2 ORDER BY name"),
+            vec!["Bob", "Cid"]
+        );
+        // through a hash table of project
+        assert_eq!(
+            query(
+                "SELECT emp.name, title FROM emp JOIN project ON project.emp_id = emp.id \
+                 ORDER BY title"
+            ),
+            vec!["Cid|Pager", "Bob|Parser", "Cid|Planner"]
+        );
+        // rows without a match are completed with NULLs
+        assert_eq!(
+            query(
+                "SELECT dept.name, typeof(emp.id) FROM dept LEFT JOIN emp \
+                 ON emp.dept_id = dept.id ORDER BY dept.id, emp.id"
+            ),
+            vec![
+                "Sales|integer",
+                "Research|integer",
+                "Research|integer",
+                "Legal|null"
+            ]
+        );
+        assert_eq!(
+            query("SELECT * FROM emp JOIN badge USING (dept_id) ORDER BY emp.id"),
+            vec!["1|Ann|1|100|red", "2|Bob|2|200|blue", "3|Cid|2|300|blue"]
+        );
+    }
+
+    #[test]
+    fn leaves_columns_merged_by_using_out_of_star() {
+        let database = Database::init_from_file(FIXTURE).unwrap();
+        let statement =
+            sql_query::select_statement("SELECT * FROM emp JOIN badge USING (dept_id)").unwrap();
+        let columns = column_names(&database, &statement, &Default::default()).unwrap();
+        assert_eq!(columns, vec!["id", "name", "dept_id", "salary", "color"]);
+    }
+}
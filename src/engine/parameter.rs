@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::engine::subquery::value_literal;
+use crate::sql::{Expression, Literal, Parameter, SelectStatement, Targetable, WithClause};
+
+/// The largest number a parameter can have, as in SQLite
+const MAX_PARAMETER_NUMBER: usize = 250000;
+
+/// The parameters of a statement, numbered from 1 like SQLite numbers them: `?NNN` is
+/// parameter NNN, while `?` and each name the first time it comes take the number following
+/// the largest one so far.
+#[derive(Debug, Default)]
+pub struct Parameters {
+    /// The name of each parameter by number, none for those written `?`
+    names: Vec<Option<String>>,
+    /// The number of each parameter of the statement, by its position in the text
+    numbers: HashMap<usize, usize>,
+}
+
+impl Parameters {
+    pub fn of(statement: &SelectStatement) -> Result<Parameters> {
+        let mut found = Vec::new();
+        collect_parameters(statement, &mut found);
+        found.sort_by_key(|parameter| parameter.position);
+
+        let mut parameters = Parameters::default();
+        for parameter in found {
+            let number = match parameter.name.strip_prefix('?') {
+                Some("") => parameters.names.len() + 1,
+                Some(digits) => match digits.parse() {
+                    Ok(number @ 1..=MAX_PARAMETER_NUMBER) => number,
+                    _ => bail!(
+                        "variable number must be between ?1 and ?{}",
+                        MAX_PARAMETER_NUMBER
+                    ),
+                },
+                None => parameters
+                    .index(&parameter.name)
+                    .unwrap_or(parameters.names.len() + 1),
+            };
+            if number > parameters.names.len() {
+                parameters.names.resize(number, None);
+            }
+            if parameter.name != "?" {
+                parameters.names[number - 1] = Some(parameter.name.clone());
+            }
+            parameters.numbers.insert(parameter.position, number);
+        }
+        Ok(parameters)
+    }
+
+    /// How many parameters the statement has: the largest number of its parameters
+    pub fn count(&self) -> usize {
+        self.names.len()
+    }
+
+    /// The name of a parameter, with the character it starts with
+    pub fn name(&self, number: usize) -> Option<&str> {
+        self.names.get(number.checked_sub(1)?)?.as_deref()
+    }
+
+    /// The number of the parameter with the given name, with the character it starts with
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .position(|n| n.as_deref() == Some(name))
+            .map(|index| index + 1)
+    }
+
+    /// Replaces the parameters of a statement by the values bound to them, in the order of their
+    /// numbers, or by NULL.
+    pub fn bind(&self, statement: &mut SelectStatement, values: &[Value]) {
+        for query in statement
+            .with
+            .iter_mut()
+            .flat_map(WithClause::queries_mut)
+            .chain(statement.compound.iter_mut().map(|term| &mut term.select))
+            .chain(
+                statement
+                    .from_target
+                    .iter_mut()
+                    .flat_map(Targetable::subqueries_mut),
+            )
+        {
+            self.bind(query, values);
+        }
+        for expression in statement.expressions_mut() {
+            self.bind_expression(expression, values);
+        }
+    }
+
+    fn bind_expression(&self, expression: &mut Expression, values: &[Value]) {
+        if let Expression::Parameter(Parameter { position, .. }) = expression {
+            let value = self
+                .numbers
+                .get(position)
+                .and_then(|number| values.get(number - 1));
+            *expression = Expression::Literal(value.map_or(Literal::Null, value_literal));
+            return;
+        }
+        if let Some(query) = expression.subquery_mut() {
+            self.bind(query, values);
+        }
+        for child in expression.children_mut() {
+            self.bind_expression(child, values);
+        }
+    }
+}
+
+fn collect_parameters<'s>(statement: &'s SelectStatement, parameters: &mut Vec<&'s Parameter>) {
+    let queries = statement
+        .with
+        .iter()
+        .flat_map(WithClause::queries)
+        .chain(statement.compound.iter().map(|term| &term.select))
+        .chain(
+            statement
+                .from_target
+                .iter()
+                .flat_map(Targetable::subqueries),
+        );
+    for query in queries {
+        collect_parameters(query, parameters);
+    }
+    for expression in statement.expressions() {
+        collect_expression_parameters(expression, parameters);
+    }
+}
+
+fn collect_expression_parameters<'s>(
+    expression: &'s Expression,
+    parameters: &mut Vec<&'s Parameter>,
+) {
+    if let Expression::Parameter(parameter) = expression {
+        parameters.push(parameter);
+    }
+    if let Some(query) = expression.subquery() {
+        collect_parameters(query, parameters);
+    }
+    for child in expression.children() {
+        collect_expression_parameters(child, parameters);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sql::sql_query;
+
+    #[test]
+    fn numbers_parameters_like_sqlite() {
+        let statement = sql_query::select_statement(
+            "SELECT :a, ?, (SELECT ?5 WHERE @b = :a), ? FROM t WHERE x IN (SELECT $c)",
+        )
+        .unwrap();
+        let parameters = Parameters::of(&statement).unwrap();
+        assert_eq!(parameters.count(), 8);
+        assert_eq!(parameters.index(":a"), Some(1));
+        assert_eq!(parameters.name(2), None);
+        assert_eq!(parameters.name(5), Some("?5"));
+        assert_eq!(parameters.index("@b"), Some(6));
+        assert_eq!(parameters.index("$c"), Some(8));
+        assert_eq!(parameters.name(9), None);
+
+        let statement = sql_query::select_statement("SELECT ?0").unwrap();
+        assert!(Parameters::of(&statement).is_err());
+    }
+
+    #[test]
+    fn binds_values_to_parameters() {
+        let mut statement = sql_query::select_statement("SELECT ?2, :x, ?1, :x").unwrap();
+        let parameters = Parameters::of(&statement).unwrap();
+        parameters.bind(&mut statement, &[Value::Int64(1), Value::Float64(2.5)]);
+        let expected = sql_query::select_statement("SELECT 2.5, NULL, 1, NULL").unwrap();
+        assert_eq!(statement.expressions(), expected.expressions());
+    }
+}
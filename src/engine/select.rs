@@ -0,0 +1,706 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::database::schema::TableInformation;
+use crate::database::{self, Database};
+use crate::sql::{self, Expression, Literal, NullsOrder, OrderingTerm, Selectable, SortOrder};
+
+use super::affinity::{expression_affinity, Affinity};
+use super::aggregate::{
+    aggregate, collect_aggregates, contains_aggregate, DEFAULT_AGGREGATE_MEMORY_BUDGET,
+};
+use super::collation::{
+    collation_of, declared_collation, index_collation, Collation, HashKeys, BINARY,
+};
+use super::compound;
+use super::cte::CommonTables;
+use super::expression::{evaluate, to_numeric, truth_value, validate, Numeric, Scope};
+use super::join::{execute_from, AccessPath, FromClause, Relation};
+use super::sort::{SortKey, Sorter, DEFAULT_SORT_MEMORY_BUDGET, TOP_K_THRESHOLD};
+use super::window::{self, collect_windows, resolve_selectables, resolve_windows};
+
+/// A stream of result rows
+pub type Rows<'a> = Box<dyn Iterator<Item = Result<Vec<Value>>> + 'a>;
+
+/// Executes a SELECT statement, returning its result rows. `tables` are the common tables it
+/// can read from besides those of its own WITH clause.
+pub fn execute<'a>(
+    database: &'a Database,
+    statement: &'a sql::SelectStatement,
+    tables: &CommonTables,
+) -> Result<Rows<'a>> {
+    let tables = tables.with_clause(database, statement.with.as_ref())?;
+    if !statement.compound.is_empty() {
+        return compound::execute(database, statement, &tables);
+    }
+    execute_select(
+        database,
+        statement,
+        &tables,
+        &statement.order_by,
+        statement.limit.as_ref(),
+    )
+}
+
+/// Executes a single SELECT, leaving aside the WITH clause and the SELECTs compounded with it,
+/// ordering and limiting its rows as given.
+pub fn execute_select<'a>(
+    database: &'a Database,
+    statement: &'a sql::SelectStatement,
+    tables: &CommonTables,
+    order_by: &'a [OrderingTerm],
+    limit: Option<&sql::Limit>,
+) -> Result<Rows<'a>> {
+    let from = FromClause::plan(database, statement.from_target.as_ref(), tables)?;
+    let scope = from.scope.clone();
+    let selectables = resolve_selectables(&statement.selectables, &statement.windows)?;
+    let aliases = Aliases::new(&selectables);
+    let filter = statement
+        .where_clause
+        .as_ref()
+        .map(|filter| aliases.substitute(filter, &scope, false));
+    if let Some(filter) = &filter {
+        validate(filter, &scope)?;
+    }
+    let group_by = resolve_group_by(statement, &selectables, &aliases, &scope)?;
+    let having = statement
+        .having
+        .as_ref()
+        .map(|having| aliases.substitute(having, &scope, false));
+    let order_by: Vec<OrderingTerm> = order_by
+        .iter()
+        .map(|term| {
+            let mut expression = aliases.substitute(&term.expression, &scope, true);
+            resolve_windows(&mut expression, &statement.windows)?;
+            Ok(OrderingTerm {
+                expression,
+                ..term.clone()
+            })
+        })
+        .collect::<Result<_>>()?;
+    let (offset, limit) = evaluate_limit(limit, &scope.with_columns(vec![]))?;
+
+    let mut aggregates = Vec::new();
+    let mut windows = Vec::new();
+    for selectable in selectables.iter() {
+        if let Selectable::Expression { expression, .. } = selectable {
+            collect_aggregates(expression, scope.functions(), &mut aggregates)?;
+            collect_windows(expression, scope.functions(), &mut windows)?;
+        }
+    }
+    if let Some(having) = &having {
+        collect_aggregates(having, scope.functions(), &mut aggregates)?;
+    }
+    for term in &order_by {
+        collect_aggregates(&term.expression, scope.functions(), &mut aggregates)?;
+        collect_windows(&term.expression, scope.functions(), &mut windows)?;
+    }
+    let grouped = !group_by.is_empty() || !aggregates.is_empty();
+    if having.is_some() && !grouped {
+        bail!("HAVING clause on a non-aggregate query");
+    }
+
+    let (rows, scope, order_satisfied): (Rows<'a>, Scope, bool) = if grouped {
+        // the input is read a second time when it has to be aggregated by sorting it
+        let input = || execute_from(database, &from, filter.as_ref(), None);
+        let rows = aggregate(
+            input,
+            &scope,
+            &group_by,
+            &aggregates,
+            DEFAULT_AGGREGATE_MEMORY_BUDGET,
+        )?;
+        let scope = Scope {
+            aggregates,
+            ..scope
+        };
+        let rows = match having {
+            Some(having) => {
+                validate(&having, &scope)?;
+                filter_rows(rows, Some(having), scope.clone())
+            }
+            None => rows,
+        };
+        (rows, scope, order_by.is_empty())
+    } else {
+        let (access_path, order_satisfied) = match from.sources.as_slice() {
+            [source] => match &source.relation {
+                Relation::Table(table) => choose_access_path(database, table, &scope, &order_by)?,
+                Relation::Subquery { .. }
+                | Relation::CommonTable(_)
+                | Relation::Function { .. } => (
+                    AccessPath::TableScan { reverse: false },
+                    order_by.is_empty(),
+                ),
+            },
+            _ => (
+                AccessPath::TableScan { reverse: false },
+                order_by.is_empty(),
+            ),
+        };
+        let access_path = order_satisfied.then_some(access_path);
+        let rows = execute_from(database, &from, filter.as_ref(), access_path)?;
+        (rows, scope, order_satisfied)
+    };
+    // window functions leave rows in the order of their first window
+    let (rows, scope, order_satisfied) = if windows.is_empty() {
+        (rows, scope, order_satisfied)
+    } else {
+        let rows = window::compute(rows, &scope, &windows)?;
+        (rows, Scope { windows, ..scope }, order_by.is_empty())
+    };
+
+    let projection = resolve_projection(&selectables, &scope)?;
+    // SELECT DISTINCT compares result columns with their collation
+    let mut seen = match statement.distinct {
+        true => {
+            let collations = projection
+                .iter()
+                .map(|projected| projected_collation(projected, &scope))
+                .collect::<Result<_>>()?;
+            Some((HashSet::new(), HashKeys::new(collations)))
+        }
+        false => None,
+    };
+    for (index, term) in order_by.iter().enumerate() {
+        match result_column_reference(term) {
+            Some(position) if position < 1 || position as usize > projection.len() => bail!(
+                "{} ORDER BY term out of range - should be between 1 and {}",
+                ordinal(index + 1),
+                projection.len()
+            ),
+            Some(_) => {}
+            None => validate(&term.expression, &scope)?,
+        }
+    }
+    let rows: Rows<'a> = if order_satisfied {
+        let scope = scope.clone();
+        let rows = rows.map(move |row| row.and_then(|row| project(&projection, &scope, &row)));
+        // SELECT DISTINCT keeps the first of each set of equal rows
+        Box::new(rows.filter(move |row| match (row, &mut seen) {
+            (Ok(row), Some((seen, keys))) => seen.insert(keys.key(row)),
+            _ => true,
+        }))
+    } else {
+        let keys = order_by
+            .iter()
+            .map(|term| {
+                // positions were checked to be in range above
+                let collation = match result_column_reference(term) {
+                    Some(position) => {
+                        projected_collation(&projection[position as usize - 1], &scope)?
+                    }
+                    None => collation_of(&term.expression, &scope)?,
+                };
+                Ok(sort_key(term, collation))
+            })
+            .collect::<Result<_>>()?;
+        let mut sorter = sorter(keys, offset, limit);
+        let mut rows = rows;
+        let sorted = rows
+            .try_for_each(|row| {
+                let row = row?;
+                let output = project(&projection, &scope, &row)?;
+                if let Some((seen, keys)) = &mut seen {
+                    if !seen.insert(keys.key(&output)) {
+                        return Ok(());
+                    }
+                }
+                let key = order_by
+                    .iter()
+                    .map(|term| sort_key_value(term, &scope, &row, &output))
+                    .collect::<Result<Vec<_>>>()?;
+                sorter.push(key, output)
+            })
+            .and_then(|()| sorter.finish());
+        read_in_full(sorted.map(|rows| Box::new(rows) as Rows))
+    };
+    Ok(apply_limit(rows, offset, limit))
+}
+
+/// The rows of a statement which reads its input in full before returning any, to sort or
+/// aggregate it. Errors met reading the input come out as its first row: SQLite reports them
+/// while stepping through the statement, not while preparing it.
+pub fn read_in_full(rows: Result<Rows>) -> Rows {
+    rows.unwrap_or_else(|e| Box::new(std::iter::once(Err(e))))
+}
+
+/// A sorter for rows of which only those up to `offset + limit` are read
+pub fn sorter(keys: Vec<SortKey>, offset: usize, limit: Option<usize>) -> Sorter {
+    match limit {
+        Some(limit) if limit.saturating_add(offset) <= TOP_K_THRESHOLD => {
+            Sorter::with_limit(keys, limit + offset)
+        }
+        _ => Sorter::new(keys, DEFAULT_SORT_MEMORY_BUDGET),
+    }
+}
+
+/// Skips the first `offset` rows, and keeps at most `limit` of the following ones
+pub fn apply_limit(rows: Rows, offset: usize, limit: Option<usize>) -> Rows {
+    let rows = rows.skip(offset);
+    match limit {
+        Some(limit) => Box::new(rows.take(limit)),
+        None => Box::new(rows),
+    }
+}
+
+/// The names of the result columns of a statement, as SQLite names them: after their alias,
+/// or after the column they refer to, or else after their expression as written.
+pub fn column_names(
+    database: &Database,
+    statement: &sql::SelectStatement,
+    tables: &CommonTables,
+) -> Result<Vec<String>> {
+    let tables = tables.with_clause(database, statement.with.as_ref())?;
+    let from = FromClause::plan(database, statement.from_target.as_ref(), &tables)?;
+    Ok(result_columns(&statement.selectables, &from.scope)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// How the values of a result column of a subquery or common table compare: with its collating
+/// sequence, once converted to its affinity
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnType {
+    pub collation: String,
+    pub affinity: Option<Affinity>,
+}
+
+/// The types of the result columns of a statement. Their collation is that of the column or
+/// COLLATE expression they are, BINARY otherwise, and their affinity that of the expression.
+///
+/// The result columns of a compound SELECT have the collation of the leftmost SELECT giving
+/// them one, and the affinity given by the leftmost SELECT, unless another one may return
+/// values it would convert: NUMERIC with text, or TEXT with numbers. They are left with BLOB
+/// affinity then.
+pub fn column_types(
+    database: &Database,
+    statement: &sql::SelectStatement,
+    tables: &CommonTables,
+) -> Result<Vec<ColumnType>> {
+    let tables = tables.with_clause(database, statement.with.as_ref())?;
+    let mut collations: Vec<Option<String>> = Vec::new();
+    let mut affinities: Vec<Option<Affinity>> = Vec::new();
+    // whether each column may hold numbers, and whether it may hold text
+    let mut holds: Vec<(bool, bool)> = Vec::new();
+    let selects = std::iter::once(statement).chain(statement.compound.iter().map(|t| &t.select));
+    for (position, select) in selects.enumerate() {
+        let from = FromClause::plan(database, select.from_target.as_ref(), &tables)?;
+        let columns = result_columns(&select.selectables, &from.scope)?;
+        collations.resize(columns.len(), None);
+        holds.resize(columns.len(), (false, false));
+        for (column, (_, projected)) in columns.into_iter().enumerate() {
+            let (collation, affinity, expression) = match projected {
+                Projected::Expression(expression) => (
+                    declared_collation(&expression, &from.scope),
+                    expression_affinity(&expression, &from.scope)?,
+                    Some(expression),
+                ),
+                Projected::Column(i) => {
+                    let scope_column = &from.scope.columns[i];
+                    (
+                        Some(scope_column.collation.clone()),
+                        scope_column.affinity,
+                        None,
+                    )
+                }
+            };
+            if collations[column].is_none() {
+                collations[column] = collation;
+            }
+            if position == 0 {
+                affinities.push(affinity);
+            }
+            let (numbers, text) = may_hold(expression.as_ref(), affinity);
+            holds[column].0 |= numbers;
+            holds[column].1 |= text;
+        }
+    }
+    Ok(collations
+        .into_iter()
+        .zip(affinities)
+        .zip(holds)
+        .map(|((collation, affinity), (numbers, text))| ColumnType {
+            collation: collation.unwrap_or_else(|| BINARY.to_string()),
+            affinity: match affinity {
+                Some(Affinity::Text) if numbers => Some(Affinity::Blob),
+                Some(affinity) if affinity.is_numeric() && text => Some(Affinity::Blob),
+                affinity => affinity,
+            },
+        })
+        .collect())
+}
+
+/// Whether a result column may hold numbers, and whether it may hold text, judging from the
+/// literal it is or else from its affinity: columns hold values of other types when they
+/// can't be converted to their affinity.
+fn may_hold(expression: Option<&Expression>, affinity: Option<Affinity>) -> (bool, bool) {
+    match (expression, affinity) {
+        (Some(Expression::Literal(literal)), _) => (
+            matches!(literal, Literal::Integer(_) | Literal::Real(_)),
+            matches!(literal, Literal::String(_)),
+        ),
+        (_, Some(affinity)) if affinity.is_numeric() => (true, false),
+        (_, Some(Affinity::Text)) => (false, true),
+        _ => (true, true),
+    }
+}
+
+/// The result columns the selectables of a statement stand for, along with their names
+pub fn result_columns(
+    selectables: &[Selectable],
+    scope: &Scope,
+) -> Result<Vec<(String, Projected)>> {
+    let mut columns = Vec::new();
+    for selectable in selectables {
+        let stars = match selectable {
+            Selectable::Expression {
+                expression,
+                alias,
+                text,
+            } => {
+                let name = match (alias, expression) {
+                    (Some(alias), _) => alias.clone(),
+                    (None, Expression::Column { table, name }) => {
+                        match scope.find(table.as_deref(), name) {
+                            Ok(Some(i)) if !scope.columns[i].hidden => {
+                                scope.columns[i].name.clone()
+                            }
+                            _ => name.clone(),
+                        }
+                    }
+                    (None, _) => text.clone(),
+                };
+                columns.push((name, Projected::Expression(expression.clone())));
+                continue;
+            }
+            Selectable::Star => star_columns(scope, None)?,
+            Selectable::TableStar(table) => star_columns(scope, Some(table))?,
+        };
+        columns.extend(
+            stars
+                .into_iter()
+                .map(|i| (scope.columns[i].name.clone(), Projected::Column(i))),
+        );
+    }
+    Ok(columns)
+}
+
+/// The positions of the columns `*` stands for, or `table.*` when a table is given. The
+/// columns a USING clause merges are part of `table.*`.
+fn star_columns(scope: &Scope, table: Option<&str>) -> Result<Vec<usize>> {
+    let columns = scope.columns.iter().enumerate();
+    let Some(table) = table else {
+        return Ok(columns
+            .filter(|(_, c)| c.in_star())
+            .map(|(i, _)| i)
+            .collect());
+    };
+    if !scope
+        .columns
+        .iter()
+        .any(|c| c.table.eq_ignore_ascii_case(table))
+    {
+        return Err(database::Error::Schema(format!("no such table: {}", table)).into());
+    }
+    Ok(columns
+        .filter(|(_, c)| !c.hidden && c.table.eq_ignore_ascii_case(table))
+        .map(|(i, _)| i)
+        .collect())
+}
+
+/// The aliases given to the result columns of a statement, which its other clauses can refer
+/// to
+struct Aliases<'s>(Vec<(&'s str, &'s Expression)>);
+
+impl<'s> Aliases<'s> {
+    fn new(selectables: &'s [Selectable]) -> Aliases<'s> {
+        Aliases(
+            selectables
+                .iter()
+                .filter_map(|selectable| match selectable {
+                    Selectable::Expression {
+                        expression,
+                        alias: Some(alias),
+                        ..
+                    } => Some((alias.as_str(), expression)),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Replaces the references an expression makes to aliases by the expressions they stand
+    /// for. ORDER BY terms prefer aliases, while the other clauses prefer the columns of the
+    /// FROM clause with the same name.
+    fn substitute(
+        &self,
+        expression: &Expression,
+        scope: &Scope,
+        prefer_aliases: bool,
+    ) -> Expression {
+        let mut expression = expression.clone();
+        if !self.0.is_empty() {
+            self.substitute_in(&mut expression, scope, prefer_aliases);
+        }
+        expression
+    }
+
+    fn substitute_in(&self, expression: &mut Expression, scope: &Scope, prefer_aliases: bool) {
+        if let Expression::Column { table: None, name } = expression {
+            let aliased = self
+                .0
+                .iter()
+                .find(|(alias, _)| alias.eq_ignore_ascii_case(name));
+            if let Some((_, aliased)) = aliased {
+                if prefer_aliases || matches!(scope.find(None, name), Ok(None)) {
+                    *expression = (*aliased).clone();
+                }
+            }
+            return;
+        }
+        for child in expression.children_mut() {
+            self.substitute_in(child, scope, prefer_aliases);
+        }
+    }
+}
+
+/// Keeps the rows for which the filter is true.
+pub fn filter_rows<'a>(rows: Rows<'a>, filter: Option<Expression>, scope: Scope<'a>) -> Rows<'a> {
+    match filter {
+        Some(filter) => Box::new(rows.filter_map(move |row| match row {
+            Ok(row) => match evaluate(&filter, &scope, &row) {
+                Ok(value) if truth_value(&value) == Some(true) => Some(Ok(row)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            },
+            Err(e) => Some(Err(e)),
+        })),
+        None => rows,
+    }
+}
+
+/// Resolves the GROUP BY terms, replacing terms made of a single integer with the result column
+/// they refer to, and aliases with the expressions they stand for.
+fn resolve_group_by(
+    statement: &sql::SelectStatement,
+    selectables: &[Selectable],
+    aliases: &Aliases,
+    scope: &Scope,
+) -> Result<Vec<Expression>> {
+    let result_columns: Vec<Expression> = result_columns(selectables, scope)?
+        .into_iter()
+        .map(|(_, projected)| match projected {
+            Projected::Expression(expression) => expression,
+            Projected::Column(i) => Expression::Column {
+                table: Some(scope.columns[i].table.clone()),
+                name: scope.columns[i].name.clone(),
+            },
+        })
+        .collect();
+    let mut group_by = Vec::with_capacity(statement.group_by.len());
+    for (index, term) in statement.group_by.iter().enumerate() {
+        let expression = match term {
+            Expression::Literal(Literal::Integer(position)) => {
+                if *position < 1 || *position as usize > result_columns.len() {
+                    bail!(
+                        "{} GROUP BY term out of range - should be between 1 and {}",
+                        ordinal(index + 1),
+                        result_columns.len()
+                    );
+                }
+                result_columns[*position as usize - 1].clone()
+            }
+            other => aliases.substitute(other, scope, false),
+        };
+        if contains_aggregate(&expression, scope.functions()) {
+            bail!("aggregate functions are not allowed in the GROUP BY clause");
+        }
+        validate(&expression, scope)?;
+        group_by.push(expression);
+    }
+    Ok(group_by)
+}
+
+/// Formats a position the way SQLite's error messages do: 1st, 2nd, 3rd, 4th...
+pub fn ordinal(position: usize) -> String {
+    let suffix = match (position % 10, position % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", position, suffix)
+}
+
+/// A projected column is either a column of the scope, as selected by `*`, or an expression
+pub enum Projected {
+    Column(usize),
+    Expression(Expression),
+}
+
+/// The collating sequence of a result column
+pub fn projected_collation(projected: &Projected, scope: &Scope) -> Result<Collation> {
+    match projected {
+        Projected::Expression(expression) => collation_of(expression, scope),
+        Projected::Column(position) => scope.collations().find(&scope.columns[*position].collation),
+    }
+}
+
+fn resolve_projection(selectables: &[Selectable], scope: &Scope) -> Result<Vec<Projected>> {
+    let projection: Vec<Projected> = result_columns(selectables, scope)?
+        .into_iter()
+        .map(|(_, projected)| projected)
+        .collect();
+    for projected in &projection {
+        if let Projected::Expression(expression) = projected {
+            validate(expression, scope)?;
+        }
+    }
+    Ok(projection)
+}
+
+fn project(projection: &[Projected], scope: &Scope, row: &[Value]) -> Result<Vec<Value>> {
+    projection
+        .iter()
+        .map(|p| match p {
+            Projected::Column(i) => Ok(row[*i].clone()),
+            Projected::Expression(expression) => evaluate(expression, scope, row),
+        })
+        .collect()
+}
+
+/// Evaluates the LIMIT and OFFSET expressions, returning `(offset, limit)`. A negative limit
+/// means there is no limit.
+pub fn evaluate_limit(limit: Option<&sql::Limit>, scope: &Scope) -> Result<(usize, Option<usize>)> {
+    let Some(limit) = limit else {
+        return Ok((0, None));
+    };
+    let constant = |expression: &Expression| -> Result<i64> {
+        let value = evaluate(expression, scope, &[])?;
+        match to_numeric(&value) {
+            Some(Numeric::Integer(i)) => Ok(i),
+            _ => bail!("datatype mismatch"),
+        }
+    };
+    let count = constant(&limit.count)?;
+    let offset = match &limit.offset {
+        Some(offset) => constant(offset)?.max(0) as usize,
+        None => 0,
+    };
+    Ok((offset, usize::try_from(count).ok()))
+}
+
+/// NULLs are considered smaller than any other value, so they come first in ascending order
+/// and last in descending order unless NULLS FIRST/LAST says otherwise.
+fn nulls_first(term: &OrderingTerm) -> bool {
+    match term.nulls {
+        Some(NullsOrder::First) => true,
+        Some(NullsOrder::Last) => false,
+        None => term.order == SortOrder::Ascending,
+    }
+}
+
+/// How an ORDER BY term sorts, text being compared with the given collation
+pub fn sort_key(term: &OrderingTerm, collation: Collation) -> SortKey {
+    SortKey {
+        descending: term.order == SortOrder::Descending,
+        nulls_first: nulls_first(term),
+        collation,
+    }
+}
+
+/// An ORDER BY term made of a single integer refers to a result column by its position.
+pub fn result_column_reference(term: &OrderingTerm) -> Option<i64> {
+    match term.expression {
+        Expression::Literal(Literal::Integer(position)) => Some(position),
+        _ => None,
+    }
+}
+
+pub fn sort_key_value(
+    term: &OrderingTerm,
+    scope: &Scope,
+    row: &[Value],
+    output: &[Value],
+) -> Result<Value> {
+    // positions were checked to be in range when planning the query
+    match result_column_reference(term) {
+        Some(position) => Ok(output[position as usize - 1].clone()),
+        None => evaluate(&term.expression, scope, row),
+    }
+}
+
+/// Picks how to read the table, and whether doing so already yields rows in the order asked by
+/// the ORDER BY clause, in which case no sort is needed.
+///
+/// Rows come out of the table b-tree in rowid order, and out of an index in the order of its
+/// columns, so ordering by the rowid or by a prefix of the columns of an index (all in the
+/// index's direction, or all in the opposite direction) can be satisfied by scanning.
+fn choose_access_path(
+    database: &Database,
+    table: &TableInformation,
+    scope: &Scope,
+    order_by: &[OrderingTerm],
+) -> Result<(AccessPath, bool)> {
+    let Some(first_term) = order_by.first() else {
+        return Ok((AccessPath::TableScan { reverse: false }, true));
+    };
+    // only ORDER BY terms made of plain columns can be satisfied by a scan
+    let term_columns: Option<Vec<usize>> = order_by
+        .iter()
+        .map(|term| match &term.expression {
+            Expression::Column { table, name } => scope.resolve(table.as_deref(), name).ok(),
+            _ => None,
+        })
+        .collect();
+    let Some(term_columns) = term_columns else {
+        return Ok((AccessPath::TableScan { reverse: false }, false));
+    };
+
+    // the rowid is unique, so the terms following it never come into play
+    let rowid_columns = [Some(0), table.rowid_alias.map(|alias| alias + 1)];
+    if rowid_columns.contains(&Some(term_columns[0])) {
+        let reverse = first_term.order == SortOrder::Descending;
+        return Ok((AccessPath::TableScan { reverse }, true));
+    }
+
+    // NULLs sort first in an index, so a scan only matches the default NULLS placement
+    if order_by
+        .iter()
+        .any(|term| nulls_first(term) != (term.order == SortOrder::Ascending))
+    {
+        return Ok((AccessPath::TableScan { reverse: false }, false));
+    }
+    for index in database.list_indexes()? {
+        if !index.table_name.eq_ignore_ascii_case(&table.table_name)
+            || index.columns.len() < order_by.len()
+        {
+            continue;
+        }
+        let mut reverse = None;
+        let matches = order_by.iter().zip(&term_columns).zip(&index.columns).all(
+            |((term, column), index_column)| {
+                let same_column = scope.resolve(None, &index_column.name).ok() == Some(*column);
+                // the column is sorted with its own collation
+                let same_collation = index_collation(index_column, scope)
+                    .eq_ignore_ascii_case(&scope.columns[*column].collation);
+                let term_reverse = term.order != index_column.order;
+                let consistent = *reverse.get_or_insert(term_reverse) == term_reverse;
+                same_column && same_collation && consistent
+            },
+        );
+        if matches {
+            let access_path = AccessPath::IndexScan {
+                root_page: index.root_page as u32,
+                reverse: reverse.unwrap_or(false),
+            };
+            return Ok((access_path, true));
+        }
+    }
+    Ok((AccessPath::TableScan { reverse: false }, false))
+}
@@ -0,0 +1,550 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use anyhow::{anyhow, Result};
+
+use crate::database::page::btree::data::record::{encode_record, Record};
+use crate::database::page::btree::data::serial_types::Value;
+use crate::parsing::utils::{encode_varint, take_varint};
+
+use super::collation::Collation;
+
+/// How much memory the rows buffered by a sort may take before being spilled to disk
+pub const DEFAULT_SORT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Sorts with at most this many rows to keep (LIMIT + OFFSET) use a top-k heap instead of
+/// sorting every row
+pub const TOP_K_THRESHOLD: usize = 1000;
+
+/// How a single sort key is ordered
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub descending: bool,
+    pub nulls_first: bool,
+    /// How text values compare
+    pub collation: Collation,
+}
+
+impl SortKey {
+    /// Ascending order with NULLs first, the order rows are grouped in
+    pub fn ascending(collation: Collation) -> SortKey {
+        SortKey {
+            descending: false,
+            nulls_first: true,
+            collation,
+        }
+    }
+}
+
+pub fn compare_sort_keys(keys: &[SortKey], left: &[Value], right: &[Value]) -> Ordering {
+    for (key, (a, b)) in keys.iter().zip(left.iter().zip(right)) {
+        let ordering = match (a.is_null(), b.is_null()) {
+            (true, true) => Ordering::Equal,
+            // NULL placement doesn't depend on the direction of the sort
+            (true, false) if key.nulls_first => return Ordering::Less,
+            (true, false) => return Ordering::Greater,
+            (false, true) if key.nulls_first => return Ordering::Greater,
+            (false, true) => return Ordering::Less,
+            (false, false) => key.collation.compare(a, b),
+        };
+        let ordering = if key.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// A row waiting to be sorted, along with its sort key values. The sequence number records
+/// insertion order so that rows with equal keys come out in the order they went in.
+struct SortEntry {
+    keys: Rc<[SortKey]>,
+    sequence: u64,
+    key: Vec<Value>,
+    row: Vec<Value>,
+}
+
+impl SortEntry {
+    fn approximate_size(&self) -> usize {
+        self.key
+            .iter()
+            .chain(self.row.iter())
+            .map(approximate_value_size)
+            .sum::<usize>()
+            + std::mem::size_of::<SortEntry>()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut values = Vec::with_capacity(1 + self.key.len() + self.row.len());
+        values.push(Value::Int64(self.sequence as i64));
+        values.extend(self.key.iter().cloned());
+        values.extend(self.row.iter().cloned());
+        encode_record(&values)
+    }
+
+    fn decode(keys: Rc<[SortKey]>, record: &[u8]) -> Result<SortEntry> {
+        let mut values = Record::try_from(record)?.values;
+        if values.len() < 1 + keys.len() {
+            return Err(anyhow!("Malformed sort run record"));
+        }
+        let row = values.split_off(1 + keys.len());
+        let key = values.split_off(1);
+        let sequence = values[0].as_i64().unwrap_or(0) as u64;
+        Ok(SortEntry {
+            keys,
+            sequence,
+            key,
+            row,
+        })
+    }
+}
+
+impl PartialEq for SortEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortEntry {}
+
+impl PartialOrd for SortEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SortEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_sort_keys(&self.keys, &self.key, &other.key)
+            .then(self.sequence.cmp(&other.sequence))
+    }
+}
+
+fn approximate_value_size(value: &Value) -> usize {
+    std::mem::size_of::<Value>()
+        + match value {
+            Value::String(s) => s.len(),
+            Value::Blob(b) => b.len(),
+            _ => 0,
+        }
+}
+
+enum SorterState {
+    /// Rows are buffered in memory and spilled to sorted runs on disk whenever the buffer
+    /// exceeds the memory budget
+    External {
+        buffer: Vec<SortEntry>,
+        buffered_bytes: usize,
+        memory_budget: usize,
+        runs: Vec<SortRun>,
+    },
+    /// Only the `limit` smallest rows are kept, in a max-heap whose top is the first row to
+    /// evict
+    TopK {
+        heap: BinaryHeap<SortEntry>,
+        limit: usize,
+    },
+}
+
+/// Sorts rows by their sort keys, using an external merge sort for inputs larger than the
+/// memory budget, or a top-k heap when only the first few rows are needed.
+pub struct Sorter {
+    keys: Rc<[SortKey]>,
+    next_sequence: u64,
+    state: SorterState,
+}
+
+impl Sorter {
+    pub fn new(keys: Vec<SortKey>, memory_budget: usize) -> Sorter {
+        Sorter {
+            keys: keys.into(),
+            next_sequence: 0,
+            state: SorterState::External {
+                buffer: Vec::new(),
+                buffered_bytes: 0,
+                memory_budget,
+                runs: Vec::new(),
+            },
+        }
+    }
+
+    /// A sorter which only keeps the `limit` first rows in sort order.
+    pub fn with_limit(keys: Vec<SortKey>, limit: usize) -> Sorter {
+        Sorter {
+            keys: keys.into(),
+            next_sequence: 0,
+            state: SorterState::TopK {
+                heap: BinaryHeap::with_capacity(limit + 1),
+                limit,
+            },
+        }
+    }
+
+    pub fn push(&mut self, key: Vec<Value>, row: Vec<Value>) -> Result<()> {
+        let entry = SortEntry {
+            keys: self.keys.clone(),
+            sequence: self.next_sequence,
+            key,
+            row,
+        };
+        self.next_sequence += 1;
+        match &mut self.state {
+            SorterState::External {
+                buffer,
+                buffered_bytes,
+                memory_budget,
+                runs,
+            } => {
+                *buffered_bytes += entry.approximate_size();
+                buffer.push(entry);
+                if *buffered_bytes > *memory_budget {
+                    runs.push(SortRun::write(std::mem::take(buffer))?);
+                    *buffered_bytes = 0;
+                }
+            }
+            SorterState::TopK { heap, limit } => {
+                if *limit == 0 {
+                    return Ok(());
+                }
+                if heap.len() < *limit {
+                    heap.push(entry);
+                } else if let Some(mut largest) = heap.peek_mut() {
+                    if entry < *largest {
+                        *largest = entry;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the sorted rows.
+    pub fn finish(self) -> Result<SortedRows> {
+        match self.state {
+            SorterState::TopK { heap, .. } => Ok(SortedRows::in_memory(heap.into_sorted_vec())),
+            SorterState::External {
+                mut buffer, runs, ..
+            } if runs.is_empty() => {
+                buffer.sort_unstable();
+                Ok(SortedRows::in_memory(buffer))
+            }
+            SorterState::External {
+                buffer, mut runs, ..
+            } => {
+                if !buffer.is_empty() {
+                    runs.push(SortRun::write(buffer)?);
+                }
+                SortedRows::merge(self.keys, runs)
+            }
+        }
+    }
+}
+
+/// Hands out the rows pushed into it smallest sort key first, rows with equal keys in the
+/// order they were pushed. Without sort keys, it is a first-in first-out queue.
+pub struct PriorityQueue {
+    keys: Rc<[SortKey]>,
+    next_sequence: u64,
+    heap: BinaryHeap<Reverse<SortEntry>>,
+}
+
+impl PriorityQueue {
+    pub fn new(keys: Vec<SortKey>) -> PriorityQueue {
+        PriorityQueue {
+            keys: keys.into(),
+            next_sequence: 0,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: Vec<Value>, row: Vec<Value>) {
+        self.heap.push(Reverse(SortEntry {
+            keys: self.keys.clone(),
+            sequence: self.next_sequence,
+            key,
+            row,
+        }));
+        self.next_sequence += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<Vec<Value>> {
+        self.heap.pop().map(|Reverse(entry)| entry.row)
+    }
+}
+
+static RUN_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A sorted run spilled to a temporary file, removed once the run is dropped. Entries are
+/// stored as records prefixed with their length.
+struct SortRun {
+    path: PathBuf,
+}
+
+impl SortRun {
+    /// Creates the file of a new run. The temporary directory being shared, names can be
+    /// taken already, by files or links of others which mustn't be followed or truncated, so
+    /// only a file which doesn't exist yet is created, trying names until one is free.
+    fn create() -> Result<(SortRun, File)> {
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "resql-sort-{}-{}",
+                std::process::id(),
+                RUN_COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+            ));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((SortRun { path }, file)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn write(mut entries: Vec<SortEntry>) -> Result<SortRun> {
+        entries.sort_unstable();
+        let (run, file) = SortRun::create()?;
+        let mut writer = BufWriter::new(file);
+        for entry in &entries {
+            let record = entry.encode();
+            writer.write_all(&encode_varint(record.len() as u64))?;
+            writer.write_all(&record)?;
+        }
+        writer.flush()?;
+        Ok(run)
+    }
+}
+
+impl Drop for SortRun {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+struct SortRunReader {
+    // keeps the file around for as long as it's being read
+    _run: SortRun,
+    reader: BufReader<File>,
+    keys: Rc<[SortKey]>,
+}
+
+impl SortRunReader {
+    fn open(run: SortRun, keys: Rc<[SortKey]>) -> Result<SortRunReader> {
+        let reader = BufReader::new(File::open(&run.path)?);
+        Ok(SortRunReader {
+            _run: run,
+            reader,
+            keys,
+        })
+    }
+
+    fn next_entry(&mut self) -> Result<Option<SortEntry>> {
+        // read the length varint one byte at a time, its last byte has the high bit cleared
+        let mut length_bytes = Vec::with_capacity(9);
+        let mut byte = [0u8; 1];
+        loop {
+            if self.reader.read(&mut byte)? == 0 {
+                if length_bytes.is_empty() {
+                    return Ok(None);
+                }
+                return Err(anyhow!("Sort run is truncated"));
+            }
+            length_bytes.push(byte[0]);
+            if byte[0] & 0x80 == 0 || length_bytes.len() == 9 {
+                break;
+            }
+        }
+        let (_, length) = take_varint::<()>(&length_bytes)
+            .map_err(|_| anyhow!("Malformed sort run record length"))?;
+        let mut record = vec![0; length as usize];
+        self.reader.read_exact(&mut record)?;
+        SortEntry::decode(self.keys.clone(), &record).map(Some)
+    }
+}
+
+/// The output of a [`Sorter`]
+pub struct SortedRows {
+    source: SortedSource,
+}
+
+enum SortedSource {
+    InMemory(std::vec::IntoIter<SortEntry>),
+    /// A k-way merge of sorted runs, holding the next entry of each run in a min-heap
+    Merge {
+        readers: Vec<SortRunReader>,
+        heap: BinaryHeap<Reverse<(SortEntry, usize)>>,
+    },
+}
+
+impl SortedRows {
+    fn in_memory(entries: Vec<SortEntry>) -> SortedRows {
+        SortedRows {
+            source: SortedSource::InMemory(entries.into_iter()),
+        }
+    }
+
+    fn merge(keys: Rc<[SortKey]>, runs: Vec<SortRun>) -> Result<SortedRows> {
+        let mut readers = runs
+            .into_iter()
+            .map(|run| SortRunReader::open(run, keys.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (index, reader) in readers.iter_mut().enumerate() {
+            if let Some(entry) = reader.next_entry()? {
+                heap.push(Reverse((entry, index)));
+            }
+        }
+        Ok(SortedRows {
+            source: SortedSource::Merge { readers, heap },
+        })
+    }
+}
+
+impl Iterator for SortedRows {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            SortedSource::InMemory(entries) => entries.next().map(|entry| Ok(entry.row)),
+            SortedSource::Merge { readers, heap } => {
+                let Reverse((entry, index)) = heap.pop()?;
+                match readers[index].next_entry() {
+                    Ok(Some(next)) => heap.push(Reverse((next, index))),
+                    Ok(None) => {}
+                    Err(e) => {
+                        heap.clear();
+                        return Some(Err(e));
+                    }
+                }
+                Some(Ok(entry.row))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    use super::{PriorityQueue, SortKey, SortRun, Sorter, RUN_COUNTER};
+    use crate::database::page::btree::data::serial_types::Value;
+    use crate::engine::collation::Collation;
+
+    const ASCENDING: SortKey = SortKey {
+        descending: false,
+        nulls_first: true,
+        collation: Collation::Binary,
+    };
+
+    fn sort_all(mut sorter: Sorter, keys: Vec<Value>) -> Vec<i64> {
+        for (i, key) in keys.into_iter().enumerate() {
+            sorter
+                .push(vec![key], vec![Value::Int64(i as i64)])
+                .unwrap();
+        }
+        sorter
+            .finish()
+            .unwrap()
+            .map(|row| row.unwrap()[0].as_i64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sorts_in_memory_with_nulls_first() {
+        let keys = vec![
+            Value::Int64(3),
+            Value::Null,
+            Value::Int8(1),
+            Value::String(String::from("a")),
+            Value::Float64(2.5),
+        ];
+        assert_eq!(
+            sort_all(Sorter::new(vec![ASCENDING], 1 << 20), keys),
+            vec![1, 2, 4, 0, 3]
+        );
+    }
+
+    #[test]
+    fn sorts_descending_with_nulls_last() {
+        let key = SortKey {
+            descending: true,
+            nulls_first: false,
+            collation: Collation::Binary,
+        };
+        let keys = vec![Value::Int64(1), Value::Null, Value::Int64(2)];
+        assert_eq!(
+            sort_all(Sorter::new(vec![key], 1 << 20), keys),
+            vec![2, 0, 1]
+        );
+    }
+
+    #[test]
+    fn spills_to_disk_and_merges_stably() {
+        // a budget this small spills every few rows
+        let keys: Vec<Value> = (0..500).map(|i| Value::Int64((i * 7) % 50)).collect();
+        let mut expected: Vec<(i64, i64)> = (0..500).map(|i| ((i * 7) % 50, i)).collect();
+        expected.sort();
+        assert_eq!(
+            sort_all(Sorter::new(vec![ASCENDING], 512), keys),
+            expected.into_iter().map(|(_, i)| i).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn leaves_files_already_named_like_runs_alone() {
+        let next = RUN_COUNTER.load(std::sync::atomic::Ordering::Relaxed);
+        // other tests may be creating runs meanwhile, whose files are left as they are
+        let taken: Vec<_> = (next..next + 4)
+            .map(|n| {
+                let name = format!("resql-sort-{}-{}", std::process::id(), n);
+                std::env::temp_dir().join(name)
+            })
+            .filter(|path| {
+                let file = OpenOptions::new().write(true).create_new(true).open(path);
+                file.and_then(|mut file| file.write_all(b"taken")).is_ok()
+            })
+            .collect();
+        let (run, _) = SortRun::create().unwrap();
+        assert!(!taken.contains(&run.path));
+        for path in taken {
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "taken");
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn keeps_only_top_k() {
+        let keys: Vec<Value> = (0..100).map(|i| Value::Int64(100 - i)).collect();
+        assert_eq!(
+            sort_all(Sorter::with_limit(vec![ASCENDING], 3), keys),
+            vec![99, 98, 97]
+        );
+    }
+
+    #[test]
+    fn pops_smallest_keys_first_in_push_order() {
+        let mut queue = PriorityQueue::new(vec![ASCENDING]);
+        for (i, key) in [2, 1, 2, 1].into_iter().enumerate() {
+            queue.push(vec![Value::Int64(key)], vec![Value::Int64(i as i64)]);
+        }
+        queue.pop();
+        queue.push(vec![Value::Int64(0)], vec![Value::Int64(4)]);
+        let rest: Vec<i64> = std::iter::from_fn(|| queue.pop())
+            .map(|row| row[0].as_i64().unwrap())
+            .collect();
+        assert_eq!(rest, vec![4, 3, 0, 2]);
+
+        let mut fifo = PriorityQueue::new(vec![]);
+        fifo.push(vec![], vec![Value::Int64(1)]);
+        fifo.push(vec![], vec![Value::Int64(2)]);
+        assert_eq!(fifo.pop(), Some(vec![Value::Int64(1)]));
+    }
+}
@@ -0,0 +1,435 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use anyhow::{bail, Result};
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::database::Database;
+use crate::sql::{Expression, Literal, SelectStatement, Targetable, WithClause};
+
+use super::affinity::{apply, comparison_affinity, expression_affinity, Affinity};
+use super::collation::{declared_collation, Collation, HashKeys, BINARY};
+use super::cte::CommonTables;
+use super::expression::Scope;
+use super::join::FromClause;
+use super::select::{column_names, column_types, execute, ColumnType};
+
+/// How the result of a subquery is used
+#[derive(Debug, Clone, PartialEq)]
+enum Usage {
+    /// As a value, by a scalar subquery
+    Value,
+    /// By EXISTS
+    Exists,
+    /// As the list of values of IN, compared with the given collation once converted to the
+    /// given affinity
+    Members(Collation, Option<Affinity>),
+}
+
+/// What running a subquery yielded, depending on how it is used
+#[derive(Clone)]
+enum Outcome {
+    Value(Value),
+    Exists(bool),
+    Members(Rc<Members>),
+}
+
+/// The values of the single column of the rows of a subquery, as looked into by IN
+pub struct Members {
+    keys: HashSet<Vec<u8>>,
+    hash_keys: HashKeys,
+    /// The affinity the members and the values looked for are converted to, if any
+    affinity: Option<Affinity>,
+    contains_null: bool,
+}
+
+impl Members {
+    /// Whether the value is one of the members. This is unknown when the value is NULL and
+    /// there are members, or when the value isn't found but one of the members is NULL.
+    pub fn contains(&self, value: &Value) -> Option<bool> {
+        let empty = self.keys.is_empty() && !self.contains_null;
+        if value.is_null() {
+            return empty.then_some(false);
+        }
+        let value = match self.affinity {
+            Some(affinity) => apply(value.clone(), affinity),
+            None => value.clone(),
+        };
+        if self
+            .keys
+            .contains(&self.hash_keys.probe(std::slice::from_ref(&value)))
+        {
+            Some(true)
+        } else if self.contains_null {
+            None
+        } else {
+            Some(false)
+        }
+    }
+}
+
+/// Runs the subqueries found in the expressions evaluated against a scope.
+///
+/// A subquery referring to columns of the query it is part of (a correlated subquery) runs
+/// for each row it is evaluated against, those columns being replaced by their values in the
+/// row. Other subqueries run once: their outcome is kept for the following rows.
+#[derive(Clone)]
+pub struct Subqueries<'a> {
+    database: &'a Database,
+    /// The common tables the subqueries can read from
+    tables: CommonTables,
+    outcomes: Rc<RefCell<Vec<(Usage, SelectStatement, Outcome)>>>,
+    /// The type of the column of the subqueries used as values, kept once worked out
+    types: Rc<RefCell<Vec<(SelectStatement, ColumnType)>>>,
+}
+
+impl<'a> Subqueries<'a> {
+    pub fn new(database: &'a Database, tables: CommonTables) -> Subqueries<'a> {
+        Subqueries {
+            database,
+            tables,
+            outcomes: Rc::new(RefCell::new(Vec::new())),
+            types: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    pub fn database(&self) -> &'a Database {
+        self.database
+    }
+
+    /// The value of a scalar subquery: the first column of its first row, NULL without rows
+    pub fn value(&self, query: &SelectStatement, scope: &Scope, row: &[Value]) -> Result<Value> {
+        match self.outcome(Usage::Value, query, scope, row)? {
+            Outcome::Value(value) => Ok(value),
+            _ => unreachable!("scalar subqueries yield a value"),
+        }
+    }
+
+    /// Whether the subquery returns any row
+    pub fn exists(&self, query: &SelectStatement, scope: &Scope, row: &[Value]) -> Result<bool> {
+        match self.outcome(Usage::Exists, query, scope, row)? {
+            Outcome::Exists(exists) => Ok(exists),
+            _ => unreachable!("EXISTS subqueries yield a boolean"),
+        }
+    }
+
+    /// The values returned by the subquery, to be looked into by IN comparing them with the
+    /// given collation, once converted to the given affinity
+    pub fn members(
+        &self,
+        query: &SelectStatement,
+        collation: &Collation,
+        affinity: Option<Affinity>,
+        scope: &Scope,
+        row: &[Value],
+    ) -> Result<Rc<Members>> {
+        let usage = Usage::Members(collation.clone(), affinity);
+        match self.outcome(usage, query, scope, row)? {
+            Outcome::Members(members) => Ok(members),
+            _ => unreachable!("IN subqueries yield members"),
+        }
+    }
+
+    /// The collation IN compares its operand with the values of a subquery with: the
+    /// collation of the operand if it has one, or else the collation of the column of the
+    /// subquery.
+    pub fn members_collation(
+        &self,
+        operand: &Expression,
+        query: &SelectStatement,
+        scope: &Scope,
+    ) -> Result<Collation> {
+        let name = match declared_collation(operand, scope) {
+            Some(name) => name,
+            None => self.column_type(query)?.collation,
+        };
+        self.database.collations().find(&name)
+    }
+
+    /// The affinity IN converts its operand and the values of a subquery to before comparing
+    /// them, as `=` would for the operand and the column of the subquery
+    pub fn members_affinity(
+        &self,
+        operand: &Expression,
+        query: &SelectStatement,
+        scope: &Scope,
+    ) -> Result<Option<Affinity>> {
+        Ok(comparison_affinity(
+            expression_affinity(operand, scope)?,
+            self.column_type(query)?.affinity,
+        ))
+    }
+
+    /// The type of the first column of a subquery
+    pub fn column_type(&self, query: &SelectStatement) -> Result<ColumnType> {
+        let kept = self
+            .types
+            .borrow()
+            .iter()
+            .find(|(q, _)| q == query)
+            .map(|(_, column_type)| column_type.clone());
+        if let Some(column_type) = kept {
+            return Ok(column_type);
+        }
+        let column_type = column_types(self.database, query, &self.tables)?
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| ColumnType {
+                collation: BINARY.to_string(),
+                affinity: None,
+            });
+        self.types
+            .borrow_mut()
+            .push((query.clone(), column_type.clone()));
+        Ok(column_type)
+    }
+
+    fn outcome(
+        &self,
+        usage: Usage,
+        query: &SelectStatement,
+        scope: &Scope,
+        row: &[Value],
+    ) -> Result<Outcome> {
+        let kept = self
+            .outcomes
+            .borrow()
+            .iter()
+            .find(|(u, q, _)| *u == usage && q == query)
+            .map(|(_, _, outcome)| outcome.clone());
+        if let Some(outcome) = kept {
+            return Ok(outcome);
+        }
+        let mut bound = query.clone();
+        let correlated = bind(
+            self.database,
+            &self.tables,
+            &mut bound,
+            &mut Vec::new(),
+            scope,
+            row,
+        )?;
+        let outcome = self.run(usage.clone(), &bound)?;
+        if !correlated {
+            self.outcomes
+                .borrow_mut()
+                .push((usage, query.clone(), outcome.clone()));
+        }
+        Ok(outcome)
+    }
+
+    fn run(&self, usage: Usage, query: &SelectStatement) -> Result<Outcome> {
+        let columns = column_names(self.database, query, &self.tables)?.len();
+        if usage != Usage::Exists && columns != 1 {
+            bail!("sub-select returns {} columns - expected 1", columns);
+        }
+        let mut rows = execute(self.database, query, &self.tables)?;
+        Ok(match usage {
+            Usage::Value => Outcome::Value(match rows.next().transpose()? {
+                Some(mut row) => row.swap_remove(0),
+                None => Value::Null,
+            }),
+            Usage::Exists => Outcome::Exists(rows.next().transpose()?.is_some()),
+            Usage::Members(collation, affinity) => {
+                let mut members = Members {
+                    keys: HashSet::new(),
+                    hash_keys: HashKeys::new(vec![collation]),
+                    affinity,
+                    contains_null: false,
+                };
+                for row in rows {
+                    let mut value = row?.swap_remove(0);
+                    if let Some(affinity) = affinity {
+                        value = apply(value, affinity);
+                    }
+                    if value.is_null() {
+                        members.contains_null = true;
+                    } else {
+                        let key = members.hash_keys.key(std::slice::from_ref(&value));
+                        members.keys.insert(key);
+                    }
+                }
+                Outcome::Members(Rc::new(members))
+            }
+        })
+    }
+}
+
+/// Replaces the references a statement makes to columns of `scope`, the scope of the query it
+/// is a subquery of, by their values in `row`. Returns whether there were any.
+///
+/// `scopes` holds the scopes of the queries between that query and the statement, whose
+/// columns hide the columns of `scope` with the same name. `tables` are the common tables the
+/// statement can read from besides those of its own WITH clause.
+fn bind<'a>(
+    database: &'a Database,
+    tables: &CommonTables,
+    statement: &mut SelectStatement,
+    scopes: &mut Vec<Scope<'a>>,
+    scope: &Scope,
+    row: &[Value],
+) -> Result<bool> {
+    let mut bound = false;
+    let tables = tables.with_clause(database, statement.with.as_ref())?;
+    // neither the queries of the WITH clause, the SELECTs compounded with the statement nor the
+    // subqueries of the FROM clause can refer to the tables of the FROM clause
+    for query in statement.with.iter_mut().flat_map(WithClause::queries_mut) {
+        bound |= bind(database, &tables, query, scopes, scope, row)?;
+    }
+    for term in &mut statement.compound {
+        bound |= bind(database, &tables, &mut term.select, scopes, scope, row)?;
+    }
+    for query in statement
+        .from_target
+        .iter_mut()
+        .flat_map(Targetable::subqueries_mut)
+    {
+        bound |= bind(database, &tables, query, scopes, scope, row)?;
+    }
+    scopes.push(FromClause::plan(database, statement.from_target.as_ref(), &tables)?.scope);
+    for expression in statement.expressions_mut() {
+        bound |= bind_expression(database, &tables, expression, scopes, scope, row)?;
+    }
+    scopes.pop();
+    Ok(bound)
+}
+
+fn bind_expression<'a>(
+    database: &'a Database,
+    tables: &CommonTables,
+    expression: &mut Expression,
+    scopes: &mut Vec<Scope<'a>>,
+    scope: &Scope,
+    row: &[Value],
+) -> Result<bool> {
+    if let Expression::Column { table, name } = expression {
+        // ambiguous names are left for the subquery to report
+        let local = scopes
+            .iter()
+            .any(|s| !matches!(s.find(table.as_deref(), name), Ok(None)));
+        if local {
+            return Ok(false);
+        }
+        return Ok(match scope.find(table.as_deref(), name)? {
+            Some(position) => {
+                *expression = Expression::Bound {
+                    value: value_literal(&row[position]),
+                    collation: scope.columns[position].collation.clone(),
+                    affinity: scope.columns[position].affinity,
+                };
+                true
+            }
+            None => false,
+        });
+    }
+    let mut bound = false;
+    if let Some(query) = expression.subquery_mut() {
+        bound |= bind(database, tables, query, scopes, scope, row)?;
+    }
+    for child in expression.children_mut() {
+        bound |= bind_expression(database, tables, child, scopes, scope, row)?;
+    }
+    Ok(bound)
+}
+
+pub fn value_literal(value: &Value) -> Literal {
+    match value {
+        Value::Null => Literal::Null,
+        Value::Float64(r) => Literal::Real(*r),
+        Value::String(s) => Literal::String(s.clone()),
+        Value::Blob(b) => Literal::Blob(b.clone()),
+        other => Literal::Integer(other.as_i64().unwrap_or(0)),
+    }
+}
+
+/// The columns a subquery refers to, in its own clauses and in those of the subqueries it
+/// contains. Columns of the subquery's own tables are part of them, so the references it makes
+/// to columns of an enclosing query are among them.
+pub fn column_references(query: &SelectStatement) -> Vec<(Option<&str>, &str)> {
+    let mut references = Vec::new();
+    let subqueries = query
+        .with
+        .iter()
+        .flat_map(WithClause::queries)
+        .chain(query.compound.iter().map(|term| &term.select))
+        .chain(query.from_target.iter().flat_map(Targetable::subqueries));
+    for subquery in subqueries {
+        references.extend(column_references(subquery));
+    }
+    for expression in query.expressions() {
+        collect_column_references(expression, &mut references);
+    }
+    references
+}
+
+fn collect_column_references<'e>(
+    expression: &'e Expression,
+    references: &mut Vec<(Option<&'e str>, &'e str)>,
+) {
+    if let Expression::Column { table, name } = expression {
+        references.push((table.as_deref(), name));
+    }
+    for child in expression.children() {
+        collect_column_references(child, references);
+    }
+    if let Some(query) = expression.subquery() {
+        references.extend(column_references(query));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sql::sql_query;
+
+    fn members(values: &[Value]) -> Members {
+        let mut hash_keys = HashKeys::new(vec![Collation::Binary]);
+        Members {
+            keys: values
+                .iter()
+                .filter(|v| !v.is_null())
+                .map(|v| hash_keys.key(std::slice::from_ref(v)))
+                .collect(),
+            hash_keys,
+            affinity: None,
+            contains_null: values.iter().any(Value::is_null),
+        }
+    }
+
+    #[test]
+    fn looks_up_members_with_null_semantics() {
+        let numbers = members(&[Value::Int8(1), Value::Float64(2.5)]);
+        assert_eq!(numbers.contains(&Value::Int64(1)), Some(true));
+        assert_eq!(numbers.contains(&Value::Float64(1.0)), Some(true));
+        assert_eq!(
+            numbers.contains(&Value::String("1".to_string())),
+            Some(false)
+        );
+        assert_eq!(numbers.contains(&Value::Null), None);
+        let with_null = members(&[Value::Int8(1), Value::Null]);
+        assert_eq!(with_null.contains(&Value::Int64(1)), Some(true));
+        assert_eq!(with_null.contains(&Value::Int64(2)), None);
+        assert_eq!(members(&[]).contains(&Value::Null), Some(false));
+    }
+
+    #[test]
+    fn lists_column_references_of_nested_subqueries() {
+        let query = sql_query::select_statement(
+            "SELECT a FROM (SELECT b FROM t WHERE c = o.d) \
+             WHERE EXISTS (SELECT 1 FROM u WHERE u.e IN (SELECT f FROM v))",
+        )
+        .unwrap();
+        assert_eq!(
+            column_references(&query),
+            vec![
+                (None, "b"),
+                (None, "c"),
+                (Some("o"), "d"),
+                (None, "a"),
+                (Some("u"), "e"),
+                (None, "f"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,962 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::database::page::btree::data::serial_types::Value;
+use crate::sql::{
+    Expression, FrameBound, FrameExclude, FrameUnits, NamedWindow, Over, Selectable, Window,
+};
+
+use super::aggregate::{check_aggregate_arguments, is_aggregate_function, Accumulator};
+use super::collation::collation_of;
+use super::expression::{evaluate, to_integer, validate, Numeric, Scope};
+use super::function::user::Functions;
+use super::function::{self, math};
+use super::select::{sort_key, Rows};
+use super::sort::{compare_sort_keys, SortKey, SortedRows, Sorter, DEFAULT_SORT_MEMORY_BUDGET};
+
+/// The functions which can only be called as window functions, along with how many arguments
+/// they take
+const WINDOW_FUNCTIONS: [(&str, Range<usize>); 11] = [
+    ("row_number", 0..1),
+    ("rank", 0..1),
+    ("dense_rank", 0..1),
+    ("percent_rank", 0..1),
+    ("cume_dist", 0..1),
+    ("ntile", 1..2),
+    ("lag", 1..4),
+    ("lead", 1..4),
+    ("first_value", 1..2),
+    ("last_value", 1..2),
+    ("nth_value", 2..3),
+];
+
+/// Returns whether the function can only be called as a window function.
+pub fn is_window_function(name: &str) -> bool {
+    WINDOW_FUNCTIONS
+        .iter()
+        .any(|(function, _)| function.eq_ignore_ascii_case(name))
+}
+
+/// Returns the name of the first window function called by the expression.
+fn find_window(expression: &Expression) -> Option<&str> {
+    match expression {
+        Expression::Function {
+            name,
+            over: Some(_),
+            ..
+        } => Some(name),
+        _ => expression.children().into_iter().find_map(find_window),
+    }
+}
+
+/// Replaces the windows named by the OVER clauses of a statement's result columns with their
+/// definitions from the WINDOW clause. The result columns are only copied when they refer to a
+/// named window.
+pub fn resolve_selectables<'s>(
+    selectables: &'s [Selectable],
+    definitions: &[NamedWindow],
+) -> Result<Cow<'s, [Selectable]>> {
+    let named = selectables.iter().any(|selectable| match selectable {
+        Selectable::Expression { expression, .. } => refers_to_named_window(expression),
+        _ => false,
+    });
+    if !named {
+        return Ok(Cow::Borrowed(selectables));
+    }
+    let mut selectables = selectables.to_vec();
+    for selectable in &mut selectables {
+        if let Selectable::Expression { expression, .. } = selectable {
+            resolve_windows(expression, definitions)?;
+        }
+    }
+    Ok(Cow::Owned(selectables))
+}
+
+fn refers_to_named_window(expression: &Expression) -> bool {
+    match expression {
+        Expression::Function {
+            over: Some(over), ..
+        } => matches!(
+            over.as_ref(),
+            Over::Named(_) | Over::Window(Window { base: Some(_), .. })
+        ),
+        _ => expression
+            .children()
+            .into_iter()
+            .any(refers_to_named_window),
+    }
+}
+
+/// Replaces the named windows an expression refers to with their definitions, which the
+/// windows of its OVER clauses may extend.
+pub fn resolve_windows(expression: &mut Expression, definitions: &[NamedWindow]) -> Result<()> {
+    if let Expression::Function {
+        over: Some(over), ..
+    } = expression
+    {
+        let window = match over.as_ref() {
+            Over::Named(name) => named_window(name, definitions)?,
+            Over::Window(window) => extend_window(window, definitions)?,
+        };
+        **over = Over::Window(window);
+    }
+    for child in expression.children_mut() {
+        resolve_windows(child, definitions)?;
+    }
+    Ok(())
+}
+
+/// The definition of a window of the WINDOW clause, which may only extend the windows defined
+/// before it
+fn named_window(name: &str, definitions: &[NamedWindow]) -> Result<Window> {
+    let position = definitions
+        .iter()
+        .position(|definition| definition.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow!("no such window: {}", name))?;
+    extend_window(&definitions[position].window, &definitions[..position])
+}
+
+/// A window extending its base window: it keeps the partitions of its base and may only order
+/// them when its base doesn't.
+fn extend_window(window: &Window, definitions: &[NamedWindow]) -> Result<Window> {
+    let Some(name) = &window.base else {
+        return Ok(window.clone());
+    };
+    let base = named_window(name, definitions)?;
+    if !window.partition_by.is_empty() {
+        bail!("cannot override PARTITION clause of window: {}", name);
+    }
+    if !base.order_by.is_empty() && !window.order_by.is_empty() {
+        bail!("cannot override ORDER BY clause of window: {}", name);
+    }
+    if base.frame.is_some() {
+        bail!("cannot override frame specification of window: {}", name);
+    }
+    Ok(Window {
+        base: None,
+        partition_by: base.partition_by,
+        order_by: if window.order_by.is_empty() {
+            base.order_by
+        } else {
+            window.order_by.clone()
+        },
+        frame: window.frame.clone(),
+    })
+}
+
+/// Collects the distinct window function calls found in an expression, in the order they
+/// appear, checking that they can be computed. Window functions can't be nested.
+pub fn collect_windows(
+    expression: &Expression,
+    functions: &Functions,
+    windows: &mut Vec<Expression>,
+) -> Result<()> {
+    let Expression::Function {
+        name,
+        distinct,
+        arguments,
+        over: Some(over),
+    } = expression
+    else {
+        for child in expression.children() {
+            collect_windows(child, functions, windows)?;
+        }
+        return Ok(());
+    };
+    let count = arguments.expressions().len();
+    match WINDOW_FUNCTIONS
+        .iter()
+        .find(|(function, _)| function.eq_ignore_ascii_case(name))
+    {
+        Some((_, arguments)) if !arguments.contains(&count) => {
+            bail!("wrong number of arguments to function {}()", name)
+        }
+        Some(_) => {}
+        None if is_aggregate_function(name, arguments, functions) => {
+            if *distinct {
+                bail!("DISTINCT is not supported for window functions");
+            }
+        }
+        None => {
+            check_aggregate_arguments(name, arguments, functions)?;
+            function::lookup(name, count, functions)?;
+            bail!("{}() may not be used as a window function", name);
+        }
+    }
+    let Over::Window(window) = over.as_ref() else {
+        unreachable!("named windows are resolved before collecting window functions");
+    };
+    if let Some(nested) = expression.children().into_iter().find_map(find_window) {
+        bail!("misuse of window function {}()", nested);
+    }
+    if let Some(frame) = &window.frame {
+        let unsupported = matches!(
+            (&frame.start, &frame.end),
+            (FrameBound::CurrentRow, FrameBound::Preceding(_))
+                | (
+                    FrameBound::Following(_),
+                    FrameBound::CurrentRow | FrameBound::Preceding(_)
+                )
+        );
+        if unsupported {
+            bail!("unsupported frame specification");
+        }
+        let offset = frame.start.offset().is_some() || frame.end.offset().is_some();
+        if frame.units == FrameUnits::Range && offset && window.order_by.len() != 1 {
+            bail!("RANGE with offset PRECEDING/FOLLOWING requires one ORDER BY expression");
+        }
+    }
+    if !windows.contains(expression) {
+        windows.push(expression.clone());
+    }
+    Ok(())
+}
+
+/// Computes the window function calls collected by `collect_windows` over the rows laid out by
+/// `scope`. Each output row holds the values of an input row followed by the values of the
+/// calls.
+///
+/// The rows are sorted by the partitions and order of each window in turn, the last window
+/// first like SQLite does, so that they come out in the order of the first window. Calls
+/// sharing a window are computed together.
+pub fn compute<'a>(rows: Rows<'a>, scope: &Scope<'a>, calls: &[Expression]) -> Result<Rows<'a>> {
+    let width = scope.columns.len() + scope.aggregates.len();
+    let mut windows: Vec<(&Window, Vec<usize>)> = Vec::new();
+    for (index, call) in calls.iter().enumerate() {
+        for child in call.children() {
+            validate(child, scope)?;
+        }
+        let window = window_of(call);
+        match windows.iter_mut().find(|(other, _)| *other == window) {
+            Some((_, indices)) => indices.push(index),
+            None => windows.push((window, vec![index])),
+        }
+    }
+    let slots = calls.len();
+    let mut rows: Rows<'a> = Box::new(rows.map(move |row| {
+        row.map(|mut row| {
+            row.resize(width + slots, Value::Null);
+            row
+        })
+    }));
+    for (window, indices) in windows.into_iter().rev() {
+        let functions = indices
+            .into_iter()
+            .map(|index| {
+                let function = WindowFunction::new(&calls[index], scope)?;
+                Ok((width + index, function))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        rows = Box::new(WindowPass::new(rows, scope, window, functions)?);
+    }
+    Ok(rows)
+}
+
+fn window_of(call: &Expression) -> &Window {
+    match call {
+        Expression::Function {
+            over: Some(over), ..
+        } => match over.as_ref() {
+            Over::Window(window) => window,
+            Over::Named(_) => unreachable!("named windows are resolved"),
+        },
+        _ => unreachable!("window function calls have an OVER clause"),
+    }
+}
+
+/// A window function, along with the arguments it is called with
+enum WindowFunction {
+    RowNumber,
+    Rank,
+    DenseRank,
+    PercentRank,
+    CumeDist,
+    Ntile(Expression),
+    /// `lag` and `lead`, whose offset goes backwards for `lag`
+    Offset {
+        backwards: bool,
+        value: Expression,
+        offset: Option<Expression>,
+        default: Option<Expression>,
+    },
+    FirstValue(Expression),
+    LastValue(Expression),
+    NthValue(Expression, Expression),
+    Aggregate(Accumulator),
+}
+
+impl WindowFunction {
+    fn new(call: &Expression, scope: &Scope) -> Result<WindowFunction> {
+        let Expression::Function {
+            name, arguments, ..
+        } = call
+        else {
+            unreachable!("window functions are function calls");
+        };
+        let arguments = arguments.expressions();
+        let argument = |index: usize| arguments.get(index).cloned();
+        let required = |index: usize| {
+            argument(index)
+                .ok_or_else(|| anyhow!("wrong number of arguments to function {}()", name))
+        };
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "row_number" => WindowFunction::RowNumber,
+            "rank" => WindowFunction::Rank,
+            "dense_rank" => WindowFunction::DenseRank,
+            "percent_rank" => WindowFunction::PercentRank,
+            "cume_dist" => WindowFunction::CumeDist,
+            "ntile" => WindowFunction::Ntile(required(0)?),
+            name @ ("lag" | "lead") => WindowFunction::Offset {
+                backwards: name == "lag",
+                value: required(0)?,
+                offset: argument(1),
+                default: argument(2),
+            },
+            "first_value" => WindowFunction::FirstValue(required(0)?),
+            "last_value" => WindowFunction::LastValue(required(0)?),
+            "nth_value" => WindowFunction::NthValue(required(0)?, required(1)?),
+            _ => WindowFunction::Aggregate(Accumulator::new(call, scope)?),
+        })
+    }
+}
+
+/// The integer a value holds once given numeric affinity, if it holds one
+fn integer_value(value: &Value) -> Option<i64> {
+    match math::number(value)? {
+        Numeric::Integer(i) => Some(i),
+        Numeric::Real(r) if r.fract() == 0.0 => Some(r as i64),
+        Numeric::Real(_) => None,
+    }
+}
+
+/// A frame bound, with its offset evaluated
+#[derive(Clone, Copy)]
+enum Bound {
+    UnboundedPreceding,
+    Preceding(Numeric),
+    CurrentRow,
+    Following(Numeric),
+    UnboundedFollowing,
+}
+
+/// The frame of a window, with its offsets evaluated
+struct Frame {
+    units: FrameUnits,
+    start: Bound,
+    end: Bound,
+    exclude: FrameExclude,
+}
+
+impl Frame {
+    fn new(window: &Window, scope: &Scope) -> Result<Frame> {
+        let Some(frame) = &window.frame else {
+            return Ok(Frame {
+                units: FrameUnits::Range,
+                start: Bound::UnboundedPreceding,
+                end: Bound::CurrentRow,
+                exclude: FrameExclude::NoOthers,
+            });
+        };
+        let bound = |bound: &FrameBound, which: &str| -> Result<Bound> {
+            let offset = match bound {
+                FrameBound::UnboundedPreceding => return Ok(Bound::UnboundedPreceding),
+                FrameBound::CurrentRow => return Ok(Bound::CurrentRow),
+                FrameBound::UnboundedFollowing => return Ok(Bound::UnboundedFollowing),
+                FrameBound::Preceding(offset) | FrameBound::Following(offset) => offset,
+            };
+            // offsets are constants, anything else isn't a valid offset
+            let value = evaluate(offset, &scope.with_columns(vec![]), &[]).unwrap_or(Value::Null);
+            let offset = match frame.units {
+                FrameUnits::Range => math::number(&value)
+                    .filter(|offset| offset.as_real() >= 0.0)
+                    .ok_or_else(|| {
+                        anyhow!("frame {} offset must be a non-negative number", which)
+                    })?,
+                _ => integer_value(&value)
+                    .filter(|offset| *offset >= 0)
+                    .map(Numeric::Integer)
+                    .ok_or_else(|| {
+                        anyhow!("frame {} offset must be a non-negative integer", which)
+                    })?,
+            };
+            Ok(match bound {
+                FrameBound::Preceding(_) => Bound::Preceding(offset),
+                _ => Bound::Following(offset),
+            })
+        };
+        Ok(Frame {
+            units: frame.units,
+            start: bound(&frame.start, "starting")?,
+            end: bound(&frame.end, "ending")?,
+            exclude: frame.exclude,
+        })
+    }
+}
+
+/// Sorts rows by the partitions and order of a window, then computes the window functions over
+/// one partition at a time
+struct WindowPass<'a> {
+    scope: Scope<'a>,
+    /// How the partition keys and the values of the ORDER BY terms preceding each sorted row
+    /// are ordered
+    partition_keys: Vec<SortKey>,
+    order_keys: Vec<SortKey>,
+    frame: Frame,
+    /// The window functions and where their values go in the rows
+    functions: Vec<(usize, WindowFunction)>,
+    rows: Peekable<SortedRows>,
+    output: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl<'a> WindowPass<'a> {
+    fn new(
+        rows: Rows<'a>,
+        scope: &Scope<'a>,
+        window: &Window,
+        functions: Vec<(usize, WindowFunction)>,
+    ) -> Result<WindowPass<'a>> {
+        let frame = Frame::new(window, scope)?;
+        let partition_keys = window
+            .partition_by
+            .iter()
+            .map(|expression| Ok(SortKey::ascending(collation_of(expression, scope)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let order_keys = window
+            .order_by
+            .iter()
+            .map(|term| Ok(sort_key(term, collation_of(&term.expression, scope)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let keys = [partition_keys.as_slice(), &order_keys].concat();
+        let mut sorter = Sorter::new(keys, DEFAULT_SORT_MEMORY_BUDGET);
+        let expressions = window
+            .partition_by
+            .iter()
+            .chain(window.order_by.iter().map(|term| &term.expression));
+        for row in rows {
+            let row = row?;
+            let key = expressions
+                .clone()
+                .map(|expression| evaluate(expression, scope, &row))
+                .collect::<Result<Vec<_>>>()?;
+            // the key is kept along with the row to find where partitions and peers end
+            let mut entry = key.clone();
+            entry.extend(row);
+            sorter.push(key, entry)?;
+        }
+        Ok(WindowPass {
+            scope: scope.clone(),
+            partition_keys,
+            order_keys,
+            frame,
+            functions,
+            rows: sorter.finish()?.peekable(),
+            output: Vec::new().into_iter(),
+        })
+    }
+
+    /// Reads the rows of the next partition, splitting the values of their ORDER BY terms
+    /// from them.
+    fn next_partition(&mut self) -> Result<Option<Partition>> {
+        let Some(first) = self.rows.next().transpose()? else {
+            return Ok(None);
+        };
+        let partition_length = self.partition_keys.len();
+        let key_length = partition_length + self.order_keys.len();
+        let partition_key = first[..partition_length].to_vec();
+        let mut entries = vec![first];
+        while let Some(Ok(next)) = self.rows.peek() {
+            if compare_sort_keys(&self.partition_keys, next, &partition_key) != Ordering::Equal {
+                break;
+            }
+            entries.extend(self.rows.next().transpose()?);
+        }
+        let mut partition = Partition {
+            rows: Vec::with_capacity(entries.len()),
+            order: Vec::with_capacity(entries.len()),
+            group_starts: Vec::new(),
+            groups: Vec::with_capacity(entries.len()),
+        };
+        for mut entry in entries {
+            let row = entry.split_off(key_length);
+            let order = entry.split_off(partition_length);
+            let peer = partition.order.last().is_some_and(|previous| {
+                compare_sort_keys(&self.order_keys, previous, &order) == Ordering::Equal
+            });
+            if !peer {
+                partition.group_starts.push(partition.rows.len());
+            }
+            partition.groups.push(partition.group_starts.len() - 1);
+            partition.rows.push(row);
+            partition.order.push(order);
+        }
+        Ok(Some(partition))
+    }
+
+    fn compute_partition(&mut self, mut partition: Partition) -> Result<Vec<Vec<Value>>> {
+        let frames: Vec<Range<usize>> = (0..partition.rows.len())
+            .map(|i| partition.frame(&self.frame, &self.order_keys, i))
+            .collect();
+        for (slot, function) in &self.functions {
+            let values = partition.compute(function, &self.frame, &frames, &self.scope)?;
+            for (row, value) in partition.rows.iter_mut().zip(values) {
+                row[*slot] = value;
+            }
+        }
+        Ok(partition.rows)
+    }
+}
+
+impl Iterator for WindowPass<'_> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(row) = self.output.next() {
+            return Some(Ok(row));
+        }
+        let partition = match self.next_partition() {
+            Ok(partition) => partition?,
+            Err(e) => return Some(Err(e)),
+        };
+        match self.compute_partition(partition) {
+            Ok(rows) => {
+                self.output = rows.into_iter();
+                self.output.next().map(Ok)
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// The rows of a partition, in the order of the window, along with the values of its ORDER BY
+/// terms. Rows whose values are equal are peers, and make up a group.
+struct Partition {
+    rows: Vec<Vec<Value>>,
+    order: Vec<Vec<Value>>,
+    /// The position of the first row of each group
+    group_starts: Vec<usize>,
+    /// The group of each row
+    groups: Vec<usize>,
+}
+
+impl Partition {
+    /// The positions of the rows of a group
+    fn group(&self, group: usize) -> Range<usize> {
+        let end = self
+            .group_starts
+            .get(group + 1)
+            .copied()
+            .unwrap_or(self.rows.len());
+        self.group_starts[group]..end
+    }
+
+    /// The positions of the peers of a row, itself included
+    fn peers(&self, row: usize) -> Range<usize> {
+        self.group(self.groups[row])
+    }
+
+    /// The positions of the rows between the bounds of the frame of a row, before leaving out
+    /// the rows the frame excludes
+    fn frame(&self, frame: &Frame, order_keys: &[SortKey], row: usize) -> Range<usize> {
+        let start = self.bound(frame, order_keys, row, frame.start, true);
+        let end = self.bound(frame, order_keys, row, frame.end, false);
+        start..end.max(start)
+    }
+
+    /// The position a frame bound stands for: that of the first row of the frame for its
+    /// start, or that following the last row of the frame for its end
+    fn bound(
+        &self,
+        frame: &Frame,
+        order_keys: &[SortKey],
+        row: usize,
+        bound: Bound,
+        start: bool,
+    ) -> usize {
+        let length = self.rows.len();
+        let (offset, preceding) = match bound {
+            Bound::UnboundedPreceding => return 0,
+            Bound::UnboundedFollowing => return length,
+            Bound::CurrentRow => {
+                return match frame.units {
+                    FrameUnits::Rows if start => row,
+                    FrameUnits::Rows => row + 1,
+                    _ if start => self.peers(row).start,
+                    _ => self.peers(row).end,
+                }
+            }
+            Bound::Preceding(offset) => (offset, true),
+            Bound::Following(offset) => (offset, false),
+        };
+        match frame.units {
+            FrameUnits::Rows => {
+                let offset = count(offset);
+                let position = if preceding {
+                    row.checked_sub(offset)
+                } else {
+                    row.checked_add(offset)
+                };
+                match position {
+                    Some(position) if start => position.min(length),
+                    Some(position) => position.saturating_add(1).min(length),
+                    None if preceding => 0,
+                    None => length,
+                }
+            }
+            FrameUnits::Groups => {
+                let offset = count(offset);
+                let group = self.groups[row];
+                let group = if preceding {
+                    group.checked_sub(offset)
+                } else {
+                    group
+                        .checked_add(offset)
+                        .filter(|group| *group < self.group_starts.len())
+                };
+                match group {
+                    Some(group) if start => self.group(group).start,
+                    Some(group) => self.group(group).end,
+                    None if preceding => 0,
+                    None => length,
+                }
+            }
+            FrameUnits::Range => {
+                let value = &self.order[row][0];
+                let number = match value {
+                    Value::Null | Value::String(_) | Value::Blob(_) => None,
+                    other => math::number(other),
+                };
+                let Some(number) = number else {
+                    // rows whose value isn't a number only have their peers as neighbours
+                    let peers = self.peers(row);
+                    return if start { peers.start } else { peers.end };
+                };
+                // the frame goes towards smaller values when the partition is in ascending
+                // order and the bound precedes the row
+                let smaller = preceding != order_keys[0].descending;
+                let limit = if smaller {
+                    number.as_real() - offset.as_real()
+                } else {
+                    number.as_real() + offset.as_real()
+                };
+                let limit = [Value::Float64(limit)];
+                self.order.partition_point(|values| {
+                    let ordering = compare_sort_keys(&order_keys[..1], &values[..1], &limit);
+                    if start {
+                        ordering == Ordering::Less
+                    } else {
+                        ordering != Ordering::Greater
+                    }
+                })
+            }
+        }
+    }
+
+    /// Whether the frame of a row leaves out another row
+    fn excludes(&self, exclude: FrameExclude, row: usize, other: usize) -> bool {
+        match exclude {
+            FrameExclude::NoOthers => false,
+            FrameExclude::CurrentRow => row == other,
+            FrameExclude::Group => self.groups[row] == self.groups[other],
+            FrameExclude::Ties => row != other && self.groups[row] == self.groups[other],
+        }
+    }
+
+    /// The positions of the rows of the frame of a row
+    fn members<'p>(
+        &'p self,
+        frame: &Frame,
+        frames: &[Range<usize>],
+        row: usize,
+    ) -> impl DoubleEndedIterator<Item = usize> + 'p {
+        let exclude = frame.exclude;
+        frames[row]
+            .clone()
+            .filter(move |other| !self.excludes(exclude, row, *other))
+    }
+
+    /// Computes the value of a window function for each row of the partition.
+    fn compute(
+        &self,
+        function: &WindowFunction,
+        frame: &Frame,
+        frames: &[Range<usize>],
+        scope: &Scope,
+    ) -> Result<Vec<Value>> {
+        let length = self.rows.len();
+        let mut values = Vec::with_capacity(length);
+        match function {
+            WindowFunction::Aggregate(accumulator) => {
+                return self.aggregate(accumulator, frame, frames, scope);
+            }
+            WindowFunction::Ntile(buckets) => {
+                // the number of buckets is read from the first row of the partition
+                let buckets = match self.rows.first() {
+                    Some(row) => to_integer(&evaluate(buckets, scope, row)?),
+                    None => 1,
+                };
+                if buckets <= 0 {
+                    bail!("argument of ntile must be a positive integer");
+                }
+                values.extend((0..length).map(|row| Value::Int64(ntile(row, length, buckets))));
+                return Ok(values);
+            }
+            _ => {}
+        }
+        for (row, group) in self.groups.iter().enumerate() {
+            let value_at = |position: Option<usize>, expression: &Expression| match position {
+                Some(position) => evaluate(expression, scope, &self.rows[position]),
+                None => Ok(Value::Null),
+            };
+            let value = match function {
+                WindowFunction::RowNumber => Value::Int64(row as i64 + 1),
+                WindowFunction::Rank => Value::Int64(self.peers(row).start as i64 + 1),
+                WindowFunction::DenseRank => Value::Int64(*group as i64 + 1),
+                WindowFunction::PercentRank => Value::Float64(if length > 1 {
+                    self.peers(row).start as f64 / (length - 1) as f64
+                } else {
+                    0.0
+                }),
+                WindowFunction::CumeDist => {
+                    Value::Float64(self.peers(row).end as f64 / length as f64)
+                }
+                WindowFunction::Offset {
+                    backwards,
+                    value,
+                    offset,
+                    default,
+                } => {
+                    let offset = match offset {
+                        Some(offset) => integer_value(&evaluate(offset, scope, &self.rows[row])?),
+                        None => Some(1),
+                    };
+                    let offset = offset.map(|offset| if *backwards { -offset } else { offset });
+                    let position = offset
+                        .and_then(|offset| (row as i64).checked_add(offset))
+                        .and_then(|position| usize::try_from(position).ok())
+                        .filter(|position| *position < length);
+                    match (position, default) {
+                        (Some(position), _) => evaluate(value, scope, &self.rows[position])?,
+                        (None, Some(default)) => evaluate(default, scope, &self.rows[row])?,
+                        (None, None) => Value::Null,
+                    }
+                }
+                WindowFunction::FirstValue(value) => {
+                    value_at(self.members(frame, frames, row).next(), value)?
+                }
+                WindowFunction::LastValue(value) => {
+                    value_at(self.members(frame, frames, row).next_back(), value)?
+                }
+                WindowFunction::NthValue(value, n) => {
+                    let n = integer_value(&evaluate(n, scope, &self.rows[row])?)
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| {
+                            anyhow!("second argument to nth_value must be a positive integer")
+                        })?;
+                    let position = self.members(frame, frames, row).nth(n as usize - 1);
+                    value_at(position, value)?
+                }
+                WindowFunction::Ntile(_) | WindowFunction::Aggregate(_) => unreachable!(),
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    /// Computes an aggregate over the frame of each row. As frames only ever move forward,
+    /// rows are fed to the aggregate as they enter frames, and taken back out as they leave
+    /// them, like SQLite does. Aggregates which can't take rows back out, and frames excluding
+    /// rows, are computed from scratch unless the frame holds the same rows as the previous
+    /// one.
+    fn aggregate(
+        &self,
+        accumulator: &Accumulator,
+        frame: &Frame,
+        frames: &[Range<usize>],
+        scope: &Scope,
+    ) -> Result<Vec<Value>> {
+        let mut values: Vec<Value> = Vec::with_capacity(self.rows.len());
+        let growing = matches!(frame.start, Bound::UnboundedPreceding);
+        if frame.exclude == FrameExclude::NoOthers && (growing || accumulator.is_invertible()) {
+            let mut running = accumulator.clone();
+            let mut fed = 0..0;
+            for (row, range) in frames.iter().enumerate() {
+                if row > 0 && *range == fed {
+                    values.push(values[row - 1].clone());
+                    continue;
+                }
+                for position in fed.end..range.end {
+                    running.update(scope, &self.rows[position])?;
+                }
+                fed.end = fed.end.max(range.end);
+                for position in fed.start..range.start.min(fed.end) {
+                    running.remove(scope, &self.rows[position])?;
+                }
+                fed.start = fed.start.max(range.start.min(fed.end));
+                values.push(running.finish()?);
+            }
+            return Ok(values);
+        }
+        let mut previous: Option<Vec<usize>> = None;
+        for row in 0..self.rows.len() {
+            let members: Vec<usize> = self.members(frame, frames, row).collect();
+            if previous.as_ref() == Some(&members) {
+                values.push(values[row - 1].clone());
+                continue;
+            }
+            let mut running = accumulator.clone();
+            for position in &members {
+                running.update(scope, &self.rows[*position])?;
+            }
+            values.push(running.finish()?);
+            previous = Some(members);
+        }
+        Ok(values)
+    }
+}
+
+/// The number of rows or groups a ROWS or GROUPS offset counts
+fn count(offset: Numeric) -> usize {
+    match offset {
+        Numeric::Integer(i) => i as usize,
+        Numeric::Real(r) => r as usize,
+    }
+}
+
+/// The bucket of a row when splitting `length` rows into `buckets` buckets of sizes differing
+/// by at most one, larger buckets first
+fn ntile(row: usize, length: usize, buckets: i64) -> i64 {
+    let (row, length) = (row as i64, length as i64);
+    let size = length / buckets;
+    if size == 0 {
+        return row + 1;
+    }
+    let large = length % buckets;
+    let large_rows = large * (size + 1);
+    if row < large_rows {
+        row / (size + 1) + 1
+    } else {
+        large + (row - large_rows) / size + 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::expression::ScopeColumn;
+    use crate::engine::function::user::NO_FUNCTIONS;
+    use crate::sql::sql_query;
+
+    fn scope() -> Scope<'static> {
+        Scope {
+            columns: ["city", "age"]
+                .iter()
+                .map(|name| ScopeColumn {
+                    table: String::from("people"),
+                    name: name.to_string(),
+                    hidden: false,
+                    merged: false,
+                    collation: String::from("BINARY"),
+                    affinity: None,
+                })
+                .collect(),
+            ..Scope::default()
+        }
+    }
+
+    /// Computes window function calls over a few rows, rendering each output row as its
+    /// values separated by `|`
+    fn run(calls: &[&str]) -> Vec<String> {
+        let city = |name: &str| Value::String(name.to_string());
+        let rows = vec![
+            vec![city("Oslo"), Value::Int64(30)],
+            vec![city("Lima"), Value::Int64(20)],
+            vec![city("Oslo"), Value::Int64(41)],
+            vec![city("Lima"), Value::Int64(20)],
+            vec![city("Oslo"), Value::Int64(35)],
+        ];
+        let mut windows = Vec::new();
+        for call in calls {
+            let call = sql_query::expression(call).unwrap();
+            collect_windows(&call, NO_FUNCTIONS, &mut windows).unwrap();
+        }
+        let input = Box::new(rows.into_iter().map(Ok)) as Rows<'static>;
+        compute(input, &scope(), &windows)
+            .unwrap()
+            .map(|row| {
+                row.unwrap()
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>()
+                    .join("|")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ranks_rows_within_partitions() {
+        let rows = run(&[
+            "row_number() OVER (PARTITION BY city ORDER BY age)",
+            "rank() OVER (PARTITION BY city ORDER BY age)",
+            "dense_rank() OVER (PARTITION BY city ORDER BY age)",
+            "ntile(2) OVER (PARTITION BY city ORDER BY age)",
+        ]);
+        assert_eq!(
+            rows,
+            vec![
+                "Lima|20|1|1|1|1",
+                "Lima|20|2|1|1|2",
+                "Oslo|30|1|1|1|1",
+                "Oslo|35|2|2|2|1",
+                "Oslo|41|3|3|3|2",
+            ]
+        );
+    }
+
+    #[test]
+    fn aggregates_over_frames() {
+        let rows = run(&[
+            "sum(age) OVER (ORDER BY age)",
+            "sum(age) OVER (ORDER BY age ROWS 1 PRECEDING)",
+            "count(*) OVER (ORDER BY age RANGE BETWEEN 5 PRECEDING AND 5 FOLLOWING)",
+            "lag(age, 1, 0) OVER (ORDER BY age)",
+            "first_value(age) OVER (ORDER BY age GROUPS BETWEEN 1 FOLLOWING AND 2 FOLLOWING)",
+        ]);
+        assert_eq!(
+            rows,
+            vec![
+                "Lima|20|40|20|2|0|30",
+                "Lima|20|40|40|2|20|30",
+                "Oslo|30|70|50|2|20|35",
+                "Oslo|35|105|65|2|30|41",
+                "Oslo|41|146|76|1|35|",
+            ]
+        );
+    }
+
+    #[test]
+    fn extends_named_windows() {
+        let definitions = sql_query::select_statement(
+            "SELECT 1 WINDOW a AS (PARTITION BY city), b AS (a ORDER BY age), c AS (b ROWS 1 PRECEDING)",
+        )
+        .unwrap()
+        .windows;
+        let mut call = sql_query::expression("sum(age) OVER (b RANGE CURRENT ROW)").unwrap();
+        resolve_windows(&mut call, &definitions).unwrap();
+        let expected = sql_query::expression(
+            "sum(age) OVER (PARTITION BY city ORDER BY age RANGE CURRENT ROW)",
+        )
+        .unwrap();
+        assert_eq!(call, expected);
+        let mut call = sql_query::expression("sum(age) OVER (c)").unwrap();
+        assert_eq!(
+            resolve_windows(&mut call, &definitions)
+                .unwrap_err()
+                .to_string(),
+            "cannot override frame specification of window: c"
+        );
+    }
+}
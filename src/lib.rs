@@ -0,0 +1,45 @@
+//! Reads SQLite databases and runs SELECT statements on them.
+//!
+//! ```
+//! use sqlite_starter_rust::{Connection, OpenFlags};
+//!
+//! let connection = Connection::open("sample.db", OpenFlags::READ_ONLY)?;
+//! let mut statement = connection.prepare("SELECT name, color FROM apples WHERE id = ?")?;
+//! for row in statement.query(&[&2])? {
+//!     let row = row?;
+//!     let name: String = row.get(0)?;
+//!     let color: Option<String> = row.get("color")?;
+//!     println!("{}: {:?}", name, color);
+//! }
+//! # Ok::<(), sqlite_starter_rust::Error>(())
+//! ```
+//!
+//! Errors are [`Error`]s, which keep those reading the database and parsing SQL as they are.
+//!
+//! Connections only read databases. Rows are inserted into tables, and tables created, with the
+//! transactions of [`Database::begin`](database::Database::begin), which the shell's `.import`
+//! uses: they take the locks SQLite takes on database files and write a rollback journal, so
+//! that SQLite connections can use the database meanwhile. Nothing is written until they are
+//! committed. Database files are only written to on 64-bit Linux, and tables with triggers,
+//! CHECK constraints or generated columns can't be.
+//!
+//! Rows are read as structs by implementing [`FromRow`], which [`from_row!`] does for structs
+//! whose fields are named after columns. They stand in for a serde integration, which would need
+//! serde as a dependency of the crate: rows aren't deserialized with serde, and structs can't be
+//! serialized into the rows transactions insert.
+
+pub mod cli;
+pub mod connection;
+pub mod database;
+pub mod engine;
+pub mod parsing;
+pub mod shell;
+// the parser generated from the SQL grammar wraps rule actions in closures
+#[allow(clippy::redundant_closure_call)]
+pub mod sql;
+
+pub use connection::{
+    ColumnIndex, Connection, Error, FromRow, FromValue, OpenFlags, Row, Rows, Statement, ToValue,
+};
+pub use database::page::btree::data::serial_types::Value;
+pub use sql::ParseError;
@@ -1,3 +1 @@
-pub mod ddl;
-// pub mod record;
 pub mod utils;
@@ -33,7 +33,7 @@ where
         // shifting left by `(8 - count - 1)*7 + 8` (the idea here being we assume the varint is of its full 8
         // bytes length (8*7bits + 8bits) which we'll re-shift right afterwards if it's shorter)
         if count < 8 {
-            result += ((byte & 127) as u64) << (8 - count - 1) * 7 + 8;
+            result += ((byte & 127) as u64) << ((8 - count - 1) * 7 + 8);
         } else {
             // (the 9th varint byte uses all of its bits for value)
             result += byte as u64;
@@ -45,11 +45,31 @@ where
         } else if (byte >> 7) == 0 {
             // e.g. if most significant bit is 0 and varint is not of full length, re-shift
             // accordingly
-            return Ok((remainder, result >> (8 - count) * 7 + 8));
+            return Ok((remainder, result >> ((8 - count) * 7 + 8)));
         }
     }
 }
 
+/// Encodes a 64-bit integer as a varint, the inverse of [`take_varint`].
+pub fn encode_varint(value: u64) -> Vec<u8> {
+    // values needing more than 56 bits use the full 9 bytes, the last one holding 8 bits
+    if value >> 56 != 0 {
+        let mut bytes: Vec<u8> = (0..8)
+            .map(|i| ((value >> (8 + (7 - i) * 7)) & 127) as u8 | 128)
+            .collect();
+        bytes.push(value as u8);
+        return bytes;
+    }
+    let mut bytes = vec![(value & 127) as u8];
+    let mut remainder = value >> 7;
+    while remainder != 0 {
+        bytes.push((remainder & 127) as u8 | 128);
+        remainder >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
 #[cfg(test)]
 mod test {
     #[test]
@@ -84,8 +104,21 @@ mod test {
             result,
             Ok((b"" as &[u8], 602446781950909951)),
             "Not equal: expected {}, got {:?}",
-            602446781950909951,
+            602446781950909951u64,
             result,
         );
     }
+
+    #[test]
+    fn encode_varint_roundtrip() {
+        for value in [0, 11, 127, 128, 514, 1 << 56, 602446781950909951, u64::MAX] {
+            let encoded = super::encode_varint(value);
+            assert_eq!(
+                super::take_varint::<()>(&encoded),
+                Ok((b"" as &[u8], value)),
+                "Roundtrip failed for {}",
+                value
+            );
+        }
+    }
 }
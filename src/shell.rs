@@ -0,0 +1,467 @@
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+
+use crate::cli::OutputOptions;
+use crate::database::{self, Database};
+use crate::engine;
+use crate::sql;
+
+use self::commands::Usage;
+use self::error::StatementError;
+use self::input::{read_line, Input, LineEditor};
+use self::output::Output;
+
+mod commands;
+mod dump;
+mod error;
+mod import;
+mod input;
+mod interrupt;
+mod output;
+
+pub use self::output::Mode;
+
+const PROMPT: &str = "sqlite> ";
+const CONTINUATION_PROMPT: &str = "   ...> ";
+
+/// What to do after a dot-command
+enum Flow {
+    Continue,
+    /// Stop reading the current input, ending the shell unless it is a file being read
+    Quit,
+    /// End the shell with the given exit code
+    Exit(i32),
+}
+
+/// Where the shell reads its input from
+enum Source {
+    Terminal(LineEditor),
+    Stdin,
+    File(BufReader<File>),
+}
+
+impl Source {
+    /// Where SQL read from the source comes from, the last line of which was read as the given
+    /// line
+    fn origin(&self, last_line: usize, sql: &str) -> Origin {
+        match self {
+            Source::Terminal(_) => Origin::Typed,
+            Source::Stdin | Source::File(_) => {
+                Origin::Line(last_line - sql.matches('\n').count(), 1)
+            }
+        }
+    }
+
+    fn read_line(&mut self, prompt: &str) -> io::Result<Input> {
+        match self {
+            Source::Terminal(editor) => editor.read_line(prompt),
+            Source::Stdin => read_line(&mut io::stdin().lock()),
+            Source::File(file) => read_line(file),
+        }
+    }
+}
+
+/// Where SQL being run was read from, which sqlite3 reports errors differently for
+#[derive(Debug, Clone, Copy)]
+enum Origin {
+    /// The command line
+    Argument,
+    /// A terminal it was typed in
+    Typed,
+    /// A file or piped input, starting at the given line and column
+    Line(usize, usize),
+}
+
+impl Origin {
+    /// Where SQL following some text from this origin starts
+    fn after(self, text: &str) -> Origin {
+        match self {
+            Origin::Line(line, column) => match text.rfind('\n') {
+                Some(newline) => Origin::Line(
+                    line + text.matches('\n').count(),
+                    text[newline + 1..].chars().count() + 1,
+                ),
+                None => Origin::Line(line, column + text.chars().count()),
+            },
+            origin => origin,
+        }
+    }
+}
+
+/// A session running statements and dot-commands read from a terminal, piped to the standard
+/// input, or given on the command line.
+struct Shell {
+    database: Database,
+    /// Set by Ctrl-C to stop the statement being run
+    interrupt: Arc<AtomicBool>,
+    output: Output,
+    /// Whether the time statements take is printed after them
+    timer: bool,
+    /// Whether a statement or a dot-command failed
+    failed: bool,
+}
+
+/// Runs the shell on the database at `filename`, or on an empty database held in memory,
+/// reading from the standard input.
+pub fn run(filename: Option<String>, options: OutputOptions) -> Result<()> {
+    let interactive = io::stdin().is_terminal();
+    // Ctrl-C only cancels statements when they are typed, otherwise it ends the process
+    let interrupt = if interactive {
+        interrupt::install()
+    } else {
+        Arc::new(AtomicBool::new(false))
+    };
+    let mut shell = Shell::new(open(filename.as_deref())?, interrupt, options);
+    let mut source = if interactive {
+        println!("Enter \".help\" for usage hints.");
+        if filename.is_none() {
+            println!("Connected to an empty in-memory database.");
+        }
+        Source::Terminal(LineEditor::new(history_path()))
+    } else {
+        Source::Stdin
+    };
+    let flow = shell.process(&mut source)?;
+    // like sqlite3, errors make the exit code 1 unless the input was typed
+    let code = match flow {
+        Flow::Exit(code) => code,
+        _ if shell.failed && !interactive => 1,
+        _ => 0,
+    };
+    shell.exit(code)
+}
+
+/// Runs the shell on the database at `filename` with the given commands: each is either a
+/// dot-command or SQL statements. Like sqlite3, the shell stops at the first error.
+pub fn run_commands(filename: &str, commands: &[String], options: OutputOptions) -> Result<()> {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let mut shell = Shell::new(open(Some(filename))?, interrupt, options);
+    // like sqlite3, the exit code is the result code of the statement failing, if any
+    let mut code = 0;
+    for command in commands {
+        let flow = if command.starts_with('.') {
+            shell.dot_command(command)
+        } else {
+            code = shell.run_sql(command, Origin::Argument);
+            Flow::Continue
+        };
+        match flow {
+            Flow::Continue if !shell.failed => {}
+            Flow::Exit(code) => return shell.exit(code),
+            _ => break,
+        }
+    }
+    let code = if shell.failed { code.max(1) } else { 0 };
+    shell.exit(code)
+}
+
+/// Opens the database at `filename`, or an empty database held in memory.
+fn open(filename: Option<&str>) -> Result<Database> {
+    let database = match filename {
+        Some(filename) => Database::init_from_file(filename).map_err(|e| match e {
+            database::Error::CantOpen(_) => {
+                anyhow!("unable to open database \"{}\": {}", filename, e)
+            }
+            e => anyhow!("{}", e),
+        })?,
+        None => Database::empty()?,
+    };
+    Ok(database)
+}
+
+/// The file the history of the lines typed is kept in: `SQLITE_HISTORY` if set, like sqlite3,
+/// `~/.sqlite_history` otherwise.
+fn history_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("SQLITE_HISTORY") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".sqlite_history"))
+}
+
+impl Shell {
+    fn new(mut database: Database, interrupt: Arc<AtomicBool>, options: OutputOptions) -> Shell {
+        database.set_interrupt(interrupt.clone());
+        let mut output = Output::default();
+        output.configure(options);
+        Shell {
+            database,
+            interrupt,
+            output,
+            timer: false,
+            failed: false,
+        }
+    }
+
+    /// Flushes the output and ends the process with the given exit code.
+    fn exit(mut self, code: i32) -> Result<()> {
+        self.output.flush()?;
+        if code != 0 {
+            std::process::exit(code);
+        }
+        Ok(())
+    }
+
+    /// Runs the statements and dot-commands read from a source until its end.
+    fn process(&mut self, source: &mut Source) -> Result<Flow> {
+        // the statements read so far which aren't ended by a semicolon yet
+        let mut pending = String::new();
+        let mut line_number = 0;
+        loop {
+            let prompt = if pending.trim().is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            let line = match source.read_line(prompt)? {
+                Input::Line(line) => line,
+                Input::Interrupted => {
+                    pending.clear();
+                    continue;
+                }
+                Input::EndOfFile => break,
+            };
+            line_number += 1;
+            if let Source::Terminal(editor) = source {
+                editor.add_history(&line);
+            }
+            // a Ctrl-C pressed since the last statement was run is meant for no other
+            self.interrupt.store(false, Ordering::Relaxed);
+            // dot-commands are only recognized at the start of a statement
+            if pending.trim().is_empty() && line.trim_start().starts_with('.') {
+                pending.clear();
+                match self.dot_command(line.trim()) {
+                    Flow::Continue => continue,
+                    flow => return Ok(flow),
+                }
+            }
+            pending.push_str(&line);
+            pending.push('\n');
+            let (statements, rest) = split_statements(&pending);
+            if !statements.is_empty() {
+                let sql = &pending[..pending.len() - rest.len()];
+                self.run_sql(sql, source.origin(line_number, sql));
+            }
+            pending = rest.to_string();
+        }
+        // like sqlite3, a last statement isn't required to end with a semicolon
+        if !is_blank(&pending) {
+            // like in sqlite3, the lines of a statement are joined by newlines, none following it
+            let sql = pending.strip_suffix('\n').unwrap_or(&pending);
+            self.run_sql(sql, source.origin(line_number, sql));
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Runs the statements of some SQL text in turn, printing their rows, up to the first one
+    /// failing. Returns the result code it fails with, 0 when none does.
+    fn run_sql(&mut self, sql: &str, origin: Origin) -> i32 {
+        let (mut statements, rest) = split_statements(sql);
+        if !is_blank(rest) {
+            statements.push(rest);
+        }
+        let result = statements
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(index, statement)| {
+                // like sqlite3, statements start after the spaces before them, apart from the
+                // first one given as an argument
+                let statement = match origin {
+                    Origin::Argument if index == 0 => statement,
+                    _ => statement.trim_start(),
+                };
+                // errors show the text from the statement on, the ones following it included
+                let start = statement.as_ptr() as usize - sql.as_ptr() as usize;
+                self.run_statement(statement, &sql[start..], origin.after(&sql[..start]))
+            });
+        let code = match &result {
+            Ok(()) => 0,
+            Err(e) => e.downcast_ref().map_or(1, StatementError::code),
+        };
+        self.report(result);
+        let ended = self.output.end_command();
+        self.report(ended);
+        code
+    }
+
+    /// Runs a statement, the text of which starts `text`, printing its rows.
+    fn run_statement(&mut self, sql: &str, text: &str, origin: Origin) -> Result<()> {
+        let start = Instant::now();
+        let times = cpu_times();
+        let prepare = |error| StatementError::prepare(error, text, origin);
+        let statement = sql::parse_statement(sql).map_err(|e| prepare(e.into()))?;
+        let columns = engine::statement_columns(&self.database, &statement).map_err(prepare)?;
+        let database = &self.database;
+        let rows = engine::execute_statement(database, &statement)
+            .map_err(|e| match e.downcast_ref::<database::Error>() {
+                // some rows are read as soon as a statement is run
+                Some(error) if error.code() > 1 => StatementError::stepping(e, origin),
+                _ => prepare(e),
+            })?
+            .map(|row| {
+                let row = database
+                    .check_interrupt()
+                    .map_err(anyhow::Error::from)
+                    .and(row);
+                row.map_err(|e| StatementError::stepping(e, origin).into())
+            });
+        self.output.print_rows(&columns, Box::new(rows))?;
+        if self.timer {
+            let (user, system) = cpu_times();
+            writeln!(
+                self.output,
+                "Run Time: real {:.6} user {:.6} sys {:.6}",
+                start.elapsed().as_secs_f64(),
+                user - times.0,
+                system - times.1
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Prints the error a command failed with, if any.
+    fn report<T>(&mut self, result: Result<T>) {
+        if let Err(e) = result {
+            self.failed = true;
+            let _ = self.output.flush();
+            if e.is::<Usage>() || e.is::<StatementError>() {
+                eprintln!("{}", e);
+            } else {
+                eprintln!("Error: {}", e);
+            }
+        }
+    }
+}
+
+/// The time in seconds the process spent running in user mode and in the kernel
+#[cfg(target_os = "linux")]
+fn cpu_times() -> (f64, f64) {
+    use std::os::raw::{c_int, c_long};
+
+    #[repr(C)]
+    struct Timeval {
+        seconds: c_long,
+        microseconds: c_long,
+    }
+
+    #[repr(C)]
+    struct Rusage {
+        user: Timeval,
+        system: Timeval,
+        // the other counters, which aren't needed
+        counters: [c_long; 14],
+    }
+
+    const RUSAGE_SELF: c_int = 0;
+
+    extern "C" {
+        fn getrusage(who: c_int, usage: *mut Rusage) -> c_int;
+    }
+
+    let seconds = |time: &Timeval| time.seconds as f64 + time.microseconds as f64 / 1e6;
+    // SAFETY: `getrusage` only writes to the given `rusage`, which all zeros is a valid value of
+    unsafe {
+        let mut usage: Rusage = std::mem::zeroed();
+        if getrusage(RUSAGE_SELF, &mut usage) != 0 {
+            return (0.0, 0.0);
+        }
+        (seconds(&usage.user), seconds(&usage.system))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_times() -> (f64, f64) {
+    (0.0, 0.0)
+}
+
+/// Where the text of statements is, as far as telling where they end goes
+#[derive(Clone, Copy, PartialEq)]
+enum Lexing {
+    Code,
+    /// Within a string or a quoted identifier, ended by the given character
+    Quoted(char),
+    LineComment,
+    BlockComment,
+}
+
+/// Splits text into the statements ended by a semicolon it holds, semicolon included, leaving
+/// out those which are empty, and the text following the last of them. Semicolons within
+/// strings, quoted identifiers and comments don't end statements.
+fn split_statements(text: &str) -> (Vec<&str>, &str) {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut lexing = Lexing::Code;
+    let mut chars = text.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        let next = chars.peek().map(|(_, next)| *next);
+        lexing = match (lexing, c) {
+            (Lexing::Code, '\'' | '"' | '`') => Lexing::Quoted(c),
+            (Lexing::Code, '[') => Lexing::Quoted(']'),
+            (Lexing::Code, '-') if next == Some('-') => Lexing::LineComment,
+            (Lexing::Code, '/') if next == Some('*') => {
+                chars.next();
+                Lexing::BlockComment
+            }
+            (Lexing::Code, ';') => {
+                if !is_blank(&text[start..position]) {
+                    statements.push(&text[start..=position]);
+                }
+                start = position + 1;
+                Lexing::Code
+            }
+            // a doubled quote within a string stands for the quote, so the string goes on
+            (Lexing::Quoted(end), _) if c == end => Lexing::Code,
+            (Lexing::LineComment, '\n') => Lexing::Code,
+            (Lexing::BlockComment, '*') if next == Some('/') => {
+                chars.next();
+                Lexing::Code
+            }
+            (lexing, _) => lexing,
+        };
+    }
+    (statements, &text[start..])
+}
+
+/// Whether text holds nothing but spaces and comments
+fn is_blank(text: &str) -> bool {
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        rest = if let Some(comment) = rest.strip_prefix("--") {
+            comment.split_once('\n').map_or("", |(_, after)| after)
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            comment.split_once("*/").map_or("", |(_, after)| after)
+        } else {
+            return false;
+        }
+        .trim_start();
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_statements_ended_by_semicolons() {
+        assert_eq!(
+            split_statements("SELECT 1; SELECT ';' -- ;\n, [a;b];;\nSELECT\n"),
+            (
+                vec!["SELECT 1;", " SELECT ';' -- ;\n, [a;b];"],
+                "\nSELECT\n"
+            )
+        );
+        assert_eq!(
+            split_statements("SELECT 'it''s;' /* ; */ ;/* comment */;"),
+            (vec!["SELECT 'it''s;' /* ; */ ;"], "")
+        );
+        assert_eq!(split_statements("SELECT \"a;"), (vec![], "SELECT \"a;"));
+        assert!(is_blank(" -- comment\n/* comment */ "));
+        assert!(!is_blank("/* comment */ SELECT 1"));
+    }
+}
@@ -0,0 +1,512 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Write};
+
+use anyhow::{anyhow, bail, Result};
+use itertools::Itertools;
+
+use crate::database::encoding::TextEncoding;
+use crate::database::schema::ObjectType;
+use crate::engine::function::pattern::{self, glob};
+use crate::engine::select::column_names;
+use crate::sql::{is_reserved_keyword, sql_query};
+
+use super::output::Mode;
+use super::{open, Flow, Shell, Source};
+
+/// The dot-commands, with their arguments and what they do, as listed by `.help`
+const COMMANDS: [(&str, &str); 18] = [
+    (".dbinfo ?DB?", "Show status information about the database"),
+    (".dump ?OBJECTS?", "Render database content as SQL"),
+    (".exit ?CODE?", "Exit this program with return-code CODE"),
+    (".headers on|off", "Turn display of headers on or off"),
+    (".help ?PATTERN?", "Show help text for PATTERN"),
+    (".import FILE TABLE", "Import data from FILE into TABLE"),
+    (".indexes ?TABLE?", "Show names of indexes"),
+    (".mode ?MODE? ?TABLE?", "Set output mode"),
+    (".nullvalue STRING", "Use STRING in place of NULL values"),
+    (".once FILE", "Output for the next SQL command only to FILE"),
+    (".open ?FILE?", "Close existing database and reopen FILE"),
+    (
+        ".output ?FILE?",
+        "Send output to FILE or stdout if FILE is omitted",
+    ),
+    (".quit", "Stop interpreting input stream, exit if primary."),
+    (".read FILE", "Read input from FILE"),
+    (
+        ".schema ?PATTERN?",
+        "Show the CREATE statements matching PATTERN",
+    ),
+    (
+        ".separator COL ?ROW?",
+        "Change the column and row separators",
+    ),
+    (
+        ".tables ?TABLE?",
+        "List names of tables matching LIKE pattern TABLE",
+    ),
+    (".timer on|off", "Turn SQL timer on or off"),
+];
+
+/// A dot-command used with the wrong arguments, reported with how it is used rather than as
+/// an error
+#[derive(Debug)]
+pub struct Usage(pub(super) &'static str);
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Usage: {}", self.0)
+    }
+}
+
+impl std::error::Error for Usage {}
+
+impl Shell {
+    /// Runs a dot-command, reporting the error it fails with if any.
+    pub(super) fn dot_command(&mut self, line: &str) -> Flow {
+        let flow = match self.run_dot_command(line) {
+            Ok(flow) => flow,
+            Err(e) => {
+                self.report::<()>(Err(e));
+                Flow::Continue
+            }
+        };
+        let ended = self.output.end_command();
+        self.report(ended);
+        flow
+    }
+
+    fn run_dot_command(&mut self, line: &str) -> Result<Flow> {
+        let arguments = parse_arguments(line.trim_start_matches('.'));
+        let Some((name, arguments)) = arguments.split_first() else {
+            return Ok(Flow::Continue);
+        };
+        let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
+        let unknown = || {
+            anyhow!(
+                "unknown command or invalid arguments:  \"{}\". Enter \".help\" for help",
+                name
+            )
+        };
+        // like sqlite3, commands can be abbreviated, to the first one in alphabetical order
+        let command = COMMANDS
+            .iter()
+            .map(|(usage, _)| command_name(usage))
+            .find(|command| command.starts_with(name.as_str()))
+            .ok_or_else(unknown)?;
+        match (command, arguments.as_slice()) {
+            ("dbinfo", [] | [_]) => self.print_database_information()?,
+            ("dump", arguments) => self.dump(arguments)?,
+            ("exit", []) => return Ok(Flow::Exit(0)),
+            ("exit", [code]) => return Ok(Flow::Exit(code.parse().unwrap_or(0))),
+            ("headers", [value]) => self.output.set_headers(boolean(value)),
+            ("headers", _) => bail!(Usage(".headers on|off")),
+            ("help", []) => self.print_help(None)?,
+            ("help", [pattern]) => self.print_help(Some(pattern))?,
+            ("import", arguments) => self.import(arguments)?,
+            ("indexes", []) => self.print_indexes(None)?,
+            ("indexes", [table]) => self.print_indexes(Some(table))?,
+            ("indexes", _) => bail!(Usage(".indexes ?LIKE-PATTERN?")),
+            ("mode", []) => {
+                let mode = self.output.mode.describe();
+                writeln!(self.output, "current output mode: {}", mode)?
+            }
+            ("mode", [mode]) => self.output.set_mode(Mode::parse(mode)?),
+            ("mode", [mode, table]) => {
+                self.output.set_mode(Mode::parse(mode)?);
+                self.output.table = table.to_string();
+            }
+            ("nullvalue", [value]) => self.output.null_value = value.to_string(),
+            ("nullvalue", _) => bail!(Usage(".nullvalue STRING")),
+            ("once", [path]) => self.output.redirect(Some(path), true)?,
+            ("once", _) => bail!(Usage(".once FILE")),
+            ("open", []) => self.open(None)?,
+            ("open", [path]) => self.open(Some(path))?,
+            ("output", []) => self.output.redirect(None, false)?,
+            ("output", [path]) => self.output.redirect(Some(path), false)?,
+            ("output", _) => bail!(Usage(".output FILE")),
+            ("quit", []) => return Ok(Flow::Quit),
+            ("read", [path]) => return self.read(path),
+            ("read", _) => bail!(Usage(".read FILE")),
+            ("schema", []) => self.print_schema(None)?,
+            ("schema", [pattern]) => self.print_schema(Some(pattern))?,
+            ("separator", [column]) => self.output.column_separator = column.to_string(),
+            ("separator", [column, row]) => {
+                self.output.column_separator = column.to_string();
+                self.output.row_separator = row.to_string();
+            }
+            ("separator", _) => bail!(Usage(".separator COL ?ROW?")),
+            ("tables", []) => self.print_tables(None)?,
+            ("tables", [pattern]) => self.print_tables(Some(pattern))?,
+            ("timer", [value]) => self.timer = boolean(value),
+            ("timer", _) => bail!(Usage(".timer on|off")),
+            _ => return Err(unknown()),
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Replaces the database with the one at `path`, or with an empty one.
+    fn open(&mut self, path: Option<&str>) -> Result<()> {
+        let mut database = open(path)?;
+        database.set_interrupt(self.interrupt.clone());
+        self.database = database;
+        Ok(())
+    }
+
+    /// Runs the statements and dot-commands of a file.
+    fn read(&mut self, path: &str) -> Result<Flow> {
+        let file = File::open(path).map_err(|_| anyhow!("cannot open \"{}\"", path))?;
+        match self.process(&mut Source::File(BufReader::new(file)))? {
+            // .quit only stops reading the file
+            Flow::Quit => Ok(Flow::Continue),
+            flow => Ok(flow),
+        }
+    }
+
+    fn print_help(&mut self, pattern: Option<&str>) -> Result<()> {
+        let commands = COMMANDS
+            .iter()
+            .filter(|(usage, _)| pattern.is_none_or(|pattern| command_name(usage) == pattern));
+        for (usage, description) in commands {
+            writeln!(self.output, "{:<24} {}", usage, description)?;
+        }
+        Ok(())
+    }
+
+    /// Prints the header fields of the database and counts of its schema objects, in
+    /// sqlite3's format.
+    fn print_database_information(&mut self) -> Result<()> {
+        let header = &self.database.header;
+        let encoding = match header.encoding() {
+            TextEncoding::Utf8 => "utf8",
+            TextEncoding::Utf16le => "utf16le",
+            TextEncoding::Utf16be => "utf16be",
+        };
+        let objects = self.database.list_objects()?;
+        let count = |object_type: ObjectType| {
+            objects
+                .iter()
+                .filter(|o| o.object_type == object_type)
+                .count()
+        };
+        let schema_size: usize = objects
+            .iter()
+            .filter_map(|o| o.object_ddl.as_ref())
+            .map(|sql| sql.chars().count())
+            .sum();
+        let fields = [
+            (
+                "database page size:",
+                header.page_size_in_bytes().to_string(),
+            ),
+            (
+                "write format:",
+                header.file_format_write_version.to_string(),
+            ),
+            ("read format:", header.file_format_read_version.to_string()),
+            ("reserved bytes:", header.page_reserved_space.to_string()),
+            (
+                "file change counter:",
+                header.file_change_counter.to_string(),
+            ),
+            ("database page count:", header.db_size_in_pages.to_string()),
+            (
+                "freelist page count:",
+                header.number_of_freelist_pages.to_string(),
+            ),
+            ("schema cookie:", header.schema_cookie.to_string()),
+            ("schema format:", header.schema_format_number.to_string()),
+            (
+                "default cache size:",
+                (header.default_page_cache_size as i32).to_string(),
+            ),
+            (
+                "autovacuum top root:",
+                header.largest_root_btree_page_number.to_string(),
+            ),
+            (
+                "incremental vacuum:",
+                header.incremental_vacuum_mode.to_string(),
+            ),
+            (
+                "text encoding:",
+                format!("{} ({})", header.text_encoding, encoding),
+            ),
+            ("user version:", (header.user_version as i32).to_string()),
+            (
+                "application id:",
+                (header.application_id as i32).to_string(),
+            ),
+            (
+                "software version:",
+                header.sqlite_version_number.to_string(),
+            ),
+            ("number of tables:", count(ObjectType::Table).to_string()),
+            ("number of indexes:", count(ObjectType::Index).to_string()),
+            (
+                "number of triggers:",
+                count(ObjectType::Trigger).to_string(),
+            ),
+            ("number of views:", count(ObjectType::View).to_string()),
+            ("schema size:", schema_size.to_string()),
+            // the database is never changed while read
+            ("data version", 1.to_string()),
+        ];
+        for (label, value) in fields {
+            writeln!(self.output, "{:<21}{}", label, value)?;
+        }
+        Ok(())
+    }
+
+    /// Prints the SQL the objects of the schema were created with, those of the tables whose
+    /// name matches the pattern if any.
+    fn print_schema(&mut self, pattern: Option<&str>) -> Result<()> {
+        for object in self.database.list_objects()? {
+            let Some(sql) = &object.object_ddl else {
+                continue;
+            };
+            let table = object.table_name.as_deref().unwrap_or_default();
+            if !pattern.is_none_or(|pattern| matches_pattern(pattern, table)) {
+                continue;
+            }
+            // like sqlite3, views are followed by a comment listing their columns
+            let columns = match object.object_type {
+                ObjectType::View => self.view_columns(sql),
+                _ => None,
+            };
+            match columns {
+                Some(columns) => writeln!(
+                    self.output,
+                    "{}\n/* {}({}) */;",
+                    sql,
+                    quote_identifier(&object.object_name),
+                    columns.iter().map(|c| quote_identifier(c)).join(",")
+                )?,
+                None => writeln!(self.output, "{};", sql)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The names of the columns of a view, from the list following its name if any, or else
+    /// from its SELECT statement
+    fn view_columns(&self, sql: &str) -> Option<Vec<String>> {
+        let lowercase = sql.to_ascii_lowercase();
+        let bytes = lowercase.as_bytes();
+        // the AS keyword is the first one preceded by the view name or its column list
+        let position = (1..bytes.len().saturating_sub(2)).find(|&i| {
+            &bytes[i..i + 2] == b"as"
+                && (bytes[i - 1].is_ascii_whitespace() || b")\"]`".contains(&bytes[i - 1]))
+                && (bytes[i + 2].is_ascii_whitespace() || bytes[i + 2] == b'(')
+        })?;
+        let (definition, select) = (&sql[..position], &sql[position + 2..]);
+        if let Some((_, list)) = definition.split_once('(') {
+            let list = list.trim_end().strip_suffix(')')?;
+            return Some(
+                list.split(',')
+                    .map(|c| unquote_identifier(c.trim()))
+                    .collect(),
+            );
+        }
+        let select = sql_query::select_statement(select).ok()?;
+        column_names(&self.database, &select, &Default::default()).ok()
+    }
+
+    /// Prints the names of the tables and views, leaving out the internal ones.
+    fn print_tables(&mut self, pattern: Option<&str>) -> Result<()> {
+        let mut names: Vec<String> = self
+            .database
+            .list_objects()?
+            .into_iter()
+            .filter(|o| matches!(o.object_type, ObjectType::Table | ObjectType::View))
+            .map(|o| o.object_name)
+            .filter(|name| !like("sqlite_%", name))
+            .filter(|name| pattern.is_none_or(|pattern| like(pattern, name)))
+            .collect();
+        names.sort();
+        self.print_in_columns(&names)
+    }
+
+    /// Prints the names of the indexes, those of the tables whose name matches the pattern if
+    /// any.
+    fn print_indexes(&mut self, pattern: Option<&str>) -> Result<()> {
+        let mut names: Vec<String> = self
+            .database
+            .list_objects()?
+            .into_iter()
+            .filter(|o| o.object_type == ObjectType::Index)
+            .filter(|o| {
+                pattern.is_none_or(|pattern| {
+                    like(pattern, o.table_name.as_deref().unwrap_or_default())
+                })
+            })
+            .map(|o| o.object_name)
+            .collect();
+        names.sort();
+        self.print_in_columns(&names)
+    }
+
+    /// Prints names in as many columns as fit in 80 characters, filled one after the other.
+    fn print_in_columns(&mut self, names: &[String]) -> Result<()> {
+        let width = names.iter().map(|name| name.chars().count()).max();
+        let Some(width) = width else {
+            return Ok(());
+        };
+        let columns = (80 / (width + 2)).max(1);
+        let rows = names.len().div_ceil(columns);
+        for row in 0..rows {
+            let line: Vec<String> = names[row..]
+                .iter()
+                .step_by(rows)
+                .map(|name| format!("{:<width$}", name))
+                .collect();
+            writeln!(self.output, "{}", line.join("  "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of a dot-command, without its dot, from its usage
+fn command_name(usage: &str) -> &str {
+    usage[1..].split(' ').next().unwrap_or_default()
+}
+
+/// An identifier, between double quotes unless it can be written without them
+pub(super) fn quote_identifier(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !is_reserved_keyword(name);
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// An identifier as written in SQL, without the quotes around it if any
+fn unquote_identifier(identifier: &str) -> String {
+    let quoted = [('"', '"'), ('[', ']'), ('`', '`')]
+        .iter()
+        .find_map(|(open, close)| identifier.strip_prefix(*open)?.strip_suffix(*close));
+    match quoted {
+        Some(name) if identifier.starts_with('"') => name.replace("\"\"", "\""),
+        Some(name) if identifier.starts_with('`') => name.replace("``", "`"),
+        Some(name) => name.to_string(),
+        None => identifier.to_string(),
+    }
+}
+
+/// Reads a boolean setting like sqlite3 does, warning about values which aren't one.
+fn boolean(value: &str) -> bool {
+    if let Ok(number) = value.parse::<i64>() {
+        return number != 0;
+    }
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "yes" | "true" => true,
+        "off" | "no" | "false" => false,
+        _ => {
+            eprintln!(
+                "ERROR: Not a boolean value: \"{}\". Assuming \"no\".",
+                value
+            );
+            false
+        }
+    }
+}
+
+/// Splits the text of a dot-command into its name and arguments, separated by spaces. An
+/// argument can be quoted to hold spaces: between single quotes it is taken as is, between
+/// double quotes backslash escapes are replaced with the character they stand for.
+fn parse_arguments(line: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return arguments;
+        };
+        let mut argument = String::new();
+        match first {
+            '\'' => argument.extend(chars.by_ref().take_while(|c| *c != '\'')),
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => argument.push(escaped(&mut chars)),
+                        c => argument.push(c),
+                    }
+                }
+            }
+            c => {
+                argument.push(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    argument.push(c);
+                }
+            }
+        }
+        arguments.push(argument);
+    }
+}
+
+/// The character a backslash escape stands for, given what follows the backslash
+fn escaped(chars: &mut std::iter::Peekable<std::str::Chars>) -> char {
+    let Some(c) = chars.next() else {
+        return '\\';
+    };
+    match c {
+        'a' => '\x07',
+        'b' => '\x08',
+        't' => '\t',
+        'n' => '\n',
+        'v' => '\x0b',
+        'f' => '\x0c',
+        'r' => '\r',
+        '0'..='7' => {
+            // up to three octal digits
+            let mut code = c.to_digit(8).unwrap_or_default();
+            for _ in 0..2 {
+                match chars.next_if(|c| c.is_digit(8)) {
+                    Some(digit) => code = code * 8 + digit.to_digit(8).unwrap_or_default(),
+                    None => break,
+                }
+            }
+            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+        }
+        c => c,
+    }
+}
+
+/// Whether a name matches a pattern given to `.schema`, either as a LIKE pattern or as a GLOB
+/// one, ignoring case. `.tables` and `.indexes` only take LIKE patterns, like in sqlite3.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    like(pattern, name) || glob(&pattern.to_lowercase(), &name.to_lowercase())
+}
+
+/// Whether a name matches a LIKE pattern, without escape character
+pub(super) fn like(pattern: &str, name: &str) -> bool {
+    pattern::like(pattern, name, None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_arguments_like_sqlite3() {
+        assert_eq!(
+            parse_arguments(r#"separator '\t' "\t|\101" a\n  "x y""#),
+            vec!["separator", "\\t", "\t|A", "a\\n", "x y"]
+        );
+    }
+
+    #[test]
+    fn matches_like_and_glob_patterns() {
+        assert!(matches_pattern("ORD%", "orders"));
+        assert!(matches_pattern("o_ders", "Orders"));
+        assert!(matches_pattern("*der*", "orders"));
+        assert!(!matches_pattern("ord", "orders"));
+        assert!(!matches_pattern("%x%", "orders"));
+        assert!(like("sqlite_%", "sqlite_sequence"));
+        assert!(!like("*der*", "orders"));
+    }
+}
@@ -0,0 +1,238 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+use crate::database::schema::{ObjectInformation, ObjectType, TableInformation};
+
+use super::commands::{like, quote_identifier, Usage};
+use super::output::format::{sql_literal, Literal};
+use super::Shell;
+
+const USAGE: &str = ".dump ?--data-only? ?OBJECTS?";
+
+impl Shell {
+    /// Prints the SQL script recreating the database, or the objects whose name matches one
+    /// of the patterns: the tables along with INSERT statements for their rows, then the
+    /// indexes, triggers and views. Rows are printed as they are read.
+    pub(super) fn dump(&mut self, arguments: &[&str]) -> Result<()> {
+        let mut data_only = false;
+        let mut patterns = Vec::new();
+        for argument in arguments {
+            match *argument {
+                "--data-only" | "-data-only" => data_only = true,
+                option if option.starts_with('-') => bail!(Usage(USAGE)),
+                pattern => patterns.push(pattern),
+            }
+        }
+        let objects = self.database.list_objects()?;
+        let objects = objects.into_iter().filter(|o| {
+            o.object_ddl.is_some()
+                && (patterns.is_empty() || patterns.iter().any(|p| like(p, &o.object_name)))
+        });
+        let (mut tables, mut others): (Vec<_>, Vec<_>) =
+            objects.partition(|o| o.object_type == ObjectType::Table);
+        // like in sqlite3, sqlite_sequence comes last so as to set the values of tables created
+        // before it, and views come before triggers, which come before indexes
+        tables.sort_by_key(|o| o.object_name == "sqlite_sequence");
+        others.sort_by_key(|o| match o.object_type {
+            ObjectType::View => 0,
+            ObjectType::Trigger => 1,
+            _ => 2,
+        });
+
+        if !data_only {
+            writeln!(self.output, "PRAGMA foreign_keys=OFF;")?;
+            writeln!(self.output, "BEGIN TRANSACTION;")?;
+        }
+        let mut writable_schema = false;
+        let mut failed = false;
+        for object in tables {
+            let name = object.object_name.as_str();
+            // of SQLite's own tables, only those it lets be written to are dumped
+            let statistics = name.starts_with("sqlite_stat");
+            if name.starts_with("sqlite_") && name != "sqlite_sequence" && !statistics {
+                continue;
+            }
+            let sql = object.object_ddl.as_deref().unwrap_or_default();
+            if name == "sqlite_sequence" {
+                // the table is created by SQLite itself, which its CREATE TABLE would fail, and
+                // its rows replace those SQLite inserted, even when only data is dumped
+                writeln!(self.output, "PRAGMA writable_schema=ON;")?;
+                writable_schema = true;
+                writeln!(
+                    self.output,
+                    "CREATE TABLE IF NOT EXISTS sqlite_sequence(name,seq);"
+                )?;
+                writeln!(self.output, "DELETE FROM sqlite_sequence;")?;
+            } else if !data_only {
+                if statistics {
+                    writeln!(self.output, "ANALYZE sqlite_schema;")?;
+                } else if let Some(rest) = sql
+                    .strip_prefix("CREATE TABLE ")
+                    .filter(|rest| rest.starts_with(['"', '\'']))
+                {
+                    writeln!(self.output, "CREATE TABLE IF NOT EXISTS {};", rest)?;
+                } else {
+                    writeln!(self.output, "{};", sql)?;
+                }
+            }
+            if let Err(e) = self.dump_rows(object) {
+                eprintln!("Error: {}", e);
+                self.failed = true;
+                failed = true;
+            }
+        }
+        for object in others {
+            if !data_only {
+                writeln!(self.output, "{};", object.object_ddl.unwrap_or_default())?;
+            }
+        }
+        if writable_schema {
+            writeln!(self.output, "PRAGMA writable_schema=OFF;")?;
+        }
+        if !data_only {
+            match failed {
+                true => writeln!(self.output, "ROLLBACK; -- due to errors")?,
+                false => writeln!(self.output, "COMMIT;")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints an INSERT statement for each row of a table, its values written so that they
+    /// read back the same. Like in sqlite3, generated columns are left out, SQLite working them
+    /// out again. The rows of WITHOUT ROWID tables are read from the index b-tree they are
+    /// stored in.
+    fn dump_rows(&mut self, object: ObjectInformation) -> Result<()> {
+        let table = TableInformation::try_from(object)?;
+        let name = quote_identifier(&table.table_name);
+        let root_page = table.root_page as u32;
+        let rows: Box<dyn Iterator<Item = _>> = match table.without_rowid {
+            true => Box::new(
+                self.database
+                    .index_cursor(root_page, false)
+                    .map_ok(|values| table.without_rowid_row_values(values)),
+            ),
+            false => Box::new(
+                self.database
+                    .table_cursor(root_page, false)
+                    .map_ok(|(rowid, values)| table.row_values(rowid, values)),
+            ),
+        };
+        for values in rows {
+            let values = values?
+                .iter()
+                .zip(&table.generated_columns)
+                .filter(|(_, generated)| generated.is_none())
+                .map(|(value, _)| sql_literal(value, Literal::Insert))
+                .join(",");
+            writeln!(self.output, "INSERT INTO {} VALUES({});", name, values)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::database::Database;
+
+    use super::*;
+
+    /// Tells apart the files the scripts of tests run at the same time are written to
+    static DUMPS: AtomicUsize = AtomicUsize::new(0);
+
+    /// The script dumping a fixture database, and whether the shell reported an error
+    fn dump(fixture: &str, arguments: &[&str]) -> (String, bool) {
+        let database = Database::init_from_file(fixture).unwrap();
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let mut shell = Shell::new(database, interrupt, Default::default());
+        let path = std::env::temp_dir().join(format!(
+            "resql-dump-{}-{}.sql",
+            std::process::id(),
+            DUMPS.fetch_add(1, Ordering::Relaxed)
+        ));
+        shell.output.redirect(path.to_str(), false).unwrap();
+        shell.dump(arguments).unwrap();
+        shell.output.redirect(None, false).unwrap();
+        let script = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        (script, shell.failed)
+    }
+
+    #[test]
+    fn dumps_databases_as_scripts() {
+        let (script, failed) = dump("tests/fixtures/dump.db", &[]);
+        assert!(!failed);
+        assert_eq!(
+            script,
+            "PRAGMA foreign_keys=OFF;\n\
+             BEGIN TRANSACTION;\n\
+             CREATE TABLE item(id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, price REAL, \
+             data BLOB);\n\
+             INSERT INTO item VALUES(1,'pen',0.1000000000000000055,X'');\n\
+             INSERT INTO item VALUES(2,'it''s',9.99999999999999956e+299,X'00ff');\n\
+             CREATE TABLE IF NOT EXISTS \"odd name\"(a, b);\n\
+             INSERT INTO \"odd name\" VALUES(1,unistr('a\\u000ab'));\n\
+             CREATE TABLE pair(v, k TEXT PRIMARY KEY) WITHOUT ROWID;\n\
+             INSERT INTO pair VALUES(2,'a');\n\
+             INSERT INTO pair VALUES(1,'x');\n\
+             PRAGMA writable_schema=ON;\n\
+             CREATE TABLE IF NOT EXISTS sqlite_sequence(name,seq);\n\
+             DELETE FROM sqlite_sequence;\n\
+             INSERT INTO sqlite_sequence VALUES('item',3);\n\
+             CREATE VIEW cheap AS SELECT name FROM item WHERE price < 1;\n\
+             CREATE INDEX item_name ON item(name);\n\
+             PRAGMA writable_schema=OFF;\n\
+             COMMIT;\n"
+        );
+        // the values of sqlite_sequence replace those SQLite sets, even with data only
+        let (script, _) = dump("tests/fixtures/dump.db", &["--data-only", "s%"]);
+        assert_eq!(
+            script,
+            "PRAGMA writable_schema=ON;\n\
+             CREATE TABLE IF NOT EXISTS sqlite_sequence(name,seq);\n\
+             DELETE FROM sqlite_sequence;\n\
+             INSERT INTO sqlite_sequence VALUES('item',3);\n\
+             PRAGMA writable_schema=OFF;\n"
+        );
+    }
+
+    #[test]
+    fn leaves_generated_columns_out() {
+        // built by tests/fixtures/generated.sql
+        let (script, failed) = dump("tests/fixtures/generated.db", &[]);
+        assert!(!failed);
+        assert_eq!(
+            script,
+            "PRAGMA foreign_keys=OFF;\n\
+             BEGIN TRANSACTION;\n\
+             CREATE TABLE a(x, y, r REAL, w AS (y || 'z'), z);\n\
+             INSERT INTO a VALUES(1,'q',2.5,1);\n\
+             INSERT INTO a VALUES(2,'p',3.0,NULL);\n\
+             CREATE TABLE g(a, b AS (a * 2) STORED, c);\n\
+             INSERT INTO g VALUES(3,'x');\n\
+             CREATE TABLE f(a INTEGER PRIMARY KEY, b AS (c + 1), \
+             c INTEGER GENERATED ALWAYS AS (a * 10) VIRTUAL, e);\n\
+             INSERT INTO f VALUES(5,'e');\n\
+             COMMIT;\n"
+        );
+    }
+
+    #[test]
+    fn rolls_back_dumps_of_tables_which_cant_be_read() {
+        // the leaf page of "odd name" has an invalid page type
+        let (script, failed) = dump("tests/fixtures/dump_corrupt.db", &["o%"]);
+        assert!(failed);
+        assert_eq!(
+            script,
+            "PRAGMA foreign_keys=OFF;\n\
+             BEGIN TRANSACTION;\n\
+             CREATE TABLE IF NOT EXISTS \"odd name\"(a, b);\n\
+             ROLLBACK; -- due to errors\n"
+        );
+    }
+}
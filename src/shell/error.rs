@@ -0,0 +1,168 @@
+use std::fmt;
+
+use crate::database;
+use crate::sql::ParseError;
+
+use super::Origin;
+
+/// The error a statement failed with, reported the way sqlite3 does: telling whether it failed
+/// while being prepared or while its rows were read, and showing where in its text it failed.
+#[derive(Debug)]
+pub(super) struct StatementError {
+    error: anyhow::Error,
+    /// Whether the statement failed while its rows were read rather than while being prepared
+    stepping: bool,
+    origin: Origin,
+    /// The text of the statement around the token it failed at, with a caret under the token
+    context: String,
+    /// What could have been written instead of the token, for statements which don't parse
+    expected: Option<Vec<String>>,
+}
+
+impl StatementError {
+    /// An error found while preparing a statement, the text of which starts `text`
+    pub(super) fn prepare(error: anyhow::Error, text: &str, origin: Origin) -> StatementError {
+        let (origin, context, expected) = match error.downcast_ref::<ParseError>() {
+            Some(parse_error) => {
+                // errors in files are reported at the token rather than the statement
+                let origin = match origin {
+                    Origin::Line(line, column) if parse_error.line == 1 => {
+                        Origin::Line(line, column + parse_error.column - 1)
+                    }
+                    Origin::Line(line, _) => {
+                        Origin::Line(line + parse_error.line - 1, parse_error.column)
+                    }
+                    origin => origin,
+                };
+                let context = match parse_error.token {
+                    Some(_) => error_context(text, parse_error.position),
+                    None => String::new(),
+                };
+                (origin, context, Some(parse_error.expected.clone()))
+            }
+            None => (origin, String::new(), None),
+        };
+        StatementError {
+            error,
+            stepping: false,
+            origin,
+            context,
+            expected,
+        }
+    }
+
+    /// An error found while reading the rows of a statement
+    pub(super) fn stepping(error: anyhow::Error, origin: Origin) -> StatementError {
+        StatementError {
+            error,
+            stepping: true,
+            origin,
+            context: String::new(),
+            expected: None,
+        }
+    }
+
+    /// The result code sqlite3 would have failed with, shown after the message when it isn't
+    /// the generic one
+    pub(super) fn code(&self) -> i32 {
+        self.error
+            .downcast_ref::<database::Error>()
+            .map_or(1, database::Error::code)
+    }
+}
+
+impl fmt::Display for StatementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.origin, self.stepping) {
+            (Origin::Argument, false) => write!(f, "Error: in prepare, ")?,
+            (Origin::Argument, true) => write!(f, "Error: stepping, ")?,
+            (Origin::Typed, false) => write!(f, "Parse error: ")?,
+            (Origin::Typed, true) => write!(f, "Runtime error: ")?,
+            (Origin::Line(line, column), false) if self.expected.is_some() => {
+                write!(f, "Parse error near line {}, column {}: ", line, column)?
+            }
+            (Origin::Line(line, _), false) => write!(f, "Parse error near line {}: ", line)?,
+            (Origin::Line(line, _), true) => write!(f, "Runtime error near line {}: ", line)?,
+        }
+        write!(f, "{}", self.error)?;
+        let code = self.code();
+        if code > 1 {
+            write!(f, " ({})", code)?;
+        }
+        write!(f, "{}", self.context)?;
+        match self.expected.as_deref() {
+            None | Some([]) => Ok(()),
+            Some([expected]) => write!(f, "\n  expected {}", expected),
+            Some(expected) => write!(f, "\n  expected one of: {}", expected.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for StatementError {}
+
+/// The text around the byte at `offset` of some SQL on a line, and a caret pointing at that
+/// byte on the next, both indented by two spaces, as sqlite3 shows them after errors. At most
+/// 50 bytes are shown before the byte, and 78 in all.
+fn error_context(sql: &str, mut offset: usize) -> String {
+    let mut start = 0;
+    while offset > 50 {
+        offset -= 1;
+        start += 1;
+        while !sql.is_char_boundary(start) {
+            start += 1;
+            offset -= 1;
+        }
+    }
+    let text = &sql[start..];
+    let mut length = text.len().min(78);
+    while !text.is_char_boundary(length) {
+        length -= 1;
+    }
+    let text: String = text[..length]
+        .chars()
+        .map(|c| if c.is_ascii_whitespace() { ' ' } else { c })
+        .collect();
+    if offset < 25 {
+        format!("\n  {}\n  {}^--- error here", text, " ".repeat(offset))
+    } else {
+        format!("\n  {}\n  {}error here ---^", text, " ".repeat(offset - 14))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shows_where_statements_fail() {
+        assert_eq!(
+            error_context("SELECT * FORM t", 9),
+            "\n  SELECT * FORM t\n           ^--- error here"
+        );
+        let sql = format!("SELECT a{} FROM\nt WHERE a b", ", a".repeat(20));
+        assert_eq!(
+            error_context(&sql, sql.len() - 1),
+            "\n  a, a, a, a, a, a, a, a, a, a, a, a FROM t WHERE a b\
+             \n                                      error here ---^"
+        );
+    }
+
+    #[test]
+    fn reports_where_statements_in_files_fail_to_parse() {
+        let sql = "SELECT id\nFROM emp WHER id = 1;";
+        let error = crate::sql::parse_statement(sql).unwrap_err();
+        let message = StatementError::prepare(error.into(), sql, Origin::Line(3, 1)).to_string();
+        let mut lines = message.lines();
+        assert_eq!(
+            lines.next(),
+            Some("Parse error near line 4, column 15: near \"id\": syntax error")
+        );
+        assert_eq!(
+            lines.nth(2),
+            Some(
+                "  expected one of: \",\", \";\", CROSS, EXCEPT, GROUP, HAVING, INNER, INTERSECT, \
+                 JOIN, LEFT, LIMIT, ORDER, UNION, WHERE, WINDOW, end of input"
+            )
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use anyhow::{anyhow, bail, Result};
+use itertools::Itertools;
+
+use crate::database;
+use crate::database::page::btree::data::serial_types::Value;
+use crate::engine::affinity::Affinity;
+use crate::engine::expression::{parse_numeric, Numeric};
+use crate::engine::function::json::{to_value, unescape, Json};
+
+use super::commands::{quote_identifier, Usage};
+use super::output::Mode;
+use super::Shell;
+
+const USAGE: &str = ".import FILE TABLE ?--csv|--json? ?--skip N?";
+
+/// How the file to import is written
+#[derive(Clone, Copy)]
+enum Format {
+    /// Comma-separated values, or values separated by another character
+    Csv(char),
+    /// An array of objects, one per row
+    Json,
+}
+
+/// A row read from a file, with the line it starts on, or its position in a JSON array
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub line: usize,
+    pub values: Vec<Value>,
+}
+
+impl Shell {
+    /// Reads the rows of a CSV or JSON file to add them to a table, created with columns named
+    /// by the first row when it doesn't exist. Rows with too few or too many values are
+    /// reported, as are those which can't be inserted, the others being inserted all at once.
+    /// The indexes of the table get their entries, but tables with triggers, CHECK
+    /// constraints, or partial indexes or indexes on expressions can't be imported into.
+    pub(super) fn import(&mut self, arguments: &[&str]) -> Result<()> {
+        let mut format = None;
+        let mut skip = 0;
+        let mut positional = Vec::new();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match *argument {
+                "--csv" | "-csv" => format = Some(Format::Csv(',')),
+                "--json" | "-json" => format = Some(Format::Json),
+                "--skip" | "-skip" => {
+                    skip = arguments
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or(Usage(USAGE))?
+                }
+                option if option.starts_with('-') => bail!(Usage(USAGE)),
+                argument => positional.push(argument),
+            }
+        }
+        let [path, table] = positional[..] else {
+            bail!(Usage(USAGE));
+        };
+        // like sqlite3, the format follows the output mode unless it is given
+        let format = match (format, self.output.mode) {
+            (Some(format), _) => format,
+            (None, Mode::Json) => Format::Json,
+            (None, Mode::Csv) => Format::Csv(','),
+            (None, _) => match self.output.column_separator.chars().collect_vec()[..] {
+                [separator] => Format::Csv(separator),
+                _ => bail!("multi-character column separators not allowed for import"),
+            },
+        };
+        let text =
+            std::fs::read_to_string(path).map_err(|_| anyhow!("cannot open \"{}\"", path))?;
+        let information = self.database.table_information(table).ok();
+        let (names, records) = match format {
+            Format::Csv(separator) => {
+                let mut records = read_csv(path, &text, separator).into_iter().skip(skip);
+                // the first row names the columns of a new table
+                let names = match information {
+                    Some(_) => None,
+                    None => records
+                        .next()
+                        .map(|header| header.values.iter().map(Value::to_string).collect()),
+                };
+                (names, records.collect_vec())
+            }
+            Format::Json => {
+                let (names, records) = read_json(path, &text)?;
+                (Some(names), records.into_iter().skip(skip).collect_vec())
+            }
+        };
+
+        let records = match (&information, &names) {
+            // the members of JSON objects go in the columns of the same name
+            (Some(information), Some(names)) => {
+                arrange(path, records, names, &information.column_names)
+            }
+            _ => records,
+        };
+        let names = names.unwrap_or_default();
+        if information.is_none() && names.is_empty() {
+            bail!("{}: empty file", path);
+        }
+        let mut transaction = self.database.begin()?;
+        let information = match information {
+            Some(information) => information,
+            None => {
+                let statement = create_table_statement(table, &names, &records);
+                transaction
+                    .create_table(table, &statement)
+                    .map_err(|e| anyhow!("{} failed:\n{}", statement, e))?
+            }
+        };
+        let width = information.column_names.len();
+        check_widths(path, &records, width);
+        for record in records {
+            let mut values = record.values;
+            values.truncate(width);
+            match transaction.insert(&information, values) {
+                Ok(_) => {}
+                // like sqlite3, rows breaking constraints are left out
+                Err(e @ (database::Error::Constraint(_) | database::Error::Mismatch)) => {
+                    eprintln!("{}:{}: INSERT failed: {}", path, record.line, e)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(transaction.commit()?)
+    }
+}
+
+/// Splits CSV text into records. Fields can be quoted to hold separators, newlines, and
+/// quotes, which are then written twice. Like sqlite3, quotes out of place are reported and
+/// kept as they are.
+pub fn read_csv(path: &str, text: &str, separator: char) -> Vec<Record> {
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    let mut records = Vec::new();
+    while chars.peek().is_some() {
+        let mut record = Record {
+            line,
+            values: Vec::new(),
+        };
+        loop {
+            let (field, end) = read_field(path, &mut chars, &mut line, separator);
+            record.values.push(Value::String(field));
+            if end {
+                break;
+            }
+        }
+        records.push(record);
+    }
+    records
+}
+
+/// Reads a CSV field, telling whether it ends its record.
+fn read_field(
+    path: &str,
+    chars: &mut Peekable<Chars>,
+    line: &mut usize,
+    separator: char,
+) -> (String, bool) {
+    let mut field = String::new();
+    if chars.next_if_eq(&'"').is_some() {
+        let start = *line;
+        loop {
+            match chars.next() {
+                Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                Some('"') => break,
+                Some(c) => {
+                    if c == '\n' {
+                        *line += 1;
+                    }
+                    field.push(c);
+                }
+                None => {
+                    eprintln!("{}:{}: unterminated \"-quoted field", path, start);
+                    return (field, true);
+                }
+            }
+        }
+        let ends = |c: &char| *c == separator || *c == '\n' || *c == '\r';
+        if chars.peek().is_some_and(|c| !ends(c)) {
+            eprintln!("{}:{}: unescaped \" character", path, line);
+            field.push('"');
+        }
+    }
+    loop {
+        match chars.next() {
+            Some(c) if c == separator => return (field, false),
+            Some('\n') => {
+                *line += 1;
+                break;
+            }
+            Some(c) => field.push(c),
+            None => break,
+        }
+    }
+    // records can end with CRLF
+    if field.ends_with('\r') {
+        field.pop();
+    }
+    (field, true)
+}
+
+/// Reads a JSON array of objects, one record per object, its values in the order of the
+/// names of the members of all objects, by first appearance. NULL stands for the missing
+/// members. Records are numbered by their position in the array.
+pub fn read_json(path: &str, text: &str) -> Result<(Vec<String>, Vec<Record>)> {
+    let Json::Array(elements) = Json::parse(text).map_err(|e| anyhow!("{}: {}", path, e))? else {
+        bail!("{}: expected an array of objects", path);
+    };
+    let mut names: Vec<String> = Vec::new();
+    let mut objects = Vec::new();
+    for (index, element) in elements.iter().enumerate() {
+        let Json::Object(members) = element else {
+            eprintln!("{}:{}: expected an object - skipped", path, index + 1);
+            continue;
+        };
+        let members = members
+            .iter()
+            .map(|(label, value)| (unescape(label), to_value(value)))
+            .collect_vec();
+        for (name, _) in &members {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        objects.push((index + 1, members));
+    }
+    let records = objects
+        .into_iter()
+        .map(|(line, members)| {
+            let mut values = vec![Value::Null; names.len()];
+            for (name, value) in members {
+                if let Some(index) = names.iter().position(|n| *n == name) {
+                    values[index] = value;
+                }
+            }
+            Record { line, values }
+        })
+        .collect();
+    Ok((names, records))
+}
+
+/// Puts the values of records, in the order of `names`, in that of the columns of a table,
+/// leaving out those no column is named after.
+fn arrange(path: &str, records: Vec<Record>, names: &[String], columns: &[String]) -> Vec<Record> {
+    let positions = names
+        .iter()
+        .map(|name| columns.iter().position(|c| c.eq_ignore_ascii_case(name)))
+        .collect_vec();
+    for (name, position) in names.iter().zip(&positions) {
+        if position.is_none() {
+            eprintln!("{}: no such column: {} - ignored", path, name);
+        }
+    }
+    records
+        .into_iter()
+        .map(|record| {
+            let mut values = vec![Value::Null; columns.len()];
+            for (value, position) in record.values.into_iter().zip(&positions) {
+                if let Some(position) = position {
+                    values[*position] = value;
+                }
+            }
+            Record {
+                line: record.line,
+                values,
+            }
+        })
+        .collect()
+}
+
+/// Reports the records not holding a value for each column, like sqlite3 does.
+fn check_widths(path: &str, records: &[Record], width: usize) {
+    for record in records {
+        let found = record.values.len();
+        if found != width {
+            let outcome = match found < width {
+                true => "filling the rest with NULL",
+                false => "extras ignored",
+            };
+            eprintln!(
+                "{}:{}: expected {} columns but found {} - {}",
+                path, record.line, width, found, outcome
+            );
+        }
+    }
+}
+
+/// The statement creating a table for records, its columns of the type their values suit.
+fn create_table_statement(table: &str, names: &[String], records: &[Record]) -> String {
+    let columns = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let values = records.iter().filter_map(|record| record.values.get(index));
+            let type_name = match infer_affinity(values) {
+                Affinity::Integer => "INTEGER",
+                Affinity::Real => "REAL",
+                _ => "TEXT",
+            };
+            format!("{} {}", quote_identifier(name), type_name)
+        })
+        .join(", ");
+    format!("CREATE TABLE {}(\n{})", quote_identifier(table), columns)
+}
+
+/// The affinity of a column holding values: INTEGER when they are all integers, REAL when
+/// they are all numbers, TEXT otherwise. NULL and empty text fit any column.
+pub fn infer_affinity<'v>(values: impl Iterator<Item = &'v Value>) -> Affinity {
+    let mut affinity = None;
+    for value in values {
+        let number = match value {
+            Value::Null => continue,
+            Value::String(text) if text.is_empty() => continue,
+            Value::String(text) => parse_numeric(text),
+            Value::Float64(real) => Some(Numeric::Real(*real)),
+            value => value.as_i64().map(Numeric::Integer),
+        };
+        affinity = match (affinity, number) {
+            (_, None) => return Affinity::Text,
+            (Some(Affinity::Real), _) | (_, Some(Numeric::Real(_))) => Some(Affinity::Real),
+            (_, Some(Numeric::Integer(_))) => Some(Affinity::Integer),
+        };
+    }
+    affinity.unwrap_or(Affinity::Text)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use crate::database::Database;
+
+    use super::*;
+
+    fn text(values: &[&str]) -> Vec<Value> {
+        values
+            .iter()
+            .map(|v| Value::String(v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_csv_records() {
+        let records = read_csv("f", "a,b\r\n1,\"x, \"\"y\"\"\nz\"\n\"q\"r,\n", ',');
+        assert_eq!(
+            records,
+            [
+                Record {
+                    line: 1,
+                    values: text(&["a", "b"])
+                },
+                Record {
+                    line: 2,
+                    values: text(&["1", "x, \"y\"\nz"])
+                },
+                Record {
+                    line: 4,
+                    values: text(&["q\"r", ""])
+                },
+            ]
+        );
+        assert_eq!(read_csv("f", "1|2", '|')[0].values, text(&["1", "2"]));
+        assert_eq!(read_csv("f", "\"open\n", ',')[0].values, text(&["open\n"]));
+    }
+
+    #[test]
+    fn reads_json_records() {
+        let (names, records) =
+            read_json("f", r#"[{"a": 1, "b": "x\ty"}, 3, {"c": null, "a": 2.5}]"#).unwrap();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(records[0].values[1], Value::String(String::from("x\ty")));
+        assert_eq!(
+            records[1],
+            Record {
+                line: 3,
+                values: vec![Value::Float64(2.5), Value::Null, Value::Null]
+            }
+        );
+        assert!(read_json("f", "{}").is_err());
+    }
+
+    #[test]
+    fn infers_column_types() {
+        let infer = |values: &[&str]| infer_affinity(text(values).iter());
+        assert_eq!(infer(&["1", " 2 ", ""]), Affinity::Integer);
+        assert_eq!(infer(&["1", "2.5"]), Affinity::Real);
+        assert_eq!(infer(&["1e3", "2"]), Affinity::Real);
+        assert_eq!(infer(&["1", "x"]), Affinity::Text);
+        assert_eq!(infer(&[]), Affinity::Text);
+        let records = [Record {
+            line: 2,
+            values: vec![Value::Int64(1), Value::String(String::from("a"))],
+        }];
+        assert_eq!(
+            create_table_statement(
+                "new table",
+                &[String::from("n"), String::from("order")],
+                &records
+            ),
+            "CREATE TABLE \"new table\"(\nn INTEGER, \"order\" TEXT)"
+        );
+    }
+
+    #[test]
+    fn imports_files_into_tables() {
+        let path = std::env::temp_dir().join(format!("resql-import-{}.csv", std::process::id()));
+        std::fs::write(&path, "n,name\n1,a\n2.5,b\n3\n").unwrap();
+        let database = Database::empty().unwrap();
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let mut shell = Shell::new(database, interrupt, Default::default());
+        let path = path.to_str().unwrap();
+        shell.import(&["--csv", path, "t"]).unwrap();
+        shell.import(&["--csv", path, "t"]).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let table = shell.database.table_information("t").unwrap();
+        assert_eq!(table.column_names, ["n", "name"]);
+        let rows: Vec<_> = shell
+            .database
+            .table_cursor(table.root_page as u32, false)
+            .map(|row| row.unwrap().1)
+            .collect();
+        let text = |text: &str| Value::String(String::from(text));
+        // the header is a row like any other once the table exists
+        assert_eq!(
+            rows,
+            [
+                vec![Value::Float64(1.0), text("a")],
+                vec![Value::Float64(2.5), text("b")],
+                vec![Value::Float64(3.0), Value::Null],
+                vec![text("n"), text("name")],
+                vec![Value::Float64(1.0), text("a")],
+                vec![Value::Float64(2.5), text("b")],
+                vec![Value::Float64(3.0), Value::Null],
+            ]
+        );
+    }
+}
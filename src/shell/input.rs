@@ -0,0 +1,348 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// The most lines kept in the history
+const HISTORY_SIZE: usize = 2000;
+
+/// What reading a line of input got
+pub enum Input {
+    Line(String),
+    /// The user pressed Ctrl-C, giving up on the line
+    Interrupted,
+    EndOfFile,
+}
+
+/// Reads a line, without its line ending.
+pub fn read_line(reader: &mut impl BufRead) -> io::Result<Input> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(Input::EndOfFile);
+    }
+    let length = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(length);
+    Ok(Input::Line(line))
+}
+
+/// A key pressed while editing a line
+#[derive(Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    KillToEnd,
+    KillToStart,
+    DeletePreviousWord,
+    Interrupt,
+    EndOfFile,
+    ClearScreen,
+    /// Keys and escape sequences without a meaning
+    Ignored,
+}
+
+/// Reads the next key from the bytes the terminal sends, if any.
+fn read_key(bytes: &mut impl Iterator<Item = u8>) -> Option<Key> {
+    let byte = bytes.next()?;
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x02 => Key::Left,
+        0x03 => Key::Interrupt,
+        0x04 => Key::EndOfFile,
+        0x05 => Key::End,
+        0x06 => Key::Right,
+        0x0b => Key::KillToEnd,
+        0x0c => Key::ClearScreen,
+        0x0e => Key::Down,
+        0x10 => Key::Up,
+        0x15 => Key::KillToStart,
+        0x17 => Key::DeletePreviousWord,
+        0x1b => read_escape_sequence(bytes),
+        byte if byte < 0x20 => Key::Ignored,
+        byte => {
+            // the leading byte of a UTF-8 sequence tells how many bytes follow it
+            let length = match byte.leading_ones() {
+                0 => 1,
+                ones @ 2..=4 => ones as usize,
+                _ => return Some(Key::Ignored),
+            };
+            let mut sequence = vec![byte];
+            sequence.extend(bytes.take(length - 1));
+            match std::str::from_utf8(&sequence).map(|s| s.chars().next()) {
+                Ok(Some(c)) => Key::Char(c),
+                _ => Key::Ignored,
+            }
+        }
+    };
+    Some(key)
+}
+
+/// Reads the rest of a sequence starting with ESC, sent for the arrow keys among others.
+fn read_escape_sequence(bytes: &mut impl Iterator<Item = u8>) -> Key {
+    match bytes.next() {
+        Some(b'[') => {
+            // parameters are digits and semicolons, ended by the byte telling the key
+            let mut parameters = String::new();
+            for byte in bytes.by_ref() {
+                match byte {
+                    b'0'..=b'9' | b';' => parameters.push(byte as char),
+                    b'~' => {
+                        return match parameters.as_str() {
+                            "1" | "7" => Key::Home,
+                            "3" => Key::Delete,
+                            "4" | "8" => Key::End,
+                            _ => Key::Ignored,
+                        }
+                    }
+                    final_byte => return cursor_key(final_byte),
+                }
+            }
+            Key::Ignored
+        }
+        Some(b'O') => bytes.next().map_or(Key::Ignored, cursor_key),
+        _ => Key::Ignored,
+    }
+}
+
+fn cursor_key(final_byte: u8) -> Key {
+    match final_byte {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        _ => Key::Ignored,
+    }
+}
+
+/// The line being edited and the position of the cursor in it, in characters
+#[derive(Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn new(text: &str) -> Line {
+        let chars: Vec<char> = text.chars().collect();
+        Line {
+            cursor: chars.len(),
+            chars,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    /// Applies a key editing the line, others being left to the editor.
+    fn edit(&mut self, key: &Key) {
+        match key {
+            Key::Char(c) => {
+                self.chars.insert(self.cursor, *c);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete | Key::EndOfFile if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.chars.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.chars.len(),
+            Key::KillToEnd => self.chars.truncate(self.cursor),
+            Key::KillToStart => {
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::DeletePreviousWord => {
+                // spaces before the cursor go along with the word they follow
+                let mut start = self.cursor;
+                while start > 0 && self.chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !self.chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.chars.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Turns off the line buffering and echoing of the terminal for as long as it is kept, so that
+/// keys can be read as they are pressed. `stty` is used to avoid depending on the layout of
+/// the terminal settings of each platform.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&[
+            "-icanon", "-echo", "-isig", "-ixon", "min", "1", "time", "0",
+        ])?;
+        Some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[self.saved.as_str()]);
+    }
+}
+
+/// Runs `stty` on the terminal of the standard input, returning what it printed.
+fn stty(arguments: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(arguments)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Reads lines typed in a terminal, which can be edited with the usual keys and recalled from
+/// the history with the up and down arrows. The history is kept in a file when given one.
+pub struct LineEditor {
+    history: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl LineEditor {
+    pub fn new(path: Option<PathBuf>) -> LineEditor {
+        let mut history: Vec<String> = match path.as_ref().map(File::open) {
+            Some(Ok(file)) => BufReader::new(file).lines().map_while(Result::ok).collect(),
+            _ => Vec::new(),
+        };
+        history.drain(..history.len().saturating_sub(HISTORY_SIZE));
+        LineEditor { history, path }
+    }
+
+    /// Adds a line to the history, and to its file.
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > HISTORY_SIZE {
+            self.history.remove(0);
+        }
+        if let Some(path) = &self.path {
+            let file = OpenOptions::new().create(true).append(true).open(path);
+            // the history is a convenience, failing to save it isn't worth reporting
+            if let Ok(mut file) = file {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    /// Reads a line after writing the prompt. Lines are read without editing when the terminal
+    /// can't be put in raw mode.
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Input> {
+        let Some(_raw_mode) = RawMode::enable() else {
+            print!("{}", prompt);
+            io::stdout().flush()?;
+            return read_line(&mut io::stdin().lock());
+        };
+        let stdin = io::stdin();
+        let mut bytes = stdin.lock().bytes().map_while(Result::ok);
+        let mut stdout = io::stdout();
+        let mut line = Line::default();
+        // browsing the history keeps the line being written, as if it were the last entry
+        let mut position = self.history.len();
+        let mut written = String::new();
+        loop {
+            render(&mut stdout, prompt, &line)?;
+            let key = read_key(&mut bytes).unwrap_or(Key::EndOfFile);
+            match key {
+                Key::Enter => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(Input::Line(line.text()));
+                }
+                Key::Interrupt => {
+                    write!(stdout, "^C\r\n")?;
+                    return Ok(Input::Interrupted);
+                }
+                Key::EndOfFile if line.chars.is_empty() => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(Input::EndOfFile);
+                }
+                Key::Up if position > 0 => {
+                    if position == self.history.len() {
+                        written = line.text();
+                    }
+                    position -= 1;
+                    line = Line::new(&self.history[position]);
+                }
+                Key::Down if position < self.history.len() => {
+                    position += 1;
+                    line = match self.history.get(position) {
+                        Some(entry) => Line::new(entry),
+                        None => Line::new(&written),
+                    };
+                }
+                Key::ClearScreen => write!(stdout, "\x1b[H\x1b[2J")?,
+                key => line.edit(&key),
+            }
+        }
+    }
+}
+
+/// Writes the prompt and the line over the current terminal line, leaving the cursor where it
+/// is in the line.
+fn render(stdout: &mut impl Write, prompt: &str, line: &Line) -> io::Result<()> {
+    write!(stdout, "\r{}{}\x1b[K", prompt, line.text())?;
+    let after_cursor = line.chars.len() - line.cursor;
+    if after_cursor > 0 {
+        write!(stdout, "\x1b[{}D", after_cursor)?;
+    }
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The line typed with the given bytes, up to the end of the line
+    fn typed(bytes: &[u8]) -> String {
+        let mut bytes = bytes.iter().copied();
+        let mut line = Line::default();
+        while let Some(key) = read_key(&mut bytes) {
+            if key == Key::Enter {
+                break;
+            }
+            line.edit(&key);
+        }
+        line.text()
+    }
+
+    #[test]
+    fn edits_lines_with_keys() {
+        assert_eq!(typed(b"abc\x1b[D\x7fd\r"), "adc");
+        assert_eq!(typed("pâté\x01\x06\x0b".as_bytes()), "p");
+        assert_eq!(typed(b"select 1 from t\x17\x17x\r"), "select 1 x");
+        assert_eq!(typed(b"abc\x1bOH\x1b[3~\x1b[4~!\r"), "bc!");
+        assert_eq!(typed(b"abc\x02\x15\x1b[1;5Dz\r"), "zc");
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// The flag set when the user presses Ctrl-C, which the database checks to stop the statement
+/// being run
+static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Makes Ctrl-C set the returned flag rather than end the process.
+pub fn install() -> Arc<AtomicBool> {
+    let flag = FLAG.get_or_init(|| Arc::new(AtomicBool::new(false)));
+    handle_interrupts();
+    flag.clone()
+}
+
+#[cfg(unix)]
+fn handle_interrupts() {
+    use std::os::raw::c_int;
+
+    const SIGINT: c_int = 2;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn on_interrupt(_signum: c_int) {
+        // only an atomic store, which is safe to do in a signal handler
+        if let Some(flag) = FLAG.get() {
+            flag.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // SAFETY: the handler only stores to an atomic flag initialized beforehand
+    unsafe {
+        signal(SIGINT, on_interrupt);
+    }
+}
+
+#[cfg(not(unix))]
+fn handle_interrupts() {}
@@ -0,0 +1,403 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::cli::OutputOptions;
+use crate::database::page::btree::data::serial_types::Value;
+use crate::engine::select::Rows;
+
+use self::columnar::expand_tabs;
+use self::format::{
+    csv_field, escape_controls, escape_html, json_string, json_value, sql_literal, sql_string,
+    tcl_string, Literal,
+};
+use super::commands::quote_identifier;
+
+mod columnar;
+pub(super) mod format;
+
+/// How result rows are printed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Values separated by the ASCII unit separator, rows by the record separator
+    Ascii,
+    /// A table drawn with box-drawing characters
+    Box,
+    /// Values aligned in columns
+    Column,
+    /// Comma-separated values, quoted as RFC 4180 has it
+    Csv,
+    /// The rows of an HTML table
+    Html,
+    /// Statements inserting the rows into a table
+    Insert,
+    /// An array with an object for each row
+    Json,
+    /// Each value on a line of its own, after the name of its column
+    Line,
+    /// Values separated by the column separator
+    List,
+    /// A Markdown table
+    Markdown,
+    /// A table drawn with box-drawing characters, holding values as SQL literals
+    QuotedBox,
+    /// Values as SQL literals
+    Quote,
+    /// A table drawn with ASCII characters
+    Table,
+    /// Values separated by tabs
+    Tabs,
+    /// Values as TCL list elements
+    Tcl,
+}
+
+impl Mode {
+    const NAMES: [(&'static str, Mode); 15] = [
+        ("ascii", Mode::Ascii),
+        ("box", Mode::Box),
+        ("column", Mode::Column),
+        ("csv", Mode::Csv),
+        ("html", Mode::Html),
+        ("insert", Mode::Insert),
+        ("json", Mode::Json),
+        ("line", Mode::Line),
+        ("list", Mode::List),
+        ("markdown", Mode::Markdown),
+        ("qbox", Mode::QuotedBox),
+        ("quote", Mode::Quote),
+        ("table", Mode::Table),
+        ("tabs", Mode::Tabs),
+        ("tcl", Mode::Tcl),
+    ];
+
+    pub fn parse(name: &str) -> Result<Mode> {
+        Mode::NAMES
+            .iter()
+            .find(|(mode_name, _)| *mode_name == name)
+            .map(|(_, mode)| *mode)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Mode::NAMES.iter().map(|(name, _)| *name).collect();
+                anyhow!("mode should be one of: {}", names.join(" "))
+            })
+    }
+
+    /// Whether the mode lines values up in columns, which takes all the rows to be known
+    fn is_columnar(self) -> bool {
+        matches!(
+            self,
+            Mode::Box | Mode::Column | Mode::Markdown | Mode::QuotedBox | Mode::Table
+        )
+    }
+
+    /// The mode as `.mode` shows it, along with the settings of sqlite3 it follows
+    pub fn describe(self) -> String {
+        let (name, options) = match self {
+            // both are shorthands for modes with other settings
+            Mode::Tabs => (Mode::List.to_string(), ""),
+            Mode::QuotedBox => (Mode::Box.to_string(), " --wrap 60 --wordwrap off --quote"),
+            mode if mode.is_columnar() => (mode.to_string(), " --wrap 60 --wordwrap off --noquote"),
+            mode => (mode.to_string(), ""),
+        };
+        format!("{}{} --escape ascii", name, options)
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = Mode::NAMES
+            .iter()
+            .find(|(_, mode)| mode == self)
+            .map_or("", |(name, _)| name);
+        write!(f, "{}", name)
+    }
+}
+
+/// Where and how the shell prints: the output mode and its settings, and the standard output
+/// or the file output was redirected to.
+pub struct Output {
+    pub mode: Mode,
+    headers: bool,
+    /// Whether headers were turned on or off, rather than left to the mode
+    headers_set: bool,
+    pub null_value: String,
+    pub column_separator: String,
+    pub row_separator: String,
+    /// The table the statements of the insert mode insert into
+    pub table: String,
+    destination: Box<dyn Write>,
+    /// How many more commands are printed to the destination of `.once`, if one is set
+    once: Option<u8>,
+}
+
+impl Default for Output {
+    fn default() -> Output {
+        Output {
+            mode: Mode::List,
+            headers: false,
+            headers_set: false,
+            null_value: String::new(),
+            column_separator: String::from("|"),
+            row_separator: String::from("\n"),
+            table: String::from("table"),
+            destination: Box::new(io::stdout()),
+            once: None,
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.destination.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.destination.flush()
+    }
+}
+
+impl Output {
+    /// Switches to a mode, along with the separators it uses. Like sqlite3, the column mode
+    /// shows headers unless they were turned off.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.table = String::from("table");
+        let separators = match mode {
+            Mode::Ascii => Some(("\x1f", "\x1e")),
+            Mode::Csv => Some((",", "\r\n")),
+            Mode::List => Some(("|", "\n")),
+            Mode::Quote => Some((",", "\n")),
+            Mode::Tabs => Some(("\t", "\n")),
+            Mode::Tcl => Some((" ", "\n")),
+            Mode::Column if !self.headers_set => {
+                self.headers = true;
+                None
+            }
+            _ => None,
+        };
+        if let Some((column, row)) = separators {
+            self.column_separator = column.to_string();
+            self.row_separator = row.to_string();
+        }
+    }
+
+    /// Applies the settings given as options on the command line. Like sqlite3's options,
+    /// modes there leave the headers and the row separator as they are.
+    pub fn configure(&mut self, options: OutputOptions) {
+        if let Some(mode) = options.mode {
+            self.mode = mode;
+            match mode {
+                Mode::Ascii => {
+                    self.column_separator = String::from("\x1f");
+                    self.row_separator = String::from("\x1e");
+                }
+                Mode::Csv | Mode::Quote => self.column_separator = String::from(","),
+                Mode::Tabs => self.column_separator = String::from("\t"),
+                Mode::Tcl => self.column_separator = String::from(" "),
+                _ => {}
+            }
+        }
+        if let Some(headers) = options.headers {
+            self.set_headers(headers);
+        }
+        if let Some(null_value) = options.null_value {
+            self.null_value = null_value;
+        }
+        if let Some(separator) = options.column_separator {
+            self.column_separator = separator;
+        }
+        if let Some(separator) = options.row_separator {
+            self.row_separator = separator;
+        }
+    }
+
+    /// Turns the headers naming the columns of rows on or off.
+    pub fn set_headers(&mut self, headers: bool) {
+        self.headers = headers;
+        self.headers_set = true;
+    }
+
+    /// Sends the output to a file, or back to the standard output. With `once`, only the
+    /// output of the next command goes to the file.
+    pub fn redirect(&mut self, path: Option<&str>, once: bool) -> Result<()> {
+        self.flush()?;
+        self.destination = match path {
+            Some(path) => match File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(_) => bail!("cannot open \"{}\"", path),
+            },
+            None => Box::new(io::stdout()),
+        };
+        // the count includes the .once command itself
+        self.once = once.then_some(2);
+        Ok(())
+    }
+
+    /// Called once a command is done with, to send the output back to the standard output once
+    /// the command following `.once` is.
+    pub fn end_command(&mut self) -> Result<()> {
+        self.flush()?;
+        match self.once {
+            Some(1) => self.redirect(None, false)?,
+            Some(count) => self.once = Some(count - 1),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Prints the rows of a statement with the given column names, in the output mode. Rows
+    /// are printed as they come, except in columns whose widths take them all to be known.
+    pub fn print_rows(&mut self, columns: &[String], rows: Rows) -> Result<()> {
+        if self.mode.is_columnar() {
+            return self.print_columns(columns, rows);
+        }
+        let mut count = 0;
+        for row in rows {
+            let row = row?;
+            if count == 0 && self.headers {
+                self.print_header(columns)?;
+            }
+            self.print_row(columns, &row, count)?;
+            count += 1;
+        }
+        if count > 0 && self.mode == Mode::Json {
+            writeln!(self.destination, "]")?;
+        }
+        Ok(())
+    }
+
+    fn print_header(&mut self, columns: &[String]) -> Result<()> {
+        let names: Vec<String> = match self.mode {
+            Mode::Ascii => columns.to_vec(),
+            Mode::Csv => columns
+                .iter()
+                .map(|name| csv_field(name, &self.column_separator))
+                .collect(),
+            Mode::Html => {
+                write!(self.destination, "<TR>")?;
+                for name in columns {
+                    writeln!(self.destination, "<TH>{}</TH>", escape_html(name))?;
+                }
+                writeln!(self.destination, "</TR>")?;
+                return Ok(());
+            }
+            Mode::Quote => columns
+                .iter()
+                .map(|name| sql_string(name, Literal::Quote))
+                .collect(),
+            Mode::Tcl => columns.iter().map(|name| tcl_string(name)).collect(),
+            Mode::List | Mode::Tabs => columns.iter().map(|name| escape_controls(name)).collect(),
+            // the other modes name columns in each row, or in columns
+            _ => return Ok(()),
+        };
+        write!(
+            self.destination,
+            "{}{}",
+            names.join(&self.column_separator),
+            self.row_separator
+        )?;
+        Ok(())
+    }
+
+    /// Prints a row, the one at `index` in the rows of a statement.
+    fn print_row(&mut self, columns: &[String], row: &[Value], index: usize) -> Result<()> {
+        let fields: Vec<String> = row.iter().map(|value| self.field(value)).collect();
+        match self.mode {
+            Mode::Html => {
+                write!(self.destination, "<TR>")?;
+                for field in fields {
+                    writeln!(self.destination, "<TD>{}</TD>", field)?;
+                }
+                writeln!(self.destination, "</TR>")?;
+            }
+            Mode::Insert => {
+                let columns = if self.headers {
+                    let names: Vec<String> =
+                        columns.iter().map(|name| quote_identifier(name)).collect();
+                    format!("({})", names.join(","))
+                } else {
+                    String::new()
+                };
+                writeln!(
+                    self.destination,
+                    "INSERT INTO {}{} VALUES({});",
+                    quote_identifier(&self.table),
+                    columns,
+                    fields.join(",")
+                )?;
+            }
+            Mode::Json => {
+                let members: Vec<String> = columns
+                    .iter()
+                    .zip(fields)
+                    .map(|(name, field)| format!("{}:{}", json_string(name, false), field))
+                    .collect();
+                let start = if index == 0 { "[" } else { ",\n" };
+                write!(self.destination, "{}{{{}}}", start, members.join(","))?;
+            }
+            Mode::Line => {
+                if index > 0 {
+                    writeln!(self.destination)?;
+                }
+                let width = columns
+                    .iter()
+                    .map(|name| name.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(5);
+                for (name, field) in columns.iter().zip(fields) {
+                    writeln!(self.destination, "{:>width$} = {}", name, field)?;
+                }
+            }
+            _ => write!(
+                self.destination,
+                "{}{}",
+                fields.join(&self.column_separator),
+                self.row_separator
+            )?,
+        }
+        Ok(())
+    }
+
+    /// Prints the rows of a statement in columns, after a header which only the column mode
+    /// can leave out.
+    fn print_columns(&mut self, columns: &[String], rows: Rows) -> Result<()> {
+        let mut fields = Vec::new();
+        for row in rows {
+            let row: Vec<String> = row?.iter().map(|value| self.field(value)).collect();
+            fields.push(row);
+        }
+        if fields.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<String> = columns.iter().map(|name| escape_controls(name)).collect();
+        let header = self.headers || self.mode != Mode::Column;
+        let text = columnar::render(self.mode, &names, header, &fields);
+        self.destination.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    /// The text a value is printed as in the output mode
+    fn field(&self, value: &Value) -> String {
+        let text = || match value {
+            Value::Null => self.null_value.clone(),
+            value => value.to_string(),
+        };
+        match self.mode {
+            Mode::Ascii => text(),
+            Mode::Csv if value.is_null() => text(),
+            Mode::Csv => csv_field(&text(), &self.column_separator),
+            Mode::Html => escape_html(&text()),
+            Mode::Insert => sql_literal(value, Literal::Insert),
+            Mode::Json => json_value(value),
+            Mode::Quote => sql_literal(value, Literal::Quote),
+            Mode::QuotedBox => expand_tabs(&sql_literal(value, Literal::QuotedBox)),
+            Mode::Tcl => tcl_string(&text()),
+            Mode::Box | Mode::Column | Mode::Markdown | Mode::Table => {
+                escape_controls(&expand_tabs(&text()))
+            }
+            Mode::Line | Mode::List | Mode::Tabs => escape_controls(&text()),
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use super::Mode;
+
+/// The width values are wrapped at
+const WRAP_WIDTH: usize = 60;
+/// The distance between tab stops
+const TAB_WIDTH: usize = 8;
+
+/// The characters a table is drawn with
+struct Borders {
+    horizontal: &'static str,
+    vertical: &'static str,
+    /// The left, inner and right joints of the top line
+    top: Option<[&'static str; 3]>,
+    /// The joints of the line under the header
+    middle: [&'static str; 3],
+    bottom: Option<[&'static str; 3]>,
+    /// Whether rows are told apart by a line when some of them take several lines
+    separate_rows: bool,
+}
+
+const ASCII: Borders = Borders {
+    horizontal: "-",
+    vertical: "|",
+    top: Some(["+", "+", "+"]),
+    middle: ["+", "+", "+"],
+    bottom: Some(["+", "+", "+"]),
+    separate_rows: true,
+};
+
+const BOX: Borders = Borders {
+    horizontal: "─",
+    vertical: "│",
+    top: Some(["┌", "┬", "┐"]),
+    middle: ["├", "┼", "┤"],
+    bottom: Some(["└", "┴", "┘"]),
+    separate_rows: true,
+};
+
+const MARKDOWN: Borders = Borders {
+    horizontal: "-",
+    vertical: "|",
+    top: None,
+    middle: ["|", "|", "|"],
+    bottom: None,
+    separate_rows: false,
+};
+
+/// Lays out the fields of rows in columns as wide as their widest line, in one of the
+/// columnar modes. Fields are split on newlines and wrapped. Like sqlite3, columns are as
+/// wide as their names even when the column mode leaves out the header.
+pub fn render(mode: Mode, columns: &[String], header: bool, rows: &[Vec<String>]) -> String {
+    let names: Vec<Vec<String>> = columns.iter().map(|name| lines(name)).collect();
+    let rows: Vec<Vec<Vec<String>>> = rows
+        .iter()
+        .map(|row| row.iter().map(|field| lines(field)).collect())
+        .collect();
+    let mut widths = vec![0; columns.len()];
+    for row in std::iter::once(&names).chain(&rows) {
+        for (width, field) in widths.iter_mut().zip(row) {
+            let widest = field.iter().map(|line| display_width(line)).max();
+            *width = (*width).max(widest.unwrap_or(0));
+        }
+    }
+    let multiline = rows.iter().flatten().any(|field| field.len() > 1);
+
+    let mut text = String::new();
+    let borders = match mode {
+        Mode::Column => {
+            if header {
+                push_lines(&mut text, &names, &widths, "", "  ", "", false);
+                let dashes: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+                text.push_str(&dashes.join("  "));
+                text.push('\n');
+            }
+            for (index, row) in rows.iter().enumerate() {
+                if index > 0 && multiline {
+                    text.push('\n');
+                }
+                push_lines(&mut text, row, &widths, "", "  ", "", false);
+            }
+            return text;
+        }
+        Mode::Markdown => MARKDOWN,
+        Mode::Table => ASCII,
+        _ => BOX,
+    };
+    let vertical = borders.vertical;
+    let (start, separator, end) = (
+        format!("{} ", vertical),
+        format!(" {} ", vertical),
+        format!(" {}", vertical),
+    );
+    let border = |text: &mut String, joints: [&str; 3]| {
+        let lines: Vec<String> = widths
+            .iter()
+            .map(|width| borders.horizontal.repeat(width + 2))
+            .collect();
+        text.push_str(joints[0]);
+        text.push_str(&lines.join(joints[1]));
+        text.push_str(joints[2]);
+        text.push('\n');
+    };
+    if let Some(top) = borders.top {
+        border(&mut text, top);
+    }
+    push_lines(&mut text, &names, &widths, &start, &separator, &end, true);
+    border(&mut text, borders.middle);
+    for (index, row) in rows.iter().enumerate() {
+        if index > 0 && multiline && borders.separate_rows {
+            border(&mut text, borders.middle);
+        }
+        push_lines(&mut text, row, &widths, &start, &separator, &end, false);
+    }
+    if let Some(bottom) = borders.bottom {
+        border(&mut text, bottom);
+    }
+    text
+}
+
+/// Adds the lines taken by a row, its fields padded to the width of their columns, or
+/// centered in them.
+fn push_lines(
+    text: &mut String,
+    row: &[Vec<String>],
+    widths: &[usize],
+    start: &str,
+    separator: &str,
+    end: &str,
+    centered: bool,
+) {
+    let height = row.iter().map(Vec::len).max().unwrap_or(1);
+    for index in 0..height {
+        let fields: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(field, width)| {
+                let line = field.get(index).map_or("", String::as_str);
+                let padding = width - display_width(line);
+                let left = if centered { padding / 2 } else { 0 };
+                format!("{}{}{}", " ".repeat(left), line, " ".repeat(padding - left))
+            })
+            .collect();
+        text.push_str(start);
+        text.push_str(&fields.join(separator));
+        text.push_str(end);
+        text.push('\n');
+    }
+}
+
+/// Replaces the tabs of text with the spaces up to the next tab stop. Like sqlite3, this is
+/// done before control characters are escaped, so they take no room.
+pub fn expand_tabs(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut width = 0;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = TAB_WIDTH - width % TAB_WIDTH;
+                expanded.push_str(&" ".repeat(spaces));
+                width += spaces;
+            }
+            '\n' => {
+                expanded.push(c);
+                width = 0;
+            }
+            c => {
+                expanded.push(c);
+                width += char_width(c);
+            }
+        }
+    }
+    expanded
+}
+
+/// The lines a field is shown on: those of its text, wrapped at the wrap width.
+fn lines(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    for text_line in text.split('\n') {
+        let mut line = String::new();
+        let mut width = 0;
+        for c in text_line.chars() {
+            if width + char_width(c) > WRAP_WIDTH && width > 0 {
+                lines.push(std::mem::take(&mut line));
+                width = 0;
+            }
+            line.push(c);
+            width += char_width(c);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// How many columns of a terminal text takes
+fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// How many columns of a terminal a character takes: none for control characters and
+/// combining marks, two for the wide characters of East Asian scripts and emoji.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x00..=0x1f
+        | 0x0300..=0x036f
+        | 0x0483..=0x0489
+        | 0x0591..=0x05bd
+        | 0x0610..=0x061a
+        | 0x064b..=0x065f
+        | 0x200b..=0x200f
+        | 0x20d0..=0x20ff
+        | 0xfe00..=0xfe0f
+        | 0xfe20..=0xfe2f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders_columns_like_sqlite3() {
+        let header = [String::from("n"), String::from("text")];
+        let rows = [
+            vec![String::from("1"), String::from("日本")],
+            vec![String::from("22"), String::from("two\nlines")],
+        ];
+        assert_eq!(
+            render(Mode::Column, &header, true, &rows),
+            "n   text \n--  -----\n1   日本 \n\n22  two  \n    lines\n"
+        );
+        assert_eq!(
+            render(Mode::Table, &header, true, &rows[..1]),
+            "+---+------+\n| n | text |\n+---+------+\n| 1 | 日本 |\n+---+------+\n"
+        );
+        assert_eq!(
+            render(Mode::Box, &header, true, &rows),
+            "┌────┬───────┐\n\
+             │ n  │ text  │\n\
+             ├────┼───────┤\n\
+             │ 1  │ 日本  │\n\
+             ├────┼───────┤\n\
+             │ 22 │ two   │\n\
+             │    │ lines │\n\
+             └────┴───────┘\n"
+        );
+        assert_eq!(
+            render(Mode::Markdown, &header, true, &rows[1..]),
+            "| n  | text  |\n|----|-------|\n| 22 | two   |\n|    | lines |\n"
+        );
+        assert_eq!(
+            render(Mode::Column, &header, false, &rows[..1]),
+            "1  日本\n"
+        );
+    }
+
+    #[test]
+    fn wraps_and_expands_tabs() {
+        assert_eq!(
+            expand_tabs("a\tbc\td\n\x01\tb"),
+            "a       bc      d\n\x01        b"
+        );
+        let long = "x".repeat(59) + " abc";
+        assert_eq!(lines(&long), ["x".repeat(59) + " ", String::from("abc")]);
+        assert_eq!(lines(""), [""]);
+    }
+}
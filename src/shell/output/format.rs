@@ -0,0 +1,225 @@
+use crate::database::page::btree::data::serial_types::{format_real, format_real_exact, Value};
+
+/// The modes writing values as SQL literals, which sqlite3 writes a little differently
+#[derive(Clone, Copy, PartialEq)]
+pub enum Literal {
+    Insert,
+    Quote,
+    QuotedBox,
+}
+
+/// Shows the control characters of text other than tabs and newlines as `^` followed by the
+/// letter of their key, like sqlite3 does so as not to have them drive the terminal.
+pub fn escape_controls(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| match c {
+            '\t' | '\n' => vec![c],
+            c if c < ' ' => vec!['^', (c as u8 + b'@') as char],
+            c => vec![c],
+        })
+        .collect()
+}
+
+/// A CSV field, quoted when it is empty or holds anything but printable ASCII characters other
+/// than quotes and spaces, or the separator
+pub fn csv_field(text: &str, separator: &str) -> String {
+    let needs_quotes = text.is_empty()
+        || text
+            .chars()
+            .any(|c| c <= ' ' || c >= '\x7f' || c == '"' || c == '\'')
+        || (!separator.is_empty() && text.contains(separator));
+    if needs_quotes {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Text as a TCL string, with C escapes
+pub fn tcl_string(text: &str) -> String {
+    let mut string = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\\' => string.push_str("\\\\"),
+            '"' => string.push_str("\\\""),
+            '\t' => string.push_str("\\t"),
+            '\n' => string.push_str("\\n"),
+            '\r' => string.push_str("\\r"),
+            '\x0c' => string.push_str("\\f"),
+            c if c < ' ' || c == '\x7f' => string.push_str(&format!("\\{:03o}", c as u32)),
+            c => string.push(c),
+        }
+    }
+    string.push('"');
+    string
+}
+
+/// A JSON string. With `ascii`, characters beyond ASCII are escaped too, which is how the
+/// bytes of blobs are written as the characters of the same code.
+pub fn json_string(text: &str, ascii: bool) -> String {
+    let mut string = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            '\x08' => string.push_str("\\b"),
+            '\x0c' => string.push_str("\\f"),
+            '\n' => string.push_str("\\n"),
+            '\r' => string.push_str("\\r"),
+            '\t' => string.push_str("\\t"),
+            c if c < ' ' || c == '\x7f' || (ascii && !c.is_ascii()) => {
+                string.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => string.push(c),
+        }
+    }
+    string.push('"');
+    string
+}
+
+/// A value as JSON, numbers written so that they read back the same
+pub fn json_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("null"),
+        Value::Float64(r) if r.is_infinite() => infinity(*r),
+        Value::Float64(r) => format_real_exact(*r),
+        Value::String(s) => json_string(s, false),
+        Value::Blob(b) => json_string(
+            &b.iter().map(|byte| *byte as char).collect::<String>(),
+            true,
+        ),
+        value => value.to_string(),
+    }
+}
+
+/// A value as an SQL literal, numbers written so that they read back the same, but in boxes
+pub fn sql_literal(value: &Value, literal: Literal) -> String {
+    match value {
+        Value::Null => String::from("NULL"),
+        Value::Float64(r) => match literal {
+            Literal::Insert if r.is_infinite() => infinity(*r),
+            Literal::Insert | Literal::Quote => format_real_exact(*r),
+            Literal::QuotedBox => format_real(*r),
+        },
+        Value::String(s) => sql_string(s, literal),
+        Value::Blob(b) => {
+            let prefix = if literal == Literal::QuotedBox {
+                'x'
+            } else {
+                'X'
+            };
+            let hex: String = b.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("{}'{}'", prefix, hex)
+        }
+        value => value.to_string(),
+    }
+}
+
+/// Text as an SQL string. Control characters other than tabs and newlines, and those too in
+/// the insert mode, call for the escapes of `unistr()`. sqlite3 then escapes all control
+/// characters and backslashes too, but in the quote mode.
+pub fn sql_string(text: &str, literal: Literal) -> String {
+    let special = |c: char| c < ' ' && (literal == Literal::Insert || (c != '\t' && c != '\n'));
+    if !text.chars().any(special) {
+        return format!("'{}'", text.replace('\'', "''"));
+    }
+    let quote = literal == Literal::Quote;
+    let mut string = String::from("unistr('");
+    for c in text.chars() {
+        match c {
+            '\'' => string.push_str("''"),
+            '\\' if !quote => string.push_str("\\\\"),
+            c if special(c) || (!quote && c < ' ') => {
+                string.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => string.push(c),
+        }
+    }
+    string.push_str("')");
+    string
+}
+
+/// An infinite real as sqlite3 writes it, as a number too large to be read as anything else
+fn infinity(real: f64) -> String {
+    String::from(if real > 0.0 { "9.0e+999" } else { "-9.0e+999" })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_values_like_sqlite3() {
+        assert_eq!(escape_controls("a\x01\tb\r\n\x1b"), "a^A\tb^M\n^[");
+        assert_eq!(csv_field("a b", ","), "\"a b\"");
+        assert_eq!(csv_field("say \"hi\"", ","), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("a|b;c", ","), "a|b;c");
+        assert_eq!(csv_field("a;b", ";"), "\"a;b\"");
+        assert_eq!(csv_field("", ","), "\"\"");
+        assert_eq!(csv_field("pâté", ","), "\"pâté\"");
+        assert_eq!(
+            escape_html("<a href='x'>&</a>"),
+            "&lt;a href=&#39;x&#39;&gt;&amp;&lt;/a&gt;"
+        );
+        assert_eq!(
+            tcl_string("a\\b\"$\n\x7f\x0b"),
+            "\"a\\\\b\\\"$\\n\\177\\013\""
+        );
+        assert_eq!(json_string("é\"\x01\x7f", false), "\"é\\\"\\u0001\\u007f\"");
+        assert_eq!(
+            json_value(&Value::Blob(vec![0x01, 0x41, 0xff])),
+            "\"\\u0001A\\u00ff\""
+        );
+        assert_eq!(json_value(&Value::Float64(f64::NEG_INFINITY)), "-9.0e+999");
+        assert_eq!(json_value(&Value::Int64(-3)), "-3");
+        assert_eq!(json_value(&Value::Null), "null");
+
+        let text = Value::String(String::from("it's\t\\\x01"));
+        assert_eq!(
+            sql_literal(&text, Literal::Quote),
+            "unistr('it''s\t\\\\u0001')"
+        );
+        assert_eq!(
+            sql_literal(&text, Literal::Insert),
+            "unistr('it''s\\u0009\\\\\\u0001')"
+        );
+        let blob = Value::Blob(vec![0xab, 0x01]);
+        assert_eq!(
+            sql_literal(&text, Literal::QuotedBox),
+            "unistr('it''s\\u0009\\\\\\u0001')"
+        );
+        let text = Value::String(String::from("a\nb"));
+        assert_eq!(sql_literal(&text, Literal::QuotedBox), "'a\nb'");
+        assert_eq!(sql_literal(&text, Literal::Insert), "unistr('a\\u000ab')");
+        assert_eq!(sql_literal(&blob, Literal::Quote), "X'ab01'");
+        assert_eq!(sql_literal(&blob, Literal::QuotedBox), "x'ab01'");
+        assert_eq!(
+            sql_literal(&Value::Float64(0.1), Literal::Quote),
+            "0.1000000000000000055"
+        );
+        assert_eq!(sql_literal(&Value::Float64(0.1), Literal::QuotedBox), "0.1");
+        assert_eq!(
+            sql_literal(&Value::Float64(f64::INFINITY), Literal::Quote),
+            "Inf"
+        );
+        assert_eq!(
+            sql_literal(&Value::Float64(f64::INFINITY), Literal::Insert),
+            "9.0e+999"
+        );
+    }
+}
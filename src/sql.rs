@@ -1,12 +1,86 @@
+use crate::engine::affinity::Affinity;
+
+pub use self::error::ParseError;
+
+mod error;
+
 // NOTE:this might be useless
+#[derive(Debug, PartialEq)]
 pub enum Statement {
-    SelectStatement(SelectStatement),
+    SelectStatement(Box<SelectStatement>),
     CreateTableStatement(CreateTableStatement),
+    CreateIndexStatement(CreateIndexStatement),
 }
 
+/// Simple representation of a SQL CREATE TABLE statement
+/// ```sql
+/// CREATE TABLE apples (id integer primary key autoincrement, name text, color text);
+/// ```
+#[derive(Debug, PartialEq)]
 pub struct CreateTableStatement {
     pub table_name: String,
-    // todo
+    pub columns: Vec<ColumnDefinition>,
+    pub constraints: Vec<TableConstraint>,
+    pub without_rowid: bool,
+}
+
+/// A single column declaration inside a CREATE TABLE statement
+#[derive(Debug, PartialEq)]
+pub struct ColumnDefinition {
+    pub name: String,
+    pub type_name: Option<String>,
+    pub constraints: Vec<ColumnConstraint>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ColumnConstraint {
+    PrimaryKey {
+        order: Option<SortOrder>,
+        autoincrement: bool,
+    },
+    NotNull,
+    Null,
+    Unique,
+    Check,
+    Default(Expression),
+    Collate(String),
+    References(String),
+    /// `GENERATED ALWAYS AS (expression)`: the expression is none when it can't be parsed.
+    /// Only STORED columns are held in table records, VIRTUAL ones (the default) being worked
+    /// out when read.
+    Generated {
+        expression: Option<Expression>,
+        stored: bool,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TableConstraint {
+    PrimaryKey(Vec<IndexedColumn>),
+    Unique(Vec<IndexedColumn>),
+    Check,
+    ForeignKey,
+}
+
+/// Simple representation of a SQL CREATE INDEX statement
+/// ```sql
+/// CREATE INDEX idx_apples_color ON apples (color);
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct CreateIndexStatement {
+    pub index_name: String,
+    pub table_name: String,
+    pub unique: bool,
+    pub columns: Vec<IndexedColumn>,
+    pub partial: bool,
+}
+
+/// A column taking part in an index or a PRIMARY KEY/UNIQUE table constraint
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedColumn {
+    pub name: String,
+    pub collation: Option<String>,
+    pub order: SortOrder,
 }
 
 /// Simple representation of a SQL SELECT statement
@@ -14,116 +88,1495 @@ pub struct CreateTableStatement {
 /// SELECT name, color FROM apples WHERE color='blue';
 /// ```
 /// will be parsed into:
-/// ```rust
+/// ```ignore
 /// SelectStatement {
-///    selectables: vec![Selectable::Column("name"), Selectable::Column("color")],
-///    from_target: Targetable::TableOrView("apples"),
-///    conditions: vec![Condition {column: "color", value: "blue"}]
+///    with: None,
+///    distinct: false,
+///    selectables: vec![
+///        Selectable::Expression { expression: Expression::Column { table: None, name: "name" }, .. },
+///        Selectable::Expression { expression: Expression::Column { table: None, name: "color" }, .. },
+///    ],
+///    from_target: Some(Targetable::TableOrView { name: "apples", alias: None }),
+///    where_clause: Some(Expression::Binary {
+///        left: Expression::Column { table: None, name: "color" },
+///        operator: BinaryOperator::Equal,
+///        right: Expression::Literal(Literal::String("blue")),
+///    }),
+///    group_by: vec![],
+///    having: None,
+///    windows: vec![],
+///    compound: vec![],
+///    order_by: vec![],
+///    limit: None,
 /// }
 /// ```
 ///
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SelectStatement {
+    pub with: Option<WithClause>,
+    /// SELECT DISTINCT only returns one of each set of equal rows
+    pub distinct: bool,
     pub selectables: Vec<Selectable>,
-    pub from_target: Targetable,
-    pub conditions: Vec<Condition>,
+    /// Without a FROM clause, the statement selects a single row
+    pub from_target: Option<Targetable>,
+    pub where_clause: Option<Expression>,
+    pub group_by: Vec<Expression>,
+    pub having: Option<Expression>,
+    /// The windows named by the WINDOW clause, which window functions can refer to
+    pub windows: Vec<NamedWindow>,
+    /// The SELECTs combined with this one, from left to right. The ORDER BY and LIMIT clauses
+    /// then apply to the combined rows.
+    pub compound: Vec<CompoundTerm>,
+    pub order_by: Vec<OrderingTerm>,
+    pub limit: Option<Limit>,
+}
+
+impl SelectStatement {
+    /// The expressions the statement is directly made of, those of the ON clauses of its joins
+    /// included. The expressions of subqueries and of the SELECTs compounded with the statement
+    /// are not part of them.
+    pub fn expressions(&self) -> Vec<&Expression> {
+        let mut expressions: Vec<&Expression> = self
+            .from_target
+            .iter()
+            .flat_map(Targetable::expressions)
+            .collect();
+        for selectable in &self.selectables {
+            if let Selectable::Expression { expression, .. } = selectable {
+                expressions.push(expression);
+            }
+        }
+        expressions.extend(&self.where_clause);
+        expressions.extend(&self.group_by);
+        expressions.extend(&self.having);
+        for window in &self.windows {
+            expressions.extend(window.window.expressions());
+        }
+        expressions.extend(self.order_by.iter().map(|term| &term.expression));
+        if let Some(limit) = &self.limit {
+            expressions.push(&limit.count);
+            expressions.extend(&limit.offset);
+        }
+        expressions
+    }
+
+    /// Mutable access to the expressions listed by `expressions`, in the same order
+    pub fn expressions_mut(&mut self) -> Vec<&mut Expression> {
+        let mut expressions: Vec<&mut Expression> = self
+            .from_target
+            .iter_mut()
+            .flat_map(Targetable::expressions_mut)
+            .collect();
+        for selectable in &mut self.selectables {
+            if let Selectable::Expression { expression, .. } = selectable {
+                expressions.push(expression);
+            }
+        }
+        expressions.extend(&mut self.where_clause);
+        expressions.extend(&mut self.group_by);
+        expressions.extend(&mut self.having);
+        for window in &mut self.windows {
+            expressions.extend(window.window.expressions_mut());
+        }
+        expressions.extend(self.order_by.iter_mut().map(|term| &mut term.expression));
+        if let Some(limit) = &mut self.limit {
+            expressions.push(&mut limit.count);
+            expressions.extend(&mut limit.offset);
+        }
+        expressions
+    }
 }
 
-/// Any column or COUNT(*) in a SELECT statement
+/// A WITH clause, naming the results of queries so that the statement can read them like tables
 /// ```sql
-/// SELECT name, COUNT(*) FROM apples;
+/// WITH RECURSIVE counter(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM counter LIMIT 10)
+/// SELECT n FROM counter;
 /// ```
 /// will be parsed into:
-/// ```rust
-/// vec![Selectable::Column("name"), Selectable::CountStar]
+/// ```ignore
+/// WithClause {
+///     recursive: true,
+///     tables: vec![CommonTableExpression {
+///         name: "counter",
+///         columns: vec!["n"],
+///         query: SelectStatement { compound: vec![CompoundTerm { .. }], .. },
+///     }],
+/// }
 /// ```
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithClause {
+    /// SQLite lets common table expressions refer to themselves whether or not RECURSIVE is
+    /// given
+    pub recursive: bool,
+    pub tables: Vec<CommonTableExpression>,
+}
+
+impl WithClause {
+    /// The queries defining the common table expressions, in the order they appear in
+    pub fn queries(&self) -> Vec<&SelectStatement> {
+        self.tables
+            .iter()
+            .map(|table| table.query.as_ref())
+            .collect()
+    }
+
+    /// Mutable access to the queries listed by `queries`, in the same order
+    pub fn queries_mut(&mut self) -> Vec<&mut SelectStatement> {
+        self.tables
+            .iter_mut()
+            .map(|table| table.query.as_mut())
+            .collect()
+    }
+}
+
+/// A single table of a WITH clause, `name(columns) AS (query)`. When the query is a compound
+/// SELECT, the SELECTs following UNION may read from the table itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommonTableExpression {
+    pub name: String,
+    /// The names of the columns, when they aren't those of the result columns of the query
+    pub columns: Vec<String>,
+    pub query: Box<SelectStatement>,
+}
+
+/// A SELECT combined with the ones before it in a compound SELECT
+/// ```sql
+/// SELECT name FROM apples UNION ALL SELECT name FROM pears;
+/// ```
+/// will be parsed into a statement selecting from `apples`, holding:
+/// ```ignore
+/// CompoundTerm {
+///     operator: CompoundOperator::UnionAll,
+///     select: SelectStatement { from_target: Some(Targetable::TableOrView { name: "pears", .. }), .. },
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompoundTerm {
+    pub operator: CompoundOperator,
+    /// A SELECT without WITH, ORDER BY or LIMIT clauses, nor SELECTs compounded with it
+    pub select: SelectStatement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompoundOperator {
+    Union,
+    UnionAll,
+    Intersect,
+    Except,
+}
+
+impl std::fmt::Display for CompoundOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CompoundOperator::Union => "UNION",
+            CompoundOperator::UnionAll => "UNION ALL",
+            CompoundOperator::Intersect => "INTERSECT",
+            CompoundOperator::Except => "EXCEPT",
+        })
+    }
+}
+
+/// A result column of a SELECT statement: an expression, `*` or `table.*`
+/// ```sql
+/// SELECT name AS n, COUNT(*), a.* FROM apples AS a;
+/// ```
+/// will be parsed into:
+/// ```ignore
+/// vec![
+///     Selectable::Expression {
+///         expression: Expression::Column { table: None, name: "name" },
+///         alias: Some("n"),
+///         text: "name",
+///     },
+///     Selectable::Expression {
+///         expression: Expression::Function {
+///             name: "COUNT",
+///             distinct: false,
+///             arguments: FunctionArguments::Star,
+///         },
+///         alias: None,
+///         text: "COUNT(*)",
+///     },
+///     Selectable::TableStar("a"),
+/// ]
+/// ```
+#[derive(Debug, Clone, PartialEq)]
 pub enum Selectable {
-    Column(String),
-    CountStar,
+    Expression {
+        expression: Expression,
+        alias: Option<String>,
+        /// The expression as written, which names the result column when it has no alias and
+        /// isn't a column reference
+        text: String,
+    },
+    Star,
+    /// The columns of a single table of the FROM clause, as selected by `*`
+    TableStar(String),
 }
 
-/// Any table or view in a FROM clause
+/// What a FROM clause reads from: a table or view, a subquery, or those joined together
 /// ```sql
-/// SELECT name FROM apples;
+/// SELECT a.name FROM apples AS a JOIN colors USING (color);
 /// ```
 /// will be parsed into:
-/// ```rust
-/// Targetable::TableOrView("apples")
+/// ```ignore
+/// Targetable::Join {
+///     left: Targetable::TableOrView { name: "apples", alias: Some("a") },
+///     operator: JoinOperator::Inner,
+///     right: Targetable::TableOrView { name: "colors", alias: None },
+///     constraint: Some(JoinConstraint::Using(vec!["color"])),
+/// }
 /// ```
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Targetable {
-    TableOrView(String),
-    Other(String),
+    TableOrView {
+        name: String,
+        alias: Option<String>,
+    },
+    /// A subquery, such as `(SELECT color, count(*) FROM apples GROUP BY color) AS c`
+    Subquery {
+        query: Box<SelectStatement>,
+        alias: Option<String>,
+    },
+    /// A table-valued function, such as `json_each(apples.tags) AS tag`
+    Function {
+        name: String,
+        arguments: Vec<Expression>,
+        alias: Option<String>,
+    },
+    /// Joins are left-associative: `a JOIN b JOIN c` joins `a` and `b` first
+    Join {
+        left: Box<Targetable>,
+        operator: JoinOperator,
+        right: Box<Targetable>,
+        constraint: Option<JoinConstraint>,
+    },
 }
 
-/// This assumes we only have "column-value" string-string equality conditions for now
-#[derive(Debug, PartialEq)]
-pub struct Condition {
-    pub column: String,
-    pub value: String,
+impl Targetable {
+    /// The arguments of the table-valued functions and the expressions of the ON clauses of
+    /// the joins
+    pub fn expressions(&self) -> Vec<&Expression> {
+        match self {
+            Targetable::Function { arguments, .. } => arguments.iter().collect(),
+            Targetable::Join {
+                left,
+                right,
+                constraint,
+                ..
+            } => {
+                let mut expressions = left.expressions();
+                expressions.extend(right.expressions());
+                if let Some(JoinConstraint::On(expression)) = constraint {
+                    expressions.push(expression);
+                }
+                expressions
+            }
+            _ => vec![],
+        }
+    }
+
+    fn expressions_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Targetable::Function { arguments, .. } => arguments.iter_mut().collect(),
+            Targetable::Join {
+                left,
+                right,
+                constraint,
+                ..
+            } => {
+                let mut expressions = left.expressions_mut();
+                expressions.extend(right.expressions_mut());
+                if let Some(JoinConstraint::On(expression)) = constraint {
+                    expressions.push(expression);
+                }
+                expressions
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The subqueries of the FROM clause, in the order they appear in
+    pub fn subqueries(&self) -> Vec<&SelectStatement> {
+        match self {
+            Targetable::TableOrView { .. } | Targetable::Function { .. } => vec![],
+            Targetable::Subquery { query, .. } => vec![query],
+            Targetable::Join { left, right, .. } => {
+                let mut subqueries = left.subqueries();
+                subqueries.extend(right.subqueries());
+                subqueries
+            }
+        }
+    }
+
+    /// Mutable access to the subqueries listed by `subqueries`, in the same order
+    pub fn subqueries_mut(&mut self) -> Vec<&mut SelectStatement> {
+        match self {
+            Targetable::TableOrView { .. } | Targetable::Function { .. } => vec![],
+            Targetable::Subquery { query, .. } => vec![query],
+            Targetable::Join { left, right, .. } => {
+                let mut subqueries = left.subqueries_mut();
+                subqueries.extend(right.subqueries_mut());
+                subqueries
+            }
+        }
+    }
+}
+
+/// How the rows of two joined tables are combined. A comma between two tables is an inner
+/// join.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinOperator {
+    Inner,
+    Left,
+    Cross,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinConstraint {
+    On(Expression),
+    Using(Vec<String>),
+}
+
+/// A single term of an ORDER BY clause
+/// ```sql
+/// SELECT name FROM apples ORDER BY color DESC NULLS FIRST;
+/// ```
+/// will be parsed into:
+/// ```ignore
+/// OrderingTerm {
+///     expression: Expression::Column { table: None, name: "color" },
+///     order: SortOrder::Descending,
+///     nulls: Some(NullsOrder::First),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderingTerm {
+    pub expression: Expression,
+    pub order: SortOrder,
+    pub nulls: Option<NullsOrder>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NullsOrder {
+    First,
+    Last,
+}
+
+/// A LIMIT clause, `LIMIT count OFFSET offset` or equivalently `LIMIT offset, count`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limit {
+    pub count: Expression,
+    pub offset: Option<Expression>,
+}
+
+/// A SQL expression
+/// ```sql
+/// price * (1 - discount)
+/// ```
+/// will be parsed into:
+/// ```ignore
+/// Expression::Binary {
+///     left: Expression::Column { table: None, name: "price" },
+///     operator: BinaryOperator::Multiply,
+///     right: Expression::Binary {
+///         left: Expression::Literal(Literal::Integer(1)),
+///         operator: BinaryOperator::Subtract,
+///         right: Expression::Column { table: None, name: "discount" },
+///     },
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(Literal),
+    Column {
+        table: Option<String>,
+        name: String,
+    },
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expression>,
+    },
+    Binary {
+        left: Box<Expression>,
+        operator: BinaryOperator,
+        right: Box<Expression>,
+    },
+    /// A function call, such as `length(name)` or `count(DISTINCT color)`, which is a call to
+    /// a window function when it has an OVER clause
+    Function {
+        name: String,
+        distinct: bool,
+        arguments: FunctionArguments,
+        over: Option<Box<Over>>,
+    },
+    /// `operand IN (...)`, or `operand NOT IN (...)` when negated
+    In {
+        operand: Box<Expression>,
+        negated: bool,
+        list: InList,
+    },
+    /// A subquery used as a value: the first column of its first row, NULL without rows
+    Subquery(Box<SelectStatement>),
+    /// `EXISTS (SELECT ...)`, true when the subquery returns at least one row
+    Exists(Box<SelectStatement>),
+    /// `CASE operand WHEN value THEN result ... ELSE result END`, which compares the operand to
+    /// each value, or `CASE WHEN condition THEN result ... END` without an operand, which
+    /// checks each condition. The result is NULL when no branch applies and there is no ELSE.
+    Case {
+        operand: Option<Box<Expression>>,
+        branches: Vec<(Expression, Expression)>,
+        else_result: Option<Box<Expression>>,
+    },
+    /// `CAST(expression AS type_name)`
+    Cast {
+        expression: Box<Expression>,
+        type_name: String,
+    },
+    /// `expression COLLATE collation`, which has the value of the expression but compares and
+    /// sorts it with the given collating sequence
+    Collate {
+        expression: Box<Expression>,
+        collation: String,
+    },
+    /// The value a column of an enclosing query has in the row a correlated subquery runs for,
+    /// which still compares with the collation and affinity of the column. Never parsed:
+    /// subqueries are bound to a row by replacing the references they make to such columns.
+    Bound {
+        value: Literal,
+        collation: String,
+        affinity: Option<Affinity>,
+    },
+    /// A parameter of a prepared statement, standing for the value bound to it when the
+    /// statement is run, NULL if there is none
+    Parameter(Parameter),
+}
+
+impl Expression {
+    /// The expressions this expression is directly made of. Subqueries are evaluated in a
+    /// scope of their own, so their expressions are not part of them.
+    pub fn children(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Literal(_)
+            | Expression::Column { .. }
+            | Expression::Bound { .. }
+            | Expression::Parameter(_)
+            | Expression::Subquery(_)
+            | Expression::Exists(_) => vec![],
+            Expression::Unary { operand, .. } => vec![operand],
+            Expression::Binary { left, right, .. } => vec![left, right],
+            Expression::Function {
+                arguments, over, ..
+            } => {
+                let mut children: Vec<&Expression> = arguments.expressions().iter().collect();
+                if let Some(Over::Window(window)) = over.as_deref() {
+                    children.extend(window.expressions());
+                }
+                children
+            }
+            Expression::In { operand, list, .. } => {
+                let mut children = vec![operand.as_ref()];
+                if let InList::Expressions(expressions) = list {
+                    children.extend(expressions);
+                }
+                children
+            }
+            Expression::Case {
+                operand,
+                branches,
+                else_result,
+            } => {
+                let mut children: Vec<&Expression> = operand.iter().map(Box::as_ref).collect();
+                for (when, then) in branches {
+                    children.extend([when, then]);
+                }
+                children.extend(else_result.iter().map(Box::as_ref));
+                children
+            }
+            Expression::Cast { expression, .. } | Expression::Collate { expression, .. } => {
+                vec![expression]
+            }
+        }
+    }
+
+    /// Mutable access to the expressions listed by `children`, in the same order
+    pub fn children_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Expression::Literal(_)
+            | Expression::Column { .. }
+            | Expression::Bound { .. }
+            | Expression::Parameter(_)
+            | Expression::Subquery(_)
+            | Expression::Exists(_) => vec![],
+            Expression::Unary { operand, .. } => vec![operand],
+            Expression::Binary { left, right, .. } => vec![left, right],
+            Expression::Function {
+                arguments, over, ..
+            } => {
+                let mut children: Vec<&mut Expression> = match arguments {
+                    FunctionArguments::Star => vec![],
+                    FunctionArguments::List(expressions) => expressions.iter_mut().collect(),
+                };
+                if let Some(Over::Window(window)) = over.as_deref_mut() {
+                    children.extend(window.expressions_mut());
+                }
+                children
+            }
+            Expression::In { operand, list, .. } => {
+                let mut children = vec![operand.as_mut()];
+                if let InList::Expressions(expressions) = list {
+                    children.extend(expressions);
+                }
+                children
+            }
+            Expression::Case {
+                operand,
+                branches,
+                else_result,
+            } => {
+                let mut children: Vec<&mut Expression> =
+                    operand.iter_mut().map(Box::as_mut).collect();
+                for (when, then) in branches {
+                    children.extend([when, then]);
+                }
+                children.extend(else_result.iter_mut().map(Box::as_mut));
+                children
+            }
+            Expression::Cast { expression, .. } | Expression::Collate { expression, .. } => {
+                vec![expression]
+            }
+        }
+    }
+
+    /// The subquery this expression directly runs, if any
+    pub fn subquery(&self) -> Option<&SelectStatement> {
+        match self {
+            Expression::Subquery(query)
+            | Expression::Exists(query)
+            | Expression::In {
+                list: InList::Subquery(query),
+                ..
+            } => Some(query),
+            _ => None,
+        }
+    }
+
+    /// Mutable access to the subquery returned by `subquery`
+    pub fn subquery_mut(&mut self) -> Option<&mut SelectStatement> {
+        match self {
+            Expression::Subquery(query)
+            | Expression::Exists(query)
+            | Expression::In {
+                list: InList::Subquery(query),
+                ..
+            } => Some(query),
+            _ => None,
+        }
+    }
+}
+
+/// What the operand of IN is looked for in: a list of values, or the rows of a subquery
+#[derive(Debug, Clone, PartialEq)]
+pub enum InList {
+    Expressions(Vec<Expression>),
+    Subquery(Box<SelectStatement>),
+}
+
+/// The arguments of a function call: `count(*)` is the only function taking a star
+#[derive(Debug, Clone, PartialEq)]
+pub enum FunctionArguments {
+    Star,
+    List(Vec<Expression>),
+}
+
+impl FunctionArguments {
+    /// The argument expressions, none for `*`
+    pub fn expressions(&self) -> &[Expression] {
+        match self {
+            FunctionArguments::Star => &[],
+            FunctionArguments::List(expressions) => expressions,
+        }
+    }
+}
+
+/// The window a window function is computed over: either one named by the WINDOW clause, as
+/// in `OVER recent`, or one defined in place, as in `OVER (PARTITION BY color ORDER BY name)`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Over {
+    Named(String),
+    Window(Window),
+}
+
+/// A window of the WINDOW clause, `name AS (...)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedWindow {
+    pub name: String,
+    pub window: Window,
+}
+
+/// How the rows of a query are split into partitions, how each partition is ordered, and
+/// which rows of its partition make up the frame of each row
+/// ```sql
+/// (recent PARTITION BY color ORDER BY picked ROWS BETWEEN 2 PRECEDING AND CURRENT ROW)
+/// ```
+/// will be parsed into:
+/// ```ignore
+/// Window {
+///     base: Some("recent"),
+///     partition_by: vec![Expression::Column { table: None, name: "color" }],
+///     order_by: vec![OrderingTerm { expression: Expression::Column { table: None, name: "picked" }, .. }],
+///     frame: Some(Frame {
+///         units: FrameUnits::Rows,
+///         start: FrameBound::Preceding(Expression::Literal(Literal::Integer(2))),
+///         end: FrameBound::CurrentRow,
+///         exclude: FrameExclude::NoOthers,
+///     }),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    /// The named window this window extends
+    pub base: Option<String>,
+    pub partition_by: Vec<Expression>,
+    pub order_by: Vec<OrderingTerm>,
+    /// Without a frame, the frame of a row goes from the start of its partition to its last
+    /// peer
+    pub frame: Option<Frame>,
+}
+
+impl Window {
+    /// The expressions of the PARTITION BY and ORDER BY clauses and of the frame bounds
+    pub fn expressions(&self) -> Vec<&Expression> {
+        let mut expressions: Vec<&Expression> = self.partition_by.iter().collect();
+        expressions.extend(self.order_by.iter().map(|term| &term.expression));
+        if let Some(frame) = &self.frame {
+            expressions.extend(frame.start.offset());
+            expressions.extend(frame.end.offset());
+        }
+        expressions
+    }
+
+    /// Mutable access to the expressions listed by `expressions`, in the same order
+    pub fn expressions_mut(&mut self) -> Vec<&mut Expression> {
+        let mut expressions: Vec<&mut Expression> = self.partition_by.iter_mut().collect();
+        expressions.extend(self.order_by.iter_mut().map(|term| &mut term.expression));
+        if let Some(frame) = &mut self.frame {
+            expressions.extend(frame.start.offset_mut());
+            expressions.extend(frame.end.offset_mut());
+        }
+        expressions
+    }
+}
+
+/// `units BETWEEN start AND end EXCLUDE exclude`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub units: FrameUnits,
+    pub start: FrameBound,
+    pub end: FrameBound,
+    pub exclude: FrameExclude,
+}
+
+/// What the offsets of frame bounds count: rows, groups of peers, or the difference between
+/// the values of the ORDER BY term
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameUnits {
+    Rows,
+    Range,
+    Groups,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(Expression),
+    CurrentRow,
+    Following(Expression),
+    UnboundedFollowing,
+}
+
+impl FrameBound {
+    /// The offset of `N PRECEDING` and `N FOLLOWING` bounds
+    pub fn offset(&self) -> Option<&Expression> {
+        match self {
+            FrameBound::Preceding(offset) | FrameBound::Following(offset) => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// Mutable access to the offset returned by `offset`
+    pub fn offset_mut(&mut self) -> Option<&mut Expression> {
+        match self {
+            FrameBound::Preceding(offset) | FrameBound::Following(offset) => Some(offset),
+            _ => None,
+        }
+    }
+}
+
+/// The rows left out of the frame of a row: none, the row itself, all its peers, or its peers
+/// but not itself
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameExclude {
+    NoOthers,
+    CurrentRow,
+    Group,
+    Ties,
+}
+
+/// A parameter as written: `?`, `?NNN`, or a name starting with `:`, `@` or `$`. Parameters
+/// are numbered in the order they come in the statement, hence their position in its text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub position: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Integer(i64),
+    Real(f64),
+    String(String),
+    Blob(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Plus,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    BitAnd,
+    BitOr,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Concat,
+    /// `IS`, which is like `=` except that NULL is the same as NULL and not unknown
+    Is,
+    /// `IS NOT`, the opposite of `IS`
+    IsNot,
+    /// `->`, extracting a value from JSON as JSON
+    Extract,
+    /// `->>`, extracting a value from JSON as an SQL value
+    ExtractValue,
+}
+
+impl BinaryOperator {
+    /// Whether the operator compares its operands, which makes it depend on their collation
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Equal
+                | BinaryOperator::NotEqual
+                | BinaryOperator::Less
+                | BinaryOperator::LessOrEqual
+                | BinaryOperator::Greater
+                | BinaryOperator::GreaterOrEqual
+                | BinaryOperator::Is
+                | BinaryOperator::IsNot
+        )
+    }
+}
+
+/// Keywords which can't be used as bare identifiers
+const RESERVED_KEYWORDS: &[&str] = &[
+    "ALL",
+    "AND",
+    "AS",
+    "ASC",
+    "AUTOINCREMENT",
+    "BY",
+    "CASE",
+    "CAST",
+    "CHECK",
+    "COLLATE",
+    "CONSTRAINT",
+    "CREATE",
+    "CROSS",
+    "DEFAULT",
+    "DESC",
+    "DISTINCT",
+    "ELSE",
+    "END",
+    "EXCEPT",
+    "EXISTS",
+    "FROM",
+    "GENERATED",
+    "GROUP",
+    "HAVING",
+    "IN",
+    "INDEX",
+    "INNER",
+    "INTERSECT",
+    "IS",
+    "ISNULL",
+    "JOIN",
+    "LEFT",
+    "LIMIT",
+    "NATURAL",
+    "NOT",
+    "NOTNULL",
+    "NULL",
+    "NULLS",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "PRIMARY",
+    "REFERENCES",
+    "SELECT",
+    "TABLE",
+    "THEN",
+    "UNION",
+    "UNIQUE",
+    "USING",
+    "WHEN",
+    "WHERE",
+    "WITH",
+];
+
+/// Parses a statement, locating the token the parser stops at when it doesn't parse.
+pub fn parse_statement(sql: &str) -> Result<Statement, ParseError> {
+    sql_query::statement(sql).map_err(|error| ParseError::new(sql, error))
+}
+
+pub fn is_reserved_keyword(word: &str) -> bool {
+    RESERVED_KEYWORDS
+        .iter()
+        .any(|keyword| keyword.eq_ignore_ascii_case(word))
+}
+
+fn binary(left: Expression, operator: BinaryOperator, right: Expression) -> Expression {
+    Expression::Binary {
+        left: Box::new(left),
+        operator,
+        right: Box::new(right),
+    }
+}
+
+fn unary(operator: UnaryOperator, operand: Expression) -> Expression {
+    Expression::Unary {
+        operator,
+        operand: Box::new(operand),
+    }
+}
+
+/// The SELECT a VALUES clause stands for, its columns being named column1, column2... A single
+/// row is selected without FROM clause, and several ones from the UNION ALL of those rows, which
+/// reports rows of different lengths.
+fn values(rows: Vec<Vec<Expression>>) -> SelectStatement {
+    let select = |row: Vec<Expression>| SelectStatement {
+        with: None,
+        distinct: false,
+        selectables: row
+            .into_iter()
+            .enumerate()
+            .map(|(i, expression)| Selectable::Expression {
+                expression,
+                alias: Some(format!("column{}", i + 1)),
+                text: String::new(),
+            })
+            .collect(),
+        from_target: None,
+        where_clause: None,
+        group_by: vec![],
+        having: None,
+        windows: vec![],
+        compound: vec![],
+        order_by: vec![],
+        limit: None,
+    };
+    let mut rows = rows.into_iter().map(select);
+    let first = rows.next().expect("VALUES has rows");
+    if rows.len() == 0 {
+        return first;
+    }
+    let union = SelectStatement {
+        compound: rows
+            .map(|select| CompoundTerm {
+                operator: CompoundOperator::UnionAll,
+                select,
+            })
+            .collect(),
+        ..first
+    };
+    SelectStatement {
+        selectables: vec![Selectable::Star],
+        from_target: Some(Targetable::Subquery {
+            query: Box::new(union),
+            alias: None,
+        }),
+        ..select(vec![])
+    }
+}
+
+/// The expression, or its negation with NOT
+fn negate(expression: Expression, negated: bool) -> Expression {
+    match negated {
+        true => unary(UnaryOperator::Not, expression),
+        false => expression,
+    }
+}
+
+fn parse_hex_blob(digits: &str) -> Result<Vec<u8>, &'static str> {
+    if !digits.len().is_multiple_of(2) {
+        return Err("an even number of hexadecimal digits");
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| "hexadecimal digits"))
+        .collect()
 }
 
 peg::parser! {
   pub grammar sql_query() for str {
+    /// Parses any supported statement
+    pub rule statement() -> Statement
+        = __ s:(
+            s:select_statement_body() {Statement::SelectStatement(Box::new(s))}
+            / s:create_table_statement_body() {Statement::CreateTableStatement(s)}
+            / s:create_index_statement_body() {Statement::CreateIndexStatement(s)}
+        ) __ ";"? __ {s}
+
     /// Parses a simple SELECT statement
     /// ```sql
-    /// SELECT name, color FROM apples WHERE color='blue';
+    /// SELECT name, color FROM apples WHERE color='blue' ORDER BY name LIMIT 10;
     /// ```
     /// will be parsed into:
-    /// ```rust
+    /// ```ignore
     /// SelectStatement {
-    ///   selectables: vec![Selectable::Column("name"), Selectable::Column("color")],
-    ///   from_target: Targetable::TableOrView("apples"),
-    ///   conditions: vec![Condition {column: "color", value: "blue"}]
+    ///   with: None,
+    ///   distinct: false,
+    ///   selectables: vec![Selectable::Expression { expression: Expression::Column("name"), .. }, ..],
+    ///   from_target: Some(Targetable::TableOrView { name: "apples", alias: None }),
+    ///   where_clause: Some(Expression::Binary { .. }),
+    ///   group_by: vec![],
+    ///   having: None,
+    ///   compound: vec![],
+    ///   order_by: vec![OrderingTerm {expression: Expression::Column("name"), ..}],
+    ///   limit: Some(Limit {count: Expression::Literal(Literal::Integer(10)), offset: None}),
     ///   }
     /// ```
     pub rule select_statement() -> SelectStatement
-        = select() _+ selectables:(selectable() ++ [',' | _] ) _+ from()
-        _+ from_target:targetable() _+ where() _+ conditions:(condition() ++ and())
-        {SelectStatement{selectables, from_target, conditions}}
+        = __ s:select_statement_body() __ ";"? __ {s}
+
+    rule select_statement_body() -> SelectStatement
+        = with:(w:with_clause() __ {w})?
+        first:select_core()
+        compound:(__ operator:compound_operator() __ select:select_core() {
+            CompoundTerm{operator, select}
+        })*
+        order_by:(__ o:order_by() {o})?
+        limit:(__ l:limit() {l})?
+        {SelectStatement{
+            with,
+            compound,
+            order_by: order_by.unwrap_or_default(),
+            limit,
+            ..first
+        }}
+
+    /// A single SELECT of a possibly compound SELECT statement, or a VALUES clause
+    rule select_core() -> SelectStatement
+        = kw("VALUES") __ rows:(values_row() ++ (__ "," __)) {values(rows)}
+        / select() distinct:(__ d:(kw("DISTINCT") {true} / kw("ALL") {false}) {d})?
+        __ selectables:(selectable() ++ (__ "," __))
+        from_target:(__ from() __ t:join_clause() {t})?
+        where_clause:(__ where() __ e:expression() {e})?
+        group_by:(__ g:group_by() {g})?
+        having:(__ kw("HAVING") __ e:expression() {e})?
+        windows:(__ kw("WINDOW") __ w:(named_window() ++ (__ "," __)) {w})?
+        {SelectStatement{
+            with: None,
+            distinct: distinct.unwrap_or(false),
+            selectables,
+            from_target,
+            where_clause,
+            group_by: group_by.unwrap_or_default(),
+            having,
+            windows: windows.unwrap_or_default(),
+            compound: vec![],
+            order_by: vec![],
+            limit: None,
+        }}
+
+    rule values_row() -> Vec<Expression>
+        = "(" __ e:(expression() ++ (__ "," __)) __ ")" {e}
+
+    rule compound_operator() -> CompoundOperator
+        = kw("UNION") __ kw("ALL") {CompoundOperator::UnionAll}
+        / kw("UNION") {CompoundOperator::Union}
+        / kw("INTERSECT") {CompoundOperator::Intersect}
+        / kw("EXCEPT") {CompoundOperator::Except}
+
+    /// Parses a CREATE TABLE statement, as found in the `sql` column of the schema table
+    pub rule create_table_statement() -> CreateTableStatement
+        = __ s:create_table_statement_body() __ ";"? __ {s}
+
+    rule create_table_statement_body() -> CreateTableStatement
+        = kw("CREATE") __ (kw("TEMPORARY") __ / kw("TEMP") __)? kw("TABLE") __ if_not_exists()?
+        table_name:qualified_name() __ "(" __ columns:(column_definition() ++ (__ "," __))
+        constraints:(__ "," __ c:table_constraint() {c})* __ ")"
+        without_rowid:(__ kw("WITHOUT") __ i("ROWID") {true})?
+        (__ "," __ i("STRICT"))? (__ i("STRICT"))?
+        {CreateTableStatement{
+            table_name,
+            columns,
+            constraints,
+            without_rowid: without_rowid.unwrap_or(false),
+        }}
+
+    /// Parses a CREATE INDEX statement, as found in the `sql` column of the schema table
+    pub rule create_index_statement() -> CreateIndexStatement
+        = __ s:create_index_statement_body() __ ";"? __ {s}
+
+    rule create_index_statement_body() -> CreateIndexStatement
+        = kw("CREATE") __ unique:(kw("UNIQUE") __)? kw("INDEX") __ if_not_exists()?
+        index_name:qualified_name() __ kw("ON") __ table_name:identifier() __
+        "(" __ columns:(indexed_column() ++ (__ "," __)) __ ")"
+        partial:(__ where() __ expression() {true})?
+        {CreateIndexStatement{
+            index_name,
+            table_name,
+            unique: unique.is_some(),
+            columns,
+            partial: partial.unwrap_or(false),
+        }}
+
+    rule if_not_exists() = kw("IF") __ kw("NOT") __ kw("EXISTS") __
+
+    rule qualified_name() -> String
+        = (identifier() __ "." __)? name:identifier() {name}
+
+    rule column_definition() -> ColumnDefinition
+        = name:identifier() type_name:(__ t:type_name() {t})?
+        constraints:(__ c:column_constraint() {c})*
+        {ColumnDefinition{name, type_name, constraints}}
 
-    rule _() = quiet!{[' ' | '\n' | '\t']+}
+    rule type_name() -> String
+        = words:(identifier() ++ _)
+        size:$(__ "(" __ signed_number() __ ("," __ signed_number() __)? ")")?
+        {format!("{}{}", words.join(" "), size.map(|s| s.trim_start()).unwrap_or_default())}
 
-    rule select() -> ()
-        = ( "select" / "SELECT")
+    rule column_constraint() -> ColumnConstraint
+        = (kw("CONSTRAINT") __ identifier() __)? c:(
+            kw("PRIMARY") __ kw("KEY") order:(__ o:sort_order() {o})? __ conflict_clause()?
+                autoincrement:(__ kw("AUTOINCREMENT"))?
+                {ColumnConstraint::PrimaryKey{order, autoincrement: autoincrement.is_some()}}
+            / kw("NOT") __ kw("NULL") __ conflict_clause()? {ColumnConstraint::NotNull}
+            / kw("NULL") __ conflict_clause()? {ColumnConstraint::Null}
+            / kw("UNIQUE") __ conflict_clause()? {ColumnConstraint::Unique}
+            / kw("CHECK") __ balanced() {ColumnConstraint::Check}
+            / kw("DEFAULT") __ e:default_value() {ColumnConstraint::Default(e)}
+            / kw("COLLATE") __ c:identifier() {ColumnConstraint::Collate(c)}
+            / r:foreign_key_clause() {ColumnConstraint::References(r)}
+            / (kw("GENERATED") __ i("ALWAYS") __)? kw("AS") __
+                expression:("(" __ e:expression() __ ")" {Some(e)} / balanced() {None})
+                stored:(__ s:(i("STORED") {true} / i("VIRTUAL") {false}) {s})?
+                {ColumnConstraint::Generated{expression, stored: stored.unwrap_or(false)}}
+        ) {c}
+
+    rule default_value() -> Expression
+        = "(" __ e:expression() __ ")" {e}
+        / "-" __ l:numeric_literal() {unary(UnaryOperator::Negate, Expression::Literal(l))}
+        / "+"? __ l:literal() {Expression::Literal(l)}
+        / name:identifier() {Expression::Column{table: None, name}}
+
+    rule conflict_clause()
+        = kw("ON") __ i("CONFLICT") __ identifier()
+
+    rule foreign_key_clause() -> String
+        = kw("REFERENCES") __ table:identifier() (__ balanced())?
+        (__ (kw("ON") / i("MATCH")) __ identifier() (_ identifier())*)*
+        (__ kw("NOT")? __ i("DEFERRABLE") (__ identifier() __ identifier())?)?
+        {table}
+
+    rule table_constraint() -> TableConstraint
+        = (kw("CONSTRAINT") __ identifier() __)? c:(
+            kw("PRIMARY") __ kw("KEY") __ "(" __ columns:(indexed_column() ++ (__ "," __)) __ ")"
+                (__ conflict_clause())? {TableConstraint::PrimaryKey(columns)}
+            / kw("UNIQUE") __ "(" __ columns:(indexed_column() ++ (__ "," __)) __ ")"
+                (__ conflict_clause())? {TableConstraint::Unique(columns)}
+            / kw("CHECK") __ balanced() {TableConstraint::Check}
+            / i("FOREIGN") __ i("KEY") __ balanced() __ foreign_key_clause() {TableConstraint::ForeignKey}
+        ) {c}
+
+    rule indexed_column() -> IndexedColumn
+        = name:identifier() collation:(__ kw("COLLATE") __ c:identifier() {c})?
+        order:(__ o:sort_order() {o})?
+        {IndexedColumn{name, collation, order: order.unwrap_or(SortOrder::Ascending)}}
+
+    /// Skips over a parenthesized group, as found in CHECK constraints or generated columns
+    rule balanced()
+        = "(" (string_litteral() / quoted_identifier() / balanced() / [^ '(' | ')' | '\'' | '"'])* ")"
+
+    rule _() = quiet!{([' ' | '\n' | '\t' | '\r'] / comment())+}
+
+    rule __() = quiet!{([' ' | '\n' | '\t' | '\r'] / comment())*}
+
+    /// Comments separate tokens like spaces do; they may be left unterminated at the end
+    rule comment()
+        = "--" (!"\n" [_])* ("\n" / ![_])
+        / "/*" (!"*/" [_])* ("*/" / ![_])
+
+    /// Matches a keyword, case-insensitively. It is expected where the token it's compared with
+    /// starts rather than after it, alongside other tokens, so that errors point at that token.
+    rule i(literal: &'static str)
+        = quiet!{
+            input:$([_]*<{literal.chars().count()}>)
+            {? if input.eq_ignore_ascii_case(literal) { Ok(()) } else { Err(literal) } }
+        }
+        // the failure is never reached, it only tells peg the alternative can't match nothing
+        / ({? Err::<(), _>(literal)}) [_]
+
+    /// Matches a whole keyword, making sure it isn't the prefix of a longer identifier
+    rule kw(literal: &'static str)
+        = i(literal) !identifier_character()
+
+    rule identifier_character() = ['a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '$']
+
+    rule select()
+        = kw("SELECT")
+
+    rule with_clause() -> WithClause
+        = kw("WITH") recursive:(__ kw("RECURSIVE"))? __
+        tables:(common_table_expression() ++ (__ "," __))
+        {WithClause{recursive: recursive.is_some(), tables}}
+
+    rule common_table_expression() -> CommonTableExpression
+        = name:identifier()
+        columns:(__ "(" __ c:(identifier() ++ (__ "," __)) __ ")" {c})? __ kw("AS") __
+        ((kw("NOT") __)? i("MATERIALIZED") __)?
+        "(" __ query:select_statement_body() __ ")"
+        {CommonTableExpression{
+            name,
+            columns: columns.unwrap_or_default(),
+            query: Box::new(query),
+        }}
 
     rule selectable() -> Selectable
-        = s:(count_star() / column()) {s}
+        = "*" {Selectable::Star}
+        / table:identifier() __ "." __ "*" {Selectable::TableStar(table)}
+        // the expression is parsed a second time to keep the text it was written as
+        / text:&(t:$(expression()) {t}) expression:expression()
+        alias:(__ (kw("AS") __ / !(kw("WINDOW") __ identifier() __ kw("AS"))) a:(identifier() / string_litteral()) {a})?
+        {Selectable::Expression{expression, alias, text: text.to_string()}}
+        // failures within the lookahead aren't reported, so an expression which doesn't parse
+        // is parsed again outside of it, for the error to point where it fails
+        / expression() {? Err("expression")}
 
-    rule count_star() -> Selectable
-        = "*" {Selectable::CountStar {}}
+    rule from()
+        = kw("FROM")
 
-    rule column() -> Selectable
-        = name:identifier() {Selectable::Column(name.to_string())}
+    rule join_clause() -> Targetable
+        = first:targetable() joins:(
+            __ operator:join_operator() __ right:targetable()
+            constraint:(__ c:join_constraint() {c})? {(operator, right, constraint)}
+        )* {
+            joins.into_iter().fold(first, |left, (operator, right, constraint)| Targetable::Join{
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+                constraint,
+            })
+        }
+
+    rule join_operator() -> JoinOperator
+        = "," {JoinOperator::Inner}
+        / kw("LEFT") (__ kw("OUTER"))? __ kw("JOIN") {JoinOperator::Left}
+        / kw("INNER") __ kw("JOIN") {JoinOperator::Inner}
+        / kw("CROSS") __ kw("JOIN") {JoinOperator::Cross}
+        / kw("JOIN") {JoinOperator::Inner}
 
-    rule from() -> ()
-        = ("from" / "FROM")
+    rule join_constraint() -> JoinConstraint
+        = kw("ON") __ e:expression() {JoinConstraint::On(e)}
+        / kw("USING") __ "(" __ columns:(identifier() ++ (__ "," __)) __ ")" {JoinConstraint::Using(columns)}
 
     rule targetable() -> Targetable
-        = name:identifier() {Targetable::TableOrView(name)}
+        = "(" __ query:select_statement_body() __ ")" alias:table_alias()?
+            {Targetable::Subquery{query: Box::new(query), alias}}
+        / name:identifier() __ "(" __ arguments:(expression() ** (__ "," __)) __ ")"
+            alias:table_alias()?
+            {Targetable::Function{name, arguments, alias}}
+        / name:qualified_name() alias:table_alias()?
+            {Targetable::TableOrView{name, alias}}
+
+    /// The alias of a table, which can't be the WINDOW keyword starting the WINDOW clause
+    rule table_alias() -> String
+        = __ kw("AS")? __ !kw("WINDOW") a:identifier() {a}
 
     rule identifier() -> String
-        = name:$(['a'..='z' | 'A'..='Z' | '_']+) {name.to_string()}
+        = quiet!{
+            name:$(['a'..='z' | 'A'..='Z' | '_'] identifier_character()*)
+            {? if is_reserved_keyword(name) { Err("identifier") } else { Ok(name.to_string()) } }
+            / quoted_identifier()
+        } / expected!("identifier")
 
-    rule where() -> ()
-        = ("where" / "WHERE")
+    rule quoted_identifier() -> String
+        = "\"" s:$(([^ '"'] / "\"\"")*) "\"" {s.replace("\"\"", "\"")}
+        / "`" s:$(([^ '`'] / "``")*) "`" {s.replace("``", "`")}
+        / "[" s:$([^ ']']*) "]" {s.to_string()}
 
-    rule condition() -> Condition
-        = column:identifier() _+ "=" +_ value:string_litteral() {Condition {column, value}}
+    rule where()
+        = kw("WHERE")
 
     rule string_litteral() -> String
-        = "'" s:$(['\'']*) {s.to_string()}
+        = quiet!{"'" s:$(([^ '\''] / "''")*) "'" {s.replace("''", "'")}} / expected!("string")
+
+    rule group_by() -> Vec<Expression>
+        = kw("GROUP") __ kw("BY") __ e:(expression() ++ (__ "," __)) {e}
+
+    rule order_by() -> Vec<OrderingTerm>
+        = kw("ORDER") __ kw("BY") __ terms:(ordering_term() ++ (__ "," __)) {terms}
 
-    rule and() -> ()
-        = ("and" / "AND")
+    rule ordering_term() -> OrderingTerm
+        = expression:expression() order:(__ o:sort_order() {o})?
+        nulls:(__ kw("NULLS") __ n:(kw("FIRST") {NullsOrder::First} / kw("LAST") {NullsOrder::Last}) {n})?
+        {OrderingTerm{expression, order: order.unwrap_or(SortOrder::Ascending), nulls}}
 
+    rule sort_order() -> SortOrder
+        = kw("ASC") {SortOrder::Ascending}
+        / kw("DESC") {SortOrder::Descending}
+
+    rule limit() -> Limit
+        = kw("LIMIT") __ first:expression() second:(
+            __ kw("OFFSET") __ e:expression() {(false, e)}
+            / __ "," __ e:expression() {(true, e)}
+        )? {
+            match second {
+                // `LIMIT offset, count`
+                Some((true, count)) => Limit{count, offset: Some(first)},
+                Some((false, offset)) => Limit{count: first, offset: Some(offset)},
+                None => Limit{count: first, offset: None},
+            }
+        }
+
+    /// Parses an expression, honouring SQLite's operator precedence
+    pub rule expression() -> Expression = precedence!{
+        l:(@) __ kw("OR") __ r:@ {binary(l, BinaryOperator::Or, r)}
+        --
+        l:(@) __ kw("AND") __ r:@ {binary(l, BinaryOperator::And, r)}
+        --
+        kw("NOT") __ e:@ {unary(UnaryOperator::Not, e)}
+        --
+        l:(@) __ ("==" / "=") __ r:@ {binary(l, BinaryOperator::Equal, r)}
+        l:(@) __ ("!=" / "<>") __ r:@ {binary(l, BinaryOperator::NotEqual, r)}
+        l:(@) __ negated:(kw("NOT") __)? kw("IN") __ list:in_list() {
+            Expression::In{operand: Box::new(l), negated: negated.is_some(), list}
+        }
+        l:(@) __ kw("IS") __ kw("NOT") __ kw("DISTINCT") __ kw("FROM") __ r:@
+            {binary(l, BinaryOperator::Is, r)}
+        l:(@) __ kw("IS") __ kw("DISTINCT") __ kw("FROM") __ r:@
+            {binary(l, BinaryOperator::IsNot, r)}
+        l:(@) __ kw("IS") __ kw("NOT") __ r:@ {binary(l, BinaryOperator::IsNot, r)}
+        l:(@) __ kw("IS") __ r:@ {binary(l, BinaryOperator::Is, r)}
+        e:(@) __ kw("ISNULL") {binary(e, BinaryOperator::Is, Expression::Literal(Literal::Null))}
+        e:(@) __ (kw("NOTNULL") / kw("NOT") __ kw("NULL"))
+            {binary(e, BinaryOperator::IsNot, Expression::Literal(Literal::Null))}
+        // `x BETWEEN low AND high` is `x >= low AND x <= high`
+        e:(@) __ negated:(kw("NOT") __)? kw("BETWEEN") __ low:comparand() __ kw("AND") __
+            high:comparand() {
+            let between = binary(
+                binary(e.clone(), BinaryOperator::GreaterOrEqual, low),
+                BinaryOperator::And,
+                binary(e, BinaryOperator::LessOrEqual, high),
+            );
+            negate(between, negated.is_some())
+        }
+        // `x LIKE pattern ESCAPE escape` and `x GLOB pattern` call like(pattern, x, escape)
+        // and glob(pattern, x)
+        e:(@) __ negated:(kw("NOT") __)? name:(kw("LIKE") {"like"} / kw("GLOB") {"glob"}) __
+            pattern:comparand() escape:(__ kw("ESCAPE") __ x:comparand() {x})? {
+            let mut arguments = vec![pattern, e];
+            arguments.extend(escape);
+            let call = Expression::Function{
+                name: name.to_string(),
+                distinct: false,
+                arguments: FunctionArguments::List(arguments),
+                over: None,
+            };
+            negate(call, negated.is_some())
+        }
+        --
+        c:comparand() {c}
+    }
+
+    /// Parses an expression binding tighter than the comparison operators, such as the bounds
+    /// of BETWEEN
+    rule comparand() -> Expression = precedence!{
+        l:(@) __ "<=" __ r:@ {binary(l, BinaryOperator::LessOrEqual, r)}
+        l:(@) __ ">=" __ r:@ {binary(l, BinaryOperator::GreaterOrEqual, r)}
+        l:(@) __ "<" !['<'] __ r:@ {binary(l, BinaryOperator::Less, r)}
+        l:(@) __ ">" !['>'] __ r:@ {binary(l, BinaryOperator::Greater, r)}
+        --
+        l:(@) __ "&" __ r:@ {binary(l, BinaryOperator::BitAnd, r)}
+        l:(@) __ "|" !['|'] __ r:@ {binary(l, BinaryOperator::BitOr, r)}
+        l:(@) __ "<<" __ r:@ {binary(l, BinaryOperator::ShiftLeft, r)}
+        l:(@) __ ">>" __ r:@ {binary(l, BinaryOperator::ShiftRight, r)}
+        --
+        l:(@) __ "+" __ r:@ {binary(l, BinaryOperator::Add, r)}
+        l:(@) __ "-" !['>'] __ r:@ {binary(l, BinaryOperator::Subtract, r)}
+        --
+        l:(@) __ "*" __ r:@ {binary(l, BinaryOperator::Multiply, r)}
+        l:(@) __ "/" __ r:@ {binary(l, BinaryOperator::Divide, r)}
+        l:(@) __ "%" __ r:@ {binary(l, BinaryOperator::Modulo, r)}
+        --
+        l:(@) __ "||" __ r:@ {binary(l, BinaryOperator::Concat, r)}
+        l:(@) __ "->>" __ r:@ {binary(l, BinaryOperator::ExtractValue, r)}
+        l:(@) __ "->" __ r:@ {binary(l, BinaryOperator::Extract, r)}
+        --
+        e:(@) __ kw("COLLATE") __ c:identifier()
+            {Expression::Collate{expression: Box::new(e), collation: c}}
+        --
+        // the smallest integer is written as the negation of one too large for 64 bits
+        "-" __ "9223372036854775808" !(identifier_character() / ".")
+            {Expression::Literal(Literal::Integer(i64::MIN))}
+        "-" __ e:@ {unary(UnaryOperator::Negate, e)}
+        "+" __ e:@ {unary(UnaryOperator::Plus, e)}
+        "~" __ e:@ {unary(UnaryOperator::BitNot, e)}
+        --
+        "(" __ s:select_statement_body() __ ")" {Expression::Subquery(Box::new(s))}
+        "(" __ e:expression() __ ")" {e}
+        kw("EXISTS") __ "(" __ s:select_statement_body() __ ")" {Expression::Exists(Box::new(s))}
+        kw("CASE") operand:(__ e:expression() {Box::new(e)})?
+            branches:(__ kw("WHEN") __ w:expression() __ kw("THEN") __ t:expression() {(w, t)})+
+            else_result:(__ kw("ELSE") __ e:expression() {Box::new(e)})? __ kw("END")
+            {Expression::Case{operand, branches, else_result}}
+        kw("CAST") __ "(" __ e:expression() __ kw("AS") __ type_name:type_name() __ ")"
+            {Expression::Cast{expression: Box::new(e), type_name}}
+        l:literal() {Expression::Literal(l)}
+        p:parameter() {Expression::Parameter(p)}
+        f:function_call() {f}
+        c:column_reference() {c}
+    }
+
+    rule in_list() -> InList
+        = "(" __ s:select_statement_body() __ ")" {InList::Subquery(Box::new(s))}
+        / "(" __ e:(expression() ** (__ "," __)) __ ")" {InList::Expressions(e)}
+
+    rule function_call() -> Expression
+        = name:identifier() __ "(" __ call:(
+            "*" {(false, FunctionArguments::Star)}
+            / distinct:(kw("DISTINCT") __)? arguments:(expression() ** (__ "," __))
+                {(distinct.is_some(), FunctionArguments::List(arguments))}
+        ) __ ")" over:(__ kw("OVER") __ o:over() {Box::new(o)})?
+        {Expression::Function{name, distinct: call.0, arguments: call.1, over}}
+
+    rule over() -> Over
+        = "(" __ w:window() __ ")" {Over::Window(w)}
+        / name:identifier() {Over::Named(name)}
+
+    rule named_window() -> NamedWindow
+        = name:identifier() __ kw("AS") __ "(" __ window:window() __ ")" {NamedWindow{name, window}}
+
+    rule window() -> Window
+        = base:(!(kw("PARTITION") / frame_units()) n:identifier() __ {n})?
+        partition_by:(kw("PARTITION") __ kw("BY") __ e:(expression() ++ (__ "," __)) __ {e})?
+        order_by:(o:order_by() __ {o})?
+        frame:frame()?
+        {Window{
+            base,
+            partition_by: partition_by.unwrap_or_default(),
+            order_by: order_by.unwrap_or_default(),
+            frame,
+        }}
+
+    rule frame() -> Frame
+        = units:frame_units() __ bounds:(
+            kw("BETWEEN") __ start:frame_bound() __ kw("AND") __ end:frame_bound() {(start, end)}
+            / start:frame_bound() {(start, FrameBound::CurrentRow)}
+        ) exclude:(__ kw("EXCLUDE") __ e:frame_exclude() {e})?
+        {? match bounds {
+            (FrameBound::UnboundedFollowing, _) | (_, FrameBound::UnboundedPreceding) => Err("frame bound"),
+            (start, end) => Ok(Frame{units, start, end, exclude: exclude.unwrap_or(FrameExclude::NoOthers)}),
+        } }
+
+    rule frame_units() -> FrameUnits
+        = kw("ROWS") {FrameUnits::Rows}
+        / kw("RANGE") {FrameUnits::Range}
+        / kw("GROUPS") {FrameUnits::Groups}
+
+    rule frame_bound() -> FrameBound
+        = kw("UNBOUNDED") __ kw("PRECEDING") {FrameBound::UnboundedPreceding}
+        / kw("UNBOUNDED") __ kw("FOLLOWING") {FrameBound::UnboundedFollowing}
+        / kw("CURRENT") __ kw("ROW") {FrameBound::CurrentRow}
+        / e:expression() __ kw("PRECEDING") {FrameBound::Preceding(e)}
+        / e:expression() __ kw("FOLLOWING") {FrameBound::Following(e)}
+
+    rule frame_exclude() -> FrameExclude
+        = kw("NO") __ kw("OTHERS") {FrameExclude::NoOthers}
+        / kw("CURRENT") __ kw("ROW") {FrameExclude::CurrentRow}
+        / kw("GROUP") {FrameExclude::Group}
+        / kw("TIES") {FrameExclude::Ties}
+
+    rule column_reference() -> Expression
+        = table:identifier() __ "." __ name:identifier() {Expression::Column{table: Some(table), name}}
+        / name:identifier() {Expression::Column{table: None, name}}
+
+    rule parameter() -> Parameter
+        = position:position!()
+        name:$(quiet!{"?" ['0'..='9']* / [':' | '@' | '$'] identifier_character()+} / expected!("parameter"))
+        {Parameter{name: name.to_string(), position}}
+
+    rule literal() -> Literal
+        = numeric_literal()
+        / s:string_litteral() {Literal::String(s)}
+        / quiet!{['x' | 'X'] "'" digits:$(['0'..='9' | 'a'..='f' | 'A'..='F']*) "'"
+            {? parse_hex_blob(digits).map(Literal::Blob)}} / expected!("blob")
+        / kw("NULL") {Literal::Null}
+        / kw("TRUE") {Literal::Integer(1)}
+        / kw("FALSE") {Literal::Integer(0)}
+
+    rule numeric_literal() -> Literal
+        = quiet!{
+            "0" ['x' | 'X'] digits:$(['0'..='9' | 'a'..='f' | 'A'..='F']+) !identifier_character()
+                {? u64::from_str_radix(digits, 16).map(|i| Literal::Integer(i as i64)).map_err(|_| "hexadecimal integer")}
+            / n:$((['0'..='9']+ ("." ['0'..='9']*)? / "." ['0'..='9']+)
+                (['e' | 'E'] ['+' | '-']? ['0'..='9']+)?) !identifier_character() {
+                if n.contains(['.', 'e', 'E']) {
+                    Literal::Real(n.parse().unwrap_or(f64::NAN))
+                } else {
+                    // integers too large for 64 bits are treated as reals, like SQLite does
+                    n.parse().map(Literal::Integer).unwrap_or_else(|_| Literal::Real(n.parse().unwrap_or(f64::NAN)))
+                }
+            }
+        } / expected!("number")
+
+    rule signed_number() = ['+' | '-']? __ numeric_literal()
   }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::sql::{Condition, SelectStatement, Selectable, Targetable};
+    use crate::sql::{
+        BinaryOperator, CompoundOperator, Expression, FunctionArguments, InList, JoinConstraint,
+        JoinOperator, Limit, Literal, NullsOrder, OrderingTerm, SelectStatement, Selectable,
+        SortOrder, Targetable, UnaryOperator,
+    };
 
     use super::sql_query;
 
@@ -133,12 +1586,36 @@ mod test {
         assert_eq!(
             result,
             Ok(SelectStatement {
-                selectables: vec![Selectable::CountStar],
-                from_target: Targetable::TableOrView(String::from("apples")),
-                conditions: vec![Condition {
-                    column: String::from("color"),
-                    value: String::from("blue")
-                }]
+                with: None,
+                distinct: false,
+                selectables: vec![Selectable::Expression {
+                    expression: Expression::Function {
+                        name: String::from("COUNT"),
+                        distinct: false,
+                        arguments: FunctionArguments::Star,
+                        over: None,
+                    },
+                    alias: None,
+                    text: String::from("COUNT(*)"),
+                }],
+                from_target: Some(Targetable::TableOrView {
+                    name: String::from("apples"),
+                    alias: None
+                }),
+                where_clause: Some(Expression::Binary {
+                    left: Box::new(Expression::Column {
+                        table: None,
+                        name: String::from("color")
+                    }),
+                    operator: BinaryOperator::Equal,
+                    right: Box::new(Expression::Literal(Literal::String(String::from("blue")))),
+                }),
+                group_by: vec![],
+                having: None,
+                windows: vec![],
+                compound: vec![],
+                order_by: vec![],
+                limit: None,
             })
         )
     }
@@ -150,16 +1627,604 @@ mod test {
         assert_eq!(
             result,
             Ok(SelectStatement {
+                with: None,
+                distinct: false,
                 selectables: vec![
-                    Selectable::Column(String::from("name")),
-                    Selectable::Column(String::from("color"))
+                    Selectable::Expression {
+                        expression: Expression::Column {
+                            table: None,
+                            name: String::from("name")
+                        },
+                        alias: None,
+                        text: String::from("name"),
+                    },
+                    Selectable::Expression {
+                        expression: Expression::Column {
+                            table: None,
+                            name: String::from("color")
+                        },
+                        alias: None,
+                        text: String::from("color"),
+                    }
+                ],
+                from_target: Some(Targetable::TableOrView {
+                    name: String::from("apples"),
+                    alias: None
+                }),
+                where_clause: Some(Expression::Binary {
+                    left: Box::new(Expression::Column {
+                        table: None,
+                        name: String::from("color")
+                    }),
+                    operator: BinaryOperator::Equal,
+                    right: Box::new(Expression::Literal(Literal::String(String::from("blue")))),
+                }),
+                group_by: vec![],
+                having: None,
+                windows: vec![],
+                compound: vec![],
+                order_by: vec![],
+                limit: None,
+            })
+        )
+    }
+
+    #[test]
+    fn parse_order_by_and_limit() {
+        let result = sql_query::select_statement(
+            "select name from apples order by color desc nulls first, id * 2 limit 5 offset 10;",
+        );
+        assert_eq!(
+            result,
+            Ok(SelectStatement {
+                with: None,
+                distinct: false,
+                selectables: vec![Selectable::Expression {
+                    expression: Expression::Column {
+                        table: None,
+                        name: String::from("name")
+                    },
+                    alias: None,
+                    text: String::from("name"),
+                }],
+                from_target: Some(Targetable::TableOrView {
+                    name: String::from("apples"),
+                    alias: None
+                }),
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                windows: vec![],
+                compound: vec![],
+                order_by: vec![
+                    OrderingTerm {
+                        expression: Expression::Column {
+                            table: None,
+                            name: String::from("color")
+                        },
+                        order: SortOrder::Descending,
+                        nulls: Some(NullsOrder::First),
+                    },
+                    OrderingTerm {
+                        expression: Expression::Binary {
+                            left: Box::new(Expression::Column {
+                                table: None,
+                                name: String::from("id")
+                            }),
+                            operator: BinaryOperator::Multiply,
+                            right: Box::new(Expression::Literal(Literal::Integer(2))),
+                        },
+                        order: SortOrder::Ascending,
+                        nulls: None,
+                    },
                 ],
-                from_target: Targetable::TableOrView(String::from("apples")),
-                conditions: vec![Condition {
-                    column: String::from("color"),
-                    value: String::from("blue")
-                }]
+                limit: Some(Limit {
+                    count: Expression::Literal(Literal::Integer(5)),
+                    offset: Some(Expression::Literal(Literal::Integer(10))),
+                }),
+            })
+        )
+    }
+
+    #[test]
+    fn parse_limit_with_comma_offset() {
+        let result = sql_query::select_statement("SELECT * FROM apples LIMIT 10, 5").unwrap();
+        assert_eq!(
+            result.limit,
+            Some(Limit {
+                count: Expression::Literal(Literal::Integer(5)),
+                offset: Some(Expression::Literal(Literal::Integer(10))),
             })
         )
     }
+
+    #[test]
+    fn parse_group_by_and_having() {
+        let result = sql_query::select_statement(
+            "SELECT city, count(DISTINCT name), group_concat(name, ';') FROM people \
+             GROUP BY city HAVING count(*) > 1",
+        )
+        .unwrap();
+        let column = |name: &str| Expression::Column {
+            table: None,
+            name: String::from(name),
+        };
+        assert_eq!(
+            result.selectables[1],
+            Selectable::Expression {
+                expression: Expression::Function {
+                    name: String::from("count"),
+                    distinct: true,
+                    arguments: FunctionArguments::List(vec![column("name")]),
+                    over: None,
+                },
+                alias: None,
+                text: String::from("count(DISTINCT name)"),
+            }
+        );
+        assert_eq!(
+            result.selectables[2],
+            Selectable::Expression {
+                expression: Expression::Function {
+                    name: String::from("group_concat"),
+                    distinct: false,
+                    arguments: FunctionArguments::List(vec![
+                        column("name"),
+                        Expression::Literal(Literal::String(String::from(";")))
+                    ]),
+                    over: None,
+                },
+                alias: None,
+                text: String::from("group_concat(name, ';')"),
+            }
+        );
+        assert_eq!(result.group_by, vec![column("city")]);
+        assert_eq!(
+            result.having,
+            Some(Expression::Binary {
+                left: Box::new(Expression::Function {
+                    name: String::from("count"),
+                    distinct: false,
+                    arguments: FunctionArguments::Star,
+                    over: None,
+                }),
+                operator: BinaryOperator::Greater,
+                right: Box::new(Expression::Literal(Literal::Integer(1))),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_joins_with_aliases() {
+        let result = sql_query::select_statement(
+            "SELECT * FROM people p LEFT JOIN cities AS c ON p.city_id = c.id, \
+             countries JOIN continents USING (continent_id)",
+        )
+        .unwrap();
+        let table = |name: &str, alias: Option<&str>| {
+            Box::new(Targetable::TableOrView {
+                name: String::from(name),
+                alias: alias.map(String::from),
+            })
+        };
+        let column = |table: &str, name: &str| {
+            Box::new(Expression::Column {
+                table: Some(String::from(table)),
+                name: String::from(name),
+            })
+        };
+        let people_and_cities = Targetable::Join {
+            left: table("people", Some("p")),
+            operator: JoinOperator::Left,
+            right: table("cities", Some("c")),
+            constraint: Some(JoinConstraint::On(Expression::Binary {
+                left: column("p", "city_id"),
+                operator: BinaryOperator::Equal,
+                right: column("c", "id"),
+            })),
+        };
+        let with_countries = Targetable::Join {
+            left: Box::new(people_and_cities),
+            operator: JoinOperator::Inner,
+            right: table("countries", None),
+            constraint: None,
+        };
+        assert_eq!(
+            result.from_target,
+            Some(Targetable::Join {
+                left: Box::new(with_countries),
+                operator: JoinOperator::Inner,
+                right: table("continents", None),
+                constraint: Some(JoinConstraint::Using(vec![String::from("continent_id")])),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_subqueries() {
+        let result = sql_query::select_statement(
+            "SELECT (SELECT max(id) FROM apples) FROM (SELECT color FROM apples) AS c \
+             WHERE color NOT IN (SELECT color FROM bananas) AND EXISTS (SELECT 1)",
+        )
+        .unwrap();
+        let subquery = |column: &str, table: Option<&str>| {
+            let mut query = sql_query::select_statement(&format!("SELECT {}", column)).unwrap();
+            query.from_target = table.map(|name| Targetable::TableOrView {
+                name: String::from(name),
+                alias: None,
+            });
+            Box::new(query)
+        };
+        assert_eq!(
+            result.selectables,
+            vec![Selectable::Expression {
+                expression: Expression::Subquery(subquery("max(id)", Some("apples"))),
+                alias: None,
+                text: String::from("(SELECT max(id) FROM apples)"),
+            }]
+        );
+        assert_eq!(
+            result.from_target,
+            Some(Targetable::Subquery {
+                query: subquery("color", Some("apples")),
+                alias: Some(String::from("c")),
+            })
+        );
+        assert_eq!(
+            result.where_clause,
+            Some(Expression::Binary {
+                left: Box::new(Expression::In {
+                    operand: Box::new(Expression::Column {
+                        table: None,
+                        name: String::from("color")
+                    }),
+                    negated: true,
+                    list: InList::Subquery(subquery("color", Some("bananas"))),
+                }),
+                operator: BinaryOperator::And,
+                right: Box::new(Expression::Exists(subquery("1", None))),
+            })
+        );
+        assert_eq!(
+            sql_query::expression("1 IN (2, 3)"),
+            Ok(Expression::In {
+                operand: Box::new(Expression::Literal(Literal::Integer(1))),
+                negated: false,
+                list: InList::Expressions(vec![
+                    Expression::Literal(Literal::Integer(2)),
+                    Expression::Literal(Literal::Integer(3)),
+                ]),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_table_valued_functions_and_json_operators() {
+        let result = sql_query::select_statement(
+            "SELECT t.value ->> '$.a' FROM apples, json_each(apples.tags) AS t",
+        )
+        .unwrap();
+        let column = |table: &str, name: &str| Expression::Column {
+            table: Some(String::from(table)),
+            name: String::from(name),
+        };
+        assert_eq!(
+            result.from_target,
+            Some(Targetable::Join {
+                left: Box::new(Targetable::TableOrView {
+                    name: String::from("apples"),
+                    alias: None,
+                }),
+                operator: JoinOperator::Inner,
+                right: Box::new(Targetable::Function {
+                    name: String::from("json_each"),
+                    arguments: vec![column("apples", "tags")],
+                    alias: Some(String::from("t")),
+                }),
+                constraint: None,
+            })
+        );
+        assert_eq!(
+            result.selectables[0],
+            Selectable::Expression {
+                expression: Expression::Binary {
+                    left: Box::new(column("t", "value")),
+                    operator: BinaryOperator::ExtractValue,
+                    right: Box::new(Expression::Literal(Literal::String(String::from("$.a")))),
+                },
+                alias: None,
+                text: String::from("t.value ->> '$.a'"),
+            }
+        );
+        assert_eq!(
+            sql_query::expression("a->1 - 2"),
+            sql_query::expression("(a -> 1) - 2")
+        );
+    }
+
+    #[test]
+    fn parse_common_table_expressions() {
+        let result = sql_query::select_statement(
+            "WITH RECURSIVE tree(id, depth) AS (\
+               SELECT id, 0 FROM nodes WHERE parent = 0 \
+               UNION ALL SELECT nodes.id, depth + 1 FROM nodes JOIN tree ON parent = tree.id \
+               LIMIT 100\
+             ), roots AS MATERIALIZED (SELECT id FROM tree WHERE depth = 0) \
+             SELECT * FROM roots",
+        )
+        .unwrap();
+        let with = result.with.unwrap();
+        assert!(with.recursive);
+        assert_eq!(with.tables.len(), 2);
+        let tree = &with.tables[0];
+        assert_eq!(tree.name, "tree");
+        assert_eq!(tree.columns, vec!["id", "depth"]);
+        assert_eq!(tree.query.compound.len(), 1);
+        assert_eq!(tree.query.compound[0].operator, CompoundOperator::UnionAll);
+        assert!(tree.query.limit.is_some());
+        let roots = &with.tables[1];
+        assert!(roots.columns.is_empty());
+        assert!(roots.query.compound.is_empty());
+        assert_eq!(with.queries().len(), 2);
+
+        let result =
+            sql_query::select_statement("SELECT (WITH c AS (SELECT 1 UNION SELECT 2) SELECT 3)")
+                .unwrap();
+        let Selectable::Expression {
+            expression: Expression::Subquery(query),
+            ..
+        } = &result.selectables[0]
+        else {
+            panic!("expected a subquery");
+        };
+        assert_eq!(
+            query.with.as_ref().unwrap().tables[0].query.compound[0].operator,
+            CompoundOperator::Union
+        );
+    }
+
+    #[test]
+    fn parse_compound_select() {
+        let result = sql_query::select_statement(
+            "SELECT a FROM t UNION ALL SELECT b FROM u WHERE b > 1 \
+             INTERSECT SELECT c FROM v EXCEPT SELECT 1 UNION SELECT 2 ORDER BY 1 DESC LIMIT 3",
+        )
+        .unwrap();
+        let operators: Vec<CompoundOperator> =
+            result.compound.iter().map(|term| term.operator).collect();
+        assert_eq!(
+            operators,
+            vec![
+                CompoundOperator::UnionAll,
+                CompoundOperator::Intersect,
+                CompoundOperator::Except,
+                CompoundOperator::Union,
+            ]
+        );
+        assert!(result.compound[0].select.where_clause.is_some());
+        assert!(result
+            .compound
+            .iter()
+            .all(|term| term.select.order_by.is_empty()));
+        assert_eq!(result.order_by.len(), 1);
+        assert!(result.limit.is_some());
+        assert!(sql_query::select_statement("SELECT 1 ORDER BY 1 UNION SELECT 2").is_err());
+    }
+
+    #[test]
+    fn parse_collate_operator() {
+        let result = sql_query::select_statement(
+            "SELECT -a COLLATE nocase || b FROM t ORDER BY a COLLATE rtrim",
+        )
+        .unwrap();
+        let collate = |expression: Expression, collation: &str| Expression::Collate {
+            expression: Box::new(expression),
+            collation: String::from(collation),
+        };
+        let column = |name: &str| Expression::Column {
+            table: None,
+            name: String::from(name),
+        };
+        let Selectable::Expression { expression, .. } = &result.selectables[0] else {
+            panic!("expected an expression");
+        };
+        // COLLATE binds tighter than binary operators, but not unary ones
+        assert_eq!(
+            *expression,
+            Expression::Binary {
+                left: Box::new(collate(
+                    Expression::Unary {
+                        operator: UnaryOperator::Negate,
+                        operand: Box::new(column("a")),
+                    },
+                    "nocase"
+                )),
+                operator: BinaryOperator::Concat,
+                right: Box::new(column("b")),
+            }
+        );
+        assert_eq!(result.order_by[0].expression, collate(column("a"), "rtrim"));
+    }
+
+    #[test]
+    fn parse_is_operators() {
+        let column = |name: &str| Expression::Column {
+            table: None,
+            name: String::from(name),
+        };
+        let is =
+            |left: Expression, operator: BinaryOperator, right: Expression| Expression::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            };
+        let null = || Expression::Literal(Literal::Null);
+        let parse = |sql: &str| sql_query::expression(sql).unwrap();
+        assert_eq!(
+            parse("a IS NOT b"),
+            is(column("a"), BinaryOperator::IsNot, column("b"))
+        );
+        assert_eq!(
+            parse("a IS NOT DISTINCT FROM b"),
+            is(column("a"), BinaryOperator::Is, column("b"))
+        );
+        assert_eq!(
+            parse("a IS DISTINCT FROM b"),
+            is(column("a"), BinaryOperator::IsNot, column("b"))
+        );
+        assert_eq!(
+            parse("a ISNULL"),
+            is(column("a"), BinaryOperator::Is, null())
+        );
+        assert_eq!(parse("a NOT NULL"), parse("a IS NOT NULL"));
+        assert_eq!(parse("a NOTNULL"), parse("a IS NOT NULL"));
+        // IS compares at the level of =, below <
+        assert_eq!(
+            parse("a IS b < c"),
+            is(
+                column("a"),
+                BinaryOperator::Is,
+                is(column("b"), BinaryOperator::Less, column("c"))
+            )
+        );
+    }
+
+    #[test]
+    fn parse_like_between_and_values() {
+        let parse = |sql: &str| sql_query::expression(sql).unwrap();
+        let call = |name: &str, arguments: &[&str]| Expression::Function {
+            name: String::from(name),
+            distinct: false,
+            arguments: FunctionArguments::List(arguments.iter().map(|a| parse(a)).collect()),
+            over: None,
+        };
+        assert_eq!(parse("a LIKE 'x%'"), call("like", &["'x%'", "a"]));
+        assert_eq!(
+            parse("a LIKE 'x!%' ESCAPE '!'"),
+            call("like", &["'x!%'", "a", "'!'"])
+        );
+        assert_eq!(parse("a NOT GLOB b || 'c'"), parse("NOT glob(b || 'c', a)"));
+        assert_eq!(parse("a BETWEEN b AND c"), parse("a >= b AND a <= c"));
+        assert_eq!(
+            parse("a NOT BETWEEN b + 1 AND c AND d"),
+            parse("NOT (a >= b + 1 AND a <= c) AND d")
+        );
+        assert_eq!(
+            parse("-9223372036854775808"),
+            Expression::Literal(Literal::Integer(i64::MIN))
+        );
+
+        let values = sql_query::select_statement("VALUES (1, 'a')").unwrap();
+        assert_eq!(
+            values.selectables[1],
+            Selectable::Expression {
+                expression: parse("'a'"),
+                alias: Some(String::from("column2")),
+                text: String::new(),
+            }
+        );
+        assert_eq!(values.from_target, None);
+        let values = sql_query::select_statement("VALUES (1), (2), (3) ORDER BY 1").unwrap();
+        let Some(Targetable::Subquery { query, .. }) = values.from_target else {
+            panic!("rows are selected from their union");
+        };
+        assert_eq!(query.compound.len(), 2);
+        assert_eq!(query.compound[0].operator, CompoundOperator::UnionAll);
+        assert_eq!(values.order_by.len(), 1);
+    }
+
+    #[test]
+    fn parse_result_columns() {
+        let result = sql_query::select_statement(
+            "SELECT DISTINCT price * qty AS total, name \"my name\", a.*, \
+             CASE id WHEN 1 THEN 'one' ELSE 'many' END, CAST( id AS INTEGER ) FROM a",
+        )
+        .unwrap();
+        assert!(result.distinct);
+        let aliases: Vec<Option<&str>> = result
+            .selectables
+            .iter()
+            .map(|selectable| match selectable {
+                Selectable::Expression { alias, .. } => alias.as_deref(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            aliases,
+            vec![Some("total"), Some("my name"), None, None, None]
+        );
+        assert_eq!(
+            result.selectables[2],
+            Selectable::TableStar(String::from("a"))
+        );
+        let Selectable::Expression {
+            expression:
+                Expression::Case {
+                    operand,
+                    branches,
+                    else_result,
+                },
+            text,
+            ..
+        } = &result.selectables[3]
+        else {
+            panic!("expected a CASE expression");
+        };
+        assert!(operand.is_some());
+        assert_eq!(branches.len(), 1);
+        assert!(else_result.is_some());
+        assert_eq!(text, "CASE id WHEN 1 THEN 'one' ELSE 'many' END");
+        assert_eq!(
+            result.selectables[4],
+            Selectable::Expression {
+                expression: Expression::Cast {
+                    expression: Box::new(Expression::Column {
+                        table: None,
+                        name: String::from("id")
+                    }),
+                    type_name: String::from("INTEGER"),
+                },
+                alias: None,
+                text: String::from("CAST( id AS INTEGER )"),
+            }
+        );
+        assert!(
+            !sql_query::select_statement("SELECT ALL 1")
+                .unwrap()
+                .distinct
+        );
+    }
+
+    #[test]
+    fn parse_create_table_with_rowid_alias() {
+        let result = sql_query::create_table_statement(
+            "CREATE TABLE apples\n(\n\tid integer primary key autoincrement,\n\tname text,\n\tcolor text\n)",
+        )
+        .unwrap();
+        assert_eq!(result.table_name, "apples");
+        assert_eq!(
+            result
+                .columns
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["id", "name", "color"]
+        );
+        assert_eq!(result.columns[0].type_name.as_deref(), Some("integer"));
+        assert_eq!(
+            result.columns[0].constraints,
+            vec![crate::sql::ColumnConstraint::PrimaryKey {
+                order: None,
+                autoincrement: true
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_create_table_without_types() {
+        let result =
+            sql_query::create_table_statement("CREATE TABLE sqlite_sequence(name,seq)").unwrap();
+        assert_eq!(result.columns.len(), 2);
+        assert_eq!(result.columns[1].type_name, None);
+    }
 }
@@ -0,0 +1,180 @@
+use std::fmt;
+
+use itertools::Itertools;
+use peg::str::LineCol;
+
+/// SQL which doesn't parse, located at the token the parser stopped at
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// The offset in bytes of the token
+    pub position: usize,
+    /// The line of the token, from 1
+    pub line: usize,
+    /// The column of the token in characters, from 1
+    pub column: usize,
+    /// The token, none when the text ended too early
+    pub token: Option<String>,
+    /// Whether the token can't even be read, like a string missing its closing quote
+    pub unrecognized: bool,
+    /// What could have been written instead of the token: keywords, punctuation in double
+    /// quotes, and kinds of tokens such as `identifier` or `number`
+    pub expected: Vec<String>,
+}
+
+impl ParseError {
+    /// Locates the error the parser failed with in the text it was given.
+    pub fn new(sql: &str, error: peg::error::ParseError<LineCol>) -> ParseError {
+        let (position, token, unrecognized) = match locate(sql, error.location.offset) {
+            Some((start, length, unrecognized)) => (
+                start,
+                Some(sql[start..start + length].to_string()),
+                unrecognized,
+            ),
+            None => (sql.len(), None, false),
+        };
+        let before = &sql[..position];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let expected = error
+            .expected
+            .tokens()
+            // character classes are details of the grammar
+            .filter(|expected| !expected.starts_with('['))
+            .map(|expected| match expected {
+                "EOF" => "end of input".to_string(),
+                expected => expected.to_string(),
+            })
+            .sorted()
+            .dedup()
+            .collect();
+        ParseError {
+            position,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            token,
+            unrecognized,
+            expected,
+        }
+    }
+}
+
+/// The messages are those of SQLite.
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.token {
+            None => write!(f, "incomplete input"),
+            Some(token) if self.unrecognized => write!(f, "unrecognized token: \"{}\"", token),
+            Some(token) => write!(f, "near \"{}\": syntax error", token),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The token holding the offset the parser stopped at, or the one following the spaces it
+/// stopped in: where it starts, its length, and whether it can't be read. None when only
+/// spaces follow.
+fn locate(sql: &str, offset: usize) -> Option<(usize, usize, bool)> {
+    let mut position = 0;
+    loop {
+        position += blank_length(&sql[position..]);
+        let (length, unrecognized) = token_length(&sql[position..])?;
+        if position + length > offset || unrecognized {
+            return Some((position, length, unrecognized));
+        }
+        position += length;
+    }
+}
+
+/// The length of the spaces and comments some text starts with
+fn blank_length(text: &str) -> usize {
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start();
+        rest = if let Some(comment) = rest.strip_prefix("--") {
+            comment.find('\n').map_or("", |end| &comment[end..])
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            comment.find("*/").map_or("", |end| &comment[end + 2..])
+        } else {
+            return text.len() - rest.len();
+        };
+    }
+}
+
+/// The length of the token some text starts with, and whether it can't be read, roughly like
+/// SQLite's tokenizer splits text: words and numbers, quoted strings and identifiers,
+/// parameters, and operators. None when the text is empty.
+fn token_length(text: &str) -> Option<(usize, bool)> {
+    let word = |c: char| c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii();
+    let mut chars = text.chars();
+    let first = chars.next()?;
+    let quoted = |start: usize, close: char| match text[start + 1..].find(close) {
+        Some(end) => {
+            let mut end = start + 1 + end + 1;
+            // quotes written twice stand for themselves
+            while text[end..].starts_with(close) {
+                match text[end + 1..].find(close) {
+                    Some(next) => end = end + 1 + next + 1,
+                    None => return (text.len(), true),
+                }
+            }
+            (end, false)
+        }
+        None => (text.len(), true),
+    };
+    let length = match first {
+        '\'' | '"' | '`' => quoted(0, first),
+        '[' => quoted(0, ']'),
+        'x' | 'X' if chars.clone().next() == Some('\'') => quoted(1, '\''),
+        '?' | ':' | '@' | '$' => (
+            1 + text[1..].find(|c| !word(c)).unwrap_or(text.len() - 1),
+            false,
+        ),
+        c if c.is_ascii_digit()
+            || c == '.' && chars.clone().next().is_some_and(|c| c.is_ascii_digit()) =>
+        {
+            let digits = |c: char| word(c) || c == '.';
+            (text.find(|c| !digits(c)).unwrap_or(text.len()), false)
+        }
+        c if word(c) => (text.find(|c| !word(c)).unwrap_or(text.len()), false),
+        _ => {
+            let operator = ["->>", "->", "||", "<=", ">=", "==", "!=", "<>", "<<", ">>"]
+                .iter()
+                .find(|operator| text.starts_with(*operator));
+            (
+                operator.map_or(first.len_utf8(), |operator| operator.len()),
+                false,
+            )
+        }
+    };
+    Some(length)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sql::parse_statement;
+
+    #[test]
+    fn locates_parse_errors() {
+        let error = parse_statement("selec 1").unwrap_err();
+        assert_eq!(error.to_string(), "near \"selec\": syntax error");
+        assert_eq!((error.position, error.line, error.column), (0, 1, 1));
+        assert!(error.expected.contains(&String::from("SELECT")));
+
+        let error = parse_statement("SELECT a,\n  b FROM t\n WHERE").unwrap_err();
+        assert_eq!(error.to_string(), "incomplete input");
+        assert_eq!((error.line, error.column), (3, 7));
+
+        let error = parse_statement("SELECT (2 +\n  FROM t").unwrap_err();
+        assert_eq!(error.to_string(), "near \"FROM\": syntax error");
+        assert_eq!((error.position, error.line, error.column), (14, 2, 3));
+        assert!(error.expected.contains(&String::from("number")));
+
+        let error = parse_statement("SELECT 'it''s").unwrap_err();
+        assert_eq!(error.to_string(), "unrecognized token: \"'it''s\"");
+        assert_eq!(error.column, 8);
+
+        let error = parse_statement("SELECT a FROM t WHERE a <> >= 2").unwrap_err();
+        assert_eq!(error.token.as_deref(), Some(">="));
+        assert!(error.expected.contains(&String::from("identifier")));
+    }
+}
@@ -0,0 +1,9 @@
+CREATE TABLE item(id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, price REAL, data BLOB);
+CREATE TABLE "odd name"(a, b);
+CREATE TABLE pair(v, k TEXT PRIMARY KEY) WITHOUT ROWID;
+CREATE INDEX item_name ON item(name);
+CREATE VIEW cheap AS SELECT name FROM item WHERE price < 1;
+INSERT INTO item(name, price, data) VALUES ('pen', 0.1, x''), ('it''s', 1e300, x'00ff'), (NULL, -2.5, NULL);
+DELETE FROM item WHERE id = 3;
+INSERT INTO "odd name" VALUES (1, 'a' || char(10) || 'b');
+INSERT INTO pair VALUES (1, 'x'), (2, 'a');
@@ -0,0 +1,6 @@
+CREATE TABLE a(x, y, r REAL, w AS (y || 'z'), z);
+CREATE TABLE g(a, b AS (a * 2) STORED, c);
+CREATE TABLE f(a INTEGER PRIMARY KEY, b AS (c + 1), c INTEGER GENERATED ALWAYS AS (a * 10) VIRTUAL, e);
+INSERT INTO a(x, y, r, z) VALUES (1, 'q', 2.5, 1), (2, 'p', 3, NULL);
+INSERT INTO g(a, c) VALUES (3, 'x');
+INSERT INTO f(a, e) VALUES (5, 'e');
@@ -0,0 +1,3 @@
+CREATE TABLE t(id INTEGER PRIMARY KEY, code TEXT COLLATE NOCASE UNIQUE, n, UNIQUE(n, id));
+CREATE INDEX t_n ON t(n DESC);
+INSERT INTO t VALUES (1, 'a', 10), (2, 'B', 20), (3, NULL, 30);
@@ -0,0 +1,9 @@
+CREATE TABLE dept(id INTEGER PRIMARY KEY, name TEXT);
+CREATE TABLE emp(id INTEGER PRIMARY KEY, name TEXT, dept_id INTEGER, salary INTEGER);
+CREATE INDEX emp_dept ON emp(dept_id DESC);
+CREATE TABLE project(emp_id INTEGER, title TEXT);
+CREATE TABLE badge(dept_id INTEGER, color TEXT);
+INSERT INTO dept VALUES (1, 'Sales'), (2, 'Research'), (3, 'Legal');
+INSERT INTO emp VALUES (1, 'Ann', 1, 100), (2, 'Bob', 2, 200), (3, 'Cid', 2, 300), (4, 'Dee', NULL, 50);
+INSERT INTO project VALUES (2, 'Parser'), (3, 'Planner'), (3, 'Pager');
+INSERT INTO badge VALUES (1, 'red'), (2, 'blue');